@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use integrationos_domain::{event_access::EventAccess, Claims, Id};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ActorType {
+    /// A user authenticated with a JWT
+    User,
+    EventAccess,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditActor {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub actor_type: ActorType,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub ownership_id: Option<String>,
+}
+
+impl AuditActor {
+    pub fn user(claims: &Claims) -> Self {
+        Self {
+            id: claims.id.clone(),
+            actor_type: ActorType::User,
+            ownership_id: Some(claims.buildable_id.clone()),
+        }
+    }
+
+    pub fn event_access(event_access: &EventAccess) -> Self {
+        Self {
+            id: event_access.id.to_string(),
+            actor_type: ActorType::EventAccess,
+            ownership_id: Some(event_access.ownership.id.to_string()),
+        }
+    }
+}
+
+/// An administrative or secret access operation. Audit logs are only ever
+/// inserted, never updated or deleted.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLog {
+    #[serde(rename = "_id")]
+    pub id: Id,
+    pub actor: AuditActor,
+    /// `{resource}.{verb}`, e.g. `secrets.read` or `event-access.rotate`
+    pub action: String,
+    pub method: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub target_id: Option<String>,
+    pub request_id: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub ip: Option<String>,
+    pub outcome: AuditOutcome,
+    pub status: u16,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub created_at: DateTime<Utc>,
+}
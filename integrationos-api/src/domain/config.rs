@@ -1,9 +1,14 @@
 use envconfig::Envconfig;
 use integrationos_domain::{cache::CacheConfig, environment::Environment};
-use integrationos_domain::{database::DatabaseConfig, secrets::SecretsConfig};
+use integrationos_domain::{
+    database::DatabaseConfig, redaction::RedactionRules, script::ScriptConfig,
+    secrets::SecretsConfig,
+};
+use ipnet::IpNet;
 use std::{
     fmt::{Display, Formatter, Result},
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
 };
 use strum::{AsRefStr, EnumString};
 
@@ -19,6 +24,10 @@ pub struct ConnectionsConfig {
     pub access_key_cache_ttl_secs: u64,
     #[envconfig(from = "ACCESS_KEY_WHITELIST_REFRESH_INTERVAL_SECS", default = "60")]
     pub access_key_whitelist_refresh_interval_secs: u64,
+    #[envconfig(from = "ACCESS_KEY_ROTATION_GRACE_PERIOD_SECS", default = "86400")]
+    pub access_key_rotation_grace_period_secs: u64,
+    #[envconfig(from = "ACCESS_KEY_LAST_USED_INTERVAL_SECS", default = "60")]
+    pub access_key_last_used_interval_secs: u64,
     #[envconfig(from = "CONNECTION_CACHE_TTL_SECS", default = "120")]
     pub connection_cache_ttl_secs: u64,
     #[envconfig(from = "ENGINEERING_ACCOUNT_ID", default = "engineering_account")]
@@ -27,6 +36,10 @@ pub struct ConnectionsConfig {
     pub connection_definition_cache_ttl_secs: u64,
     #[envconfig(from = "CONNECTION_OAUTH_DEFINITION_CACHE_TTL_SECS", default = "86400")]
     pub connection_oauth_definition_cache_ttl_secs: u64,
+    #[envconfig(from = "OAUTH_STATE_TTL_SECS", default = "600")]
+    pub oauth_state_ttl_secs: u64,
+    #[envconfig(from = "AUTHKIT_TOKEN_TTL_SECS", default = "600")]
+    pub authkit_token_ttl_secs: u64,
     #[envconfig(from = "CONNECTION_MODEL_SCHEMA_TTL_SECS", default = "86400")]
     pub connection_model_schema_cache_ttl_secs: u64,
     #[envconfig(from = "CONNECTION_MODEL_DEFINITION_CACHE_TTL_SECS", default = "86400")]
@@ -73,6 +86,12 @@ pub struct ConnectionsConfig {
     pub db_config: DatabaseConfig,
     #[envconfig(nested = true)]
     pub cache_config: CacheConfig,
+    #[envconfig(nested = true)]
+    pub script_config: ScriptConfig,
+    /// Comma separated addresses or networks of the load balancers in front of the API,
+    /// only their `X-Forwarded-For` header is used to find the address of the client
+    #[envconfig(from = "TRUSTED_PROXIES", default = "")]
+    pub trusted_proxies: TrustedProxies,
     #[envconfig(from = "RATE_LIMIT_ENABLED", default = "true")]
     pub rate_limit_enabled: bool,
     #[envconfig(from = "ENVIRONMENT", default = "development")]
@@ -87,6 +106,9 @@ pub struct ConnectionsConfig {
     pub k8s_mode: K8sMode,
     #[envconfig(from = "OTLP_ENDPOINT")]
     pub otlp_endpoint: Option<String>,
+    /// JSON encoded `RedactionRules` applied to every stored payload
+    #[envconfig(from = "REDACTION_RULES", default = "{}")]
+    pub redaction_rules: RedactionRules,
 }
 
 impl Display for ConnectionsConfig {
@@ -104,6 +126,16 @@ impl Display for ConnectionsConfig {
             "ACCESS_KEY_WHITELIST_REFRESH_INTERVAL_SECS: {}",
             self.access_key_whitelist_refresh_interval_secs
         )?;
+        writeln!(
+            f,
+            "ACCESS_KEY_ROTATION_GRACE_PERIOD_SECS: {}",
+            self.access_key_rotation_grace_period_secs
+        )?;
+        writeln!(
+            f,
+            "ACCESS_KEY_LAST_USED_INTERVAL_SECS: {}",
+            self.access_key_last_used_interval_secs
+        )?;
         writeln!(f, "EVENT_ACCESS_PASSWORD: ***")?;
         writeln!(
             f,
@@ -127,6 +159,8 @@ impl Display for ConnectionsConfig {
             "CONNECTION_OAUTH_DEFINITION_CACHE_TTL_SECS: {}",
             self.connection_oauth_definition_cache_ttl_secs
         )?;
+        writeln!(f, "OAUTH_STATE_TTL_SECS: {}", self.oauth_state_ttl_secs)?;
+        writeln!(f, "AUTHKIT_TOKEN_TTL_SECS: {}", self.authkit_token_ttl_secs)?;
         writeln!(
             f,
             "EVENT_SAVE_TIMEOUT_SECS: {}",
@@ -146,8 +180,11 @@ impl Display for ConnectionsConfig {
         writeln!(f, "{}", self.headers)?;
         writeln!(f, "{}", self.db_config)?;
         writeln!(f, "{}", self.cache_config)?;
+        writeln!(f, "{}", self.script_config)?;
+        writeln!(f, "TRUSTED_PROXIES: {}", self.trusted_proxies)?;
         writeln!(f, "RATE_LIMIT_ENABLED: {}", self.rate_limit_enabled)?;
         writeln!(f, "ENVIRONMENT: {}", self.environment)?;
+        writeln!(f, "REDACTION_RULES: {}", self.redaction_rules)?;
         writeln!(
             f,
             "DATABASE_CONNECTION_DOCKER_IMAGE: {}",
@@ -157,6 +194,40 @@ impl Display for ConnectionsConfig {
     }
 }
 
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TrustedProxies(pub Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(ip))
+    }
+}
+
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| {
+                proxy
+                    .parse::<IpNet>()
+                    .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|e| format!("Invalid trusted proxy {proxy}: {e}"))
+            })
+            .collect::<std::result::Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl Display for TrustedProxies {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let proxies = self.0.iter().map(ToString::to_string).collect::<Vec<_>>();
+        write!(f, "{}", proxies.join(","))
+    }
+}
+
 #[derive(Envconfig, Default, Clone)]
 pub struct Headers {
     #[envconfig(from = "HEADER_AUTH", default = "x-pica-secret")]
@@ -1,5 +1,7 @@
+pub mod audit;
 pub mod config;
 pub mod metrics;
 
+pub use audit::*;
 pub use config::*;
 pub use metrics::*;
@@ -126,7 +126,12 @@ mod test {
             group: "group".to_string(),
             ownership: Ownership::new("baz".to_string()),
             paths: Paths::default(),
+            webhook_handshakes: vec![],
+            scope: None,
+            data_key_id: None,
             access_key: "access_key".to_string(),
+            previous_access_keys: vec![],
+            last_used_at: None,
             environment: Environment::Test,
             record_metadata: RecordMetadata::default(),
             throughput: 1000,
@@ -159,7 +164,12 @@ mod test {
             group: "group".to_string(),
             ownership: Ownership::new("baz".to_string()),
             paths: Paths::default(),
+            webhook_handshakes: vec![],
+            scope: None,
+            data_key_id: None,
             access_key: "access_key".to_string(),
+            previous_access_keys: vec![],
+            last_used_at: None,
             environment: Environment::Test,
             record_metadata: RecordMetadata::default(),
             throughput: 1000,
@@ -0,0 +1,90 @@
+use super::ReadResponse;
+use crate::{
+    helper::{shape_mongo_filter, DELETED_STR},
+    router::ServerResponse,
+    server::AppState,
+};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use bson::{doc, Document};
+use integrationos_domain::{ApplicationError, IntegrationOSError};
+use serde_json::Value;
+use std::{collections::BTreeMap, sync::Arc};
+use tracing::error;
+
+const FROM: &str = "from";
+const TO: &str = "to";
+
+/// Lists audit logs, newest first.
+///
+/// Any field can be filtered on, e.g. `actor.id`, `action` or `targetId`. `from`
+/// and `to` are inclusive unix timestamps in milliseconds.
+pub async fn read_audit_logs(
+    query: Option<Query<BTreeMap<String, String>>>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServerResponse<ReadResponse<Value>>>, IntegrationOSError> {
+    let mut params = query.map(|Query(q)| q).unwrap_or_default();
+    let created_at = created_at_filter(params.remove(FROM), params.remove(TO))?;
+
+    let mut query = shape_mongo_filter(Some(Query(params)), None, None);
+    // Audit logs are append only and never soft deleted
+    query.filter.remove(DELETED_STR);
+    if let Some(created_at) = created_at {
+        query.filter.insert("createdAt", created_at);
+    }
+
+    let store = &state.app_stores.audit_logs;
+    let (rows, total) = tokio::try_join!(
+        store.get_many(
+            Some(query.filter.clone()),
+            None,
+            Some(doc! { "createdAt": -1 }),
+            Some(query.limit),
+            Some(query.skip),
+        ),
+        store.count(query.filter, None),
+    )
+    .map_err(|e| {
+        error!("Error reading audit logs: {e}");
+        e
+    })?;
+
+    Ok(Json(ServerResponse::new(
+        "read",
+        ReadResponse {
+            rows: rows
+                .into_iter()
+                .map(|row| serde_json::to_value(row).unwrap_or_default())
+                .collect(),
+            total,
+            skip: query.skip,
+            limit: query.limit,
+        },
+    )))
+}
+
+fn created_at_filter(
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Option<Document>, IntegrationOSError> {
+    let parse = |name: &str, value: String| {
+        value.parse::<i64>().map_err(|_| {
+            ApplicationError::bad_request(
+                &format!("{name} must be a unix timestamp in milliseconds"),
+                None,
+            )
+        })
+    };
+
+    let mut range = doc! {};
+    if let Some(from) = from {
+        range.insert("$gte", parse(FROM, from)?);
+    }
+    if let Some(to) = to {
+        range.insert("$lte", parse(TO, to)?);
+    }
+
+    Ok((!range.is_empty()).then_some(range))
+}
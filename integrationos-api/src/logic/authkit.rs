@@ -0,0 +1,118 @@
+use crate::{router::ServerResponse, server::AppState};
+use axum::{extract::State, routing::post, Extension, Json, Router};
+use chrono::{Duration, Utc};
+use integrationos_domain::{
+    event_access::EventAccess,
+    id::{prefix::IdPrefix, Id},
+    record_metadata::RecordMetadata,
+    token::AuthKitToken,
+    ApplicationError, ConnectionIdentityType, IntegrationOSError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::error;
+use validator::Validate;
+
+pub fn get_router() -> Router<Arc<AppState>> {
+    Router::new().route("/", post(create_token))
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAuthKitTokenRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub identity: String,
+    pub identity_type: ConnectionIdentityType,
+    /// Group of the connections made with the token, the identity if unset
+    #[validate(length(min = 1, max = 128))]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub connection_definition_ids: Vec<Id>,
+}
+
+fn public(token: &AuthKitToken) -> Value {
+    json!({
+        "identity": token.identity,
+        "identityType": token.identity_type,
+        "group": token.group,
+        "connectionDefinitionIds": token.connection_definition_ids,
+        "environment": token.environment,
+        "expiresAt": token.expires_at.timestamp_millis(),
+    })
+}
+
+/// Exchanges the secret key of the request for a short lived token bound to
+/// an identity, which frontends connect accounts with. The token is only
+/// returned here, it can not be read again.
+async fn create_token(
+    Extension(access): Extension<Arc<EventAccess>>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateAuthKitTokenRequest>,
+) -> Result<Json<ServerResponse<Value>>, IntegrationOSError> {
+    if let Err(validation_errors) = payload.validate() {
+        return Err(ApplicationError::bad_request(
+            &format!("Invalid payload: {:?}", validation_errors),
+            None,
+        ));
+    }
+
+    let expires_at = Utc::now() + Duration::seconds(state.config.authkit_token_ttl_secs as i64);
+    let generated = AuthKitToken::generate_token();
+    let token = AuthKitToken {
+        id: Id::now(IdPrefix::EmbedToken),
+        token_hash: AuthKitToken::hash(&generated),
+        group: payload.group.unwrap_or_else(|| payload.identity.clone()),
+        identity: payload.identity,
+        identity_type: payload.identity_type,
+        connection_definition_ids: payload.connection_definition_ids,
+        event_access_id: access.id,
+        environment: access.environment,
+        ownership: access.ownership.clone(),
+        expires_at: bson::DateTime::from_millis(expires_at.timestamp_millis()),
+        record_metadata: RecordMetadata::default(),
+    };
+
+    state
+        .app_stores
+        .authkit_tokens
+        .create_one(&token)
+        .await
+        .inspect_err(|e| error!("Could not create AuthKit token: {e}"))?;
+
+    let mut response = public(&token);
+    response["token"] = json!(generated);
+
+    Ok(Json(ServerResponse::new("create", response)))
+}
+
+/// Describes the token of the request, so that the frontend knows which
+/// platforms it can offer
+pub async fn read_token(
+    Extension(token): Extension<Arc<AuthKitToken>>,
+) -> Json<ServerResponse<Value>> {
+    Json(ServerResponse::new("read", public(&token)))
+}
+
+/// Connections made with an AuthKit token belong to the identity of the token,
+/// whatever the request says
+pub(crate) fn authorize_connection(
+    token: &AuthKitToken,
+    connection_definition_id: &Id,
+    identity: &mut Option<String>,
+    identity_type: &mut Option<ConnectionIdentityType>,
+    group: &mut Option<String>,
+) -> Result<(), IntegrationOSError> {
+    if !token.allows(connection_definition_id) {
+        return Err(ApplicationError::forbidden(
+            &format!("This token cannot connect {connection_definition_id}"),
+            None,
+        ));
+    }
+
+    *identity = Some(token.identity.clone());
+    *identity_type = Some(token.identity_type.clone());
+    *group = Some(token.group.clone());
+
+    Ok(())
+}
@@ -1,15 +1,20 @@
-use super::{delete, event_access::DEFAULT_NAMESPACE, read, PublicExt, RequestExt};
+use super::{
+    authkit::authorize_connection, delete, event_access::DEFAULT_NAMESPACE, read, PublicExt,
+    ReadResponse, RequestExt,
+};
 use crate::{
-    helper::{DeploymentSpecParams, ServiceName, ServiceSpecParams},
+    helper::{shape_mongo_filter, DeploymentSpecParams, ServiceName, ServiceSpecParams},
     logic::event_access::{
-        generate_event_access, get_client_throughput, CreateEventAccessPayloadWithOwnership,
+        add_data_key, generate_event_access, get_client_throughput,
+        CreateEventAccessPayloadWithOwnership,
     },
+    middleware::scope::{authorize_connection_key, connection_filter},
     router::ServerResponse,
     server::{AppState, AppStores},
 };
 use anyhow::{bail, Result};
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     routing::{delete as axum_delete, get, patch, post},
     Extension, Json, Router,
 };
@@ -19,22 +24,27 @@ use http::HeaderMap;
 use integrationos_domain::{
     algebra::MongoStore,
     connection_definition::{ConnectionDefinition, ConnectionDefinitionType},
+    connection_model_definition::PlatformInfo,
     database::{DatabasePodConfig, PostgresConfig},
     database_secret::DatabaseConnectionSecret,
     domain::connection::SanitizedConnection,
     event_access::EventAccess,
+    health::HealthStatus,
     id::{prefix::IdPrefix, Id},
+    lifecycle::ConnectionLifecycle,
     record_metadata::RecordMetadata,
     settings::Settings,
+    token::AuthKitToken,
     ApplicationError, Connection, ConnectionIdentityType, ConnectionType, IntegrationOSError,
-    InternalError, Throughput,
+    InternalError, OAuth, Throughput, Unit,
 };
 use k8s_openapi::{
     api::core::v1::{ContainerPort, EnvVar, EnvVarSource, SecretKeySelector, ServicePort},
     apimachinery::pkg::util::intstr::IntOrString,
 };
 use mongodb::bson::doc;
-use mongodb::bson::Regex;
+use mongodb::bson::{Document, Regex};
+use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::{
@@ -50,7 +60,9 @@ pub fn get_router() -> Router<Arc<AppState>> {
     Router::new()
         .route("/", post(create_connection))
         .route("/", get(read::<CreateConnectionPayload, Connection>))
+        .route("/unhealthy", get(read_unhealthy_connections))
         .route("/:id", patch(update_connection))
+        .route("/:id/reauthorize", post(reauthorize_connection))
         .route("/:id", axum_delete(delete_connection))
 }
 
@@ -70,9 +82,12 @@ pub struct CreateConnectionPayload {
     pub identity_type: Option<ConnectionIdentityType>,
     pub group: Option<String>,
     pub name: Option<String>,
+    /// Encrypts the payloads of events received for the connection at rest
+    #[serde(default)]
+    pub encrypt_events: bool,
 }
 
-async fn test_connection(
+pub(crate) async fn test_connection(
     state: &AppState,
     connection_config: &ConnectionDefinition,
     auth_form_data_value: &Value,
@@ -85,7 +100,14 @@ async fn test_connection(
             .await?;
 
         let test_connection_model_config = match test_connection_model_config {
-            Some(config) => Arc::new(config),
+            Some(mut config) => {
+                // The connection does not exist yet to carry the platform settings
+                if connection_config.settings.mtls {
+                    let PlatformInfo::Api(ref mut api_config) = config.platform_info;
+                    api_config.mtls = true;
+                }
+                Arc::new(config)
+            }
             None => {
                 return Err(anyhow::anyhow!(
                     "Test connection model config {} not found",
@@ -157,6 +179,7 @@ impl PublicExt<Connection> for CreateConnectionPayload {
             throughput: input.throughput,
             ownership: input.ownership,
             error: input.error,
+            health: input.health,
             has_error: input.has_error,
             oauth: input.oauth,
             record_metadata: input.record_metadata,
@@ -171,12 +194,17 @@ impl RequestExt for CreateConnectionPayload {
     fn get_store(stores: AppStores) -> MongoStore<Self::Output> {
         stores.connection
     }
+
+    fn scope_filter(access: &EventAccess) -> Option<Document> {
+        connection_filter(access)
+    }
 }
 
 pub async fn create_connection(
     Extension(access): Extension<Arc<EventAccess>>,
+    authkit: Option<Extension<Arc<AuthKitToken>>>,
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<CreateConnectionPayload>,
+    Json(mut payload): Json<CreateConnectionPayload>,
 ) -> Result<Json<SanitizedConnection>, IntegrationOSError> {
     if let Err(validation_errors) = payload.validate() {
         return Err(ApplicationError::not_found(
@@ -185,6 +213,16 @@ pub async fn create_connection(
         ));
     }
 
+    if let Some(Extension(token)) = authkit {
+        authorize_connection(
+            &token,
+            &payload.connection_definition_id,
+            &mut payload.identity,
+            &mut payload.identity_type,
+            &mut payload.group,
+        )?;
+    }
+
     if let Some(identity) = &payload.identity {
         if identity.len() > 128 {
             return Err(ApplicationError::bad_request(
@@ -240,7 +278,7 @@ pub async fn create_connection(
 
     let throughput = get_client_throughput(&access.ownership.id, &state).await?;
 
-    let event_access = generate_event_access(
+    let mut event_access = generate_event_access(
         state.config.clone(),
         CreateEventAccessPayloadWithOwnership {
             name: format!("{} {}", access.environment, connection_config.name),
@@ -249,6 +287,9 @@ pub async fn create_connection(
             connection_type: connection_config.r#type.clone(),
             environment: access.environment,
             paths: connection_config.paths.clone(),
+            webhook_handshakes: connection_config.webhook_handshakes.clone(),
+            // Connections created by a scoped key are restricted the same way
+            scope: access.scope.clone(),
             ownership: access.ownership.clone(),
             throughput: Some(throughput),
         },
@@ -303,6 +344,20 @@ pub async fn create_connection(
             error!("Error creating secret for connection: {:?}", e);
         })?;
 
+    // The gateway looks the data key up from the event access of the key events
+    // arrive with, which is otherwise not stored for connections
+    if payload.encrypt_events {
+        add_data_key(&state, &mut event_access).await?;
+        state
+            .app_stores
+            .event_access
+            .create_one(&event_access)
+            .await
+            .inspect_err(|e| {
+                error!("Error saving event access for connection: {:?}", e);
+            })?;
+    }
+
     let connection = Connection {
         id: connection_id,
         platform_version: connection_config.clone().platform_version,
@@ -314,6 +369,7 @@ pub async fn create_connection(
         name: payload.name,
         has_error: false,
         error: None,
+        health: None,
         identity_type: payload.identity_type,
         platform: connection_config.platform.into(),
         environment: event_access.environment,
@@ -339,6 +395,8 @@ pub async fn create_connection(
             error!("Error creating connection: {:?}", e);
         })?;
 
+    publish_lifecycle(&state, &connection, ConnectionLifecycle::Created, json!({})).await;
+
     Ok(Json(SanitizedConnection {
         id: connection.id,
         platform_version: connection.platform_version,
@@ -358,6 +416,7 @@ pub async fn create_connection(
         ownership: connection.ownership,
         has_error: connection.has_error,
         error: connection.error,
+        health: connection.health,
         oauth: connection.oauth,
         record_metadata: connection.record_metadata,
     }))
@@ -510,7 +569,121 @@ pub async fn update_connection(
     State(state): State<Arc<AppState>>,
     Json(req): Json<UpdateConnectionPayload>,
 ) -> Result<Json<ServerResponse<Value>>, IntegrationOSError> {
-    let Some(mut connection) = (match state.app_stores.connection.get_one_by_id(&id).await {
+    let mut connection = get_owned_connection(&state, &id, &event_access).await?;
+
+    if let Some(settings) = req.settings {
+        connection.settings = settings;
+    }
+
+    if let Some(throughput) = req.throughput {
+        connection.throughput = throughput;
+    }
+
+    if let Some(identity) = req.identity {
+        connection.identity = Some(identity);
+    }
+
+    if let Some(identity_type) = req.identity_type {
+        connection.identity_type = Some(identity_type);
+    }
+
+    let reauthorized = req.auth_form_data.is_some();
+    if let Some(auth_form_data) = req.auth_form_data {
+        replace_auth_form_data(&state, &connection, auth_form_data, &event_access).await?;
+        connection.clear_error();
+    }
+
+    if let Some(active) = req.active {
+        connection.record_metadata.active = active;
+    }
+
+    let Ok(document) = bson::to_document(&connection) else {
+        error!("Could not serialize connection into document");
+
+        return Err(InternalError::serialize_error(
+            "Could not serialize connection into document",
+            None,
+        ));
+    };
+
+    connection
+        .record_metadata
+        .mark_updated(&event_access.ownership.id);
+
+    let mut update = doc! {
+        "$set": document
+    };
+    if reauthorized {
+        // The health of the old credentials says nothing about the new ones
+        update.insert("$unset", doc! { "health": "" });
+    }
+
+    match state.app_stores.connection.update_one(&id, update).await {
+        Ok(_) => {
+            if reauthorized {
+                evict_connection(&state, connection.id).await;
+            }
+
+            Ok(Json(ServerResponse::new(
+                "connection",
+                json!({
+                    id: connection.id,
+                }),
+            )))
+        }
+        Err(e) => {
+            error!("Error updating connection: {:?}", e);
+
+            Err(e)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReauthorizeConnectionPayload {
+    pub auth_form_data: HashMap<String, String>,
+}
+
+/// Replaces the credentials of a connection whose key was revoked, keeping its
+/// key and access key so pipelines and clients referencing it keep working
+pub async fn reauthorize_connection(
+    Extension(event_access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ReauthorizeConnectionPayload>,
+) -> Result<Json<ServerResponse<Value>>, IntegrationOSError> {
+    let mut connection = get_owned_connection(&state, &id, &event_access).await?;
+
+    if let Some(OAuth::Enabled { .. }) = connection.oauth {
+        return Err(ApplicationError::bad_request(
+            "OAuth connections are reauthorized through the oauth flow",
+            None,
+        ));
+    }
+
+    replace_auth_form_data(&state, &connection, req.auth_form_data, &event_access).await?;
+
+    connection.clear_error();
+    connection
+        .record_metadata
+        .mark_updated(&event_access.ownership.id);
+
+    save_reauthorized_connection(&state, &connection).await?;
+
+    Ok(Json(ServerResponse::new(
+        "connection",
+        CreateConnectionPayload::public(connection),
+    )))
+}
+
+/// Fetches a connection, making sure it belongs to the caller
+pub(crate) async fn get_owned_connection(
+    state: &AppState,
+    id: &str,
+    event_access: &EventAccess,
+) -> Result<Connection, IntegrationOSError> {
+    let Some(connection) = (match state.app_stores.connection.get_one_by_id(id).await {
         Ok(connection) => connection,
         Err(e) => {
             error!("Error fetching connection for update: {:?}", e);
@@ -532,92 +705,96 @@ pub async fn update_connection(
             None,
         ));
     }
+    authorize_connection_key(event_access, &connection.key)?;
 
-    if let Some(settings) = req.settings {
-        connection.settings = settings;
-    }
-
-    if let Some(throughput) = req.throughput {
-        connection.throughput = throughput;
-    }
+    Ok(connection)
+}
 
-    if let Some(identity) = req.identity {
-        connection.identity = Some(identity);
-    }
+/// Tests the new auth form data of a connection and replaces its secret with it
+async fn replace_auth_form_data(
+    state: &AppState,
+    connection: &Connection,
+    auth_form_data: HashMap<String, String>,
+    event_access: &EventAccess,
+) -> Result<Unit, IntegrationOSError> {
+    let auth_form_data_value = serde_json::to_value(auth_form_data).map_err(|e| {
+        error!(
+            "Error serializing auth form data for connection update: {:?}",
+            e
+        );
 
-    if let Some(identity_type) = req.identity_type {
-        connection.identity_type = Some(identity_type);
-    }
+        ApplicationError::bad_request(&format!("Invalid auth form data: {:?}", e), None)
+    })?;
 
-    if let Some(auth_form_data) = req.auth_form_data {
-        let auth_form_data_value = serde_json::to_value(auth_form_data).map_err(|e| {
+    let connection_config = match state
+        .app_stores
+        .connection_config
+        .get_one(doc! {
+            "_id": connection.connection_definition_id.to_string(),
+            "deleted": false
+        })
+        .await
+    {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            return Err(ApplicationError::not_found(
+                "Connection definition not found",
+                None,
+            ));
+        }
+        Err(e) => {
             error!(
-                "Error serializing auth form data for connection update: {:?}",
+                "Error fetching connection definition in connection update: {:?}",
                 e
             );
 
-            ApplicationError::bad_request(&format!("Invalid auth form data: {:?}", e), None)
-        })?;
-
-        let connection_config = match state
-            .app_stores
-            .connection_config
-            .get_one(doc! {
-                "_id": connection.connection_definition_id.to_string(),
-                "deleted": false
-            })
-            .await
-        {
-            Ok(Some(data)) => data,
-            Ok(None) => {
-                return Err(ApplicationError::not_found(
-                    "Connection definition not found",
-                    None,
-                ));
-            }
-            Err(e) => {
-                error!(
-                    "Error fetching connection definition in connection update: {:?}",
-                    e
-                );
-
-                return Err(e);
-            }
-        };
-
-        if connection_config.r#type == ConnectionDefinitionType::DatabaseSql {
-            return Err(ApplicationError::bad_request(
-                "Unsupported platform for SQL connection",
-                None,
-            ));
+            return Err(e);
         }
+    };
 
-        test_connection(&state, &connection_config, &auth_form_data_value)
-            .await
-            .map_err(|e| {
-                error!("Error executing model definition in connections update for connection testing: {:?}", e);
+    if connection_config.r#type == ConnectionDefinitionType::DatabaseSql {
+        return Err(ApplicationError::bad_request(
+            "Unsupported platform for SQL connection",
+            None,
+        ));
+    }
 
-                ApplicationError::bad_request(&format!("Invalid auth form data: {:?}", e), None)
-            })?;
+    test_connection(state, &connection_config, &auth_form_data_value)
+        .await
+        .map_err(|e| {
+            error!("Error executing model definition in connections update for connection testing: {:?}", e);
 
-        let secret_result = state
-            .secrets_client
-            .create(&auth_form_data_value, &event_access.ownership.id)
-            .await
-            .map_err(|e| {
-                error!("Error creating secret for connection update: {:?}", e);
+            ApplicationError::bad_request(&format!("Invalid auth form data: {:?}", e), None)
+        })?;
 
-                e
-            })?;
+    // The secret is replaced in place so the old credentials do not linger
+    state
+        .secrets_client
+        .update(
+            &connection.secrets_service_id,
+            &auth_form_data_value,
+            &event_access.ownership.id,
+        )
+        .await
+        .map_err(|e| {
+            error!("Error updating secret for connection update: {:?}", e);
 
-        connection.secrets_service_id = secret_result.id();
-    }
+            e
+        })?;
+    // Evicted right away so the old secret and the clients built from it are not
+    // used even if saving the connection fails
+    evict_connection(state, connection.id).await;
 
-    if let Some(active) = req.active {
-        connection.record_metadata.active = active;
-    }
+    Ok(())
+}
 
-    let Ok(document) = bson::to_document(&connection) else {
+/// Stores the new state of a connection whose secret was replaced, forgetting
+/// the health of the old one, and evicts it from the caches
+pub(crate) async fn save_reauthorized_connection(
+    state: &AppState,
+    connection: &Connection,
+) -> Result<Unit, IntegrationOSError> {
+    let Ok(document) = bson::to_document(connection) else {
         error!("Could not serialize connection into document");
 
         return Err(InternalError::serialize_error(
@@ -626,33 +803,115 @@ pub async fn update_connection(
         ));
     };
 
-    connection
-        .record_metadata
-        .mark_updated(&event_access.ownership.id);
-
-    match state
+    state
         .app_stores
         .connection
         .update_one(
-            &id,
+            &connection.id.to_string(),
             doc! {
-                "$set": document
+                "$set": document,
+                "$unset": { "health": "" }
             },
         )
         .await
+        .inspect_err(|e| {
+            error!("Error updating reauthorized connection: {:?}", e);
+        })?;
+
+    evict_connection(state, connection.id).await;
+
+    Ok(())
+}
+
+/// Drops the connection from the caches of this instance, then tells every
+/// other instance to do the same.
+///
+/// Failures are only logged, the caches expire on their own eventually.
+pub(crate) async fn evict_connection(state: &AppState, id: Id) {
+    remove_cached_connection(state, id).await;
+
+    let channel = &state.config.cache_config.reauthorized_connections_key;
+    if let Err(e) = state
+        .redis
+        .clone()
+        .publish::<_, _, ()>(channel, id.to_string())
+        .await
     {
-        Ok(_) => Ok(Json(ServerResponse::new(
-            "connection",
-            json!({
-                id: connection.id,
-            }),
-        ))),
-        Err(e) => {
-            error!("Error updating connection: {:?}", e);
+        error!("Could not publish reauthorized connection {id}: {e}");
+    }
+}
 
-            Err(e)
-        }
+/// Announces a state change of the connection, which is delivered to the webhook
+/// subscriptions of the account. Failures are only logged, the change itself
+/// already happened.
+pub(crate) async fn publish_lifecycle(
+    state: &AppState,
+    connection: &Connection,
+    lifecycle: ConnectionLifecycle,
+    data: Value,
+) {
+    if let Err(e) = state.lifecycle.publish(connection, lifecycle, data).await {
+        error!(
+            "Could not publish {lifecycle} event of connection {}: {e}",
+            connection.id
+        );
+    }
+}
+
+pub(crate) async fn remove_cached_connection(state: &AppState, id: Id) {
+    if let Err(e) = state.connections_cache.remove_connection(id) {
+        error!("Could not evict connection {id}: {e}");
+    }
+    if let Err(e) = state.extractor_caller.remove_connection(id).await {
+        error!("Could not evict cached secret of connection {id}: {e}");
+    }
+}
+
+/// Lists the connections of the account whose health checks keep failing, most
+/// recently checked first
+pub async fn read_unhealthy_connections(
+    headers: HeaderMap,
+    Extension(access): Extension<Arc<EventAccess>>,
+    query: Option<Query<BTreeMap<String, String>>>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServerResponse<ReadResponse<Value>>>, IntegrationOSError> {
+    let scope_filter = connection_filter(&access);
+    let mut query = shape_mongo_filter(query, Some(access), Some(headers));
+    query
+        .filter
+        .insert("health.status", HealthStatus::Unhealthy.as_ref());
+    if let Some(scope_filter) = scope_filter {
+        query.filter.extend(scope_filter);
     }
+
+    let store = &state.app_stores.connection;
+    let (rows, total) = tokio::try_join!(
+        store.get_many(
+            Some(query.filter.clone()),
+            None,
+            Some(doc! { "health.checkedAt": -1 }),
+            Some(query.limit),
+            Some(query.skip),
+        ),
+        store.count(query.filter, None),
+    )
+    .map_err(|e| {
+        error!("Error reading unhealthy connections: {e}");
+        e
+    })?;
+
+    Ok(Json(ServerResponse::new(
+        "read",
+        ReadResponse {
+            rows: rows
+                .into_iter()
+                .map(CreateConnectionPayload::public)
+                .collect(),
+            total,
+            skip: query.skip,
+            limit: query.limit,
+        },
+    )))
 }
 
 pub async fn delete_connection(
@@ -703,6 +962,14 @@ pub async fn delete_connection(
             e
         })?;
 
+    publish_lifecycle(
+        &state,
+        &connection.args,
+        ConnectionLifecycle::Deleted,
+        json!({}),
+    )
+    .await;
+
     Ok(Json(ServerResponse::new(
         "connection",
         json!({
@@ -19,7 +19,9 @@ use integrationos_domain::{
     connection_model_definition::{ConnectionModelDefinition, CrudAction},
     id::{prefix::IdPrefix, Id},
     record_metadata::RecordMetadata,
+    redaction::RedactionRules,
     settings::Settings,
+    webhook_handshake::WebhookHandshake,
     ApplicationError, IntegrationOSError,
 };
 use mongodb::bson::doc;
@@ -63,6 +65,10 @@ pub struct CreateRequest {
     pub multi_env: bool,
     pub settings: Settings,
     pub paths: Paths,
+    #[serde(default)]
+    pub webhook_handshakes: Vec<WebhookHandshake>,
+    #[serde(default)]
+    pub redaction: Option<RedactionRules>,
     pub test_connection: Option<Id>,
     pub active: bool,
     #[serde(default)]
@@ -357,6 +363,8 @@ impl RequestExt for CreateRequest {
             multi_env: self.multi_env,
             paths: self.paths.clone(),
             settings: self.settings.clone(),
+            webhook_handshakes: self.webhook_handshakes.clone(),
+            redaction: self.redaction.clone(),
             hidden: false,
             record_metadata: RecordMetadata::default(),
         };
@@ -378,6 +386,10 @@ impl RequestExt for CreateRequest {
         record.test_connection = self.test_connection;
         record.platform.clone_from(&self.platform);
         record.multi_env = self.multi_env;
+        record
+            .webhook_handshakes
+            .clone_from(&self.webhook_handshakes);
+        record.redaction.clone_from(&self.redaction);
         record.record_metadata.active = self.active;
         record
     }
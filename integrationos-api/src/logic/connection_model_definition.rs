@@ -284,6 +284,8 @@ pub struct CreateRequest {
     pub base_url: String,
     pub path: String,
     pub auth_method: AuthMethod,
+    #[serde(default)]
+    pub mtls: bool,
     pub action_name: CrudAction,
     #[serde(with = "http_serde_ext_ios::method", rename = "action")]
     pub http_method: http::Method,
@@ -343,6 +345,7 @@ impl RequestExt for CreateRequest {
                 path: self.path.clone(),
                 content: Default::default(),
                 auth_method: self.auth_method.clone(),
+                mtls: self.mtls,
                 headers: self.headers.clone(),
                 query_params: self.query_params.clone(),
                 schemas: self.schemas.clone(),
@@ -391,6 +394,7 @@ impl RequestExt for CreateRequest {
             path: self.path.clone(),
             content: Default::default(),
             auth_method: self.auth_method.clone(),
+            mtls: self.mtls,
             headers: self.headers.clone(),
             query_params: self.query_params.clone(),
             schemas: self.schemas.clone(),
@@ -45,6 +45,8 @@ pub struct CreateRequest {
     pub scopes: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub separator: Option<String>,
+    #[serde(default)]
+    pub pkce: bool,
     pub init: RequestParams,
     pub refresh: RequestParams,
     pub is_full_template_enabled: bool,
@@ -126,6 +128,7 @@ impl RequestExt for CreateRequest {
                 ios_redirect_uri: self.ios_redirect_uri.clone(),
                 scopes: self.scopes.clone(),
                 separator: self.separator.clone(),
+                pkce: self.pkce,
             },
             record_metadata: Default::default(),
             hooks: Default::default(),
@@ -187,6 +190,7 @@ impl RequestExt for CreateRequest {
             ios_redirect_uri: self.ios_redirect_uri.clone(),
             scopes: self.scopes.clone(),
             separator: self.separator.clone(),
+            pkce: self.pkce,
         };
         record.record_metadata.updated_at = Utc::now().timestamp_millis();
         record.record_metadata.updated = true;
@@ -1,19 +1,23 @@
 use super::{delete, read, PublicExt, RequestExt};
 use crate::{
     domain::config::ConnectionsConfig,
+    helper::shape_mongo_filter,
     router::ServerResponse,
     server::{AppState, AppStores},
 };
 use anyhow::Result;
 use axum::{
-    extract::State,
+    extract::{Path, State},
     routing::{delete as axum_delete, get, post},
     Extension, Json, Router,
 };
+use chrono::{Duration, Utc};
 use fake::Dummy;
+use http::HeaderValue;
 use integrationos_domain::{
     access_key_data::AccessKeyData,
     access_key_prefix::AccessKeyPrefix,
+    access_scope::AccessScope,
     algebra::MongoStore,
     connection_definition::{ConnectionDefinitionType, Paths},
     environment::Environment,
@@ -22,10 +26,12 @@ use integrationos_domain::{
     id::{prefix::IdPrefix, Id},
     ownership::Ownership,
     record_metadata::RecordMetadata,
+    webhook_handshake::WebhookHandshake,
     AccessKey, ApplicationError, IntegrationOSError, InternalError,
 };
 use mongodb::bson::doc;
 use rand::Rng;
+use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::{error, warn};
@@ -42,6 +48,8 @@ pub fn get_router() -> Router<Arc<AppState>> {
             "/:id",
             axum_delete(delete::<CreateEventAccessRequest, EventAccess>),
         )
+        .route("/:id/rotate", post(rotate_event_access))
+        .route("/:id/revoke", post(revoke_previous_access_key))
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Validate)]
@@ -53,6 +61,13 @@ pub struct CreateEventAccessRequest {
     pub namespace: Option<String>,
     pub connection_type: ConnectionDefinitionType,
     pub paths: Paths,
+    #[serde(default)]
+    pub webhook_handshakes: Vec<WebhookHandshake>,
+    #[serde(default)]
+    pub scope: Option<AccessScope>,
+    /// Encrypts the payloads of events received with the key at rest
+    #[serde(default)]
+    pub encrypt_events: bool,
 }
 
 impl RequestExt for CreateEventAccessRequest {
@@ -73,6 +88,10 @@ pub struct CreateEventAccessPayloadWithOwnership {
     pub connection_type: ConnectionDefinitionType,
     pub environment: Environment,
     pub paths: Paths,
+    #[serde(default)]
+    pub webhook_handshakes: Vec<WebhookHandshake>,
+    #[serde(default)]
+    pub scope: Option<AccessScope>,
     pub ownership: Ownership,
     pub throughput: Option<u64>,
 }
@@ -140,13 +159,38 @@ pub fn generate_event_access(
         ownership: payload.ownership,
         key,
         paths: payload.paths,
+        webhook_handshakes: payload.webhook_handshakes,
+        scope: payload.scope,
+        data_key_id: None,
         access_key: encoded_access_key.to_string(),
+        previous_access_keys: vec![],
+        last_used_at: None,
         environment: payload.environment,
         record_metadata: RecordMetadata::default(),
         throughput: payload.throughput.unwrap_or(config.event_access_throughput),
     })
 }
 
+/// Generates the data key the payloads of events received with the key are
+/// encrypted with at rest
+pub(crate) async fn add_data_key(
+    state: &AppState,
+    event_access: &mut EventAccess,
+) -> Result<(), IntegrationOSError> {
+    let data_key_id = state
+        .payload_encryption
+        .create_data_key(&event_access.ownership.id)
+        .await
+        .map_err(|e| {
+            error!("Error creating data key for event access: {e}");
+
+            e
+        })?;
+    event_access.data_key_id = Some(data_key_id);
+
+    Ok(())
+}
+
 pub async fn get_client_throughput(client_id: &str, state: &Arc<AppState>) -> Result<u64> {
     let client_record = match state
         .app_stores
@@ -225,6 +269,14 @@ pub async fn create_event_access(
         ));
     }
 
+    // A scoped key could otherwise hand out keys with more access than it has
+    if access.scope.is_some() {
+        return Err(ApplicationError::forbidden(
+            "Scoped keys cannot create event access keys",
+            None,
+        ));
+    }
+
     let throughput = get_client_throughput(&access.ownership.id, &state).await?;
 
     let event_access_payload = CreateEventAccessPayloadWithOwnership {
@@ -234,17 +286,23 @@ pub async fn create_event_access(
         connection_type: payload.connection_type.clone(),
         environment: access.environment,
         paths: payload.paths.clone(),
+        webhook_handshakes: payload.webhook_handshakes.clone(),
+        scope: payload.scope.clone(),
         ownership: access.ownership.clone(),
         throughput: Some(throughput),
     };
 
-    let event_access =
-        generate_event_access(state.config.clone(), event_access_payload).map_err(|e| {
+    let mut event_access = generate_event_access(state.config.clone(), event_access_payload)
+        .map_err(|e| {
             error!("Error generating event access for existing user: {:?}", e);
 
             InternalError::io_err("Could not generate event access", None)
         })?;
 
+    if payload.encrypt_events {
+        add_data_key(&state, &mut event_access).await?;
+    }
+
     state
         .app_stores
         .event_access
@@ -258,3 +316,244 @@ pub async fn create_event_access(
 
     Ok(Json(ServerResponse::new("event_access", event_access)))
 }
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotateEventAccessRequest {
+    /// How long the replaced key keeps working, `0` revokes it right away
+    pub grace_period_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokeAccessKeyRequest {
+    pub access_key: String,
+}
+
+/// Issues a new access key for an event access record. The replaced key keeps
+/// working for the grace period so clients can be moved over without downtime.
+pub async fn rotate_event_access(
+    Extension(access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    payload: Option<Json<RotateEventAccessRequest>>,
+) -> Result<Json<ServerResponse<EventAccess>>, IntegrationOSError> {
+    if access.scope.is_some() {
+        return Err(ApplicationError::forbidden(
+            "Scoped keys cannot rotate event access keys",
+            None,
+        ));
+    }
+
+    let mut event_access = get_event_access(&access, &id, &state).await?;
+
+    let password = state
+        .config
+        .event_access_password
+        .as_bytes()
+        .try_into()
+        .map_err(|_| InternalError::configuration_error("Invalid event access password", None))?;
+    let iv = rand::thread_rng().gen::<[u8; 16]>();
+    let access_key = AccessKey::parse_str(&event_access.access_key, password)?
+        .encode(password, &iv)?
+        .to_string();
+
+    let grace_period_seconds = payload
+        .and_then(|Json(payload)| payload.grace_period_seconds)
+        .unwrap_or(state.config.access_key_rotation_grace_period_secs);
+    let now = Utc::now();
+    let expires_at = Duration::try_seconds(grace_period_seconds as i64)
+        .and_then(|grace_period| now.checked_add_signed(grace_period))
+        .ok_or_else(|| ApplicationError::bad_request("Invalid grace period", None))?;
+
+    let previous_access_key = event_access.access_key.clone();
+    event_access.rotate(access_key, expires_at, now);
+    event_access
+        .record_metadata
+        .mark_updated(&access.ownership.id);
+
+    let Ok(document) = bson::to_document(&event_access) else {
+        error!("Could not serialize event access into document");
+
+        return Err(InternalError::serialize_error(
+            "Could not serialize event access into document",
+            None,
+        ));
+    };
+
+    state
+        .app_stores
+        .event_access
+        .update_one(
+            &id,
+            doc! {
+                "$set": document,
+                "$unset": { "lastUsedAt": "" },
+            },
+        )
+        .await
+        .map_err(|e| {
+            error!("Error rotating event access: {e}");
+            e
+        })?;
+
+    // Connections reference the key of the event access record they were created with
+    state
+        .app_stores
+        .connection
+        .update_many(
+            doc! {
+                "accessKey": &previous_access_key,
+                "ownership.buildableId": &access.ownership.id,
+            },
+            doc! {
+                "$set": { "accessKey": &event_access.access_key },
+            },
+        )
+        .await
+        .map_err(|e| {
+            error!("Error updating connections of rotated event access: {e}");
+            e
+        })?;
+
+    publish_access_keys(
+        &state,
+        &event_access,
+        Some(&event_access.access_key),
+        (expires_at <= now).then_some(previous_access_key.as_str()),
+    )
+    .await?;
+
+    Ok(Json(ServerResponse::new("event_access", event_access)))
+}
+
+/// Revokes a key replaced by a rotation before its grace period ends
+pub async fn revoke_previous_access_key(
+    Extension(access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RevokeAccessKeyRequest>,
+) -> Result<Json<ServerResponse<EventAccess>>, IntegrationOSError> {
+    if access.scope.is_some() {
+        return Err(ApplicationError::forbidden(
+            "Scoped keys cannot revoke event access keys",
+            None,
+        ));
+    }
+
+    let mut event_access = get_event_access(&access, &id, &state).await?;
+
+    let count = event_access.previous_access_keys.len();
+    event_access
+        .previous_access_keys
+        .retain(|previous| previous.access_key != payload.access_key);
+    if event_access.previous_access_keys.len() == count {
+        return Err(ApplicationError::not_found(
+            "Access key is not a previous key of this event access, rotate it instead",
+            None,
+        ));
+    }
+
+    let Ok(previous_access_keys) = bson::to_bson(&event_access.previous_access_keys) else {
+        error!("Could not serialize previous access keys");
+
+        return Err(InternalError::serialize_error(
+            "Could not serialize previous access keys",
+            None,
+        ));
+    };
+
+    state
+        .app_stores
+        .event_access
+        .update_one(
+            &id,
+            doc! {
+                "$set": { "previousAccessKeys": previous_access_keys },
+            },
+        )
+        .await
+        .map_err(|e| {
+            error!("Error revoking access key: {e}");
+            e
+        })?;
+
+    publish_access_keys(&state, &event_access, None, Some(&payload.access_key)).await?;
+
+    if let Ok(header_value) = HeaderValue::from_str(&payload.access_key) {
+        let _ = state.event_access_cache.remove(&header_value).await;
+    }
+
+    Ok(Json(ServerResponse::new("event_access", event_access)))
+}
+
+async fn get_event_access(
+    access: &Arc<EventAccess>,
+    id: &str,
+    state: &Arc<AppState>,
+) -> Result<EventAccess, IntegrationOSError> {
+    let mut query = shape_mongo_filter(None, Some(access.clone()), None);
+    query.filter.insert("_id", id);
+
+    state
+        .app_stores
+        .event_access
+        .get_one(query.filter)
+        .await?
+        .ok_or_else(|| {
+            ApplicationError::not_found(&format!("Event access with id {id} not found"), None)
+        })
+}
+
+/// Stores the keys the record still accepts under its group so the gateway,
+/// which only decrypts keys, rejects the others. There is one entry per rotated
+/// record, however often it is rotated. Keys issued by a rotation and keys revoked
+/// right away are published so every instance of the API and gateway picks them up
+/// without waiting for the next refresh.
+async fn publish_access_keys(
+    state: &Arc<AppState>,
+    event_access: &EventAccess,
+    issued: Option<&str>,
+    revoked: Option<&str>,
+) -> Result<(), IntegrationOSError> {
+    let cache_config = &state.config.cache_config;
+    let mut redis = state.redis.clone();
+
+    let accepted = serde_json::to_string(&event_access.accepted_access_keys()).map_err(|e| {
+        error!("Could not serialize accepted access keys: {e}");
+        InternalError::serialize_error("Could not serialize accepted access keys", None)
+    })?;
+    redis
+        .hset::<_, _, _, ()>(
+            &cache_config.revoked_access_keys_key,
+            &event_access.group,
+            accepted,
+        )
+        .await
+        .map_err(|e| {
+            error!("Could not store accepted access keys: {e}");
+            InternalError::io_err("Could not store accepted access keys", None)
+        })?;
+
+    if let Some(access_key) = issued {
+        redis
+            .publish::<_, _, ()>(&cache_config.rotated_access_keys_key, access_key)
+            .await
+            .map_err(|e| {
+                error!("Could not publish rotated access key: {e}");
+                InternalError::io_err("Could not publish rotated access key", None)
+            })?;
+    }
+
+    if let Some(access_key) = revoked {
+        redis
+            .publish::<_, _, ()>(&cache_config.revoked_access_keys_key, access_key)
+            .await
+            .map_err(|e| {
+                error!("Could not publish revoked access key: {e}");
+                InternalError::io_err("Could not revoke access key", None)
+            })?;
+    }
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use crate::{helper::ServiceName, server::AppState};
+use crate::{helper::ServiceName, logic::connection::publish_lifecycle, server::AppState};
 use axum::{
     extract::{Path, State},
     routing::post,
@@ -7,8 +7,9 @@ use axum::{
 use bson::doc;
 use integrationos_domain::{
     database_secret::DatabaseConnectionSecret, emitted_events::ConnectionLostReason,
-    ApplicationError, Connection, Id, IntegrationOSError,
+    lifecycle::ConnectionLifecycle, ApplicationError, Connection, Id, IntegrationOSError,
 };
+use serde_json::json;
 use std::sync::Arc;
 
 pub fn get_router() -> Router<Arc<AppState>> {
@@ -80,6 +81,9 @@ async fn database_connection_lost_callback(
                     .connection
                     .update_one(id.as_str(), doc! { "$set": updated })
                     .await?;
+
+                let data = json!({ "reason": reason.reason });
+                publish_lifecycle(&state, &conn, ConnectionLifecycle::Lost, data).await;
             }
 
             Ok(Json(conn))
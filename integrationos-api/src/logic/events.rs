@@ -1,13 +1,32 @@
-use super::{read_without_count, PublicExt, RequestExt};
-use crate::server::{AppState, AppStores};
-use axum::{routing::get, Router};
-use bson::doc;
-use integrationos_domain::{algebra::MongoStore, Event};
+use super::{PublicExt, ReadResponse, RequestExt};
+use crate::{
+    helper::shape_mongo_filter,
+    middleware::scope::authorize_reveal,
+    router::ServerResponse,
+    server::{AppState, AppStores},
+};
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Extension, Json, Router,
+};
+use bson::{doc, Document};
+use http::HeaderMap;
+use integrationos_domain::{
+    algebra::MongoStore, event_access::EventAccess, ApplicationError, Event, IntegrationOSError,
+};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use serde_json::Value;
+use std::{collections::BTreeMap, sync::Arc};
+use tracing::error;
+
+const OCCURRED_AFTER: &str = "occurredAfter";
+const OCCURRED_BEFORE: &str = "occurredBefore";
 
 pub fn get_router() -> Router<Arc<AppState>> {
-    Router::new().route("/", get(read_without_count::<CreateEventRequest, Event>))
+    Router::new()
+        .route("/", get(read_events))
+        .route("/:id/reveal", get(reveal_event))
 }
 
 #[derive(Serialize, Deserialize)]
@@ -25,3 +44,140 @@ impl RequestExt for CreateEventRequest {
         stores.event
     }
 }
+
+/// Lists events, optionally filtered by the time the provider says they occurred.
+///
+/// `occurredAfter` and `occurredBefore` are unix timestamps in milliseconds and
+/// are inclusive. Events without a provider timestamp never match a time filter.
+async fn read_events(
+    headers: HeaderMap,
+    access: Option<Extension<Arc<EventAccess>>>,
+    query: Option<Query<BTreeMap<String, String>>>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServerResponse<ReadResponse<Value>>>, IntegrationOSError> {
+    let mut params = query.map(|Query(q)| q).unwrap_or_default();
+    let occurred_at = occurred_at_filter(
+        params.remove(OCCURRED_AFTER),
+        params.remove(OCCURRED_BEFORE),
+    )?;
+
+    let mut query = shape_mongo_filter(
+        Some(Query(params)),
+        access.map(|Extension(e)| e),
+        Some(headers),
+    );
+    if let Some(occurred_at) = occurred_at {
+        query.filter.insert("occurredAt", occurred_at);
+    }
+
+    let rows = state
+        .app_stores
+        .event
+        .get_many(
+            Some(query.filter),
+            None,
+            None,
+            Some(query.limit),
+            Some(query.skip),
+        )
+        .await
+        .map_err(|e| {
+            error!("Error reading from store: {e}");
+            e
+        })?;
+
+    let mut events = Vec::with_capacity(rows.len());
+    for mut event in rows {
+        state
+            .payload_encryption
+            .decrypt_event(&mut event)
+            .await
+            .map_err(|e| {
+                error!("Could not decrypt event {}: {e}", event.id);
+                e
+            })?;
+        events.push(event);
+    }
+
+    Ok(Json(ServerResponse::new(
+        "read",
+        ReadResponse {
+            rows: events.into_iter().map(CreateEventRequest::public).collect(),
+            skip: query.skip,
+            limit: query.limit,
+            total: 0,
+        },
+    )))
+}
+
+/// Reads an event with the originals of its tokenized values put back. Only keys
+/// whose scope allows revealing redacted values can call it.
+async fn reveal_event(
+    Extension(access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServerResponse<Value>>, IntegrationOSError> {
+    authorize_reveal(&access)?;
+
+    let mut query = shape_mongo_filter(None, Some(access.clone()), None);
+    query.filter.insert("_id", &id);
+
+    let mut event = state
+        .app_stores
+        .event
+        .get_one(query.filter)
+        .await?
+        .ok_or_else(|| ApplicationError::not_found(&format!("Event {id} not found"), None))?;
+
+    state
+        .payload_encryption
+        .decrypt_event(&mut event)
+        .await
+        .map_err(|e| {
+            error!("Could not decrypt event {}: {e}", event.id);
+            e
+        })?;
+    if let Some(sealed) = &event.redacted_values {
+        let redactions = state
+            .redaction
+            .reveal(sealed, &event.ownership.id)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Could not reveal redacted values of event {}: {e}",
+                    event.id
+                );
+                e
+            })?;
+        event.reveal(&redactions);
+    }
+
+    Ok(Json(ServerResponse::new(
+        "read",
+        CreateEventRequest::public(event),
+    )))
+}
+
+fn occurred_at_filter(
+    after: Option<String>,
+    before: Option<String>,
+) -> Result<Option<Document>, IntegrationOSError> {
+    let parse = |name: &str, value: String| {
+        value.parse::<i64>().map_err(|_| {
+            ApplicationError::bad_request(
+                &format!("{name} must be a unix timestamp in milliseconds"),
+                None,
+            )
+        })
+    };
+
+    let mut range = doc! {};
+    if let Some(after) = after {
+        range.insert("$gte", parse(OCCURRED_AFTER, after)?);
+    }
+    if let Some(before) = before {
+        range.insert("$lte", parse(OCCURRED_BEFORE, before)?);
+    }
+
+    Ok((!range.is_empty()).then_some(range))
+}
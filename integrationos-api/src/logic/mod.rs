@@ -1,5 +1,6 @@
 use crate::{
-    helper::shape_mongo_filter,
+    helper::{shape_mongo_filter, MongoQuery},
+    middleware::scope::authorize_connection_key,
     router::ServerResponse,
     server::{AppState, AppStores},
 };
@@ -7,7 +8,7 @@ use axum::{
     extract::{Path, Query, State},
     Extension, Json,
 };
-use bson::doc;
+use bson::{doc, Document};
 use http::{HeaderMap, HeaderValue};
 use integrationos_cache::local::connection_cache::ConnectionCacheArcStrHeaderKey;
 use integrationos_domain::{
@@ -21,6 +22,8 @@ use std::{collections::BTreeMap, fmt::Debug, future::Future, sync::Arc};
 use tokio::try_join;
 use tracing::error;
 
+pub mod audit_logs;
+pub mod authkit;
 pub mod common_enum;
 pub mod common_model;
 pub mod connection;
@@ -38,12 +41,14 @@ pub mod passthrough;
 pub mod pipeline;
 pub mod platform;
 pub mod platform_page;
+pub mod scheduled_events;
 pub mod schema_generator;
 pub mod secrets;
 pub mod transactions;
 pub mod unified;
 pub mod utils;
 pub mod vault_connection;
+pub mod webhook;
 
 const INTEGRATION_OS_PASSTHROUGH_HEADER: &str = "x-pica-passthrough";
 
@@ -71,6 +76,28 @@ pub trait RequestExt: Sized {
     }
 
     fn get_store(stores: AppStores) -> MongoStore<Self::Output>;
+
+    /// Further limits the records the event access can reach beyond its ownership,
+    /// e.g. to the connections allowed by the scope of the key
+    fn scope_filter(_: &EventAccess) -> Option<Document> {
+        None
+    }
+}
+
+/// Builds the filter of a request made with the event access, including its scope
+fn scoped_mongo_filter<T: RequestExt>(
+    query: Option<Query<BTreeMap<String, String>>>,
+    access: Option<Extension<Arc<EventAccess>>>,
+    headers: Option<HeaderMap>,
+) -> MongoQuery {
+    let access = access.map(|Extension(access)| access);
+    let scope_filter = access.as_deref().and_then(T::scope_filter);
+
+    let mut query = shape_mongo_filter(query, access, headers);
+    if let Some(scope_filter) = scope_filter {
+        query.filter.extend(scope_filter);
+    }
+    query
 }
 
 pub trait HookExt<Input>
@@ -202,14 +229,7 @@ where
     T: RequestExt<Output = U> + HookExt<U> + 'static,
     U: Serialize + DeserializeOwned + Unpin + Sync + Send + 'static,
 {
-    let mut query = shape_mongo_filter(
-        None,
-        access.map(|e| {
-            let Extension(e) = e;
-            e
-        }),
-        None,
-    );
+    let mut query = scoped_mongo_filter::<T>(None, access, None);
     query.filter.insert("_id", id.clone());
 
     let store = T::get_store(state.app_stores.clone());
@@ -269,14 +289,7 @@ where
 {
     let store = T::get_store(state.app_stores.clone());
 
-    let mut query = shape_mongo_filter(
-        None,
-        event_access.map(|e| {
-            let Extension(e) = e;
-            e
-        }),
-        None,
-    );
+    let mut query = scoped_mongo_filter::<T>(None, event_access, None);
     query.filter.insert("_id", id.clone());
 
     let Some(res) = (match store.get_one(query.filter).await {
@@ -337,6 +350,7 @@ async fn get_connection(
             },
         )
         .await?;
+    authorize_connection_key(access, &connection.key)?;
 
     // If Oauth is enabled, fetching the latest secret (due to refresh, cache can't be used)
     if let Some(OAuth::Enabled { .. }) = connection.oauth {
@@ -384,14 +398,7 @@ where
     T: RequestExt<Output = U> + PublicExt<U> + 'static,
     U: Serialize + DeserializeOwned + Unpin + Sync + Send + Debug + 'static,
 {
-    let query = shape_mongo_filter(
-        query,
-        access.map(|e| {
-            let Extension(e) = e;
-            e
-        }),
-        Some(headers),
-    );
+    let query = scoped_mongo_filter::<T>(query, access, Some(headers));
 
     let store = T::get_store(state.app_stores.clone());
 
@@ -1,6 +1,12 @@
 use super::event_access::CreateEventAccessPayloadWithOwnership;
 use crate::{
-    logic::event_access::{get_client_throughput, DEFAULT_NAMESPACE},
+    logic::{
+        authkit::authorize_connection,
+        connection::{
+            get_owned_connection, publish_lifecycle, save_reauthorized_connection, test_connection,
+        },
+        event_access::{add_data_key, get_client_throughput, DEFAULT_NAMESPACE},
+    },
     server::AppState,
 };
 use axum::{
@@ -10,40 +16,49 @@ use axum::{
 };
 use chrono::{Duration, Utc};
 use fake::Dummy;
-use http::{HeaderMap, HeaderName, HeaderValue};
 use integrationos_domain::{
     algebra::{MongoStore, TemplateExt},
-    api_model_config::ContentType,
     connection_definition::ConnectionDefinition,
     connection_oauth_definition::{
-        Computation, ConnectionOAuthDefinition, OAuthResponse, PlatformSecret, Settings,
+        ConnectionOAuthDefinition, OAuthResponse, Pkce, PlatformSecret, Settings,
     },
+    environment::Environment,
     event_access::EventAccess,
     id::{prefix::IdPrefix, Id},
+    lifecycle::ConnectionLifecycle,
     oauth_secret::OAuthSecret,
     ownership::Ownership,
+    scripting::ScriptService,
+    token::AuthKitToken,
     ApplicationError, Connection, ConnectionIdentityType, ErrorMeta, IntegrationOSError,
     InternalError, OAuth, Throughput,
 };
+use integrationos_unified::oauth::{oauth_request, EXPIRY_MARGIN_SECS};
 use mongodb::bson::doc;
-use reqwest::Request;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use serde_json::{to_string_pretty, Value};
-use std::{
-    collections::{BTreeMap, HashMap},
-    str::FromStr,
-    sync::Arc,
+use redis::AsyncCommands;
+use reqwest::{
+    header::{HeaderValue, CONTENT_TYPE},
+    Request,
 };
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
 use tracing::{debug, error};
 use uuid::Uuid;
 
 pub fn get_router() -> Router<Arc<AppState>> {
-    Router::new().route("/:platform", post(oauth_handler))
+    Router::new()
+        .route("/:platform", post(oauth_handler))
+        .route("/:platform/authorize", post(authorize_handler))
+        .route("/:platform/callback", post(callback_handler))
 }
 
+const OAUTH_STATE_PREFIX: &str = "oauth-state";
+const CODE_VERIFIER: &str = "code_verifier";
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Dummy)]
 #[serde(rename_all = "camelCase")]
-struct OAuthRequest {
+pub(crate) struct OAuthRequest {
     #[serde(rename = "__isEngineeringAccount__", default)]
     is_engineering_account: bool,
     connection_definition_id: Id,
@@ -54,6 +69,12 @@ struct OAuthRequest {
     group: Option<String>,
     identity: Option<String>,
     identity_type: Option<ConnectionIdentityType>,
+    /// Connection whose tokens are replaced, instead of creating a new one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    connection_id: Option<Id>,
+    /// Encrypts the payloads of events received for the connection at rest
+    #[serde(default)]
+    encrypt_events: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Dummy)]
@@ -70,12 +91,68 @@ impl OAuthPayload {
     }
 }
 
-async fn oauth_handler(
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Dummy)]
+#[serde(rename_all = "camelCase")]
+struct AuthorizeRequest {
+    #[serde(rename = "__isEngineeringAccount__", default)]
+    is_engineering_account: bool,
+    connection_definition_id: Id,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<Value>,
+    name: Option<String>,
+    group: Option<String>,
+    identity: Option<String>,
+    identity_type: Option<ConnectionIdentityType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    connection_id: Option<Id>,
+    #[serde(default)]
+    encrypt_events: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthorizeResponse {
+    url: String,
+    state: String,
+    expires_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Dummy)]
+#[serde(rename_all = "camelCase")]
+struct CallbackRequest {
+    state: String,
+    code: String,
+}
+
+/// What an authorization started with, kept until the platform redirects back
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OAuthState {
+    platform: String,
+    ownership_id: String,
+    environment: Environment,
+    client_id: String,
+    redirect_uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_verifier: Option<String>,
+    request: AuthorizeRequest,
+}
+
+fn oauth_state_key(state: &str) -> String {
+    format!("{OAUTH_STATE_PREFIX}::{state}")
+}
+
+async fn authorize_handler(
     state: State<Arc<AppState>>,
     Extension(user_event_access): Extension<Arc<EventAccess>>,
     Path(platform): Path<String>,
-    Json(payload): Json<OAuthRequest>,
-) -> Result<Json<Connection>, IntegrationOSError> {
+    Json(payload): Json<AuthorizeRequest>,
+) -> Result<Json<AuthorizeResponse>, IntegrationOSError> {
+    if let Some(connection_id) = &payload.connection_id {
+        // Fails before sending the user to the platform rather than after
+        get_reauthorized_connection(&state, &user_event_access, &platform, connection_id).await?;
+    }
+
     let conn_oauth_definition = get_conn_oauth_definition(&state, &platform).await?;
     let setting = get_user_settings(
         &state,
@@ -89,35 +166,198 @@ async fn oauth_handler(
     })?;
 
     let environment = user_event_access.environment;
-
-    let secret = get_secret::<PlatformSecret>(
+    let secret = get_platform_secret(
         &state,
-        setting
-            .platform_secret(&payload.connection_definition_id, environment)
-            .ok_or_else(|| {
-                error!("Settings does not have a secret service id for the connection platform");
-                InternalError::invalid_argument(
-                    "Provided connection definition does not have a secret entry",
-                    None,
-                )
-            })?,
-        if payload.is_engineering_account {
-            tracing::info!("Using engineering account id for secret");
-            state.config.engineering_account_id.clone()
-        } else {
-            tracing::info!("Using user event access id for secret");
-            user_event_access.clone().ownership.id.to_string()
-        },
+        &setting,
+        &user_event_access,
+        &payload.connection_definition_id,
+        payload.is_engineering_account,
+    )
+    .await?;
+
+    let scopes = setting
+        .connected_platform(&payload.connection_definition_id, environment)
+        .and_then(|p| p.scopes.clone());
+    let pkce = conn_oauth_definition.frontend.pkce.then(Pkce::generate);
+    let oauth_state = Uuid::new_v4().simple().to_string();
+
+    let url = conn_oauth_definition.frontend.authorization_url(
+        environment,
+        &secret.client_id,
+        scopes.as_deref(),
+        &oauth_state,
+        pkce.as_ref().map(|p| p.challenge.as_str()),
+    )?;
+
+    let stored = serde_json::to_string(&OAuthState {
+        platform,
+        ownership_id: user_event_access.ownership.id.to_string(),
+        environment,
+        client_id: secret.client_id,
+        redirect_uri: conn_oauth_definition.frontend.ios_redirect_uri,
+        code_verifier: pkce.map(|p| p.verifier),
+        request: payload,
+    })
+    .map_err(|e| InternalError::serialize_error(&e.to_string(), None))?;
+
+    let ttl = state.config.oauth_state_ttl_secs;
+    state
+        .redis
+        .clone()
+        .set_ex::<_, _, ()>(oauth_state_key(&oauth_state), stored, ttl)
+        .await
+        .map_err(|e| {
+            error!("Could not store oauth state: {e}");
+            InternalError::io_err("Could not start oauth authorization", None)
+        })?;
+
+    Ok(Json(AuthorizeResponse {
+        url: url.to_string(),
+        state: oauth_state,
+        expires_at: (Utc::now() + Duration::seconds(ttl as i64)).timestamp_millis(),
+    }))
+}
+
+async fn callback_handler(
+    state: State<Arc<AppState>>,
+    Extension(user_event_access): Extension<Arc<EventAccess>>,
+    Path(platform): Path<String>,
+    Json(payload): Json<CallbackRequest>,
+) -> Result<Json<Connection>, IntegrationOSError> {
+    // States are single use, so a replayed callback never exchanges a code twice
+    let stored: OAuthState = state
+        .redis
+        .clone()
+        .get_del::<_, Option<String>>(oauth_state_key(&payload.state))
+        .await
+        .map_err(|e| {
+            error!("Could not read oauth state: {e}");
+            InternalError::io_err("Could not complete oauth authorization", None)
+        })?
+        .ok_or_else(|| ApplicationError::bad_request("OAuth state is invalid or expired", None))
+        .and_then(|stored| {
+            serde_json::from_str(&stored)
+                .map_err(|e| InternalError::deserialize_error(&e.to_string(), None))
+        })?;
+
+    if stored.platform != platform
+        || stored.ownership_id != user_event_access.ownership.id.to_string()
+        || stored.environment != user_event_access.environment
+    {
+        return Err(ApplicationError::bad_request(
+            "OAuth state does not belong to this authorization",
+            None,
+        ));
+    }
+
+    let mut metadata = match stored.request.payload {
+        Some(Value::Object(metadata)) => metadata,
+        _ => Map::new(),
+    };
+    metadata.insert("code".to_string(), Value::String(payload.code));
+    metadata.insert(
+        "redirectUri".to_string(),
+        Value::String(stored.redirect_uri),
+    );
+    if let Some(code_verifier) = stored.code_verifier {
+        metadata.insert("codeVerifier".to_string(), Value::String(code_verifier));
+    }
+
+    let request = OAuthRequest {
+        is_engineering_account: stored.request.is_engineering_account,
+        connection_definition_id: stored.request.connection_definition_id,
+        client_id: stored.client_id,
+        payload: Some(Value::Object(metadata)),
+        name: stored.request.name,
+        group: stored.request.group,
+        identity: stored.request.identity,
+        identity_type: stored.request.identity_type,
+        connection_id: stored.request.connection_id,
+        encrypt_events: stored.request.encrypt_events,
+    };
+
+    complete_oauth(&state, &user_event_access, platform, request)
+        .await
+        .map(Json)
+}
+
+pub(crate) async fn oauth_handler(
+    state: State<Arc<AppState>>,
+    Extension(user_event_access): Extension<Arc<EventAccess>>,
+    authkit: Option<Extension<Arc<AuthKitToken>>>,
+    Path(platform): Path<String>,
+    Json(mut payload): Json<OAuthRequest>,
+) -> Result<Json<Connection>, IntegrationOSError> {
+    if let Some(Extension(token)) = authkit {
+        if payload.connection_id.is_some() {
+            return Err(ApplicationError::forbidden(
+                "AuthKit tokens cannot reauthorize connections",
+                None,
+            ));
+        }
+        authorize_connection(
+            &token,
+            &payload.connection_definition_id,
+            &mut payload.identity,
+            &mut payload.identity_type,
+            &mut payload.group,
+        )?;
+    }
+
+    complete_oauth(&state, &user_event_access, platform, payload)
+        .await
+        .map(Json)
+}
+
+async fn complete_oauth(
+    state: &State<Arc<AppState>>,
+    user_event_access: &Arc<EventAccess>,
+    platform: String,
+    payload: OAuthRequest,
+) -> Result<Connection, IntegrationOSError> {
+    match payload.connection_id {
+        Some(connection_id) => {
+            reauthorize_oauth_connection(state, user_event_access, platform, connection_id, payload)
+                .await
+        }
+        None => create_oauth_connection(state, user_event_access, platform, payload).await,
+    }
+}
+
+/// Exchanges the authorization code of the request for the tokens of the
+/// connection
+async fn exchange_code(
+    state: &State<Arc<AppState>>,
+    user_event_access: &EventAccess,
+    platform: &str,
+    payload: &OAuthRequest,
+) -> Result<(ConnectionOAuthDefinition, OAuthSecret), IntegrationOSError> {
+    let conn_oauth_definition = get_conn_oauth_definition(state, platform).await?;
+    let setting = get_user_settings(
+        state,
+        &user_event_access.ownership,
+        payload.is_engineering_account,
     )
     .await
     .map_err(|e| {
-        error!("Failed to get platform secret for connection: {:?}", e);
+        error!("Failed to get user settings: {:?}", e);
         e
     })?;
 
+    let environment = user_event_access.environment;
+
+    let secret = get_platform_secret(
+        state,
+        &setting,
+        user_event_access,
+        &payload.connection_definition_id,
+        payload.is_engineering_account,
+    )
+    .await?;
+
     let mut oauth_payload = OAuthPayload {
         metadata: payload.payload.clone().unwrap_or(Value::Null),
-        client_id: payload.client_id,
+        client_id: payload.client_id.clone(),
         client_secret: secret.client_secret,
     };
 
@@ -140,11 +380,17 @@ async fn oauth_handler(
         conn_oauth_definition
     };
 
-    let request =
-        request(&conn_oauth_definition, &oauth_payload, &state.template).map_err(|e| {
-            error!("Failed to create oauth request: {}", e);
-            e
-        })?;
+    let request = request(
+        &conn_oauth_definition,
+        &oauth_payload,
+        &state.template,
+        &state.scripts,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to create oauth request: {}", e);
+        e
+    })?;
 
     debug!("Request: {:?}", request);
     let response = state
@@ -168,7 +414,8 @@ async fn oauth_handler(
         .compute
         .init
         .response
-        .compute(&response)
+        .compute(&state.scripts, &response)
+        .await
         .map_err(|e| {
             error!("Failed to decode oauth response: {:?}", e);
             InternalError::script_error(e.message().as_ref(), None)
@@ -179,9 +426,22 @@ async fn oauth_handler(
         oauth_payload.client_id,
         oauth_payload.client_secret,
         response,
-        payload.payload,
+        payload.payload.clone(),
     );
 
+    Ok((conn_oauth_definition, oauth_secret))
+}
+
+/// Exchanges the authorization code of the request and creates the connection
+async fn create_oauth_connection(
+    state: &State<Arc<AppState>>,
+    user_event_access: &Arc<EventAccess>,
+    platform: String,
+    payload: OAuthRequest,
+) -> Result<Connection, IntegrationOSError> {
+    let (conn_oauth_definition, oauth_secret) =
+        exchange_code(state, user_event_access, &platform, &payload).await?;
+
     let secret = state
         .secrets_client
         .create(
@@ -194,7 +454,7 @@ async fn oauth_handler(
             InternalError::encryption_error(e.message().as_ref(), None)
         })?;
 
-    let conn_definition = get_conn_definition(&state, &payload.connection_definition_id).await?;
+    let conn_definition = get_conn_definition(state, &payload.connection_definition_id).await?;
 
     let uuid = Uuid::new_v4().to_string().replace('-', "");
     let group = payload.group.unwrap_or_else(|| uuid.clone());
@@ -211,15 +471,17 @@ async fn oauth_handler(
         user_event_access.environment, conn_definition.platform, DEFAULT_NAMESPACE, key_suffix
     );
 
-    let throughput = get_client_throughput(&user_event_access.ownership.id, &state).await?;
+    let throughput = get_client_throughput(&user_event_access.ownership.id, state).await?;
 
-    let event_access = CreateEventAccessPayloadWithOwnership {
+    let mut event_access = CreateEventAccessPayloadWithOwnership {
         name: format!("{} {}", user_event_access.environment, conn_definition.name),
         platform: conn_definition.platform.clone(),
         namespace: None,
         connection_type: conn_definition.r#type.clone(),
         environment: user_event_access.environment,
         paths: conn_definition.paths.clone(),
+        webhook_handshakes: conn_definition.webhook_handshakes.clone(),
+        scope: user_event_access.scope.clone(),
         ownership: user_event_access.ownership.clone(),
         throughput: Some(throughput),
     }
@@ -228,6 +490,9 @@ async fn oauth_handler(
         error!("Error creating event access for oauth connection: {:?}", e);
         ApplicationError::service_unavailable("Failed to create event access", None)
     })?;
+    if payload.encrypt_events {
+        add_data_key(state, &mut event_access).await?;
+    }
 
     state
         .app_stores
@@ -257,23 +522,16 @@ async fn oauth_handler(
         settings: conn_definition.settings,
         has_error: false,
         error: None,
+        health: None,
         throughput: Throughput {
             key,
             limit: throughput,
         },
         ownership: user_event_access.ownership.clone(),
-        oauth: Some(OAuth::Enabled {
-            connection_oauth_definition_id: conn_oauth_definition.id,
-            expires_in: Some(oauth_secret.expires_in),
-            expires_at: Some(
-                chrono::Utc::now()
-                    .checked_add_signed(Duration::seconds(oauth_secret.expires_in as i64))
-                    .unwrap_or_else(chrono::Utc::now)
-                    .checked_sub_signed(Duration::seconds(120))
-                    .unwrap_or_else(chrono::Utc::now)
-                    .timestamp(),
-            ),
-        }),
+        oauth: Some(oauth_enabled(
+            conn_oauth_definition.id,
+            oauth_secret.expires_in,
+        )),
         record_metadata: Default::default(),
     };
 
@@ -287,180 +545,189 @@ async fn oauth_handler(
             ApplicationError::service_unavailable("Failed to create connection", None)
         })?;
 
-    Ok(Json(connection))
+    publish_lifecycle(state, &connection, ConnectionLifecycle::Created, json!({})).await;
+
+    Ok(connection)
 }
 
-fn request(
-    oauth_definition: &ConnectionOAuthDefinition,
-    payload: &OAuthPayload,
-    template: &impl TemplateExt,
-) -> Result<Request, IntegrationOSError> {
-    let payload = serde_json::to_value(payload).map_err(|e| {
-        error!("Failed to serialize oauth payload: {}", e);
-        InternalError::serialize_error(&e.to_string(), None)
-    })?;
-    let computation = oauth_definition
-        .compute
-        .init
-        .computation
-        .clone()
-        .map(|computation| computation.compute::<Computation>(&payload))
-        .transpose()
+/// Exchanges the authorization code of the request and replaces the tokens of
+/// an existing connection, keeping its key and access key
+async fn reauthorize_oauth_connection(
+    state: &State<Arc<AppState>>,
+    user_event_access: &Arc<EventAccess>,
+    platform: String,
+    connection_id: Id,
+    payload: OAuthRequest,
+) -> Result<Connection, IntegrationOSError> {
+    let mut connection =
+        get_reauthorized_connection(state, user_event_access, &platform, &connection_id).await?;
+
+    if connection.connection_definition_id != payload.connection_definition_id {
+        return Err(ApplicationError::bad_request(
+            "Connection belongs to a different connection definition",
+            None,
+        ));
+    }
+
+    let (conn_oauth_definition, oauth_secret) =
+        exchange_code(state, user_event_access, &platform, &payload).await?;
+
+    let connection_config = state
+        .app_stores
+        .connection_config
+        .get_one(doc! {
+            "_id": connection.connection_definition_id.to_string(),
+            "deleted": false
+        })
+        .await?
+        .ok_or_else(|| ApplicationError::not_found("Connection definition not found", None))?;
+
+    // The tokens of the connection are only replaced once the platform accepts the new ones
+    test_connection(state, &connection_config, &oauth_secret.as_json())
+        .await
         .map_err(|e| {
-            error!("Failed to compute oauth payload: {}", e);
-            InternalError::script_error(e.message().as_ref(), None)
+            error!("Error executing model definition in oauth reauthorization for connection testing: {:?}", e);
+
+            ApplicationError::bad_request(&format!("Invalid connection credentials: {:?}", e), None)
         })?;
 
-    let headers = header(oauth_definition, computation.as_ref(), template)?;
-    let query = query(oauth_definition, computation.as_ref(), template)?;
-    let body = body(&payload, computation.as_ref(), template)?;
+    state
+        .secrets_client
+        .update(
+            &connection.secrets_service_id,
+            &oauth_secret.as_json(),
+            user_event_access.ownership.id.as_ref(),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to replace oauth secret: {}", e);
+            InternalError::encryption_error(e.message().as_ref(), None)
+        })?;
 
-    let request = reqwest::Client::new()
-        .post(oauth_definition.configuration.init.uri())
-        .headers(headers);
+    connection.oauth = Some(oauth_enabled(
+        conn_oauth_definition.id,
+        oauth_secret.expires_in,
+    ));
+    connection.clear_error();
+    connection
+        .record_metadata
+        .mark_updated(&user_event_access.ownership.id);
 
-    let request = match oauth_definition.configuration.init.content {
-        Some(ContentType::Json) => request.json(&body).query(&query),
-        Some(ContentType::Form) => request.form(&body).query(&query),
-        _ => request.query(&query),
-    };
+    save_reauthorized_connection(state, &connection).await?;
 
-    request.build().map_err(|e| {
-        error!("Failed to build static request: {}", e);
-        InternalError::unknown(&e.to_string(), None)
-    })
+    Ok(connection)
 }
 
-fn query(
-    oauth_definition: &ConnectionOAuthDefinition,
-    computation: Option<&Computation>,
-    template: &impl TemplateExt,
-) -> Result<Option<Value>, IntegrationOSError> {
-    let query_params = oauth_definition
-        .configuration
-        .init
-        .query_params
-        .as_ref()
-        .map(|query_params| {
-            let mut map = HashMap::new();
-            for (key, value) in query_params {
-                let key = key.to_string();
-                let value = value.as_str();
-
-                map.insert(key, value.to_string());
-            }
-            map
-        });
-
-    match query_params {
-        Some(query_params) => {
-            let payload = computation.and_then(|computation| computation.clone().query_params);
-
-            let query_params_str = to_string_pretty(&query_params).map_err(|e| {
-                error!("Failed to serialize query params: {}", e);
-                InternalError::serialize_error(&e.to_string(), None)
-            })?;
-
-            let query_params = template.render(&query_params_str, payload.as_ref())?;
-
-            let query_params: BTreeMap<String, String> = serde_json::from_str(&query_params)
-                .map_err(|e| {
-                    error!("Failed to deserialize query params: {}", e);
-                    InternalError::deserialize_error(&e.to_string(), None)
-                })?;
-
-            Ok(Some(serde_json::to_value(query_params).map_err(|e| {
-                error!("Failed to serialize query params: {}", e);
-                InternalError::serialize_error(&e.to_string(), None)
-            })?))
-        }
-        None => Ok(None),
+async fn get_reauthorized_connection(
+    state: &State<Arc<AppState>>,
+    user_event_access: &EventAccess,
+    platform: &str,
+    connection_id: &Id,
+) -> Result<Connection, IntegrationOSError> {
+    let connection =
+        get_owned_connection(state, &connection_id.to_string(), user_event_access).await?;
+
+    if connection.platform.as_ref() != platform {
+        return Err(ApplicationError::bad_request(
+            "Connection belongs to a different platform",
+            None,
+        ));
     }
+
+    Ok(connection)
 }
 
-fn body(
-    payload: &Value,
-    computation: Option<&Computation>,
-    template: &impl TemplateExt,
-) -> Result<Option<Value>, IntegrationOSError> {
-    let body = computation.and_then(|computation| computation.clone().body);
-
-    match body {
-        Some(body) => {
-            let body_str = to_string_pretty(&body).map_err(|e| {
-                error!("Failed to serialize body: {}", e);
-                InternalError::serialize_error(&e.to_string(), None)
-            })?;
-
-            let body = template.render(&body_str, Some(payload))?;
-
-            Ok(Some(serde_json::from_str(&body).map_err(|e| {
-                error!("Failed to deserialize body: {}", e);
-                InternalError::deserialize_error(&e.to_string(), None)
-            })?))
-        }
-        None => Ok(None),
+fn oauth_enabled(connection_oauth_definition_id: Id, expires_in: i32) -> OAuth {
+    OAuth::Enabled {
+        connection_oauth_definition_id,
+        expires_in: Some(expires_in),
+        expires_at: Some(
+            chrono::Utc::now()
+                .checked_add_signed(Duration::seconds(expires_in as i64))
+                .unwrap_or_else(chrono::Utc::now)
+                .checked_sub_signed(Duration::seconds(EXPIRY_MARGIN_SECS))
+                .unwrap_or_else(chrono::Utc::now)
+                .timestamp(),
+        ),
     }
 }
 
-fn header(
-    conn_oauth_definition: &ConnectionOAuthDefinition,
-    computation: Option<&Computation>,
+async fn request(
+    oauth_definition: &ConnectionOAuthDefinition,
+    payload: &OAuthPayload,
     template: &impl TemplateExt,
-) -> Result<HeaderMap, IntegrationOSError> {
-    let headers = conn_oauth_definition
-        .configuration
-        .init
-        .headers
-        .as_ref()
-        .and_then(|headers| {
-            let mut map = HashMap::new();
-            for (key, value) in headers {
-                let key = key.to_string();
-                let value = value.to_str().ok()?;
-
-                map.insert(key, value.to_string());
-            }
-            Some(map)
-        });
-
-    match headers {
-        Some(headers) => {
-            let payload = computation.and_then(|computation| computation.clone().headers);
-
-            let headers_str = to_string_pretty(&headers).map_err(|e| {
-                error!("Failed to serialize headers: {}", e);
-                InternalError::serialize_error(&e.to_string(), None)
-            })?;
-
-            let headers = template.render(&headers_str, payload.as_ref())?;
-
-            let headers: BTreeMap<String, String> =
-                serde_json::from_str(&headers).map_err(|e| {
-                    error!("Failed to deserialize headers: {}", e);
-                    InternalError::deserialize_error(&e.to_string(), None)
-                })?;
-
-            headers
-                .iter()
-                .try_fold(HeaderMap::new(), |mut header_map, (key, value)| {
-                    let key = HeaderName::from_str(key).map_err(|e| {
-                        error!("Failed to parse header name: {}", e);
-                        InternalError::invalid_argument(&e.to_string(), None)
-                    })?;
-
-                    let value = HeaderValue::from_str(value).map_err(|e| {
-                        error!("Failed to parse header value: {}", e);
-                        InternalError::invalid_argument(&e.to_string(), None)
-                    })?;
-
-                    header_map.insert(key, value);
-
-                    Ok(header_map)
-                })
-        }
-        None => Ok(HeaderMap::new()),
+    scripts: &ScriptService,
+) -> Result<Request, IntegrationOSError> {
+    let code_verifier = payload
+        .metadata
+        .get("codeVerifier")
+        .and_then(Value::as_str)
+        .map(str::to_owned);
+    let payload = serde_json::to_value(payload).map_err(|e| {
+        error!("Failed to serialize oauth payload: {}", e);
+        InternalError::serialize_error(&e.to_string(), None)
+    })?;
+
+    let mut request = oauth_request(
+        &oauth_definition.configuration.init,
+        &oauth_definition.compute.init,
+        &payload,
+        template,
+        scripts,
+    )
+    .await?;
+
+    if let Some(code_verifier) = code_verifier {
+        with_code_verifier(&mut request, &code_verifier)?;
     }
+
+    Ok(request)
+}
+
+/// Adds the PKCE verifier to the body of the token exchange, platforms reject
+/// the code without it. A verifier set by the oauth definition itself is kept.
+fn with_code_verifier(
+    request: &mut Request,
+    code_verifier: &str,
+) -> Result<(), IntegrationOSError> {
+    let is_json = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .unwrap_or_default();
+
+    let body = if is_json {
+        let mut body: Map<String, Value> = match body {
+            [] => Map::new(),
+            body => serde_json::from_slice(body)
+                .map_err(|e| InternalError::deserialize_error(&e.to_string(), None))?,
+        };
+        body.entry(CODE_VERIFIER)
+            .or_insert_with(|| Value::String(code_verifier.to_owned()));
+        serde_json::to_vec(&body)
+            .map_err(|e| InternalError::serialize_error(&e.to_string(), None))?
+    } else {
+        let mut form = form_urlencoded::parse(body)
+            .into_owned()
+            .collect::<Vec<(String, String)>>();
+        if !form.iter().any(|(key, _)| key == CODE_VERIFIER) {
+            form.push((CODE_VERIFIER.to_owned(), code_verifier.to_owned()));
+        }
+        request.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(form)
+            .finish()
+            .into_bytes()
+    };
+
+    *request.body_mut() = Some(body.into());
+    Ok(())
 }
 
 async fn get_conn_definition(
@@ -514,6 +781,39 @@ pub async fn get_user_settings(
     Ok(setting)
 }
 
+async fn get_platform_secret(
+    state: &State<Arc<AppState>>,
+    setting: &Settings,
+    user_event_access: &EventAccess,
+    connection_definition_id: &Id,
+    is_engineering_account: bool,
+) -> Result<PlatformSecret, IntegrationOSError> {
+    get_secret::<PlatformSecret>(
+        state,
+        setting
+            .platform_secret(connection_definition_id, user_event_access.environment)
+            .ok_or_else(|| {
+                error!("Settings does not have a secret service id for the connection platform");
+                InternalError::invalid_argument(
+                    "Provided connection definition does not have a secret entry",
+                    None,
+                )
+            })?,
+        if is_engineering_account {
+            tracing::info!("Using engineering account id for secret");
+            state.config.engineering_account_id.clone()
+        } else {
+            tracing::info!("Using user event access id for secret");
+            user_event_access.ownership.id.to_string()
+        },
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to get platform secret for connection: {:?}", e);
+        e
+    })
+}
+
 async fn get_secret<S: DeserializeOwned>(
     state: &State<Arc<AppState>>,
     id: String,
@@ -525,3 +825,39 @@ async fn get_secret<S: DeserializeOwned>(
 
     encoded_secret.decode::<S>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(request: &Request) -> String {
+        String::from_utf8_lossy(request.body().and_then(|body| body.as_bytes()).unwrap()).into()
+    }
+
+    #[test]
+    fn test_code_verifier_is_added_to_the_token_exchange() {
+        let client = reqwest::Client::new();
+
+        let mut form = client
+            .post("https://auth.example.com/token")
+            .form(&[("grant_type", "authorization_code"), ("code", "abc")])
+            .build()
+            .unwrap();
+        with_code_verifier(&mut form, "verifier").unwrap();
+        assert_eq!(
+            body(&form),
+            "grant_type=authorization_code&code=abc&code_verifier=verifier"
+        );
+
+        let mut json = client
+            .post("https://auth.example.com/token")
+            .json(&json!({ "code": "abc", "code_verifier": "own" }))
+            .build()
+            .unwrap();
+        with_code_verifier(&mut json, "verifier").unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&body(&json)).unwrap(),
+            json!({ "code": "abc", "code_verifier": "own" })
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use super::{read, PublicExt, RequestExt};
+use crate::{
+    helper::shape_mongo_filter,
+    router::ServerResponse,
+    server::{AppState, AppStores},
+};
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use bson::doc;
+use chrono::Utc;
+use integrationos_domain::{
+    algebra::MongoStore,
+    event_access::EventAccess,
+    event_state::EventState,
+    scheduled_event::{ScheduledEvent, ScheduledEventState},
+    ApplicationError, IntegrationOSError, InternalError,
+};
+use mongodb::options::ReturnDocument;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn get_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(read::<ScheduledEventCrud, ScheduledEvent>))
+        .route("/:id", delete(cancel_scheduled_event))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ScheduledEventCrud;
+
+impl PublicExt<ScheduledEvent> for ScheduledEventCrud {}
+impl RequestExt for ScheduledEventCrud {
+    type Output = ScheduledEvent;
+
+    fn get_store(stores: AppStores) -> MongoStore<Self::Output> {
+        stores.scheduled_events
+    }
+}
+
+async fn cancel_scheduled_event(
+    Extension(event_access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServerResponse<ScheduledEvent>>, IntegrationOSError> {
+    let mut query = shape_mongo_filter(None, Some(event_access), None);
+    query.filter.insert("_id", id.clone());
+    // Only events that have not been enqueued yet can be cancelled
+    query
+        .filter
+        .insert("state", ScheduledEventState::Scheduled.as_ref());
+
+    let Some(scheduled_event) = state
+        .app_stores
+        .scheduled_events
+        .collection
+        .find_one_and_update(
+            query.filter,
+            doc! {
+                "$set": {
+                    "state": ScheduledEventState::Cancelled.as_ref(),
+                    "updatedAt": Utc::now().timestamp_millis(),
+                }
+            },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+        .map_err(|e| {
+            error!("Could not cancel scheduled event {id}: {e}");
+            IntegrationOSError::from(e)
+        })?
+    else {
+        return Err(ApplicationError::not_found(
+            &format!("Scheduled event with id {id} not found or already delivered"),
+            None,
+        ));
+    };
+
+    // Delayed pipelines belong to an event that was already delivered
+    if scheduled_event.pipeline_key.is_none() {
+        let event_state = bson::to_bson(&EventState::Cancelled)
+            .map_err(|e| InternalError::serialize_error(&e.to_string(), None))?;
+        state
+            .app_stores
+            .event
+            .update_one(
+                &scheduled_event.event_key.to_string(),
+                doc! { "$set": { "state": event_state } },
+            )
+            .await?;
+    }
+
+    Ok(Json(ServerResponse::new("cancel", scheduled_event)))
+}
@@ -1,21 +1,28 @@
-use crate::server::AppState;
+use super::connection::evict_connection;
+use crate::{middleware::scope::connection_filter, server::AppState};
 use axum::{
     extract::{Path, State},
     routing::{get, post},
     Extension, Json, Router,
 };
-use bson::doc;
+use bson::{doc, Document};
+use chrono::Utc;
+use futures_util::StreamExt;
 use integrationos_domain::{
-    event_access::EventAccess, secret::Secret, ApplicationError, Id, IntegrationOSError,
+    algebra::MongoStore, event_access::EventAccess, secret::Secret, ApplicationError, Id,
+    IntegrationOSError, InternalError, SecretExt,
 };
+use mongodb::error::{ErrorKind, WriteFailure};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
+use tracing::{error, info};
 
 pub fn get_router() -> Router<Arc<AppState>> {
-    Router::new()
-        .route("/", post(create_secret))
-        .route("/:id", get(get_secret))
+    Router::new().route("/", post(create_secret)).route(
+        "/:id",
+        get(get_secret).patch(update_secret).delete(delete_secret),
+    )
 }
 
 #[derive(Serialize, Deserialize)]
@@ -42,6 +49,8 @@ async fn get_secret(
     Extension(event_access): Extension<Arc<EventAccess>>,
     Path(id): Path<String>,
 ) -> Result<Json<Secret>, IntegrationOSError> {
+    authorize_secret(&state, &event_access, &id).await?;
+
     Ok(Json(
         state
             .secrets_client
@@ -50,6 +59,113 @@ async fn get_secret(
     ))
 }
 
+async fn update_secret(
+    state: State<Arc<AppState>>,
+    Extension(event_access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+    Json(payload): Json<CreateSecretRequest>,
+) -> Result<Json<Secret>, IntegrationOSError> {
+    authorize_secret(&state, &event_access, &id).await?;
+
+    let secret = state
+        .secrets_client
+        .update(&id, &payload.secret, &event_access.ownership.id)
+        .await?;
+    evict_secret_connections(&state, &event_access, &id).await;
+
+    Ok(Json(secret))
+}
+
+/// Drops the connections using the secret from the caches, along with the
+/// clients built from it. Failures are only logged, the caches expire eventually.
+async fn evict_secret_connections(state: &AppState, event_access: &EventAccess, id: &str) {
+    let connections = state
+        .app_stores
+        .connection
+        .get_many(
+            Some(doc! {
+                "secretsServiceId": id,
+                "ownership.buildableId": event_access.ownership.id.as_ref(),
+                "deleted": false,
+            }),
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+
+    match connections {
+        Ok(connections) => {
+            for connection in connections {
+                evict_connection(state, connection.id).await;
+            }
+        }
+        Err(e) => error!("Could not find the connections of secret {id}: {e}"),
+    }
+}
+
+async fn delete_secret(
+    state: State<Arc<AppState>>,
+    Extension(event_access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, IntegrationOSError> {
+    authorize_secret(&state, &event_access, &id).await?;
+
+    let in_use = state
+        .app_stores
+        .connection
+        .count(
+            doc! {
+                "secretsServiceId": &id,
+                "ownership.buildableId": event_access.ownership.id.as_ref(),
+                "deleted": false,
+            },
+            Some(1),
+        )
+        .await?;
+    if in_use > 0 {
+        return Err(ApplicationError::conflict(
+            "This secret is used by a connection, delete the connection first",
+            None,
+        ));
+    }
+
+    state
+        .secrets_client
+        .delete(&id, &event_access.ownership.id)
+        .await?;
+
+    Ok(Json(serde_json::json!({ "id": id })))
+}
+
+/// Keys limited to some connections can only reach the secrets of those connections
+async fn authorize_secret(
+    state: &AppState,
+    event_access: &EventAccess,
+    id: &str,
+) -> Result<(), IntegrationOSError> {
+    let Some(scope_filter) = connection_filter(event_access) else {
+        return Ok(());
+    };
+
+    let mut filter = doc! {
+        "secretsServiceId": id,
+        "ownership.buildableId": event_access.ownership.id.as_ref(),
+        "deleted": false,
+    };
+    filter.extend(scope_filter);
+
+    if state.app_stores.connection.count(filter, Some(1)).await? == 0 {
+        return Err(ApplicationError::forbidden(
+            "This key cannot access this secret",
+            None,
+        ));
+    }
+
+    Ok(())
+}
+
 pub async fn get_admin_secret(
     state: State<Arc<AppState>>,
     Path(connection_id): Path<Id>,
@@ -67,3 +183,184 @@ pub async fn get_admin_secret(
 
     Ok(Json(state.secrets_client.get(&secret_id, &owner).await?))
 }
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReencryptionProgress {
+    pub running: bool,
+    /// Key secrets are being re-encrypted with
+    pub key_id: Option<String>,
+    pub total: u64,
+    pub reencrypted: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    /// Refreshed as secrets are processed, a run not updated for `STALE_RUN_MILLIS`
+    /// was interrupted and can be started again
+    pub updated_at: Option<i64>,
+}
+
+/// Id of the single document tracking the re-encryption
+const PROGRESS_ID: &str = "secrets";
+const STALE_RUN_MILLIS: i64 = 10 * 60 * 1000;
+
+/// Background job that re-encrypts every secret still encrypted with an old key,
+/// so the old key can be retired after a rotation.
+///
+/// Progress is kept in Mongo, so it is shared by every instance and survives restarts.
+#[derive(Debug, Clone)]
+pub struct SecretReencryption {
+    store: MongoStore<ReencryptionProgress>,
+}
+
+impl SecretReencryption {
+    pub fn new(store: MongoStore<ReencryptionProgress>) -> Self {
+        Self { store }
+    }
+
+    pub async fn progress(&self) -> Result<ReencryptionProgress, IntegrationOSError> {
+        Ok(self
+            .store
+            .get_one_by_id(PROGRESS_ID)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Returns `false` without starting a new run when one is in progress
+    pub async fn spawn(
+        &self,
+        store: MongoStore<Secret>,
+        secrets_client: Arc<dyn SecretExt + Sync + Send>,
+    ) -> Result<bool, IntegrationOSError> {
+        let key_id = secrets_client.key_id();
+        let now = Utc::now().timestamp_millis();
+        let progress = ReencryptionProgress {
+            running: true,
+            key_id: Some(key_id.clone()),
+            started_at: Some(now),
+            updated_at: Some(now),
+            ..Default::default()
+        };
+        let progress = bson::to_document(&progress).map_err(|e| {
+            InternalError::serialize_error(&format!("Could not serialize progress: {e}"), None)
+        })?;
+
+        // Only one instance can claim the document, the upsert of the others fails
+        // on the duplicate id while a run is ongoing
+        let claimed = self
+            .store
+            .collection
+            .update_one(
+                doc! {
+                    "_id": PROGRESS_ID,
+                    "$or": [
+                        { "running": { "$ne": true } },
+                        { "updatedAt": { "$lt": now - STALE_RUN_MILLIS } },
+                    ]
+                },
+                doc! { "$set": progress },
+            )
+            .upsert(true)
+            .await;
+        match claimed {
+            Ok(_) => {}
+            Err(e) if is_duplicate_key(&e) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+
+        let job = self.clone();
+        tokio::spawn(async move {
+            let filter = doc! {
+                "$or": [
+                    { "keyId": { "$ne": &key_id } },
+                    { "version": { "$ne": "v2" } },
+                ]
+            };
+
+            match store.count(filter.clone(), None).await {
+                Ok(total) => job.update(doc! { "$set": { "total": total as i64 } }).await,
+                Err(e) => error!("Could not count secrets to re-encrypt: {e}"),
+            }
+
+            match store.collection.find(filter).await {
+                Ok(mut secrets) => {
+                    while let Some(secret) = secrets.next().await {
+                        let result = match secret {
+                            Ok(secret) => secrets_client.reencrypt(secret).await,
+                            Err(e) => Err(e.into()),
+                        };
+
+                        let counter = match result {
+                            Ok(true) => "reencrypted",
+                            Ok(false) => "skipped",
+                            Err(e) => {
+                                error!("Could not re-encrypt secret: {e}");
+                                "failed"
+                            }
+                        };
+                        job.update(doc! { "$inc": { counter: 1 } }).await;
+                    }
+                }
+                Err(e) => error!("Could not fetch secrets to re-encrypt: {e}"),
+            }
+
+            job.update(doc! {
+                "$set": { "running": false, "finishedAt": Utc::now().timestamp_millis() }
+            })
+            .await;
+            info!("Finished re-encrypting secrets with key {key_id}");
+        });
+
+        Ok(true)
+    }
+
+    async fn update(&self, mut update: Document) {
+        let updated_at = Utc::now().timestamp_millis();
+        match update.get_document_mut("$set") {
+            Ok(set) => {
+                set.insert("updatedAt", updated_at);
+            }
+            Err(_) => {
+                update.insert("$set", doc! { "updatedAt": updated_at });
+            }
+        }
+
+        if let Err(e) = self.store.update_one(PROGRESS_ID, update).await {
+            error!("Could not save the progress of the secret re-encryption: {e}");
+        }
+    }
+}
+
+fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == 11000
+    )
+}
+
+pub async fn start_secret_reencryption(
+    state: State<Arc<AppState>>,
+) -> Result<Json<ReencryptionProgress>, IntegrationOSError> {
+    let started = state
+        .secret_reencryption
+        .spawn(
+            state.app_stores.secrets.clone(),
+            state.secrets_client.clone(),
+        )
+        .await?;
+    if !started {
+        return Err(ApplicationError::conflict(
+            "Secrets are already being re-encrypted",
+            None,
+        ));
+    }
+
+    Ok(Json(state.secret_reencryption.progress().await?))
+}
+
+pub async fn get_secret_reencryption(
+    state: State<Arc<AppState>>,
+) -> Result<Json<ReencryptionProgress>, IntegrationOSError> {
+    Ok(Json(state.secret_reencryption.progress().await?))
+}
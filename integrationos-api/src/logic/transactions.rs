@@ -1,13 +1,29 @@
 use super::{read, PublicExt, RequestExt};
-use crate::server::{AppState, AppStores};
-use axum::{routing::get, Router};
+use crate::{
+    helper::shape_mongo_filter,
+    middleware::scope::authorize_reveal,
+    router::ServerResponse,
+    server::{AppState, AppStores},
+};
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Extension, Json, Router,
+};
 use bson::doc;
-use integrationos_domain::{algebra::MongoStore, Transaction};
+use integrationos_domain::{
+    algebra::MongoStore, event_access::EventAccess, ApplicationError, IntegrationOSError,
+    Transaction,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::sync::Arc;
+use tracing::error;
 
 pub fn get_router() -> Router<Arc<AppState>> {
-    Router::new().route("/", get(read::<TransactionCrud, Transaction>))
+    Router::new()
+        .route("/", get(read::<TransactionCrud, Transaction>))
+        .route("/:id/reveal", get(reveal_transaction))
 }
 
 #[derive(Serialize, Deserialize)]
@@ -21,3 +37,40 @@ impl RequestExt for TransactionCrud {
         stores.transactions
     }
 }
+
+/// Reads a transaction with the originals of its tokenized values put back. Only
+/// keys whose scope allows revealing redacted values can call it.
+async fn reveal_transaction(
+    Extension(access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServerResponse<Value>>, IntegrationOSError> {
+    authorize_reveal(&access)?;
+
+    let mut query = shape_mongo_filter(None, Some(access.clone()), None);
+    query.filter.insert("_id", &id);
+
+    let mut transaction = state
+        .app_stores
+        .transactions
+        .get_one(query.filter)
+        .await?
+        .ok_or_else(|| ApplicationError::not_found(&format!("Transaction {id} not found"), None))?;
+
+    if let Some(sealed) = &transaction.redacted_values {
+        let redactions = state
+            .redaction
+            .reveal(sealed, &transaction.ownership.id)
+            .await
+            .map_err(|e| {
+                error!("Could not reveal redacted values of transaction {id}: {e}");
+                e
+            })?;
+        transaction.reveal(&redactions);
+    }
+
+    Ok(Json(ServerResponse::new(
+        "read",
+        TransactionCrud::public(transaction),
+    )))
+}
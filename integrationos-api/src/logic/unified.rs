@@ -324,15 +324,52 @@ pub async fn process_request(
             } else {
                 format!("{event_name}::request-failed",)
             };
-            let event = Event::new(
+            let mut event = Event::new(
                 &access_key,
                 &encrypted_access_key,
                 &name,
                 parts.headers.clone(),
                 body,
             );
-            if let Err(e) = state.event_tx.send(event).await {
-                error!("Could not send event to receiver: {e}");
+
+            // Without the definition the event is still redacted with the global rules
+            let connection_definition = state
+                .connection_definitions_cache
+                .get_or_insert_with_filter(
+                    &connection.connection_definition_id,
+                    state.app_stores.connection_config.clone(),
+                    doc! { "_id": connection.connection_definition_id.to_string() },
+                )
+                .await
+                .inspect_err(|e| {
+                    error!("Could not get connection definition to redact event: {e}");
+                })
+                .ok();
+            state
+                .redaction
+                .redact_event(
+                    &mut event,
+                    connection_definition
+                        .as_ref()
+                        .and_then(|definition| definition.redaction.as_ref()),
+                )
+                .await;
+
+            // An event that cannot be encrypted is dropped rather than stored in plaintext
+            let encrypted = match &access.data_key_id {
+                Some(data_key_id) => state
+                    .payload_encryption
+                    .encrypt_event(&mut event, data_key_id)
+                    .await
+                    .inspect_err(|e| error!("Could not encrypt event: {e}"))
+                    .is_ok(),
+                None => true,
+            };
+
+            if encrypted {
+                if let Err(e) = state.event_tx.send(event).await {
+                    error!("Could not send event to receiver: {e}");
+                }
             }
         }
     };
@@ -0,0 +1,315 @@
+use super::{delete, read, PublicExt, ReadResponse, RequestExt};
+use crate::{
+    helper::shape_mongo_filter,
+    router::ServerResponse,
+    server::{AppState, AppStores},
+};
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, patch, post},
+    Extension, Json, Router,
+};
+use bson::doc;
+use chrono::Utc;
+use http::HeaderMap;
+use integrationos_domain::{
+    algebra::MongoStore,
+    event_access::EventAccess,
+    id::{prefix::IdPrefix, Id},
+    lifecycle::ConnectionLifecycle,
+    record_metadata::RecordMetadata,
+    scheduled_event::ScheduledEvent,
+    webhook_delivery::WebhookDelivery,
+    webhook_subscription::{WebhookSecret, WebhookSubscription},
+    ApplicationError, IntegrationOSError, InternalError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, sync::Arc};
+use tracing::error;
+use validator::Validate;
+
+pub fn get_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/",
+            post(create_subscription).get(read::<WebhookSubscriptionCrud, WebhookSubscription>),
+        )
+        .route(
+            "/:id",
+            patch(update_subscription).delete(delete_subscription),
+        )
+        .route("/:id/deliveries", get(read_deliveries))
+        .route("/:id/deliveries/:delivery_id/redeliver", post(redeliver))
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateWebhookSubscriptionRequest {
+    #[validate(url)]
+    pub url: String,
+    #[validate(length(min = 1))]
+    pub events: Vec<ConnectionLifecycle>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateWebhookSubscriptionRequest {
+    #[validate(url)]
+    pub url: Option<String>,
+    #[validate(length(min = 1))]
+    pub events: Option<Vec<ConnectionLifecycle>>,
+    pub active: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WebhookSubscriptionCrud;
+
+impl PublicExt<WebhookSubscription> for WebhookSubscriptionCrud {}
+impl RequestExt for WebhookSubscriptionCrud {
+    type Output = WebhookSubscription;
+
+    fn get_store(stores: AppStores) -> MongoStore<Self::Output> {
+        stores.webhook_subscriptions
+    }
+}
+
+/// Creates a subscription along with its signing secret. The secret is only
+/// returned here, it can not be read again.
+async fn create_subscription(
+    Extension(access): Extension<Arc<EventAccess>>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateWebhookSubscriptionRequest>,
+) -> Result<Json<ServerResponse<Value>>, IntegrationOSError> {
+    if let Err(validation_errors) = payload.validate() {
+        return Err(ApplicationError::bad_request(
+            &format!("Invalid payload: {:?}", validation_errors),
+            None,
+        ));
+    }
+
+    WebhookSubscription::validate_url(&payload.url).await?;
+
+    let secret = WebhookSecret::generate();
+    let stored = state
+        .secrets_client
+        .create(&json!(secret), &access.ownership.id)
+        .await
+        .inspect_err(|e| error!("Could not store webhook secret: {e}"))?;
+
+    let subscription = WebhookSubscription {
+        id: Id::now(IdPrefix::WebhookSubscription),
+        url: payload.url,
+        events: payload.events,
+        secret_id: stored.id(),
+        environment: access.environment,
+        ownership: access.ownership.clone(),
+        record_metadata: RecordMetadata::default(),
+    };
+
+    state
+        .app_stores
+        .webhook_subscriptions
+        .create_one(&subscription)
+        .await
+        .inspect_err(|e| error!("Could not create webhook subscription: {e}"))?;
+
+    let mut response = WebhookSubscriptionCrud::public(subscription);
+    response["secret"] = json!(secret.secret);
+
+    Ok(Json(ServerResponse::new("create", response)))
+}
+
+async fn update_subscription(
+    Extension(access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdateWebhookSubscriptionRequest>,
+) -> Result<Json<ServerResponse<Value>>, IntegrationOSError> {
+    if let Err(validation_errors) = payload.validate() {
+        return Err(ApplicationError::bad_request(
+            &format!("Invalid payload: {:?}", validation_errors),
+            None,
+        ));
+    }
+
+    let mut subscription = get_owned_subscription(&state, &id, &access).await?;
+    if let Some(url) = payload.url {
+        WebhookSubscription::validate_url(&url).await?;
+        subscription.url = url;
+    }
+    if let Some(events) = payload.events {
+        subscription.events = events;
+    }
+    if let Some(active) = payload.active {
+        subscription.record_metadata.active = active;
+    }
+    subscription
+        .record_metadata
+        .mark_updated(&access.ownership.id);
+
+    let events = bson::to_bson(&subscription.events)
+        .map_err(|e| InternalError::serialize_error(&e.to_string(), None))?;
+    state
+        .app_stores
+        .webhook_subscriptions
+        .update_one(
+            &id,
+            doc! {
+                "$set": {
+                    "url": &subscription.url,
+                    "events": events,
+                    "active": subscription.record_metadata.active,
+                    "updated": true,
+                    "updatedAt": subscription.record_metadata.updated_at,
+                }
+            },
+        )
+        .await?;
+
+    Ok(Json(ServerResponse::new(
+        "update",
+        WebhookSubscriptionCrud::public(subscription),
+    )))
+}
+
+async fn delete_subscription(
+    Extension(access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServerResponse<Value>>, IntegrationOSError> {
+    let subscription = delete::<WebhookSubscriptionCrud, WebhookSubscription>(
+        Some(Extension(access.clone())),
+        Path(id),
+        State(state.clone()),
+    )
+    .await?;
+
+    let subscription = subscription.0.args;
+    if let Err(e) = state
+        .secrets_client
+        .delete(&subscription.secret_id, &access.ownership.id)
+        .await
+    {
+        error!(
+            "Could not delete secret of webhook subscription {}: {e}",
+            subscription.id
+        );
+    }
+
+    Ok(Json(ServerResponse::new(
+        "delete",
+        WebhookSubscriptionCrud::public(subscription),
+    )))
+}
+
+/// Lists the delivery attempts of a subscription, most recent first
+async fn read_deliveries(
+    headers: HeaderMap,
+    Extension(access): Extension<Arc<EventAccess>>,
+    Path(id): Path<String>,
+    query: Option<Query<BTreeMap<String, String>>>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServerResponse<ReadResponse<WebhookDelivery>>>, IntegrationOSError> {
+    let subscription = get_owned_subscription(&state, &id, &access).await?;
+
+    let mut query = shape_mongo_filter(query, Some(access), Some(headers));
+    query
+        .filter
+        .insert("subscriptionId", subscription.id.to_string());
+
+    let store = &state.app_stores.webhook_deliveries;
+    let (rows, total) = tokio::try_join!(
+        store.get_many(
+            Some(query.filter.clone()),
+            None,
+            Some(doc! { "createdAt": -1 }),
+            Some(query.limit),
+            Some(query.skip),
+        ),
+        store.count(query.filter, None),
+    )
+    .map_err(|e| {
+        error!("Error reading webhook deliveries: {e}");
+        e
+    })?;
+
+    Ok(Json(ServerResponse::new(
+        "read",
+        ReadResponse {
+            rows,
+            total,
+            skip: query.skip,
+            limit: query.limit,
+        },
+    )))
+}
+
+/// Delivers the event of a past delivery to the subscription again, through the
+/// scheduler so that it is retried like the original delivery
+async fn redeliver(
+    Extension(access): Extension<Arc<EventAccess>>,
+    Path((id, delivery_id)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServerResponse<ScheduledEvent>>, IntegrationOSError> {
+    let subscription = get_owned_subscription(&state, &id, &access).await?;
+
+    let Some(delivery) = state
+        .app_stores
+        .webhook_deliveries
+        .get_one(doc! {
+            "_id": &delivery_id,
+            "subscriptionId": subscription.id.to_string(),
+        })
+        .await?
+    else {
+        return Err(ApplicationError::not_found(
+            &format!("Webhook delivery with id {delivery_id} not found"),
+            None,
+        ));
+    };
+
+    let Some(event) = state
+        .app_stores
+        .event
+        .get_one_by_id(&delivery.event_key.to_string())
+        .await?
+    else {
+        return Err(ApplicationError::not_found(
+            &format!("Event {} of the delivery not found", delivery.event_key),
+            None,
+        ));
+    };
+
+    let scheduled_event =
+        ScheduledEvent::new(&event, Some(subscription.pipeline_key()), Utc::now());
+    state
+        .app_stores
+        .scheduled_events
+        .create_one(&scheduled_event)
+        .await
+        .inspect_err(|e| error!("Could not schedule redelivery of {delivery_id}: {e}"))?;
+
+    Ok(Json(ServerResponse::new("redeliver", scheduled_event)))
+}
+
+async fn get_owned_subscription(
+    state: &AppState,
+    id: &str,
+    access: &Arc<EventAccess>,
+) -> Result<WebhookSubscription, IntegrationOSError> {
+    let mut query = shape_mongo_filter(None, Some(access.clone()), None);
+    query.filter.insert("_id", id);
+
+    state
+        .app_stores
+        .webhook_subscriptions
+        .get_one(query.filter)
+        .await?
+        .ok_or_else(|| {
+            ApplicationError::not_found(
+                &format!("Webhook subscription with id {id} not found"),
+                None,
+            )
+        })
+}
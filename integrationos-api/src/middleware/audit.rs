@@ -0,0 +1,294 @@
+use super::scope::client_ip;
+use crate::{
+    domain::{AuditActor, AuditLog, AuditOutcome},
+    server::AppState,
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use futures_util::{
+    future::ready,
+    stream::{self, StreamExt},
+};
+use http::{header::CONTENT_LENGTH, Method, Request};
+use integrationos_domain::{
+    event_access::EventAccess,
+    id::{prefix::IdPrefix, Id},
+    Claims,
+};
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Data plane routes, their writes go to third party platforms and are tracked as events
+const UNAUDITED_ROUTES: [&str; 2] = ["unified", "passthrough"];
+/// Create responses larger than this are not inspected for the id of the new object
+const MAX_CREATE_RESPONSE_SIZE: usize = 1024 * 1024;
+const AUDIT_WRITE_ATTEMPTS: u32 = 3;
+const AUDIT_WRITE_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Records an audit log for every write and every read of a decrypted secret.
+///
+/// Has to run after the authentication middleware, which resolves the actor.
+/// Logs are written in the background so they never slow down the request, and
+/// retried a few times when Mongo is unavailable.
+pub async fn audit_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    // Routes are matched relative to the version prefix, e.g. `/secrets/{id}`
+    let path = req.uri().path().to_owned();
+    let segments = path
+        .trim_start_matches('/')
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let Some((action, target_id)) = audit_action(req.method(), &segments) else {
+        return next.run(req).await;
+    };
+
+    let actor = req
+        .extensions()
+        .get::<Arc<EventAccess>>()
+        .map(|event_access| AuditActor::event_access(event_access))
+        .or_else(|| {
+            req.extensions()
+                .get::<Arc<Claims>>()
+                .map(|claims| AuditActor::user(claims))
+        });
+    let Some(actor) = actor else {
+        // Unauthenticated requests are rejected before they get here
+        return next.run(req).await;
+    };
+
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|header| header.to_str().ok())
+        .map(str::to_owned)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let ip = client_ip(&req, &state.config.trusted_proxies).map(|ip| ip.to_string());
+    let method = req.method().clone();
+
+    let response = next.run(req).await;
+    let status = response.status();
+
+    let (response, target_id) = match target_id {
+        None if method == Method::POST && status.is_success() => created_id(response).await,
+        target_id => (response, target_id),
+    };
+
+    let log = AuditLog {
+        id: Id::now(IdPrefix::AuditLog),
+        actor,
+        action,
+        method: method.to_string(),
+        path,
+        target_id,
+        request_id,
+        ip,
+        outcome: if status.is_success() {
+            AuditOutcome::Success
+        } else {
+            AuditOutcome::Failure
+        },
+        status: status.as_u16(),
+        created_at: Utc::now(),
+    };
+
+    let store = state.app_stores.audit_logs.clone();
+    tokio::spawn(async move {
+        for attempt in 1..=AUDIT_WRITE_ATTEMPTS {
+            match store.create_one(&log).await {
+                Ok(()) => return,
+                Err(e) if attempt == AUDIT_WRITE_ATTEMPTS => {
+                    error!("Could not write audit log for {}: {e}", log.action);
+                }
+                Err(e) => {
+                    warn!(
+                        "Could not write audit log for {}, attempt {attempt}: {e}",
+                        log.action
+                    );
+                    tokio::time::sleep(AUDIT_WRITE_BACKOFF * 2u32.pow(attempt - 1)).await;
+                }
+            }
+        }
+    });
+
+    response
+}
+
+/// Returns the action and target id of a request that has to be audited.
+///
+/// Writes are audited on every route, reads only where they return decrypted
+/// secrets. A segment after the id names the action, e.g. `event-access/{id}/rotate`.
+fn audit_action(method: &Method, segments: &[&str]) -> Option<(String, Option<String>)> {
+    let (resource, rest) = match segments {
+        [] => return None,
+        [route, ..] if UNAUDITED_ROUTES.contains(route) => return None,
+        ["admin", resource, rest @ ..] => (format!("admin.{resource}"), rest),
+        [resource, rest @ ..] => (resource.to_string(), rest),
+    };
+
+    let (verb, target_id) = match (method, rest) {
+        (&Method::GET, [id]) if matches!(resource.as_str(), "secrets" | "admin.connection") => {
+            ("read", Some(id))
+        }
+        (&Method::GET, _) => return None,
+        (&Method::POST, [verb @ "test", id]) | (&Method::POST, [id, verb]) => (*verb, Some(id)),
+        (&Method::POST, rest) => ("create", rest.first()),
+        (&Method::PUT | &Method::PATCH, rest) => ("update", rest.first()),
+        (&Method::DELETE, rest) => ("delete", rest.first()),
+        _ => return None,
+    };
+
+    Some((
+        format!("{resource}.{verb}"),
+        target_id.map(|id| id.to_string()),
+    ))
+}
+
+/// The id of a created object is only known once the handler responds.
+///
+/// Responses too large to inspect are passed on untouched, without an id.
+async fn created_id(response: Response) -> (Response, Option<String>) {
+    let (parts, body) = response.into_parts();
+
+    let content_length = parts
+        .headers
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<usize>().ok());
+    if content_length.is_some_and(|length| length > MAX_CREATE_RESPONSE_SIZE) {
+        return (Response::from_parts(parts, body), None);
+    }
+
+    let mut chunks = body.into_data_stream();
+    let mut buffered = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                error!("Could not read create response for audit log: {e}");
+                let read = stream::once(ready(Ok(Bytes::from(buffered))));
+                let body = Body::from_stream(read.chain(stream::once(ready(Err(e)))));
+                return (Response::from_parts(parts, body), None);
+            }
+        };
+
+        buffered.extend_from_slice(&chunk);
+        if buffered.len() > MAX_CREATE_RESPONSE_SIZE {
+            // Hands back what was read, followed by the rest of the response
+            let read = stream::once(ready(Ok(Bytes::from(buffered))));
+            let body = Body::from_stream(read.chain(chunks));
+            return (Response::from_parts(parts, body), None);
+        }
+    }
+
+    let id = serde_json::from_slice::<Value>(&buffered)
+        .ok()
+        .and_then(|body| {
+            body.get("_id")
+                .or_else(|| body.get("id"))
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+        });
+
+    (Response::from_parts(parts, Body::from(buffered)), id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_audit_action() {
+        assert_eq!(
+            audit_action(&Method::GET, &["secrets", "sec::1"]),
+            Some(("secrets.read".to_owned(), Some("sec::1".to_owned())))
+        );
+        assert_eq!(
+            audit_action(&Method::GET, &["admin", "connection", "conn::1"]),
+            Some((
+                "admin.connection.read".to_owned(),
+                Some("conn::1".to_owned())
+            ))
+        );
+        assert_eq!(audit_action(&Method::GET, &["connections"]), None);
+        assert_eq!(
+            audit_action(&Method::POST, &["connections"]),
+            Some(("connections.create".to_owned(), None))
+        );
+        assert_eq!(
+            audit_action(&Method::POST, &["event-access", "evt_ac::1", "rotate"]),
+            Some((
+                "event-access.rotate".to_owned(),
+                Some("evt_ac::1".to_owned())
+            ))
+        );
+        assert_eq!(
+            audit_action(&Method::PATCH, &["connection-definitions", "conn_def::1"]),
+            Some((
+                "connection-definitions.update".to_owned(),
+                Some("conn_def::1".to_owned())
+            ))
+        );
+        assert_eq!(
+            audit_action(&Method::DELETE, &["connections", "conn::1"]),
+            Some(("connections.delete".to_owned(), Some("conn::1".to_owned())))
+        );
+        assert_eq!(
+            audit_action(
+                &Method::POST,
+                &["connection-model-definitions", "test", "conn_mod_def::1"]
+            ),
+            Some((
+                "connection-model-definitions.test".to_owned(),
+                Some("conn_mod_def::1".to_owned())
+            ))
+        );
+        assert_eq!(audit_action(&Method::POST, &["unified", "contacts"]), None);
+        assert_eq!(audit_action(&Method::DELETE, &["passthrough", "x"]), None);
+    }
+
+    #[tokio::test]
+    async fn test_created_id_keeps_large_responses() {
+        let body =
+            serde_json::json!({ "_id": "conn::1", "data": "a".repeat(MAX_CREATE_RESPONSE_SIZE) })
+                .to_string();
+        let chunks = body
+            .as_bytes()
+            .chunks(64 * 1024)
+            .map(|chunk| Ok::<_, std::io::Error>(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let response = Response::new(Body::from_stream(stream::iter(chunks)));
+
+        let (response, id) = created_id(response).await;
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        assert_eq!(id, None);
+        assert_eq!(bytes, body.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_created_id_reads_small_responses() {
+        let response = Response::new(Body::from(r#"{"_id":"conn::1"}"#));
+
+        let (response, id) = created_id(response).await;
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        assert_eq!(id, Some("conn::1".to_owned()));
+        assert_eq!(bytes, r#"{"_id":"conn::1"}"#.as_bytes());
+    }
+}
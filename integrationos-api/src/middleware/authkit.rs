@@ -0,0 +1,68 @@
+use crate::server::AppState;
+use axum::{body::Body, extract::State, middleware::Next, response::Response};
+use chrono::Utc;
+use http::{HeaderMap, Request};
+use integrationos_domain::{token::AuthKitToken, ApplicationError, IntegrationOSError};
+use mongodb::bson::doc;
+use std::sync::Arc;
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Resolves the AuthKit token of the request along with the event access of
+/// the key it was issued with, so that handlers behind it work as they do
+/// with the key.
+pub async fn authkit_token_middleware(
+    State(state): State<Arc<AppState>>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response, IntegrationOSError> {
+    let Some(token) = bearer_token(req.headers()) else {
+        return Err(ApplicationError::unauthorized(
+            "You're not authorized to access this resource",
+            None,
+        ));
+    };
+
+    let Some(token) = state
+        .app_stores
+        .authkit_tokens
+        .get_one(doc! { "tokenHash": AuthKitToken::hash(token), "deleted": false })
+        .await?
+    else {
+        return Err(ApplicationError::unauthorized(
+            "Invalid AuthKit token",
+            None,
+        ));
+    };
+
+    if token.is_expired(Utc::now().timestamp_millis()) {
+        return Err(ApplicationError::unauthorized(
+            "This AuthKit token has expired",
+            None,
+        ));
+    }
+
+    let Some(event_access) = state
+        .app_stores
+        .event_access
+        .get_one(doc! { "_id": token.event_access_id.to_string(), "deleted": false })
+        .await?
+    else {
+        return Err(ApplicationError::unauthorized(
+            "The key this AuthKit token was issued with has been revoked",
+            None,
+        ));
+    };
+
+    req.extensions_mut().insert(Arc::new(event_access));
+    req.extensions_mut().insert(Arc::new(token));
+    Ok(next.run(req).await)
+}
+
+/// AuthKit token of the request, sent as a bearer token
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(http::header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix(BEARER_PREFIX))
+}
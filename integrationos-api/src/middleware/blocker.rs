@@ -1,21 +1,26 @@
-use crate::server::AppState;
+use crate::{middleware::authkit::bearer_token, server::AppState};
 use axum::response::IntoResponse;
+use chrono::{DateTime, Utc};
 use futures_util::StreamExt;
 use http::{HeaderName, HeaderValue, Request};
-use integrationos_domain::{ApplicationError, Store};
+use integrationos_cache::remote::subscribe;
+use integrationos_domain::{
+    event_access::PreviousAccessKey, token::AuthKitToken, ApplicationError, Store,
+};
 use mongodb::options::FindOptions;
 use serde::Deserialize;
 use std::{
-    collections::BTreeSet,
+    collections::BTreeMap,
     error::Error,
     fmt::Display,
     sync::{Arc, RwLock},
     time::Duration,
 };
 use tower::{filter::Predicate, BoxError};
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 
-pub type Whitelist = Arc<RwLock<BTreeSet<HeaderValue>>>;
+/// Valid access keys, along with the time they expire for keys replaced by a rotation
+pub type Whitelist = Arc<RwLock<BTreeMap<HeaderValue, Option<DateTime<Utc>>>>>;
 
 #[derive(Debug, Clone)]
 pub struct BlockInvalidHeaders {
@@ -25,11 +30,52 @@ pub struct BlockInvalidHeaders {
 
 impl BlockInvalidHeaders {
     pub async fn from_state(state: Arc<AppState>) -> Self {
-        let whitelist = Arc::new(RwLock::new(BTreeSet::new()));
+        let whitelist = Arc::new(RwLock::new(BTreeMap::new()));
 
         let header_name =
             HeaderName::from_lowercase(state.config.headers.auth_header.as_bytes()).unwrap();
 
+        // Revoked keys are dropped right away instead of at the next refresh
+        let revoked_whitelist = whitelist.clone();
+        let event_access_cache = state.event_access_cache.clone();
+        subscribe(
+            &state.config.cache_config,
+            state.config.cache_config.revoked_access_keys_key.clone(),
+            move |access_key| {
+                let Ok(mut header_value) = HeaderValue::from_str(&access_key) else {
+                    warn!("Received an invalid revoked access key");
+                    return;
+                };
+                header_value.set_sensitive(true);
+                revoked_whitelist.write().unwrap().remove(&header_value);
+
+                let event_access_cache = event_access_cache.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = event_access_cache.remove(&header_value).await {
+                        error!("Could not evict revoked access key: {e}");
+                    }
+                });
+            },
+        );
+
+        // Keys issued by a rotation are accepted right away instead of at the next refresh
+        let rotated_whitelist = whitelist.clone();
+        subscribe(
+            &state.config.cache_config,
+            state.config.cache_config.rotated_access_keys_key.clone(),
+            move |access_key| {
+                let Ok(mut header_value) = HeaderValue::from_str(&access_key) else {
+                    warn!("Received an invalid rotated access key");
+                    return;
+                };
+                header_value.set_sensitive(true);
+                rotated_whitelist
+                    .write()
+                    .unwrap()
+                    .insert(header_value, None);
+            },
+        );
+
         let (tx, mut rx) = tokio::sync::mpsc::channel::<()>(1);
 
         let whitelist_clone = whitelist.clone();
@@ -39,6 +85,8 @@ impl BlockInvalidHeaders {
                 struct SparseEventAccess {
                     #[serde(with = "http_serde_ext_ios::header_value", rename = "accessKey")]
                     access_key: HeaderValue,
+                    #[serde(default, rename = "previousAccessKeys")]
+                    previous_access_keys: Vec<PreviousAccessKey>,
                 }
 
                 let mut records = match state
@@ -49,7 +97,8 @@ impl BlockInvalidHeaders {
                     .with_options(
                         FindOptions::builder()
                             .projection(bson::doc! {
-                               "accessKey": 1
+                               "accessKey": 1,
+                               "previousAccessKeys": 1
                             })
                             .build(),
                     )
@@ -63,14 +112,29 @@ impl BlockInvalidHeaders {
                 };
 
                 #[allow(clippy::mutable_key_type)]
-                let mut new_whitelist = BTreeSet::new();
+                let mut new_whitelist = BTreeMap::new();
+                let now = Utc::now();
                 while let Some(result) = records.next().await {
                     match result {
                         Ok(record) => {
                             let mut header_value = record.access_key;
                             header_value.set_sensitive(true);
 
-                            new_whitelist.insert(header_value);
+                            new_whitelist.insert(header_value, None);
+
+                            for previous in record.previous_access_keys {
+                                if previous.expires_at <= now {
+                                    continue;
+                                }
+                                let Ok(mut header_value) =
+                                    HeaderValue::from_str(&previous.access_key)
+                                else {
+                                    continue;
+                                };
+                                header_value.set_sensitive(true);
+
+                                new_whitelist.insert(header_value, Some(previous.expires_at));
+                            }
                         }
 
                         Err(e) => {
@@ -127,8 +191,12 @@ impl<T> Predicate<Request<T>> for BlockInvalidHeaders {
 
         {
             let whitelist = self.whitelist.read().unwrap();
-            if !whitelist.contains(header_value) {
-                return Err(Box::new(FastError));
+            match whitelist.get(header_value) {
+                Some(Some(expires_at)) if *expires_at <= Utc::now() => {
+                    return Err(Box::new(FastError));
+                }
+                Some(_) => {}
+                None => return Err(Box::new(FastError)),
             }
         }
 
@@ -136,6 +204,22 @@ impl<T> Predicate<Request<T>> for BlockInvalidHeaders {
     }
 }
 
+/// Rejects requests without a well formed AuthKit token before the token is
+/// looked up
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockInvalidAuthKitTokens;
+
+impl<T> Predicate<Request<T>> for BlockInvalidAuthKitTokens {
+    type Request = Request<T>;
+
+    fn check(&mut self, request: Request<T>) -> Result<Self::Request, BoxError> {
+        match bearer_token(request.headers()) {
+            Some(token) if AuthKitToken::is_well_formed(token) => Ok(request),
+            _ => Err(Box::new(FastError)),
+        }
+    }
+}
+
 pub async fn handle_blocked_error(_: BoxError) -> impl IntoResponse {
     ApplicationError::unauthorized("You are not authorized to access this resource", None)
 }
@@ -1,8 +1,11 @@
 use crate::server::AppState;
 use axum::{body::Body, extract::State, middleware::Next, response::Response};
-use http::Request;
-use integrationos_domain::{ApplicationError, IntegrationOSError, InternalError};
-use mongodb::bson::doc;
+use chrono::Utc;
+use http::{HeaderValue, Request};
+use integrationos_domain::{
+    event_access::EventAccess, ApplicationError, IntegrationOSError, InternalError,
+};
+use mongodb::bson::{doc, Document};
 use std::sync::Arc;
 use tracing::error;
 
@@ -41,7 +44,10 @@ pub async fn header_auth_middleware(
             auth_header,
             state.app_stores.event_access.clone(),
             doc! {
-                "accessKey": key,
+                "$or": [
+                    { "accessKey": key },
+                    { "previousAccessKeys.accessKey": key },
+                ],
                 "deleted": false
             },
         )
@@ -49,6 +55,15 @@ pub async fn header_auth_middleware(
 
     match event_access_result {
         Ok(data) => {
+            if !data.accepts(key, Utc::now()) {
+                return Err(ApplicationError::unauthorized(
+                    "This key has been rotated and is no longer valid",
+                    None,
+                ));
+            }
+
+            record_last_used(&state, auth_header.clone(), &data, key).await;
+
             req.extensions_mut().insert(Arc::new(data));
             Ok(next.run(req).await)
         }
@@ -64,8 +79,70 @@ pub async fn header_auth_middleware(
     }
 }
 
+/// Records when a key was used, at most once per `ACCESS_KEY_LAST_USED_INTERVAL_SECS`
+async fn record_last_used(
+    state: &Arc<AppState>,
+    auth_header: HeaderValue,
+    event_access: &EventAccess,
+    key: &str,
+) {
+    if state.access_key_usage.contains_key(&auth_header) {
+        return;
+    }
+    state.access_key_usage.insert(auth_header, ()).await;
+
+    let now = Utc::now().timestamp_millis();
+    let (filter, update) = last_used_update(event_access, key, now);
+
+    let store = state.app_stores.event_access.clone();
+    tokio::spawn(async move {
+        if let Err(e) = store.collection.update_one(filter, update).await {
+            error!("Could not record access key usage: {e}");
+        }
+    });
+}
+
+fn last_used_update(event_access: &EventAccess, key: &str, now: i64) -> (Document, Document) {
+    if event_access.access_key == key {
+        (
+            doc! { "_id": event_access.id.to_string() },
+            doc! { "$set": { "lastUsedAt": now } },
+        )
+    } else {
+        (
+            doc! {
+                "_id": event_access.id.to_string(),
+                "previousAccessKeys.accessKey": key,
+            },
+            doc! { "$set": { "previousAccessKeys.$.lastUsedAt": now } },
+        )
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use super::*;
+    use fake::{Fake, Faker};
+
+    #[test]
+    fn test_last_used_update() {
+        let event_access: EventAccess = Faker.fake();
+
+        let (filter, update) = last_used_update(&event_access, &event_access.access_key, 1);
+        assert_eq!(filter, doc! { "_id": event_access.id.to_string() });
+        assert_eq!(update, doc! { "$set": { "lastUsedAt": 1_i64 } });
+
+        let (filter, update) = last_used_update(&event_access, "previous_key", 1);
+        assert_eq!(
+            filter.get_str("previousAccessKeys.accessKey").unwrap(),
+            "previous_key"
+        );
+        assert_eq!(
+            update,
+            doc! { "$set": { "previousAccessKeys.$.lastUsedAt": 1_i64 } }
+        );
+    }
+
     #[test]
     fn test_header_check() {
         let conn = b"test::key";
@@ -1,7 +1,10 @@
+pub mod audit;
+pub mod authkit;
 pub mod blocker;
 pub mod extractor;
 pub mod header_auth;
 pub mod jwt_auth;
+pub mod scope;
 
 pub use header_auth::header_auth_middleware;
 pub use jwt_auth::jwt_auth_middleware;
@@ -0,0 +1,223 @@
+use crate::{domain::config::TrustedProxies, server::AppState};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use bson::{doc, Document};
+use chrono::Utc;
+use http::{Method, Request};
+use integrationos_domain::{
+    connection_model_definition::CrudAction, event_access::EventAccess, ApplicationError,
+    IntegrationOSError, Unit,
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+const UNIFIED_ROUTE: &str = "unified";
+
+/// Rejects requests the scope of the event access key does not allow.
+///
+/// Has to run after `header_auth_middleware`, which resolves the key.
+pub async fn scope_middleware(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Arc<EventAccess>>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, IntegrationOSError> {
+    let Some(scope) = &access.scope else {
+        return Ok(next.run(req).await);
+    };
+
+    if scope.is_expired(Utc::now()) {
+        return Err(ApplicationError::unauthorized("This key has expired", None));
+    }
+
+    if !scope.allows_ip(client_ip(&req, &state.config.trusted_proxies)) {
+        return Err(ApplicationError::forbidden(
+            "This key cannot be used from this IP address",
+            None,
+        ));
+    }
+
+    // Routes are matched relative to the version prefix, e.g. `/unified/contacts`
+    let mut segments = req.uri().path().trim_start_matches('/').split('/');
+    let route = segments.next().unwrap_or_default();
+    if !scope.allows_route(route) {
+        return Err(ApplicationError::forbidden(
+            &format!("This key cannot access {route}"),
+            None,
+        ));
+    }
+
+    if !scope.allows_method(req.method()) {
+        return Err(ApplicationError::forbidden("This key is read only", None));
+    }
+
+    // Routes taking the connection from their path or body check it themselves
+    if let Some(connection_key) = req
+        .headers()
+        .get(&state.config.headers.connection_header)
+        .and_then(|header| header.to_str().ok())
+    {
+        authorize_connection_key(&access, connection_key)?;
+    }
+
+    if route == UNIFIED_ROUTE {
+        let segments = segments.collect::<Vec<_>>();
+        if let Some((model, action)) = unified_action(req.method(), &segments) {
+            if !scope.allows_unified(model, &action) {
+                return Err(ApplicationError::forbidden(
+                    &format!("This key cannot {action} {model}"),
+                    None,
+                ));
+            }
+        }
+    }
+
+    Ok(next.run(req).await)
+}
+
+/// Rejects connections outside of the scope of the event access key
+pub(crate) fn authorize_connection_key(
+    access: &EventAccess,
+    connection_key: &str,
+) -> Result<Unit, IntegrationOSError> {
+    match &access.scope {
+        Some(scope) if !scope.allows_connection(connection_key) => Err(
+            ApplicationError::forbidden("This key cannot access this connection", None),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Rejects revealing redacted values unless the scope of the event access key
+/// explicitly allows it
+pub(crate) fn authorize_reveal(access: &EventAccess) -> Result<Unit, IntegrationOSError> {
+    match &access.scope {
+        Some(scope) if scope.allows_reveal() => Ok(()),
+        _ => Err(ApplicationError::forbidden(
+            "This key cannot reveal redacted values",
+            None,
+        )),
+    }
+}
+
+/// Limits a query over connections to the ones the scope of the event access key allows
+pub(crate) fn connection_filter(access: &EventAccess) -> Option<Document> {
+    let scope = access.scope.as_ref()?;
+
+    let mut conditions = vec![];
+    if !scope.connection_keys.is_empty() {
+        conditions.push(doc! { "key": { "$in": &scope.connection_keys } });
+    }
+    if !scope.platforms.is_empty() {
+        conditions.push(doc! { "platform": { "$in": &scope.platforms } });
+    }
+
+    (!conditions.is_empty()).then(|| doc! { "$and": conditions })
+}
+
+/// Uses the address of the peer, unless it is a trusted proxy. Then `X-Forwarded-For`
+/// is walked from the right, skipping the trusted proxies, as the entries before them
+/// are sent by the client and cannot be trusted
+pub(crate) fn client_ip(req: &Request<Body>, trusted_proxies: &TrustedProxies) -> Option<IpAddr> {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())?;
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+
+    let forwarded_for = req
+        .headers()
+        .get(FORWARDED_FOR_HEADER)
+        .and_then(|header| header.to_str().ok())
+        .unwrap_or_default();
+
+    let mut client = peer;
+    for ip in forwarded_for.rsplit(',') {
+        let Ok(ip) = ip.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = ip;
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+/// Mirrors the routes of `logic::unified::get_router`
+fn unified_action<'a>(method: &Method, segments: &[&'a str]) -> Option<(&'a str, CrudAction)> {
+    let action = match (method, segments) {
+        (&Method::GET, [_, "count"]) => CrudAction::GetCount,
+        (&Method::GET, [_]) => CrudAction::GetMany,
+        (&Method::GET, [_, _]) => CrudAction::GetOne,
+        (&Method::POST, [_]) => CrudAction::Create,
+        (&Method::PUT, [_]) => CrudAction::Upsert,
+        (&Method::PATCH, [_, _]) => CrudAction::Update,
+        (&Method::DELETE, [_, _]) => CrudAction::Delete,
+        _ => return None,
+    };
+    Some((segments[0], action))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unified_action() {
+        assert_eq!(
+            unified_action(&Method::GET, &["contacts"]),
+            Some(("contacts", CrudAction::GetMany))
+        );
+        assert_eq!(
+            unified_action(&Method::GET, &["contacts", "count"]),
+            Some(("contacts", CrudAction::GetCount))
+        );
+        assert_eq!(
+            unified_action(&Method::GET, &["contacts", "123"]),
+            Some(("contacts", CrudAction::GetOne))
+        );
+        assert_eq!(
+            unified_action(&Method::DELETE, &["contacts", "123"]),
+            Some(("contacts", CrudAction::Delete))
+        );
+        assert_eq!(unified_action(&Method::POST, &["contacts", "123"]), None);
+    }
+
+    #[test]
+    fn test_client_ip_only_trusts_forwarded_for_from_proxies() {
+        let trusted_proxies: TrustedProxies = "10.0.0.0/8".parse().unwrap();
+        let request = |peer: [u8; 4]| {
+            let mut req = Request::builder()
+                .header(FORWARDED_FOR_HEADER, "198.51.100.1, 203.0.113.7, 10.0.0.3")
+                .body(Body::empty())
+                .unwrap();
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from((peer, 443))));
+            req
+        };
+
+        assert_eq!(
+            client_ip(&request([10, 0, 0, 2]), &trusted_proxies),
+            "203.0.113.7".parse().ok()
+        );
+        assert_eq!(
+            client_ip(&request([192, 0, 2, 5]), &trusted_proxies),
+            "192.0.2.5".parse().ok()
+        );
+        assert_eq!(
+            client_ip(&request([10, 0, 0, 2]), &TrustedProxies::default()),
+            "10.0.0.2".parse().ok()
+        );
+    }
+}
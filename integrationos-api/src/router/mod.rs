@@ -74,7 +74,7 @@ pub async fn get_router(state: &Arc<AppState>) -> Router<Arc<AppState>> {
     let path = format!("/{}", state.config.api_version);
     let public_path = format!("{path}/public");
     Router::new()
-        .nest(&public_path, public::get_router(state))
+        .nest(&public_path, public::get_router(state).await)
         .nest(&path, secured_key::get_router(state).await)
         .nest(&path, secured_jwt::get_router(state).await)
         .route("/", get(get_root))
@@ -1,15 +1,22 @@
 use crate::{
     logic::{
-        common_enum, common_model,
+        authkit, common_enum, common_model, connection,
         connection_definition::{self, GetPublicConnectionDetailsRequest},
         connection_model_schema, connection_oauth_definition,
         event_access::create_event_access_for_new_user,
-        openapi, read, schema_generator, utils,
+        oauth, openapi, read, schema_generator, utils,
+    },
+    middleware::{
+        audit, authkit as authkit_auth,
+        blocker::{handle_blocked_error, BlockInvalidAuthKitTokens},
+        extractor::{rate_limit_middleware, RateLimiter},
+        jwt_auth::{self, JwtState},
+        scope,
     },
-    middleware::jwt_auth::{self, JwtState},
     server::AppState,
 };
 use axum::{
+    error_handling::HandleErrorLayer,
     middleware::{from_fn, from_fn_with_state},
     routing::{get, post},
     Router,
@@ -20,10 +27,44 @@ use integrationos_domain::{
     telemetry::log_request_middleware,
 };
 use std::sync::Arc;
+use tower::{filter::FilterLayer, ServiceBuilder};
 use tower_http::trace::TraceLayer;
+use tracing::warn;
+
+pub async fn get_router(state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    let authkit_routes = Router::new()
+        .route("/", get(authkit::read_token))
+        .route("/connections", post(connection::create_connection))
+        .route("/oauth/:platform", post(oauth::oauth_handler));
+
+    // Calls made with an AuthKit token count towards the throughput of the key
+    // it was issued with
+    let authkit_routes = match RateLimiter::from_state(state.clone()).await {
+        Ok(rate_limiter) => authkit_routes.layer(from_fn_with_state(
+            Arc::new(rate_limiter),
+            rate_limit_middleware,
+        )),
+        Err(e) => {
+            warn!("Could not connect to redis: {e}");
+            authkit_routes
+        }
+    };
+
+    let authkit_routes = authkit_routes
+        .layer(from_fn_with_state(state.clone(), audit::audit_middleware))
+        .layer(from_fn_with_state(state.clone(), scope::scope_middleware))
+        .layer(from_fn_with_state(
+            state.clone(),
+            authkit_auth::authkit_token_middleware,
+        ))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_blocked_error))
+                .layer(FilterLayer::new(BlockInvalidAuthKitTokens)),
+        );
 
-pub fn get_router(state: &Arc<AppState>) -> Router<Arc<AppState>> {
     Router::new()
+        .nest("/authkit", authkit_routes)
         .nest(
             "/sdk",
             Router::new()
@@ -1,11 +1,14 @@
 use crate::{
     logic::{
-        common_enum, common_model, connection_definition,
+        audit_logs, common_enum, common_model, connection_definition,
         connection_model_definition::{self},
         connection_model_schema, connection_oauth_definition, event_callback, openapi, platform,
         platform_page, secrets,
     },
-    middleware::jwt_auth::{self, JwtState},
+    middleware::{
+        audit,
+        jwt_auth::{self, JwtState},
+    },
     server::AppState,
 };
 use axum::{
@@ -40,10 +43,16 @@ pub async fn get_router(state: &Arc<AppState>) -> Router<Arc<AppState>> {
         .nest("/event-callbacks", event_callback::get_router())
         .nest("/platform-pages", platform_page::get_router())
         .nest("/platforms", platform::get_router())
+        .route("/admin/audit-logs", get(audit_logs::read_audit_logs))
         .route("/admin/connection/:id", get(secrets::get_admin_secret))
+        .route(
+            "/admin/secrets/reencrypt",
+            get(secrets::get_secret_reencryption).post(secrets::start_secret_reencryption),
+        )
         .route("/openapi", post(openapi::refresh_openapi));
 
     routes
+        .layer(from_fn_with_state(state.clone(), audit::audit_middleware))
         .layer(from_fn_with_state(
             Arc::new(JwtState::from_state(state)),
             jwt_auth::jwt_auth_middleware,
@@ -1,17 +1,18 @@
 use crate::{
     logic::{
-        connection,
+        authkit, connection,
         connection_model_definition::test_connection_model_definition,
         connection_model_schema::{
             public_get_connection_model_schema, PublicGetConnectionModelSchema,
         },
-        event_access, events, metrics, oauth, passthrough, pipeline, secrets, transactions,
-        unified, vault_connection,
+        event_access, events, metrics, oauth, passthrough, pipeline, scheduled_events, secrets,
+        transactions, unified, vault_connection, webhook,
     },
     middleware::{
+        audit,
         blocker::{handle_blocked_error, BlockInvalidHeaders},
         extractor::{rate_limit_middleware, RateLimiter},
-        header_auth,
+        header_auth, scope,
     },
     server::AppState,
 };
@@ -33,6 +34,7 @@ use tracing::warn;
 pub async fn get_router(state: &Arc<AppState>) -> Router<Arc<AppState>> {
     let routes = Router::new()
         .layer(TraceLayer::new_for_http())
+        .nest("/authkit-token", authkit::get_router())
         .nest("/connections", connection::get_router())
         .nest("/event-access", event_access::get_router())
         .nest("/events", events::get_router())
@@ -40,10 +42,12 @@ pub async fn get_router(state: &Arc<AppState>) -> Router<Arc<AppState>> {
         .nest("/oauth", oauth::get_router())
         .nest("/passthrough", passthrough::get_router())
         .nest("/pipelines", pipeline::get_router())
+        .nest("/scheduled-events", scheduled_events::get_router())
         .nest("/secrets", secrets::get_router())
         .nest("/transactions", transactions::get_router())
         .nest("/unified", unified::get_router())
         .nest("/vault/connections", vault_connection::get_router())
+        .nest("/webhooks", webhook::get_router())
         .route(
             "/connection-model-definitions/test/:id",
             post(test_connection_model_definition),
@@ -67,7 +71,11 @@ pub async fn get_router(state: &Arc<AppState>) -> Router<Arc<AppState>> {
         }
     };
 
+    // Audit outside of the scope checks so forbidden calls are recorded too, but
+    // inside of the authentication which resolves the actor
     routes
+        .layer(from_fn_with_state(state.clone(), scope::scope_middleware))
+        .layer(from_fn_with_state(state.clone(), audit::audit_middleware))
         .layer(from_fn_with_state(
             state.clone(),
             header_auth::header_auth_middleware,
@@ -1,16 +1,25 @@
 use crate::{
-    domain::{ConnectionsConfig, K8sMode, Metric},
+    domain::{AuditLog, ConnectionsConfig, K8sMode, Metric},
     helper::{K8sDriver, K8sDriverImpl, K8sDriverLogger},
-    logic::{connection_oauth_definition::FrontendOauthConnectionDefinition, openapi::OpenAPIData},
+    logic::{
+        connection::remove_cached_connection,
+        connection_oauth_definition::FrontendOauthConnectionDefinition, openapi::OpenAPIData,
+        secrets::SecretReencryption,
+    },
     router,
 };
 use anyhow::{anyhow, Context, Result};
 use axum::Router;
-use integrationos_cache::local::{
-    connection_cache::ConnectionCacheArcStrHeaderKey,
-    connection_definition_cache::ConnectionDefinitionCache,
-    connection_oauth_definition_cache::ConnectionOAuthDefinitionCache,
-    event_access_cache::EventAccessCache,
+use http::HeaderValue;
+use integrationos_cache::{
+    lifecycle::LifecyclePublisher,
+    local::{
+        connection_cache::ConnectionCacheArcStrHeaderKey,
+        connection_definition_cache::ConnectionDefinitionCache,
+        connection_oauth_definition_cache::ConnectionOAuthDefinitionCache,
+        event_access_cache::EventAccessCache,
+    },
+    remote::{subscribe, RedisCache},
 };
 use integrationos_domain::{
     algebra::{DefaultTemplate, MongoStore},
@@ -21,23 +30,37 @@ use integrationos_domain::{
     connection_oauth_definition::{ConnectionOAuthDefinition, Settings},
     cursor::Cursor,
     event_access::EventAccess,
+    id::Id,
     page::PlatformPage,
+    payload_encryption::PayloadEncryption,
+    redaction::{RedactionService, RedactionVault},
+    scheduled_event::ScheduledEvent,
+    scripting::ScriptService,
     secret::Secret,
-    secrets::SecretServiceProvider,
     stage::Stage,
+    token::AuthKitToken,
     user::UserClient,
-    Connection, Event, GoogleKms, IOSKms, Pipeline, PlatformData, PublicConnection, SecretExt,
-    Store, Transaction,
+    webhook_delivery::WebhookDelivery,
+    webhook_subscription::WebhookSubscription,
+    Connection, Event, Pipeline, PlatformData, PublicConnection, RootContext, SecretExt,
+    SecretsClient, Store, Transaction,
 };
 use integrationos_unified::unified::{UnifiedCacheTTLs, UnifiedDestination};
-use mongodb::{options::UpdateOptions, Client, Database};
+use moka::future::Cache;
+use mongodb::{
+    options::{IndexOptions, UpdateOptions},
+    Client, Database, IndexModel,
+};
+use redis::aio::ConnectionManager;
 use segment::{AutoBatcher, Batcher, HttpClient};
-use std::{sync::Arc, time::Duration};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::{net::TcpListener, sync::mpsc::Sender, time::timeout, try_join};
 use tracing::{error, info, trace, warn};
 
 #[derive(Clone)]
 pub struct AppStores {
+    pub audit_logs: MongoStore<AuditLog>,
+    pub authkit_tokens: MongoStore<AuthKitToken>,
     pub clients: MongoStore<UserClient>,
     pub common_enum: MongoStore<CommonEnum>,
     pub common_model: MongoStore<CommonModel>,
@@ -57,14 +80,19 @@ pub struct AppStores {
     pub public_connection: MongoStore<PublicConnection>,
     pub public_connection_details: MongoStore<PublicConnectionDetails>,
     pub public_model_schema: MongoStore<PublicConnectionModelSchema>,
+    pub scheduled_events: MongoStore<ScheduledEvent>,
     pub secrets: MongoStore<Secret>,
     pub settings: MongoStore<Settings>,
     pub stages: MongoStore<Stage>,
     pub transactions: MongoStore<Transaction>,
+    pub webhook_deliveries: MongoStore<WebhookDelivery>,
+    pub webhook_subscriptions: MongoStore<WebhookSubscription>,
 }
 
 #[derive(Clone)]
 pub struct AppState {
+    /// Access keys whose last use was recorded recently, so it is not written on every request
+    pub access_key_usage: Cache<HeaderValue, ()>,
     pub app_stores: AppStores,
     pub config: ConnectionsConfig,
     pub connection_definitions_cache: ConnectionDefinitionCache,
@@ -75,8 +103,15 @@ pub struct AppState {
     pub extractor_caller: UnifiedDestination,
     pub http_client: reqwest::Client,
     pub k8s_client: Arc<dyn K8sDriver>,
+    pub lifecycle: LifecyclePublisher,
     pub metric_tx: Sender<Metric>,
     pub openapi_data: OpenAPIData,
+    pub payload_encryption: PayloadEncryption,
+    pub redaction: RedactionService,
+    /// Shared by every request, the connection manager reconnects on its own
+    pub redis: ConnectionManager,
+    pub scripts: ScriptService,
+    pub secret_reencryption: SecretReencryption,
     pub secrets_client: Arc<dyn SecretExt + Sync + Send>,
     pub template: DefaultTemplate,
 }
@@ -115,26 +150,59 @@ impl Server {
         let pipeline = MongoStore::new(&db, &Store::Pipelines).await?;
         let event_access = MongoStore::new(&db, &Store::EventAccess).await?;
         let event = MongoStore::new(&db, &Store::Events).await?;
+        let scheduled_events = MongoStore::new(&db, &Store::ScheduledEvents).await?;
         let transactions = MongoStore::new(&db, &Store::Transactions).await?;
         let cursors = MongoStore::new(&db, &Store::Cursors).await?;
+        let audit_logs = MongoStore::new(&db, &Store::AuditLogs).await?;
         let stages = MongoStore::new(&db, &Store::Stages).await?;
         let clients = MongoStore::new(&db, &Store::Clients).await?;
+        let webhook_subscriptions = MongoStore::new(&db, &Store::WebhookSubscriptions).await?;
+        let webhook_deliveries = MongoStore::new(&db, &Store::WebhookDeliveries).await?;
+        let authkit_tokens: MongoStore<AuthKitToken> =
+            MongoStore::new(&db, &Store::AuthKitTokens).await?;
+        // Expired AuthKit tokens are removed by mongodb. Without the index they are
+        // still rejected once expired, so failing to create it is not fatal
+        if let Err(e) = authkit_tokens
+            .collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(bson::doc! { "expiresAt": 1 })
+                    .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+                    .build(),
+            )
+            .await
+        {
+            warn!("Could not create the expiry index of AuthKit tokens: {e}");
+        }
+        let secret_reencryption =
+            SecretReencryption::new(MongoStore::new(&db, &Store::SecretReencryptions).await?);
         let secrets_store = MongoStore::<Secret>::new(&db, &Store::Secrets).await?;
 
-        let secrets_client: Arc<dyn SecretExt + Sync + Send> = match config.secrets_config.provider
-        {
-            SecretServiceProvider::GoogleKms => {
-                Arc::new(GoogleKms::new(&config.secrets_config, secrets_store).await?)
-            }
-            SecretServiceProvider::IosKms => {
-                Arc::new(IOSKms::new(&config.secrets_config, secrets_store).await?)
-            }
-        };
+        let secrets_client: Arc<dyn SecretExt + Sync + Send> =
+            Arc::new(SecretsClient::new(&config.secrets_config, secrets_store).await?);
+
+        let scripts = ScriptService::new(&config.script_config)?;
+
+        let redis = RedisCache::new(&config.cache_config)
+            .await
+            .with_context(|| "Could not connect to redis")?
+            .inner;
+
+        let redaction_vault = RedactionVault::new(
+            secrets_client.clone(),
+            MongoStore::new(&db, &Store::RedactionKeys).await?,
+        );
+        let redaction =
+            RedactionService::new(config.redaction_rules.clone(), Some(redaction_vault))
+                .with_context(|| "Invalid redaction rules")?;
+
+        let payload_encryption = PayloadEncryption::new(secrets_client.clone());
 
         let extractor_caller = UnifiedDestination::new(
             config.db_config.clone(),
             config.cache_size,
             secrets_client.clone(),
+            scripts.clone(),
             UnifiedCacheTTLs {
                 connection_cache_ttl_secs: config.connection_cache_ttl_secs,
                 connection_model_schema_cache_ttl_secs: config
@@ -145,7 +213,13 @@ impl Server {
             },
         )
         .await
-        .with_context(|| "Could not initialize extractor caller")?;
+        .with_context(|| "Could not initialize extractor caller")?
+        .with_redactor(redaction.redactor().clone())
+        .with_http_client(
+            http_client.clone(),
+            Duration::from_secs(config.http_client_timeout_secs),
+        )
+        .with_oauth_refresh(redis.clone());
 
         let app_stores = AppStores {
             db: db.clone(),
@@ -167,14 +241,25 @@ impl Server {
             pipeline,
             event_access,
             event,
+            scheduled_events,
             transactions,
             cursors,
             stages,
             clients,
+            audit_logs,
+            authkit_tokens,
+            webhook_subscriptions,
+            webhook_deliveries,
         };
 
         let event_access_cache =
             EventAccessCache::new(config.cache_size, config.access_key_cache_ttl_secs);
+        let access_key_usage = Cache::builder()
+            .max_capacity(config.cache_size)
+            .time_to_live(Duration::from_secs(
+                config.access_key_last_used_interval_secs,
+            ))
+            .build();
         let connections_cache = ConnectionCacheArcStrHeaderKey::create(
             config.cache_size,
             config.connection_cache_ttl_secs,
@@ -193,6 +278,22 @@ impl Server {
             app_stores.common_enum.clone(),
         );
 
+        // Lifecycle events are processed by the event service like any other event
+        let event_db = Client::with_uri_str(&config.db_config.event_db_url)
+            .await?
+            .database(&config.db_config.event_db_name);
+        let context_collection = Client::with_uri_str(&config.db_config.context_db_url)
+            .await?
+            .database(&config.db_config.context_db_name)
+            .collection::<RootContext>(&config.db_config.context_collection_name);
+        let lifecycle = LifecyclePublisher::new(
+            MongoStore::new(&event_db, &Store::Events).await?,
+            context_collection,
+            redis.clone(),
+            config.cache_config.queue_name.clone(),
+            &config.event_access_password,
+        )?;
+
         let k8s_client: Arc<dyn K8sDriver> = match config.k8s_mode {
             K8sMode::Real => Arc::new(K8sDriverImpl::new().await?),
             K8sMode::Logger => Arc::new(K8sDriverLogger),
@@ -306,24 +407,51 @@ impl Server {
             }
         });
 
-        Ok(Self {
-            state: Arc::new(AppState {
-                app_stores,
-                config,
-                connection_definitions_cache,
-                connection_oauth_definitions_cache,
-                connections_cache,
-                event_access_cache,
-                event_tx,
-                extractor_caller,
-                http_client,
-                k8s_client,
-                metric_tx,
-                openapi_data,
-                secrets_client,
-                template,
-            }),
-        })
+        let state = Arc::new(AppState {
+            access_key_usage,
+            app_stores,
+            config,
+            connection_definitions_cache,
+            connection_oauth_definitions_cache,
+            connections_cache,
+            event_access_cache,
+            event_tx,
+            extractor_caller,
+            http_client,
+            k8s_client,
+            lifecycle,
+            metric_tx,
+            openapi_data,
+            payload_encryption,
+            redaction,
+            redis,
+            scripts,
+            secret_reencryption,
+            secrets_client,
+            template,
+        });
+
+        // Connections reauthorized on any instance are dropped from the caches of this one
+        let subscriber_state = state.clone();
+        subscribe(
+            &state.config.cache_config,
+            state
+                .config
+                .cache_config
+                .reauthorized_connections_key
+                .clone(),
+            move |id| {
+                let Ok(id) = id.parse::<Id>() else {
+                    warn!("Received an invalid reauthorized connection id");
+                    return;
+                };
+
+                let state = subscriber_state.clone();
+                tokio::spawn(async move { remove_cached_connection(&state, id).await });
+            },
+        );
+
+        Ok(Self { state })
     }
 
     pub async fn run(&self) -> Result<()> {
@@ -335,8 +463,11 @@ impl Server {
 
         let tcp_listener = TcpListener::bind(&self.state.config.address).await?;
 
-        axum::serve(tcp_listener, app.into_make_service())
-            .await
-            .map_err(|e| anyhow!("Server error: {}", e))
+        axum::serve(
+            tcp_listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .map_err(|e| anyhow!("Server error: {}", e))
     }
 }
@@ -101,6 +101,27 @@ impl SecretExt for MockSecretsClient {
             None,
         ))
     }
+
+    async fn update(
+        &self,
+        _id: &str,
+        secret: &Value,
+        buildable_id: &str,
+    ) -> Result<Secret, IntegrationOSError> {
+        self.create(secret, buildable_id).await
+    }
+
+    async fn delete(&self, _id: &str, _buildable_id: &str) -> Result<(), IntegrationOSError> {
+        Ok(())
+    }
+
+    async fn reencrypt(&self, _secret: Secret) -> Result<bool, IntegrationOSError> {
+        Ok(false)
+    }
+
+    fn key_id(&self) -> String {
+        "mock".to_string()
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -461,6 +482,7 @@ impl TestServer {
             identity_type: None,
             group: None,
             name: None,
+            encrypt_events: false,
         };
 
         let res = self
@@ -492,6 +514,7 @@ impl TestServer {
             base_url: api_config.base_url,
             path: api_config.path,
             auth_method: api_config.auth_method,
+            mtls: api_config.mtls,
             http_method: test_connection.action,
             action_name: Faker.fake::<CrudAction>(),
             headers: api_config.headers,
@@ -1,6 +1,12 @@
 use crate::context::{ApiResponse, TestServer, PUBLIC_PATHS};
 use http::{Method, StatusCode};
+use integrationos_domain::Store;
+use mongodb::{
+    bson::{doc, Document},
+    Client,
+};
 use serde_json::{json, Value};
+use std::time::Duration;
 
 #[tokio::test]
 async fn test_root() {
@@ -45,3 +51,44 @@ async fn test_404() {
         );
     }
 }
+
+#[tokio::test]
+async fn test_forbidden_call_is_audited() {
+    let server = TestServer::new(None).await;
+
+    let db = Client::with_uri_str(&server.config.db_config.control_db_url)
+        .await
+        .unwrap()
+        .database(&server.config.db_config.control_db_name);
+    db.collection::<Document>(&Store::EventAccess.to_string())
+        .update_one(
+            doc! { "accessKey": &server.live_key },
+            doc! { "$set": { "scope": { "permission": "read" } } },
+        )
+        .await
+        .unwrap();
+
+    let res = server
+        .send_request::<Value, Value>(
+            "v1/pipelines",
+            Method::POST,
+            Some(&server.live_key),
+            Some(&json!({})),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::FORBIDDEN);
+
+    // Audit logs are written in the background
+    let audit_logs = db.collection::<Document>(&Store::AuditLogs.to_string());
+    let filter = doc! { "action": "pipelines.create", "status": 403, "outcome": "failure" };
+    let mut count = 0;
+    for _ in 0..50 {
+        count = audit_logs.count_documents(filter.clone()).await.unwrap();
+        if count > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(count, 1);
+}
@@ -0,0 +1,92 @@
+use crate::context::TestServer;
+use http::{header::AUTHORIZATION, Method, StatusCode};
+use integrationos_domain::id::{prefix::IdPrefix, Id};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+#[tokio::test]
+async fn test_authkit_token() {
+    let server = TestServer::new(None).await;
+    let allowed = Id::now(IdPrefix::ConnectionDefinition);
+
+    let res = server
+        .send_request::<Value, Value>(
+            "v1/authkit-token",
+            Method::POST,
+            Some(&server.live_key),
+            Some(&json!({ "identity": "", "identityType": "user" })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::BAD_REQUEST);
+
+    let res = server
+        .send_request::<Value, Value>(
+            "v1/authkit-token",
+            Method::POST,
+            Some(&server.live_key),
+            Some(&json!({
+                "identity": "org_123",
+                "identityType": "organization",
+                "connectionDefinitionIds": [allowed],
+            })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::OK);
+    assert_eq!(res.data["group"], "org_123");
+    let token = res.data["token"].as_str().unwrap().to_owned();
+    assert!(token.starts_with("ln_tk_"));
+
+    let bearer = |token: &str| {
+        Some(BTreeMap::from_iter([(
+            AUTHORIZATION.to_string(),
+            format!("Bearer {token}"),
+        )]))
+    };
+
+    let res = server
+        .send_request_with_headers::<Value, Value>(
+            "v1/public/authkit",
+            Method::GET,
+            None,
+            None,
+            bearer(&token),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::OK);
+    assert_eq!(res.data["identity"], "org_123");
+    assert_eq!(res.data["connectionDefinitionIds"], json!([allowed]));
+    // Only the hash of the token is kept
+    assert_eq!(res.data.get("token"), None);
+
+    let res = server
+        .send_request_with_headers::<Value, Value>(
+            "v1/public/authkit",
+            Method::GET,
+            None,
+            None,
+            bearer("ln_tk_invalid"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::UNAUTHORIZED);
+
+    // Tokens only connect the platforms they were issued for
+    let res = server
+        .send_request_with_headers::<Value, Value>(
+            "v1/public/authkit/connections",
+            Method::POST,
+            None,
+            Some(&json!({
+                "connectionDefinitionId": Id::now(IdPrefix::ConnectionDefinition),
+                "authFormData": {},
+                "active": true,
+            })),
+            bearer(&token),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::FORBIDDEN);
+}
@@ -1,6 +1,15 @@
 use crate::context::TestServer;
+use chrono::Utc;
 use http::{Method, StatusCode};
-use serde_json::Value;
+use integrationos_domain::{
+    algebra::MongoStore,
+    environment::Environment,
+    health::HealthStatus,
+    id::{prefix::IdPrefix, Id},
+    Connection, SanitizedConnection, Store,
+};
+use mongodb::{bson::doc, Client};
+use serde_json::{json, Value};
 
 #[tokio::test]
 async fn test_connection_data_models_api() {
@@ -11,3 +20,74 @@ async fn test_connection_data_models_api() {
         .unwrap();
     assert_eq!(res.code, StatusCode::OK);
 }
+
+#[tokio::test]
+async fn test_reauthorize_unknown_connection() {
+    let server = TestServer::new(None).await;
+    let res = server
+        .send_request::<Value, Value>(
+            &format!(
+                "v1/connections/{}/reauthorize",
+                Id::now(IdPrefix::Connection)
+            ),
+            Method::POST,
+            Some(&server.live_key),
+            Some(&json!({ "authFormData": {} })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_read_unhealthy_connections() {
+    let mut server = TestServer::new(None).await;
+    let (unhealthy, _) = server.create_connection(Environment::Live).await;
+    server.create_connection(Environment::Live).await;
+
+    let db = Client::with_uri_str(&server.config.db_config.control_db_url)
+        .await
+        .unwrap()
+        .database(&server.config.db_config.control_db_name);
+    let store: MongoStore<Connection> = MongoStore::new(&db, &Store::Connections).await.unwrap();
+    store
+        .update_one(
+            &unhealthy.id.to_string(),
+            doc! {
+                "$set": {
+                    "hasError": true,
+                    "error": "Health check failed: Test connection failed: 401 Unauthorized",
+                    "health": {
+                        "status": HealthStatus::Unhealthy.as_ref(),
+                        "passed": false,
+                        "error": "Test connection failed: 401 Unauthorized",
+                        "latencyMs": 120,
+                        "checkedAt": Utc::now().timestamp_millis(),
+                        "streak": 3,
+                    },
+                }
+            },
+        )
+        .await
+        .unwrap();
+
+    let res = server
+        .send_request::<Value, Value>(
+            "v1/connections/unhealthy",
+            Method::GET,
+            Some(&server.live_key),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::OK);
+    assert_eq!(res.data["total"], 1);
+
+    let rows: Vec<SanitizedConnection> = serde_json::from_value(res.data["rows"].clone()).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].id, unhealthy.id);
+    assert_eq!(
+        rows[0].health.as_ref().map(|health| health.status),
+        Some(HealthStatus::Unhealthy)
+    );
+}
@@ -1,9 +1,12 @@
 pub mod auth;
+pub mod authkit;
 pub mod callback;
 pub mod connection;
 pub mod crud;
 pub mod pagination;
+pub mod oauth_refresh;
 pub mod passthrough;
 pub mod schema;
 pub mod transaction;
 pub mod unified;
+pub mod webhook;
@@ -0,0 +1,203 @@
+use crate::context::TestServer;
+use axum::async_trait;
+use chrono::Utc;
+use fake::{Fake, Faker};
+use futures::future::join_all;
+use integrationos_domain::{
+    algebra::MongoStore,
+    api_model_config::{
+        ApiModelConfig, AuthMethod, Compute, ContentType, Function, Lang, SamplesInput,
+        SchemasInput,
+    },
+    connection_oauth_definition::{ComputeRequest, ConnectionOAuthDefinition},
+    environment::Environment,
+    script::ScriptConfig,
+    scripting::ScriptService,
+    secret::Secret,
+    Connection, IntegrationOSError, OAuth, SecretExt, SecretVersion, Store,
+};
+use integrationos_unified::oauth::OAuthRefresh;
+use mockito::Server;
+use mongodb::{
+    bson::{self, doc},
+    Client,
+};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+/// Keeps the last secret written, so refreshes see each other's tokens
+struct InMemorySecretsClient {
+    secret: Mutex<Value>,
+}
+
+#[async_trait]
+impl SecretExt for InMemorySecretsClient {
+    async fn get(&self, _id: &str, buildable_id: &str) -> Result<Secret, IntegrationOSError> {
+        Ok(Secret::new(
+            self.secret.lock().unwrap().to_string(),
+            Some(SecretVersion::V2),
+            buildable_id.to_string(),
+            None,
+        ))
+    }
+
+    async fn create(
+        &self,
+        secret: &Value,
+        buildable_id: &str,
+    ) -> Result<Secret, IntegrationOSError> {
+        *self.secret.lock().unwrap() = secret.clone();
+        self.get("", buildable_id).await
+    }
+
+    async fn update(
+        &self,
+        _id: &str,
+        secret: &Value,
+        buildable_id: &str,
+    ) -> Result<Secret, IntegrationOSError> {
+        self.create(secret, buildable_id).await
+    }
+
+    async fn delete(&self, _id: &str, _buildable_id: &str) -> Result<(), IntegrationOSError> {
+        Ok(())
+    }
+
+    async fn reencrypt(&self, _secret: Secret) -> Result<bool, IntegrationOSError> {
+        Ok(false)
+    }
+
+    fn key_id(&self) -> String {
+        "memory".to_string()
+    }
+}
+
+#[tokio::test]
+async fn test_concurrent_refreshes_call_the_token_endpoint_once() {
+    let mut server = TestServer::new(None).await;
+    let (connection, _) = server.create_connection(Environment::Live).await;
+
+    let mut token_endpoint = Server::new_async().await;
+    let mock = token_endpoint
+        .mock("POST", "/oauth/token")
+        .expect(1)
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            json!({
+                "accessToken": "refreshed",
+                "expiresIn": 3600,
+                "instanceUrl": "https://acme.example.com",
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let db = Client::with_uri_str(&server.config.db_config.control_db_url)
+        .await
+        .unwrap()
+        .database(&server.config.db_config.control_db_name);
+
+    let mut definition: ConnectionOAuthDefinition = Faker.fake();
+    definition.is_full_template_enabled = false;
+    definition.configuration.refresh = ApiModelConfig {
+        base_url: token_endpoint.url() + "/",
+        path: "oauth/token".to_string(),
+        auth_method: AuthMethod::None,
+        mtls: false,
+        headers: None,
+        query_params: None,
+        content: Some(ContentType::Json),
+        schemas: SchemasInput {
+            headers: None,
+            query_params: None,
+            path_params: None,
+            body: None,
+        },
+        samples: SamplesInput {
+            headers: None,
+            query_params: None,
+            path_params: None,
+            body: None,
+        },
+        responses: vec![],
+        paths: None,
+    };
+    definition.compute.refresh = ComputeRequest {
+        computation: None,
+        response: Function(Compute {
+            entry: "compute".to_string(),
+            function: "function compute(response) { return response; }".to_string(),
+            language: Lang::JavaScript,
+        }),
+    };
+    let definitions: MongoStore<ConnectionOAuthDefinition> =
+        MongoStore::new(&db, &Store::ConnectionOAuthDefinitions)
+            .await
+            .unwrap();
+    definitions.create_one(&definition).await.unwrap();
+
+    let connections: MongoStore<Connection> =
+        MongoStore::new(&db, &Store::Connections).await.unwrap();
+    let oauth = OAuth::Enabled {
+        connection_oauth_definition_id: definition.id,
+        expires_in: Some(3600),
+        expires_at: Some(Utc::now().timestamp() - 1),
+    };
+    connections
+        .update_one(
+            &connection.id.to_string(),
+            doc! { "$set": { "oauth": bson::to_bson(&oauth).unwrap() } },
+        )
+        .await
+        .unwrap();
+    let connection = connections
+        .get_one_by_id(&connection.id.to_string())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let rejected = json!({
+        "OAUTH_CLIENT_ID": "client",
+        "OAUTH_CLIENT_SECRET": "secret",
+        "OAUTH_ACCESS_TOKEN": "expired",
+        "OAUTH_TOKEN_TYPE": null,
+        "OAUTH_REFRESH_TOKEN": "refresh",
+        "OAUTH_EXPIRES_IN": 3600,
+        "OAUTH_METADATA": { "tenant": "acme" },
+    });
+    let secrets_client = Arc::new(InMemorySecretsClient {
+        secret: Mutex::new(rejected.clone()),
+    });
+    let redis = redis::Client::open(server.config.cache_config.url.as_str())
+        .unwrap()
+        .get_connection_manager()
+        .await
+        .unwrap();
+    let oauth_refresh = OAuthRefresh::new(
+        definitions,
+        connections,
+        secrets_client,
+        ScriptService::new(&ScriptConfig::default()).unwrap(),
+        reqwest::Client::new(),
+        redis,
+    );
+
+    // Every request got a 401 with the same token, only the first one to take the
+    // lock refreshes it and the others pick up the stored secret
+    let secrets =
+        join_all((0..5).map(|_| oauth_refresh.refresh(&connection, Some(&rejected)))).await;
+
+    mock.assert_async().await;
+    for secret in secrets {
+        let secret = secret.unwrap().unwrap();
+        assert_eq!(secret["OAUTH_ACCESS_TOKEN"], "refreshed");
+        assert_eq!(secret["OAUTH_REFRESH_TOKEN"], "refresh");
+        assert_eq!(secret["OAUTH_METADATA"]["tenant"], "acme");
+        assert_eq!(
+            secret["OAUTH_METADATA"]["instanceUrl"],
+            "https://acme.example.com"
+        );
+    }
+}
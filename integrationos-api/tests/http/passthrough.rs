@@ -48,6 +48,7 @@ async fn test_passthrough_api() {
         auth_method: AuthMethod::BearerToken {
             value: secret_key.to_string(),
         },
+        mtls: false,
         http_method: http::Method::GET,
         headers: None,
         query_params: None,
@@ -7,7 +7,11 @@ use http::{Method, StatusCode};
 use integrationos_api::logic::{pipeline::CreatePipelineRequest, ReadResponse};
 use integrationos_domain::{
     connection_model_definition::PlatformInfo, destination::Action, environment::Environment,
-    Transaction,
+    ownership::Ownership, Event, Store, Transaction,
+};
+use mongodb::{
+    bson::{doc, Document},
+    Client, Database,
 };
 use serde_json::{json, Value};
 use std::time::Duration;
@@ -80,3 +84,87 @@ async fn test_event_core() {
     let txs: ReadResponse<Transaction> = serde_json::from_value(res.data).unwrap();
     assert_eq!(txs.rows.len(), 2);
 }
+
+async fn control_db(server: &TestServer) -> Database {
+    Client::with_uri_str(&server.config.db_config.control_db_url)
+        .await
+        .unwrap()
+        .database(&server.config.db_config.control_db_name)
+}
+
+async fn create_transaction(server: &TestServer) -> Transaction {
+    let mut event: Event = Faker.fake();
+    event.ownership = Ownership::new(server.live_access_key.data.id.clone());
+    let transaction = Transaction::completed(
+        &event,
+        "key".to_owned(),
+        json!({ "email": "jane@example.com" }).to_string(),
+        "{}".to_owned(),
+    );
+
+    control_db(server)
+        .await
+        .collection::<Transaction>(&Store::Transactions.to_string())
+        .insert_one(&transaction)
+        .await
+        .unwrap();
+
+    transaction
+}
+
+#[tokio::test]
+async fn test_reveal_needs_the_scope_to_allow_it() {
+    let server = TestServer::new(None).await;
+    let transaction = create_transaction(&server).await;
+
+    let res = server
+        .send_request::<Value, Value>(
+            &format!("v1/transactions/{}/reveal", transaction.id),
+            Method::GET,
+            Some(&server.live_key),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_reveal_transaction() {
+    let server = TestServer::new(None).await;
+    let transaction = create_transaction(&server).await;
+
+    control_db(&server)
+        .await
+        .collection::<Document>(&Store::EventAccess.to_string())
+        .update_one(
+            doc! { "accessKey": &server.live_key },
+            doc! { "$set": { "scope": { "revealRedacted": true } } },
+        )
+        .await
+        .unwrap();
+
+    let res = server
+        .send_request::<Value, Transaction>(
+            &format!("v1/transactions/{}/reveal", transaction.id),
+            Method::GET,
+            Some(&server.live_key),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::OK);
+    assert_eq!(res.data.id, transaction.id);
+    assert_eq!(res.data.input, transaction.input);
+
+    let res = server
+        .send_request::<Value, Value>(
+            &format!("v1/transactions/{}/reveal", Id::now(IdPrefix::Transaction)),
+            Method::GET,
+            Some(&server.live_key),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::NOT_FOUND);
+}
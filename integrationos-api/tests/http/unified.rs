@@ -560,6 +560,7 @@ async fn create_connection_model_definition(
         auth_method: AuthMethod::BearerToken {
             value: secret_key.to_string(),
         },
+        mtls: false,
         http_method: http::Method::GET,
         headers: None,
         query_params: None,
@@ -0,0 +1,90 @@
+use crate::context::TestServer;
+use http::{Method, StatusCode};
+use integrationos_domain::id::{prefix::IdPrefix, Id};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_webhook_subscriptions() {
+    let server = TestServer::new(None).await;
+
+    let res = server
+        .send_request::<Value, Value>(
+            "v1/webhooks",
+            Method::POST,
+            Some(&server.live_key),
+            Some(&json!({ "url": "not a url", "events": ["connection.lost"] })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::BAD_REQUEST);
+
+    // Only public https endpoints can be subscribed
+    for url in [
+        "http://example.com/hooks",
+        "https://127.0.0.1/hooks",
+        "https://169.254.169.254/latest/meta-data",
+    ] {
+        let res = server
+            .send_request::<Value, Value>(
+                "v1/webhooks",
+                Method::POST,
+                Some(&server.live_key),
+                Some(&json!({ "url": url, "events": ["connection.lost"] })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.code, StatusCode::BAD_REQUEST, "{url}");
+    }
+
+    let res = server
+        .send_request::<Value, Value>(
+            "v1/webhooks",
+            Method::POST,
+            Some(&server.live_key),
+            Some(&json!({
+                "url": "https://example.com/hooks",
+                "events": ["connection.created", "connection.lost"],
+            })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::OK);
+    assert!(res.data["secret"].as_str().unwrap().starts_with("whsec_"));
+    let id = res.data["_id"].as_str().unwrap().to_owned();
+
+    // The signing secret is only returned once
+    let res = server
+        .send_request::<Value, Value>("v1/webhooks", Method::GET, Some(&server.live_key), None)
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::OK);
+    assert_eq!(res.data["total"], 1);
+    assert_eq!(res.data["rows"][0]["_id"], json!(id));
+    assert_eq!(res.data["rows"][0].get("secret"), None);
+
+    let res = server
+        .send_request::<Value, Value>(
+            &format!("v1/webhooks/{id}/deliveries"),
+            Method::GET,
+            Some(&server.live_key),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::OK);
+    assert_eq!(res.data["total"], 0);
+
+    let res = server
+        .send_request::<Value, Value>(
+            &format!(
+                "v1/webhooks/{id}/deliveries/{}/redeliver",
+                Id::now(IdPrefix::WebhookDelivery)
+            ),
+            Method::POST,
+            Some(&server.live_key),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.code, StatusCode::NOT_FOUND);
+}
@@ -40,6 +40,7 @@ fn test_json_connection_model_definition() {
             base_url: "base-url".to_string(),
             path: "path".to_string(),
             auth_method: AuthMethod::OAuth,
+            mtls: false,
             headers: Some(HeaderMap::from_iter(vec![(
                 HeaderName::from_static("authorization"),
                 HeaderValue::from_static("Bearer secret"),
@@ -191,6 +192,7 @@ fn test_json_connection() {
             show_secret: false,
             allow_custom_events: false,
             oauth: false,
+            mtls: false,
         },
         throughput: Throughput {
             key: "throughput-key".to_string(),
@@ -198,6 +200,7 @@ fn test_json_connection() {
         },
         has_error: false,
         error: None,
+        health: None,
         ownership: Ownership {
             id: "owner-id".to_string().into(),
             client_id: "client-id".to_string(),
@@ -273,7 +276,10 @@ fn test_json_connection_definition() {
             show_secret: false,
             allow_custom_events: false,
             oauth: false,
+            mtls: false,
         },
+        webhook_handshakes: vec![],
+        redaction: None,
         hidden: true,
         test_connection: Some(Id::test(IdPrefix::Connection)),
         record_metadata: RecordMetadata::test(),
@@ -2,7 +2,10 @@ use crate::context::MockSecretsClient;
 use envconfig::Envconfig;
 use http::StatusCode;
 use integrationos_api::domain::config::ConnectionsConfig as ApiConfig;
-use integrationos_domain::{event_response::EventResponse, event_with_context::EventWithContext};
+use integrationos_domain::{
+    event_response::EventResponse, event_with_context::EventWithContext,
+    redaction::RedactionService, scripting::ScriptService,
+};
 use integrationos_event::{
     config::EventCoreConfig, dispatcher::Dispatcher, event_handler::EventHandler,
     mongo_context_store::MongoContextStore, mongo_control_data_store::MongoControlDataStore,
@@ -38,8 +41,10 @@ impl TestCore {
         config.db_config = api_config.db_config.clone();
         config.cache = gateway_config.redis.clone();
 
+        let scripts = ScriptService::new(&config.script_config).unwrap();
+
         let control_store = Arc::new(
-            MongoControlDataStore::new(&config, secrets_client)
+            MongoControlDataStore::new(&config, secrets_client, scripts.clone())
                 .await
                 .unwrap(),
         );
@@ -50,6 +55,8 @@ impl TestCore {
             context_store: context_store.clone(),
             event_store: control_store.clone(),
             control_data_store: control_store.clone(),
+            scripts,
+            redaction: RedactionService::default(),
         };
 
         let event_handler = EventHandler::new(config.cache.clone(), control_store, context_store)
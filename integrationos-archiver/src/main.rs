@@ -95,6 +95,9 @@ async fn main() -> Result<Unit> {
     }
 }
 
+/// Dumps the events as they are stored. Payloads encrypted at rest stay encrypted
+/// with the data key of their event access, the archiver has no restore command
+/// and does not decrypt them.
 async fn dump(
     config: &Arc<ArchiverConfig>,
     archives: &Arc<MongoStore<Event>>,
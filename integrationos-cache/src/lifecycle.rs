@@ -0,0 +1,75 @@
+use integrationos_domain::{
+    encrypted_data::PASSWORD_LENGTH, event_with_context::EventWithContext,
+    lifecycle::ConnectionLifecycle, prelude::MongoStore, Connection, Event, IntegrationOSError,
+    InternalError, RootContext,
+};
+use mongodb::Collection;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde_json::Value;
+use tracing::error;
+
+/// Publishes connection lifecycle events onto the event queue, the same way the
+/// gateway does with incoming events
+#[derive(Clone)]
+pub struct LifecyclePublisher {
+    event_store: MongoStore<Event>,
+    context_collection: Collection<RootContext>,
+    redis: ConnectionManager,
+    queue_name: String,
+    password: [u8; PASSWORD_LENGTH],
+}
+
+impl LifecyclePublisher {
+    pub fn new(
+        event_store: MongoStore<Event>,
+        context_collection: Collection<RootContext>,
+        redis: ConnectionManager,
+        queue_name: String,
+        password: &str,
+    ) -> Result<Self, IntegrationOSError> {
+        let password = password.as_bytes().try_into().map_err(|_| {
+            InternalError::configuration_error(
+                "EVENT_ACCESS_PASSWORD is not 32 bytes in length",
+                None,
+            )
+        })?;
+
+        Ok(Self {
+            event_store,
+            context_collection,
+            redis,
+            queue_name,
+            password,
+        })
+    }
+
+    pub async fn publish(
+        &self,
+        connection: &Connection,
+        lifecycle: ConnectionLifecycle,
+        data: Value,
+    ) -> Result<(), IntegrationOSError> {
+        let event = connection.lifecycle_event(lifecycle, data, &self.password)?;
+        self.event_store.create_one(&event).await?;
+
+        let context = RootContext::new(event.id);
+        self.context_collection
+            .insert_one(&context)
+            .await
+            .map_err(|e| {
+                error!("Could not save context of {lifecycle} event: {e}");
+                InternalError::io_err(e.to_string().as_str(), None)
+            })?;
+
+        let payload = serde_json::to_vec(&EventWithContext::new(event, context))
+            .map_err(|e| InternalError::serialize_error(&e.to_string(), None))?;
+        let mut redis = self.redis.clone();
+        redis
+            .lpush::<_, _, ()>(&self.queue_name, payload)
+            .await
+            .map_err(|e| {
+                error!("Could not publish {lifecycle} event to redis: {e}");
+                InternalError::io_err(e.to_string().as_str(), None)
+            })
+    }
+}
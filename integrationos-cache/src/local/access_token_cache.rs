@@ -0,0 +1,116 @@
+use integrationos_domain::{oauth_secret::AccessToken, Id, IntegrationOSError, Unit};
+use moka::{future::Cache, Expiry};
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Evicts tokens once they reach their own expiry instead of after a fixed ttl
+struct UntilExpiry;
+
+impl Expiry<Id, AccessToken> for UntilExpiry {
+    fn expire_after_create(&self, _: &Id, value: &AccessToken, _: Instant) -> Option<Duration> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        Some(Duration::from_secs(
+            value.expires_at.saturating_sub(now).max(0) as u64,
+        ))
+    }
+}
+
+/// Access tokens by the connection they were exchanged for, so that they can be
+/// dropped along with the connection
+#[derive(Clone)]
+pub struct AccessTokenCache {
+    inner: Arc<Cache<Id, AccessToken>>,
+}
+
+impl AccessTokenCache {
+    pub fn new(size: u64) -> Self {
+        Self {
+            inner: Arc::new(
+                Cache::builder()
+                    .max_capacity(size)
+                    .expire_after(UntilExpiry)
+                    .build(),
+            ),
+        }
+    }
+
+    /// Concurrent callers of a missing key wait for a single `fa` instead of
+    /// each requesting a token
+    pub async fn get_or_insert_with_fn<F, Fut>(
+        &self,
+        key: Id,
+        fa: F,
+    ) -> Result<AccessToken, IntegrationOSError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<AccessToken, IntegrationOSError>>,
+    {
+        self.inner
+            .try_get_with(key, fa())
+            .await
+            .map_err(|e| e.as_ref().clone())
+    }
+
+    pub async fn get(&self, key: &Id) -> Option<AccessToken> {
+        self.inner.get(key).await
+    }
+
+    pub async fn set(&self, key: Id, value: AccessToken) -> Result<Unit, IntegrationOSError> {
+        self.inner.insert(key, value).await;
+        Ok(())
+    }
+
+    pub async fn remove(&self, key: &Id) -> Result<Unit, IntegrationOSError> {
+        self.inner.invalidate(key).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use integrationos_domain::id::prefix::IdPrefix;
+
+    fn token(expires_in: i64) -> AccessToken {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        AccessToken {
+            access_token: "token".to_string(),
+            token_type: None,
+            expires_at: now + expires_in,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_access_token_expiry() {
+        let cache = AccessTokenCache::new(10);
+        let valid_id = Id::now(IdPrefix::Connection);
+        let expired_id = Id::now(IdPrefix::Connection);
+        let valid = token(3600);
+
+        cache.set(valid_id, valid.clone()).await.unwrap();
+        cache.set(expired_id, token(-10)).await.unwrap();
+
+        assert_eq!(cache.get(&valid_id).await, Some(valid));
+        assert_eq!(cache.get(&expired_id).await, None);
+
+        let refreshed = token(60);
+        let fetched = cache
+            .get_or_insert_with_fn(expired_id, || async { Ok(refreshed.clone()) })
+            .await
+            .unwrap();
+        assert_eq!(fetched, refreshed);
+        assert_eq!(cache.get(&expired_id).await, Some(refreshed));
+
+        cache.remove(&valid_id).await.unwrap();
+        assert_eq!(cache.get(&valid_id).await, None);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::LocalCacheExt;
 use http::HeaderValue;
-use integrationos_domain::{Connection, IntegrationOSError, MongoStore, Unit};
+use integrationos_domain::{Connection, Id, IntegrationOSError, InternalError, MongoStore, Unit};
 use moka::future::Cache;
 use mongodb::bson::Document;
 use std::fmt::Debug;
@@ -19,6 +19,7 @@ impl<K: Clone + Send + Sync + Eq + Hash + Debug + 'static> ConnectionCacheForKey
                 Cache::builder()
                     .max_capacity(size)
                     .time_to_live(Duration::from_secs(ttl))
+                    .support_invalidation_closures()
                     .build(),
             ),
         }
@@ -46,6 +47,14 @@ impl<K: Clone + Send + Sync + Eq + Hash + Debug + 'static> ConnectionCacheForKey
     pub async fn remove(&self, key: K) -> Result<Unit, IntegrationOSError> {
         self.inner.remove(&key).await
     }
+
+    /// Drops every entry of the connection, whatever key it was cached under
+    pub fn remove_connection(&self, id: Id) -> Result<Unit, IntegrationOSError> {
+        self.inner
+            .invalidate_entries_if(move |_, connection| connection.id == id)
+            .map(|_| ())
+            .map_err(|e| InternalError::io_err(&e.to_string(), None))
+    }
 }
 
 pub type ConnectionCacheArcStrKey = ConnectionCacheForKey<Arc<str>>;
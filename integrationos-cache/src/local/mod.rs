@@ -1,3 +1,4 @@
+pub mod access_token_cache;
 pub mod connection_cache;
 pub mod connection_definition_cache;
 pub mod connection_model_definition_cache;
@@ -1,6 +1,6 @@
 use crate::LocalCacheExt;
 use futures::Future;
-use integrationos_domain::{Connection, IntegrationOSError, InternalError, MongoStore, Unit};
+use integrationos_domain::{Connection, Id, IntegrationOSError, InternalError, MongoStore, Unit};
 use moka::future::Cache;
 use mongodb::bson::Document;
 use serde_json::Value;
@@ -18,6 +18,7 @@ impl SecretCache {
                 Cache::builder()
                     .max_capacity(size)
                     .time_to_live(Duration::from_secs(ttl))
+                    .support_invalidation_closures()
                     .build(),
             ),
         }
@@ -71,4 +72,12 @@ impl SecretCache {
     pub async fn remove(&self, key: &Connection) -> Result<Unit, IntegrationOSError> {
         self.inner.remove(key).await
     }
+
+    /// Drops every entry of the connection, whatever key it was cached under
+    pub fn remove_connection(&self, id: Id) -> Result<Unit, IntegrationOSError> {
+        self.inner
+            .invalidate_entries_if(move |connection, _| connection.id == id)
+            .map(|_| ())
+            .map_err(|e| InternalError::io_err(&e.to_string(), None))
+    }
 }
@@ -1,6 +1,8 @@
+use futures::StreamExt;
 use integrationos_domain::{cache::CacheConfig, IntegrationOSError, InternalError};
 use redis::aio::{ConnectionManager, ConnectionManagerConfig};
 use std::time::Duration;
+use tokio::task::JoinHandle;
 
 pub struct RedisCache {
     pub inner: ConnectionManager,
@@ -28,3 +30,44 @@ impl RedisCache {
         Ok(Self { inner })
     }
 }
+
+/// Calls `on_message` with the payload of every message published on `channel`.
+///
+/// Messages published while the subscription is being re-established are lost,
+/// so subscribers should also reconcile their state periodically.
+pub fn subscribe<F>(configuration: &CacheConfig, channel: String, on_message: F) -> JoinHandle<()>
+where
+    F: Fn(String) + Send + Sync + 'static,
+{
+    let url = configuration.url.clone();
+    let retry_delay = Duration::from_secs(configuration.max_delay);
+
+    tokio::spawn(async move {
+        loop {
+            let pubsub = match redis::Client::open(url.clone()) {
+                Ok(client) => client.get_async_pubsub().await,
+                Err(e) => Err(e),
+            };
+
+            match pubsub {
+                Ok(mut pubsub) => match pubsub.subscribe(&channel).await {
+                    Ok(()) => {
+                        tracing::info!("Subscribed to {channel}");
+                        let mut messages = pubsub.on_message();
+                        while let Some(message) = messages.next().await {
+                            match message.get_payload::<String>() {
+                                Ok(payload) => on_message(payload),
+                                Err(e) => tracing::warn!("Invalid message on {channel}: {e}"),
+                            }
+                        }
+                        tracing::warn!("Subscription to {channel} ended");
+                    }
+                    Err(e) => tracing::warn!("Could not subscribe to {channel}: {e}"),
+                },
+                Err(e) => tracing::warn!("Could not connect to redis to subscribe: {e}"),
+            }
+
+            tokio::time::sleep(retry_delay).await;
+        }
+    })
+}
@@ -1,4 +1,8 @@
-use crate::{secrets::SecretsConfig, IntegrationOSError, InternalError, SecretVersion};
+use crate::{
+    prelude::secret::Secret,
+    secrets::{SecretServiceProvider, SecretsConfig},
+    IntegrationOSError, InternalError, SecretVersion,
+};
 use async_trait::async_trait;
 use base64::{prelude::BASE64_STANDARD, Engine};
 use chacha20poly1305::aead::generic_array::typenum::Unsigned;
@@ -9,8 +13,19 @@ use google_cloud_kms::{
     client::{Client, ClientConfig},
     grpc::kms::v1::DecryptRequest,
 };
-use secrecy::ExposeSecret;
+use reqwest::Method;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    sync::Arc,
+    time::Duration,
+};
 use tracing::debug;
+use uuid::Uuid;
 
 #[async_trait]
 pub trait CryptoExt {
@@ -21,6 +36,16 @@ pub trait CryptoExt {
         data: String,
         version: Option<SecretVersion>,
     ) -> Result<String, IntegrationOSError>;
+
+    /// Identifies the key new data is encrypted with, so data encrypted with an
+    /// older key can be found and re-encrypted
+    fn key_id(&self) -> String;
+
+    /// Releases what the provider keeps for `data` outside of it, once `data` is
+    /// replaced or deleted
+    async fn discard(&self, _data: String) -> Result<(), IntegrationOSError> {
+        Ok(())
+    }
 }
 
 type NonceSize = <ChaCha20Poly1305 as AeadCore>::NonceSize;
@@ -28,6 +53,8 @@ type NonceSize = <ChaCha20Poly1305 as AeadCore>::NonceSize;
 #[derive(Debug, Clone)]
 pub struct IOSCrypto {
     key: Vec<u8>,
+    /// Key being rotated out, only used to decrypt
+    previous_key: Option<Vec<u8>>,
 }
 
 #[async_trait]
@@ -43,11 +70,51 @@ impl CryptoExt for IOSCrypto {
     ) -> Result<String, IntegrationOSError> {
         self.decrypt(data).await
     }
+
+    fn key_id(&self) -> String {
+        let digest = Sha256::digest(&self.key);
+        hex::encode(&digest[..8])
+    }
 }
 
 impl IOSCrypto {
     pub fn new(config: SecretsConfig) -> Result<Self, IntegrationOSError> {
-        let len = config.ios_crypto_secret.expose_secret().as_bytes().len();
+        let key = Self::parse_key(config.ios_crypto_secret.expose_secret())?;
+        let previous_key = config
+            .ios_crypto_previous_secret
+            .as_ref()
+            .map(|secret| Self::parse_key(secret.expose_secret()))
+            .transpose()?;
+
+        Ok(Self { key, previous_key })
+    }
+
+    /// Generates a hex encoded key for [`IOSCrypto::with_data_key`]
+    pub fn generate_data_key() -> String {
+        hex::encode(ChaCha20Poly1305::generate_key(&mut OsRng))
+    }
+
+    /// Creates a client that encrypts with a data key instead of the configured secret
+    pub fn with_data_key(data_key: &str) -> Result<Self, IntegrationOSError> {
+        let key = hex::decode(data_key).map_err(|_| {
+            InternalError::deserialize_error("The provided value is not a valid data key", None)
+        })?;
+
+        if key.len() != 32 {
+            return Err(InternalError::invalid_argument(
+                "The provided value is not a valid data key",
+                None,
+            ));
+        }
+
+        Ok(Self {
+            key,
+            previous_key: None,
+        })
+    }
+
+    fn parse_key(secret: &str) -> Result<Vec<u8>, IntegrationOSError> {
+        let len = secret.as_bytes().len();
 
         if len != 32 {
             return Err(InternalError::invalid_argument(
@@ -56,9 +123,7 @@ impl IOSCrypto {
             ));
         }
 
-        let key: [u8; 32] = config
-            .ios_crypto_secret
-            .expose_secret()
+        let key: [u8; 32] = secret
             .as_bytes()
             .iter()
             .take(32)
@@ -72,14 +137,22 @@ impl IOSCrypto {
                 )
             })?;
 
-        Ok(Self { key: key.to_vec() })
+        Ok(key.to_vec())
     }
 
     async fn decrypt(&self, encrypted_secret: String) -> Result<String, IntegrationOSError> {
         let obsf = hex::decode(encrypted_secret).map_err(|_| {}).map_err(|_| {
             InternalError::deserialize_error("The provided value is not a valid UTF-8 string", None)
         })?;
-        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(&self.key));
+
+        match (Self::decrypt_with(&self.key, &obsf), &self.previous_key) {
+            (Err(_), Some(previous_key)) => Self::decrypt_with(previous_key, &obsf),
+            (result, _) => result,
+        }
+    }
+
+    fn decrypt_with(key: &[u8], obsf: &[u8]) -> Result<String, IntegrationOSError> {
+        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(key));
         let (nonce, ciphertext) = obsf.split_at(NonceSize::to_usize());
         let nonce = GenericArray::from_slice(nonce);
         let plaintext = cipher.decrypt(nonce, ciphertext).map_err(|_| {
@@ -93,7 +166,11 @@ impl IOSCrypto {
     }
 
     async fn encrypt(&self, secret: String) -> Result<String, IntegrationOSError> {
-        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(&self.key));
+        Self::encrypt_with(&self.key, &secret)
+    }
+
+    fn encrypt_with(key: &[u8], secret: &str) -> Result<String, IntegrationOSError> {
+        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(key));
         let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
         let mut obsf = cipher.encrypt(&nonce, secret.as_bytes()).map_err(|_| {
             InternalError::serialize_error("The provided value is not a valid UTF-8 string", None)
@@ -124,6 +201,10 @@ impl CryptoExt for GoogleCryptoKms {
     ) -> Result<String, IntegrationOSError> {
         self.decrypt(data, version).await
     }
+
+    fn key_id(&self) -> String {
+        self.fallback.key_id()
+    }
 }
 
 impl GoogleCryptoKms {
@@ -200,6 +281,535 @@ impl GoogleCryptoKms {
     }
 }
 
+/// Authenticated requests to the HTTP API of Vault, shared by its engines
+#[derive(Debug, Clone)]
+struct VaultClient {
+    client: reqwest::Client,
+    config: SecretsConfig,
+    token: SecretString,
+}
+
+#[derive(Deserialize)]
+struct VaultResponse<T> {
+    data: T,
+}
+
+impl VaultClient {
+    fn new(secrets_config: &SecretsConfig) -> Result<Self, IntegrationOSError> {
+        let token = secrets_config.vault_token.clone().ok_or_else(|| {
+            InternalError::configuration_error(
+                "VAULT_TOKEN is required for the vault providers",
+                None,
+            )
+        })?;
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(secrets_config.vault_timeout_secs))
+            .build()
+            .map_err(|e| {
+                InternalError::configuration_error(
+                    &format!("Failed to create the Vault client: {e}"),
+                    None,
+                )
+            })?;
+
+        Ok(Self {
+            client,
+            config: secrets_config.clone(),
+            token,
+        })
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<T, IntegrationOSError> {
+        self.send(method, path, body)
+            .await?
+            .json::<VaultResponse<T>>()
+            .await
+            .map(|response| response.data)
+            .map_err(|e| {
+                debug!("Error deserializing Vault response: {e}");
+                InternalError::deserialize_error("Invalid response from Vault", None)
+            })
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<reqwest::Response, IntegrationOSError> {
+        let url = format!(
+            "{address}/v1/{path}",
+            address = self.config.vault_address.trim_end_matches('/'),
+        );
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("X-Vault-Token", self.token.expose_secret());
+        if let Some(body) = &body {
+            request = request.json(body);
+        }
+        if let Some(namespace) = &self.config.vault_namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+
+        let response = request.send().await.map_err(|e| {
+            InternalError::connection_error(&format!("Failed to reach Vault: {e}"), None)
+        })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(InternalError::connection_error(
+                &format!("Vault failed to handle {path} with status {status}"),
+                None,
+            ));
+        }
+
+        Ok(response)
+    }
+}
+
+/// Encrypts secrets with a key of the Transit engine of Vault
+#[derive(Debug, Clone)]
+pub struct VaultCrypto {
+    vault: VaultClient,
+}
+
+#[derive(Deserialize)]
+struct VaultCiphertext {
+    ciphertext: String,
+}
+
+#[derive(Deserialize)]
+struct VaultPlaintext {
+    plaintext: String,
+}
+
+#[async_trait]
+impl CryptoExt for VaultCrypto {
+    async fn encrypt(&self, secret: String) -> Result<String, IntegrationOSError> {
+        let response: VaultCiphertext = self
+            .transit(
+                "encrypt",
+                json!({ "plaintext": BASE64_STANDARD.encode(secret.as_bytes()) }),
+            )
+            .await?;
+
+        Ok(response.ciphertext)
+    }
+
+    async fn decrypt(
+        &self,
+        data: String,
+        _: Option<SecretVersion>,
+    ) -> Result<String, IntegrationOSError> {
+        let response: VaultPlaintext = self
+            .transit("decrypt", json!({ "ciphertext": data }))
+            .await?;
+
+        let plaintext = BASE64_STANDARD
+            .decode(response.plaintext.as_bytes())
+            .map_err(|e| {
+                debug!("Error decoding secret from Vault: {e}");
+                InternalError::deserialize_error(
+                    "The provided value is not a valid UTF-8 string",
+                    None,
+                )
+            })?;
+
+        String::from_utf8(plaintext).map_err(|_| {
+            InternalError::deserialize_error("The provided value is not a valid UTF-8 string", None)
+        })
+    }
+
+    fn key_id(&self) -> String {
+        format!(
+            "{VAULT_KEY_PREFIX}{}/{}",
+            self.vault.config.vault_transit_mount, self.vault.config.vault_transit_key
+        )
+    }
+}
+
+impl VaultCrypto {
+    pub fn new(secrets_config: &SecretsConfig) -> Result<Self, IntegrationOSError> {
+        Ok(Self {
+            vault: VaultClient::new(secrets_config)?,
+        })
+    }
+
+    async fn transit<T: DeserializeOwned>(
+        &self,
+        operation: &str,
+        body: Value,
+    ) -> Result<T, IntegrationOSError> {
+        let path = format!(
+            "{mount}/{operation}/{key}",
+            mount = self.vault.config.vault_transit_mount,
+            key = self.vault.config.vault_transit_key,
+        );
+
+        self.vault.request(Method::POST, &path, Some(body)).await
+    }
+}
+
+/// Keeps secrets in the KV engine of Vault. Only the path of the secret in Vault is
+/// stored, in place of the encrypted secret.
+#[derive(Debug, Clone)]
+pub struct VaultKvCrypto {
+    vault: VaultClient,
+}
+
+#[derive(Deserialize)]
+struct VaultKvData {
+    data: VaultKvValue,
+}
+
+#[derive(Deserialize)]
+struct VaultKvValue {
+    value: String,
+}
+
+#[async_trait]
+impl CryptoExt for VaultKvCrypto {
+    async fn encrypt(&self, secret: String) -> Result<String, IntegrationOSError> {
+        let path = Uuid::new_v4().to_string();
+        let _: Value = self
+            .vault
+            .request(
+                Method::POST,
+                &self.data_path(&path),
+                Some(json!({ "data": { "value": secret } })),
+            )
+            .await?;
+
+        Ok(path)
+    }
+
+    async fn decrypt(
+        &self,
+        data: String,
+        _: Option<SecretVersion>,
+    ) -> Result<String, IntegrationOSError> {
+        let response: VaultKvData = self
+            .vault
+            .request(Method::GET, &self.data_path(&data), None)
+            .await?;
+
+        Ok(response.data.value)
+    }
+
+    fn key_id(&self) -> String {
+        format!("{VAULT_KV_KEY_PREFIX}{}", self.vault.config.vault_kv_mount)
+    }
+
+    /// Deletes every version of the secret at the path
+    async fn discard(&self, data: String) -> Result<(), IntegrationOSError> {
+        let path = format!("{}/metadata/{data}", self.vault.config.vault_kv_mount);
+        self.vault.send(Method::DELETE, &path, None).await?;
+
+        Ok(())
+    }
+}
+
+impl VaultKvCrypto {
+    pub fn new(secrets_config: &SecretsConfig) -> Result<Self, IntegrationOSError> {
+        Ok(Self {
+            vault: VaultClient::new(secrets_config)?,
+        })
+    }
+
+    fn data_path(&self, path: &str) -> String {
+        format!("{}/data/{path}", self.vault.config.vault_kv_mount)
+    }
+}
+
+/// Encrypts secrets with a key read from a local file, which is generated when it
+/// does not exist yet
+#[derive(Debug, Clone)]
+pub struct LocalCrypto {
+    crypto: IOSCrypto,
+}
+
+#[async_trait]
+impl CryptoExt for LocalCrypto {
+    async fn encrypt(&self, secret: String) -> Result<String, IntegrationOSError> {
+        self.crypto.encrypt(secret).await
+    }
+
+    async fn decrypt(
+        &self,
+        data: String,
+        _: Option<SecretVersion>,
+    ) -> Result<String, IntegrationOSError> {
+        self.crypto.decrypt(data).await
+    }
+
+    fn key_id(&self) -> String {
+        format!("{LOCAL_KEY_PREFIX}{}", CryptoExt::key_id(&self.crypto))
+    }
+}
+
+impl LocalCrypto {
+    pub fn new(secrets_config: &SecretsConfig) -> Result<Self, IntegrationOSError> {
+        let path = &secrets_config.local_secrets_key_file;
+        if let Some(crypto) = Self::open(secrets_config)? {
+            return Ok(crypto);
+        }
+
+        let data_key = IOSCrypto::generate_data_key();
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(path)
+            .and_then(|mut file| file.write_all(data_key.as_bytes()))
+            .map_err(|e| {
+                InternalError::io_err(&format!("Could not write the key to {path}: {e}"), None)
+            })?;
+
+        Ok(Self {
+            crypto: IOSCrypto::with_data_key(&data_key)?,
+        })
+    }
+
+    /// Reads the key without generating one, so secrets of the local provider can
+    /// still be read after moving to another one
+    pub fn open(secrets_config: &SecretsConfig) -> Result<Option<Self>, IntegrationOSError> {
+        let path = &secrets_config.local_secrets_key_file;
+        match fs::read_to_string(path) {
+            Ok(data_key) => Ok(Some(Self {
+                crypto: IOSCrypto::with_data_key(data_key.trim())?,
+            })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(InternalError::io_err(
+                &format!("Could not read the key from {path}: {e}"),
+                None,
+            )),
+        }
+    }
+}
+
+/// Encrypts every secret with its own data key and stores the data key wrapped
+/// by the master key next to it, as `{wrapped data key}.{encrypted secret}`.
+///
+/// The master key only ever encrypts data keys, so it can live in an external KMS
+/// without every secret being sent to it.
+#[derive(Clone)]
+pub struct EnvelopeCrypto {
+    master: Arc<dyn CryptoExt + Sync + Send>,
+}
+
+const ENVELOPE_SEPARATOR: char = '.';
+const ENVELOPE_KEY_PREFIX: &str = "envelope:";
+const VAULT_KEY_PREFIX: &str = "vault:";
+const VAULT_KV_KEY_PREFIX: &str = "vault-kv:";
+const LOCAL_KEY_PREFIX: &str = "local:";
+
+#[async_trait]
+impl CryptoExt for EnvelopeCrypto {
+    async fn encrypt(&self, secret: String) -> Result<String, IntegrationOSError> {
+        let data_key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        let encrypted_secret = IOSCrypto::encrypt_with(&data_key, &secret)?;
+        let wrapped_key = self.master.encrypt(hex::encode(data_key)).await?;
+
+        Ok(format!(
+            "{wrapped_key}{ENVELOPE_SEPARATOR}{encrypted_secret}"
+        ))
+    }
+
+    async fn decrypt(
+        &self,
+        data: String,
+        _: Option<SecretVersion>,
+    ) -> Result<String, IntegrationOSError> {
+        // The encrypted secret is hex, so the last separator is always ours
+        let (wrapped_key, encrypted_secret) =
+            data.rsplit_once(ENVELOPE_SEPARATOR).ok_or_else(|| {
+                InternalError::deserialize_error("The provided value is not an envelope", None)
+            })?;
+
+        let data_key = self.master.decrypt(wrapped_key.to_owned(), None).await?;
+        let data_key = hex::decode(data_key).map_err(|_| {
+            InternalError::deserialize_error("The provided value is not a valid data key", None)
+        })?;
+        let encrypted_secret = hex::decode(encrypted_secret).map_err(|_| {
+            InternalError::deserialize_error("The provided value is not a valid UTF-8 string", None)
+        })?;
+
+        IOSCrypto::decrypt_with(&data_key, &encrypted_secret)
+    }
+
+    fn key_id(&self) -> String {
+        format!("{ENVELOPE_KEY_PREFIX}{}", self.master.key_id())
+    }
+
+    async fn discard(&self, data: String) -> Result<(), IntegrationOSError> {
+        match data.rsplit_once(ENVELOPE_SEPARATOR) {
+            Some((wrapped_key, _)) => self.master.discard(wrapped_key.to_owned()).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl EnvelopeCrypto {
+    pub fn new(master: Arc<dyn CryptoExt + Sync + Send>) -> Self {
+        Self { master }
+    }
+}
+
+/// Encrypts new secrets with the configured provider, and decrypts every secret with
+/// the provider recorded in its key id. Secrets keep working while the re-encryption
+/// job moves them from one provider to another.
+#[derive(Clone)]
+pub struct SecretCrypto {
+    current: Arc<dyn CryptoExt + Sync + Send>,
+    ios: Arc<dyn CryptoExt + Sync + Send>,
+    google: Option<Arc<dyn CryptoExt + Sync + Send>>,
+    vault: Option<Arc<dyn CryptoExt + Sync + Send>>,
+    vault_kv: Option<Arc<dyn CryptoExt + Sync + Send>>,
+    local: Option<Arc<dyn CryptoExt + Sync + Send>>,
+}
+
+impl SecretCrypto {
+    pub async fn new(config: &SecretsConfig) -> Result<Self, IntegrationOSError> {
+        let uses = |provider: SecretServiceProvider| {
+            config.provider == provider
+                || (config.provider == SecretServiceProvider::Envelope
+                    && config.envelope_master_provider == provider)
+        };
+
+        let ios: Arc<dyn CryptoExt + Sync + Send> = Arc::new(IOSCrypto::new(config.clone())?);
+        let google: Option<Arc<dyn CryptoExt + Sync + Send>> =
+            if uses(SecretServiceProvider::GoogleKms) {
+                Some(Arc::new(GoogleCryptoKms::new(config).await?))
+            } else {
+                None
+            };
+        // Providers not in use are still set up when configured, to read the secrets
+        // not re-encrypted yet
+        let vault: Option<Arc<dyn CryptoExt + Sync + Send>> = match VaultCrypto::new(config) {
+            Ok(vault) => Some(Arc::new(vault)),
+            Err(e) if uses(SecretServiceProvider::Vault) => return Err(e),
+            Err(_) => None,
+        };
+        let vault_kv: Option<Arc<dyn CryptoExt + Sync + Send>> = match VaultKvCrypto::new(config) {
+            Ok(vault_kv) => Some(Arc::new(vault_kv)),
+            Err(e) if uses(SecretServiceProvider::VaultKv) => return Err(e),
+            Err(_) => None,
+        };
+        let local: Option<Arc<dyn CryptoExt + Sync + Send>> = if uses(SecretServiceProvider::Local)
+        {
+            Some(Arc::new(LocalCrypto::new(config)?))
+        } else {
+            LocalCrypto::open(config)?
+                .map(|local| Arc::new(local) as Arc<dyn CryptoExt + Sync + Send>)
+        };
+
+        let master = |provider: SecretServiceProvider| match provider {
+            SecretServiceProvider::IosKms => Ok(ios.clone()),
+            SecretServiceProvider::GoogleKms => google.clone().ok_or(()),
+            SecretServiceProvider::Vault => vault.clone().ok_or(()),
+            SecretServiceProvider::VaultKv => vault_kv.clone().ok_or(()),
+            SecretServiceProvider::Local => local.clone().ok_or(()),
+            SecretServiceProvider::Envelope => Err(()),
+        };
+        let current = match config.provider {
+            SecretServiceProvider::Envelope => {
+                master(config.envelope_master_provider).map(|master| {
+                    Arc::new(EnvelopeCrypto::new(master)) as Arc<dyn CryptoExt + Sync + Send>
+                })
+            }
+            provider => master(provider),
+        }
+        .map_err(|_| {
+            InternalError::configuration_error("ENVELOPE_MASTER_PROVIDER cannot be envelope", None)
+        })?;
+
+        Ok(Self {
+            current,
+            ios,
+            google,
+            vault,
+            vault_kv,
+            local,
+        })
+    }
+
+    pub async fn encrypt(&self, secret: String) -> Result<String, IntegrationOSError> {
+        self.current.encrypt(secret).await
+    }
+
+    pub async fn decrypt(&self, secret: &Secret) -> Result<String, IntegrationOSError> {
+        let crypto = self.reader(secret.key_id().as_deref(), secret.version())?;
+        let encrypted_secret = secret.encrypted_secret().expose_secret().to_owned();
+
+        crypto.decrypt(encrypted_secret, secret.version()).await
+    }
+
+    /// Releases what the provider of the secret keeps for it outside of Mongo
+    pub async fn discard(&self, secret: &Secret) -> Result<(), IntegrationOSError> {
+        let crypto = self.reader(secret.key_id().as_deref(), secret.version())?;
+        let encrypted_secret = secret.encrypted_secret().expose_secret().to_owned();
+
+        crypto.discard(encrypted_secret).await
+    }
+
+    /// Id of the key new secrets are encrypted with
+    pub fn key_id(&self) -> String {
+        self.current.key_id()
+    }
+
+    fn reader(
+        &self,
+        key_id: Option<&str>,
+        version: Option<SecretVersion>,
+    ) -> Result<Arc<dyn CryptoExt + Sync + Send>, IntegrationOSError> {
+        let not_configured = |provider: SecretServiceProvider| {
+            InternalError::configuration_error(
+                &format!(
+                    "The secret was encrypted by the {} provider, which is not configured",
+                    provider.as_ref()
+                ),
+                None,
+            )
+        };
+
+        if let Some(master_key_id) = key_id.and_then(|id| id.strip_prefix(ENVELOPE_KEY_PREFIX)) {
+            let master = self.reader(Some(master_key_id), Some(SecretVersion::V2))?;
+            return Ok(Arc::new(EnvelopeCrypto::new(master)));
+        }
+
+        match key_id {
+            Some(key_id) if key_id.starts_with(VAULT_KV_KEY_PREFIX) => self
+                .vault_kv
+                .clone()
+                .ok_or_else(|| not_configured(SecretServiceProvider::VaultKv)),
+            Some(key_id) if key_id.starts_with(VAULT_KEY_PREFIX) => self
+                .vault
+                .clone()
+                .ok_or_else(|| not_configured(SecretServiceProvider::Vault)),
+            Some(key_id) if key_id.starts_with(LOCAL_KEY_PREFIX) => self
+                .local
+                .clone()
+                .ok_or_else(|| not_configured(SecretServiceProvider::Local)),
+            // Secrets of the IOS key have its digest as key id, or no key id when
+            // written before keys were recorded. Only Google KMS wrote v1 secrets.
+            _ if version == Some(SecretVersion::V2) => Ok(self.ios.clone()),
+            _ => self
+                .google
+                .clone()
+                .ok_or_else(|| not_configured(SecretServiceProvider::GoogleKms)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -246,6 +856,32 @@ mod tests {
         assert!(decrypted.is_err());
     }
 
+    #[tokio::test]
+    async fn should_decrypt_data_encrypted_with_the_previous_key() {
+        let config = SecretsConfig::default().with_provider(SecretServiceProvider::IosKms);
+        let old_crypto = IOSCrypto::new(config).expect("Failed to create IOSCrypto client");
+
+        let data = "lorem_ipsum-dolor_sit-amet";
+        let encrypted = old_crypto
+            .encrypt(data.to_owned())
+            .await
+            .expect("Failed to encrypt data");
+
+        let config = SecretsConfig::new()
+            .with_secret("lorem_ipsum-dolor_sit_amet-neque".into())
+            .with_previous_secret("xTtUQejH8eSNmWP5rlnHLkOWkHeflivG".into())
+            .with_provider(SecretServiceProvider::IosKms);
+        let crypto = IOSCrypto::new(config).expect("Failed to create IOSCrypto client");
+
+        let decrypted = crypto
+            .decrypt(encrypted)
+            .await
+            .expect("Failed to decrypt data");
+
+        assert_eq!(data, decrypted);
+        assert_ne!(old_crypto.key_id(), crypto.key_id());
+    }
+
     #[tokio::test]
     async fn should_fail_to_decrypt_if_the_data_is_tampered() {
         let config = SecretsConfig::default().with_provider(SecretServiceProvider::IosKms);
@@ -265,4 +901,192 @@ mod tests {
 
         assert!(decrypted.is_err());
     }
+
+    #[tokio::test]
+    async fn should_encrypt_every_secret_with_its_own_data_key() {
+        let config = SecretsConfig::default().with_provider(SecretServiceProvider::Envelope);
+        let crypto = EnvelopeCrypto::new(Arc::new(
+            IOSCrypto::new(config).expect("Failed to create IOSCrypto client"),
+        ));
+
+        let data = "lorem_ipsum-dolor_sit-amet";
+        let first = crypto
+            .encrypt(data.to_owned())
+            .await
+            .expect("Failed to encrypt data");
+        let second = crypto
+            .encrypt(data.to_owned())
+            .await
+            .expect("Failed to encrypt data");
+
+        let (first_key, _) = first.rsplit_once(ENVELOPE_SEPARATOR).unwrap();
+        let (second_key, _) = second.rsplit_once(ENVELOPE_SEPARATOR).unwrap();
+        assert_ne!(first_key, second_key);
+
+        let decrypted = crypto
+            .decrypt(first, None)
+            .await
+            .expect("Failed to decrypt data");
+        assert_eq!(data, decrypted);
+    }
+
+    #[tokio::test]
+    async fn should_encrypt_and_decrypt_data_with_vault_transit() {
+        let mut server = mockito::Server::new_async().await;
+        let data = "lorem_ipsum-dolor_sit-amet";
+        let plaintext = BASE64_STANDARD.encode(data);
+
+        let encrypt = server
+            .mock("POST", "/v1/transit/encrypt/integrationos")
+            .match_header("X-Vault-Token", "root")
+            .match_body(mockito::Matcher::Json(json!({ "plaintext": plaintext })))
+            .with_body(json!({ "data": { "ciphertext": "vault:v1:abcd" } }).to_string())
+            .create_async()
+            .await;
+        let decrypt = server
+            .mock("POST", "/v1/transit/decrypt/integrationos")
+            .match_header("X-Vault-Token", "root")
+            .match_body(mockito::Matcher::Json(
+                json!({ "ciphertext": "vault:v1:abcd" }),
+            ))
+            .with_body(json!({ "data": { "plaintext": plaintext } }).to_string())
+            .create_async()
+            .await;
+
+        let config = SecretsConfig::new()
+            .with_vault(server.url(), "root".into())
+            .with_provider(SecretServiceProvider::Vault);
+        let crypto = VaultCrypto::new(&config).expect("Failed to create VaultCrypto client");
+
+        let encrypted = crypto
+            .encrypt(data.to_owned())
+            .await
+            .expect("Failed to encrypt data");
+        assert_eq!(encrypted, "vault:v1:abcd");
+
+        let decrypted = crypto
+            .decrypt(encrypted, None)
+            .await
+            .expect("Failed to decrypt data");
+        assert_eq!(data, decrypted);
+
+        encrypt.assert_async().await;
+        decrypt.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn should_fail_when_vault_rejects_the_request() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/transit/encrypt/integrationos")
+            .with_status(403)
+            .create_async()
+            .await;
+
+        let config = SecretsConfig::new()
+            .with_vault(server.url(), "invalid".into())
+            .with_provider(SecretServiceProvider::Vault);
+        let crypto = VaultCrypto::new(&config).expect("Failed to create VaultCrypto client");
+
+        assert!(crypto.encrypt("data".to_owned()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_store_and_read_secrets_with_vault_kv() {
+        let mut server = mockito::Server::new_async().await;
+        let data = "lorem_ipsum-dolor_sit-amet";
+
+        let write = server
+            .mock(
+                "POST",
+                mockito::Matcher::Regex(r"^/v1/secret/data/[0-9a-f-]+$".to_owned()),
+            )
+            .match_header("X-Vault-Token", "root")
+            .match_body(mockito::Matcher::Json(json!({ "data": { "value": data } })))
+            .with_body(json!({ "data": { "version": 1 } }).to_string())
+            .create_async()
+            .await;
+
+        let config = SecretsConfig::new()
+            .with_vault(server.url(), "root".into())
+            .with_provider(SecretServiceProvider::VaultKv);
+        let crypto = VaultKvCrypto::new(&config).expect("Failed to create VaultKvCrypto client");
+
+        let path = crypto
+            .encrypt(data.to_owned())
+            .await
+            .expect("Failed to encrypt data");
+        write.assert_async().await;
+
+        let read = server
+            .mock("GET", format!("/v1/secret/data/{path}").as_str())
+            .match_header("X-Vault-Token", "root")
+            .with_body(json!({ "data": { "data": { "value": data } } }).to_string())
+            .create_async()
+            .await;
+
+        let decrypted = crypto
+            .decrypt(path, None)
+            .await
+            .expect("Failed to decrypt data");
+        assert_eq!(data, decrypted);
+        read.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn should_reuse_the_generated_local_key() {
+        let path = std::env::temp_dir().join(format!("{}.key", Uuid::new_v4()));
+        let config = SecretsConfig::new()
+            .with_local_key_file(path.to_string_lossy().into_owned())
+            .with_provider(SecretServiceProvider::Local);
+
+        let crypto = LocalCrypto::new(&config).expect("Failed to create LocalCrypto client");
+        let encrypted = crypto
+            .encrypt("lorem_ipsum".to_owned())
+            .await
+            .expect("Failed to encrypt data");
+
+        let reopened = LocalCrypto::new(&config).expect("Failed to create LocalCrypto client");
+        let decrypted = reopened
+            .decrypt(encrypted, None)
+            .await
+            .expect("Failed to decrypt data");
+        fs::remove_file(path).expect("Failed to remove the key");
+
+        assert_eq!(decrypted, "lorem_ipsum");
+        assert_eq!(crypto.key_id(), reopened.key_id());
+    }
+
+    #[tokio::test]
+    async fn should_decrypt_secrets_of_the_previous_provider() {
+        let path = std::env::temp_dir().join(format!("{}.key", Uuid::new_v4()));
+        let config = SecretsConfig::new()
+            .with_local_key_file(path.to_string_lossy().into_owned())
+            .with_provider(SecretServiceProvider::IosKms);
+        let ios = SecretCrypto::new(&config)
+            .await
+            .expect("Failed to create SecretCrypto");
+
+        let data = "lorem_ipsum-dolor_sit-amet";
+        let secret = Secret::new(
+            ios.encrypt(data.to_owned())
+                .await
+                .expect("Failed to encrypt data"),
+            Some(SecretVersion::V2),
+            "buildable_id".to_owned(),
+            None,
+        )
+        .with_key_id(ios.key_id());
+
+        let envelope = SecretCrypto::new(&config.with_provider(SecretServiceProvider::Envelope))
+            .await
+            .expect("Failed to create SecretCrypto");
+        assert_ne!(ios.key_id(), envelope.key_id());
+
+        let decrypted = envelope
+            .decrypt(&secret)
+            .await
+            .expect("Failed to decrypt data");
+        assert_eq!(data, decrypted);
+    }
 }
@@ -4,6 +4,7 @@ mod hash;
 mod oauth;
 mod pipeline;
 mod secret;
+mod sigv4;
 mod store;
 mod string;
 mod template;
@@ -15,6 +16,7 @@ pub use hash::*;
 pub use oauth::*;
 pub use pipeline::*;
 pub use secret::*;
+pub use sigv4::*;
 pub use store::*;
 pub use string::*;
 pub use template::*;
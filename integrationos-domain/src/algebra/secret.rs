@@ -1,12 +1,14 @@
-use super::{CryptoExt, GoogleCryptoKms, IOSCrypto, MongoStore};
+use super::{MongoStore, SecretCrypto};
 use crate::{
     prelude::secret::Secret, secrets::SecretsConfig, IntegrationOSError, InternalError,
     SecretVersion,
 };
 use async_trait::async_trait;
-use bson::doc;
+use bson::{doc, Document};
+use chrono::Utc;
 use secrecy::ExposeSecret;
 use serde_json::Value;
+use tracing::warn;
 
 #[async_trait]
 pub trait SecretExt {
@@ -17,37 +19,60 @@ pub trait SecretExt {
         secret: &Value,
         buildable_id: &str,
     ) -> Result<Secret, IntegrationOSError>;
+
+    /// Replaces the value of a secret, encrypting it with the current key
+    async fn update(
+        &self,
+        id: &str,
+        secret: &Value,
+        buildable_id: &str,
+    ) -> Result<Secret, IntegrationOSError>;
+
+    async fn delete(&self, id: &str, buildable_id: &str) -> Result<(), IntegrationOSError>;
+
+    /// Encrypts a stored secret again with the current key. Returns `false` when
+    /// the secret already is encrypted with it, or was changed since it was read.
+    async fn reencrypt(&self, secret: Secret) -> Result<bool, IntegrationOSError>;
+
+    /// Id of the key new secrets are encrypted with
+    fn key_id(&self) -> String;
 }
 
-#[derive(Debug, Clone)]
-pub struct IOSKms {
+/// Secrets stored in Mongo, encrypted by the provider of `SecretsConfig`
+#[derive(Clone)]
+pub struct SecretsClient {
     storage: MongoStore<Secret>,
-    crypto: IOSCrypto,
+    crypto: SecretCrypto,
 }
 
-impl IOSKms {
+impl SecretsClient {
+    /// Releases a replaced or deleted value of a secret. No record points at it
+    /// anymore, so a failure only leaves it behind in the provider.
+    async fn discard(&self, secret: &Secret) {
+        if let Err(e) = self.crypto.discard(secret).await {
+            warn!("Could not discard a value of secret {}: {e}", secret.id());
+        }
+    }
+
     pub async fn new(
         secrets_config: &SecretsConfig,
         storage: MongoStore<Secret>,
     ) -> Result<Self, IntegrationOSError> {
-        let crypto = IOSCrypto::new(secrets_config.clone())?;
-        Ok(Self { crypto, storage })
+        let crypto = SecretCrypto::new(secrets_config).await?;
+        Ok(Self { storage, crypto })
     }
 }
 
 #[async_trait]
-impl SecretExt for IOSKms {
+impl SecretExt for SecretsClient {
     async fn get(&self, id: &str, buildable_id: &str) -> Result<Secret, IntegrationOSError> {
         let secret = self
             .storage
-            .get_one(doc! { "_id": id, "buildableId": buildable_id })
+            .get_one(secret_filter(id, buildable_id))
             .await?
             .ok_or_else(|| InternalError::key_not_found("Secret", None))?;
 
-        let encrypted_secret = secret.encrypted_secret().expose_secret().to_owned();
-        let version = secret.version();
-
-        let decrypted_secret = self.crypto.decrypt(encrypted_secret, version).await?;
+        let decrypted_secret = self.crypto.decrypt(&secret).await?;
 
         Ok(Secret::new(
             decrypted_secret,
@@ -65,7 +90,6 @@ impl SecretExt for IOSKms {
         let string = serde_json::to_string(&secret).map_err(|_| {
             InternalError::serialize_error("The provided value is not a valid UTF-8 string", None)
         })?;
-
         let encrypted_secret = self.crypto.encrypt(string).await?;
 
         let secret = Secret::new(
@@ -73,7 +97,8 @@ impl SecretExt for IOSKms {
             Some(SecretVersion::V2),
             buildable_id.to_owned(),
             None,
-        );
+        )
+        .with_key_id(self.crypto.key_id());
 
         self.storage
             .create_one(&secret)
@@ -82,68 +107,104 @@ impl SecretExt for IOSKms {
 
         Ok(secret)
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct GoogleKms {
-    storage: MongoStore<Secret>,
-    crypto: GoogleCryptoKms,
-}
-
-impl GoogleKms {
-    pub async fn new(
-        secrets_config: &SecretsConfig,
-        storage: MongoStore<Secret>,
-    ) -> Result<Self, IntegrationOSError> {
-        let crypto = GoogleCryptoKms::new(secrets_config).await?;
-        Ok(Self { crypto, storage })
-    }
-}
-
-#[async_trait]
-impl SecretExt for GoogleKms {
-    async fn get(&self, id: &str, buildable_id: &str) -> Result<Secret, IntegrationOSError> {
-        let secret = self
-            .storage
-            .get_one(doc! { "_id": id, "buildableId": buildable_id })
-            .await?
-            .ok_or_else(|| InternalError::key_not_found("Secret", None))?;
-
-        let encrypted_secret = secret.encrypted_secret().expose_secret().to_owned();
-        let version = secret.version();
-
-        let decrypted_secret = self.crypto.decrypt(encrypted_secret, version).await?;
-
-        Ok(Secret::new(
-            decrypted_secret,
-            secret.version(),
-            secret.buildable_id(),
-            Some(secret.created_at()),
-        ))
-    }
-
-    async fn create(
+    async fn update(
         &self,
+        id: &str,
         secret: &Value,
         buildable_id: &str,
     ) -> Result<Secret, IntegrationOSError> {
+        let stored = self
+            .storage
+            .get_one(secret_filter(id, buildable_id))
+            .await?
+            .ok_or_else(|| InternalError::key_not_found("Secret", None))?;
+
         let string = serde_json::to_string(&secret).map_err(|_| {
             InternalError::serialize_error("The provided value is not a valid UTF-8 string", None)
         })?;
         let encrypted_secret = self.crypto.encrypt(string).await?;
 
-        let secret = Secret::new(
+        let secret = stored.clone().with_encrypted_secret(
             encrypted_secret,
-            Some(SecretVersion::V2),
-            buildable_id.to_owned(),
-            None,
+            SecretVersion::V2,
+            self.crypto.key_id(),
         );
-
-        self.storage
-            .create_one(&secret)
-            .await
-            .map_err(|e| InternalError::io_err(e.as_ref(), None))?;
+        if !replace_secret(&self.storage, secret_filter(id, buildable_id), &secret).await? {
+            return Err(InternalError::key_not_found("Secret", None));
+        }
+        self.discard(&stored).await;
 
         Ok(secret)
     }
+
+    /// Secrets are only flagged as deleted, so a connection still pointing at one
+    /// fails to find it instead of reading another record. What the provider keeps
+    /// for the secret outside of Mongo is released.
+    async fn delete(&self, id: &str, buildable_id: &str) -> Result<(), IntegrationOSError> {
+        let stored = self
+            .storage
+            .collection
+            .find_one_and_update(
+                secret_filter(id, buildable_id),
+                doc! { "$set": { "deleted": true, "deletedAt": Utc::now().timestamp_millis() } },
+            )
+            .await?
+            .ok_or_else(|| InternalError::key_not_found("Secret", None))?;
+        self.discard(&stored).await;
+
+        Ok(())
+    }
+
+    async fn reencrypt(&self, secret: Secret) -> Result<bool, IntegrationOSError> {
+        let key_id = self.crypto.key_id();
+        if secret.version() == Some(SecretVersion::V2) && secret.key_id().as_ref() == Some(&key_id)
+        {
+            return Ok(false);
+        }
+
+        let decrypted_secret = self.crypto.decrypt(&secret).await?;
+        let encrypted_secret = self.crypto.encrypt(decrypted_secret).await?;
+
+        // Only replace the value that was decrypted, a concurrent update must not be
+        // overwritten with the previous value
+        let filter = doc! {
+            "_id": secret.id(),
+            "encryptedSecret": secret.encrypted_secret().expose_secret(),
+            "keyId": secret.key_id(),
+        };
+        let reencrypted =
+            secret
+                .clone()
+                .with_encrypted_secret(encrypted_secret, SecretVersion::V2, key_id);
+        let replaced = replace_secret(&self.storage, filter, &reencrypted).await?;
+        // Release whichever value no record points at anymore
+        self.discard(if replaced { &secret } else { &reencrypted })
+            .await;
+
+        Ok(replaced)
+    }
+
+    fn key_id(&self) -> String {
+        self.crypto.key_id()
+    }
+}
+
+fn secret_filter(id: &str, buildable_id: &str) -> Document {
+    doc! { "_id": id, "buildableId": buildable_id, "deleted": { "$ne": true } }
+}
+
+/// Returns `false` when no secret matches `filter`, e.g. when it changed meanwhile
+async fn replace_secret(
+    storage: &MongoStore<Secret>,
+    filter: Document,
+    secret: &Secret,
+) -> Result<bool, IntegrationOSError> {
+    let result = storage
+        .collection
+        .replace_one(filter, secret)
+        .await
+        .map_err(|e| InternalError::io_err(&e.to_string(), None))?;
+
+    Ok(result.matched_count > 0)
 }
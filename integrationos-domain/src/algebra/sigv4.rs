@@ -0,0 +1,409 @@
+use crate::{IntegrationOSError, InternalError};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use http::{HeaderMap, HeaderName, HeaderValue, Method};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const TERMINATOR: &str = "aws4_request";
+const S3: &str = "s3";
+const X_AMZ_DATE: &str = "x-amz-date";
+const X_AMZ_SECURITY_TOKEN: &str = "x-amz-security-token";
+const X_AMZ_CONTENT_SHA256: &str = "x-amz-content-sha256";
+/// Headers clients and proxies are free to change in flight, they are never signed
+const UNSIGNED_HEADERS: [&str; 6] = [
+    "authorization",
+    "connection",
+    "content-length",
+    "expect",
+    "transfer-encoding",
+    "user-agent",
+];
+/// Everything but the unreserved characters of RFC 3986 is encoded
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AwsCredentials {
+    #[serde(rename = "AWS_ACCESS_KEY_ID")]
+    pub access_key_id: String,
+    #[serde(rename = "AWS_SECRET_ACCESS_KEY")]
+    pub secret_access_key: String,
+    #[serde(
+        rename = "AWS_SESSION_TOKEN",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub session_token: Option<String>,
+}
+
+/// Signs requests to AWS services with Signature Version 4
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigV4Signer<'a> {
+    pub credentials: &'a AwsCredentials,
+    pub service: &'a str,
+    pub region: &'a str,
+}
+
+impl SigV4Signer<'_> {
+    /// Adds the date, session token and authorization headers to `headers`,
+    /// signing them along with the method, url and body of the request
+    pub fn sign(
+        &self,
+        method: &Method,
+        url: &Url,
+        headers: &mut HeaderMap,
+        body: &[u8],
+        now: DateTime<Utc>,
+    ) -> Result<(), IntegrationOSError> {
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(body));
+
+        insert(headers, HeaderName::from_static(X_AMZ_DATE), &timestamp)?;
+        if let Some(session_token) = &self.credentials.session_token {
+            insert(
+                headers,
+                HeaderName::from_static(X_AMZ_SECURITY_TOKEN),
+                session_token,
+            )?;
+        }
+        if self.service == S3 {
+            insert(
+                headers,
+                HeaderName::from_static(X_AMZ_CONTENT_SHA256),
+                &payload_hash,
+            )?;
+        }
+
+        let (canonical_headers, signed_headers) = canonical_headers(url, headers)?;
+        let canonical_request = format!(
+            "{method}\n{}\n{}\n{canonical_headers}\n{signed_headers}\n{payload_hash}",
+            self.canonical_uri(url),
+            canonical_query(url),
+        );
+
+        let scope = format!("{date}/{}/{}/{TERMINATOR}", self.region, self.service);
+        let string_to_sign = format!(
+            "{ALGORITHM}\n{timestamp}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let signing_key = [date.as_str(), self.region, self.service, TERMINATOR]
+            .iter()
+            .try_fold(
+                format!("AWS4{}", self.credentials.secret_access_key).into_bytes(),
+                |key, part| hmac_sha256(&key, part.as_bytes()),
+            )?;
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes())?);
+
+        insert(
+            headers,
+            http::header::AUTHORIZATION,
+            &format!(
+                "{ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                self.credentials.access_key_id
+            ),
+        )
+    }
+
+    /// S3 signs the path encoded once, every other service signs the path as
+    /// it was sent encoded once more
+    fn canonical_uri(&self, url: &Url) -> String {
+        let path = url.path();
+        if path.is_empty() {
+            return "/".to_string();
+        }
+
+        path.split('/')
+            .map(|segment| {
+                if self.service == S3 {
+                    let decoded = percent_decode_str(segment).decode_utf8_lossy();
+                    utf8_percent_encode(&decoded, UNRESERVED).to_string()
+                } else {
+                    utf8_percent_encode(segment, UNRESERVED).to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Rewrites the query of `url` in its canonical form.
+///
+/// Form encoding, as used by `RequestBuilder::query`, sends spaces as `+` while
+/// the signature covers them as `%20`, so the query has to be canonicalized
+/// before the url is signed and sent.
+pub fn canonicalize_query(url: &mut Url) {
+    if url.query().is_none() {
+        return;
+    }
+    let query = canonical_query(url);
+    url.set_query((!query.is_empty()).then_some(query.as_str()));
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut params = url
+        .query_pairs()
+        .map(|(key, value)| {
+            (
+                utf8_percent_encode(&key, UNRESERVED).to_string(),
+                utf8_percent_encode(&value, UNRESERVED).to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    params.sort();
+
+    params
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Returns the canonical headers block and the names of the signed headers
+fn canonical_headers(
+    url: &Url,
+    headers: &HeaderMap,
+) -> Result<(String, String), IntegrationOSError> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| InternalError::invalid_argument("Url has no host", Some("sigv4")))?;
+    let host = match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    };
+
+    let mut canonical = BTreeMap::from([("host".to_string(), vec![host])]);
+    for (name, value) in headers {
+        let name = name.as_str();
+        if name == "host" || UNSIGNED_HEADERS.contains(&name) {
+            continue;
+        }
+        let value = value.to_str().map_err(|e| {
+            InternalError::invalid_argument(
+                &format!("Header {name} can not be signed: {e}"),
+                Some("sigv4"),
+            )
+        })?;
+        canonical
+            .entry(name.to_string())
+            .or_default()
+            .push(value.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+
+    let signed_headers = canonical
+        .keys()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers = canonical
+        .iter()
+        .map(|(name, values)| format!("{name}:{}\n", values.join(",")))
+        .collect();
+
+    Ok((canonical_headers, signed_headers))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, IntegrationOSError> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .map_err(|e| InternalError::invalid_argument(&e.to_string(), Some("sigv4")))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn insert(
+    headers: &mut HeaderMap,
+    name: HeaderName,
+    value: &str,
+) -> Result<(), IntegrationOSError> {
+    let value = HeaderValue::from_str(value).map_err(|e| {
+        InternalError::invalid_argument(
+            &format!("Invalid value for header {name}: {e}"),
+            Some("sigv4"),
+        )
+    })?;
+    headers.insert(name, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const SESSION_TOKEN: &str = "AQoDYXdzEPT//////////wEXAMPLEtc764bNrC9SAPBSM22wDOk4x4HIZ8j4FZTwdQWLWsKWHGBuFqwAeMicRXmxfpSPfIeoIYRqTflfKD8YUuwthAx7mSEI/qkPpKPi/kMcGdQrmGdeehM4IC1NtBmUpp2wUE8phUZampKsburEDy0KPkyQDYwT7WZ0wq5VSXDvp75YU9HFvlRd8Tx6q6fE8YQcHNVXAkiY9q6d+xo0rKwT38xVqr7ZD0u0iPPkUL64lIZbqBAz+scqKmlzm8FDrypNC9Yjc8fPOLn9FX9KSYvKTr4rvx3iSIlTJabIQwj2ICCR/oLxBA==";
+
+    fn credentials(session_token: Option<&str>) -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: session_token.map(str::to_string),
+        }
+    }
+
+    /// Signs a request of the AWS Signature Version 4 test suite and returns its
+    /// authorization header
+    fn sign(
+        method: Method,
+        url: &str,
+        headers: &[(&'static str, &str)],
+        body: &[u8],
+        session_token: Option<&str>,
+    ) -> String {
+        let credentials = credentials(session_token);
+        let signer = SigV4Signer {
+            credentials: &credentials,
+            service: "service",
+            region: "us-east-1",
+        };
+
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+
+        signer
+            .sign(
+                &method,
+                &Url::parse(url).unwrap(),
+                &mut header_map,
+                body,
+                Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap(),
+            )
+            .unwrap();
+
+        assert_eq!(header_map[X_AMZ_DATE], "20150830T123600Z");
+        header_map[http::header::AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_get_vanilla() {
+        assert_eq!(
+            sign(
+                Method::GET,
+                "https://example.amazonaws.com/",
+                &[],
+                b"",
+                None
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn test_get_vanilla_query_order_key_case() {
+        assert_eq!(
+            sign(
+                Method::GET,
+                "https://example.amazonaws.com/?Param2=value2&Param1=value1",
+                &[],
+                b"",
+                None
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+        );
+    }
+
+    #[test]
+    fn test_get_vanilla_query_unreserved() {
+        let unreserved = "-._~0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+        assert_eq!(
+            sign(
+                Method::GET,
+                &format!("https://example.amazonaws.com/?{unreserved}={unreserved}"),
+                &[],
+                b"",
+                None
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=9c3e54bfcdf0b19771a7f523ee5669cdf59bc7cc0884027167c21bb143a40197"
+        );
+    }
+
+    #[test]
+    fn test_post_x_www_form_urlencoded() {
+        assert_eq!(
+            sign(
+                Method::POST,
+                "https://example.amazonaws.com/",
+                &[("content-type", "application/x-www-form-urlencoded")],
+                b"Param1=value1",
+                None
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=ff11897932ad3f4e8b18135d722051e5ac45fc38421b1da7b9d196a0fe09473a"
+        );
+    }
+
+    #[test]
+    fn test_post_sts_header_before() {
+        assert_eq!(
+            sign(
+                Method::POST,
+                "https://example.amazonaws.com/",
+                &[],
+                b"",
+                Some(SESSION_TOKEN)
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date;x-amz-security-token, \
+             Signature=85d96828115b5dc0cfc3bd16ad9e210dd772bbebba041836c64533a82be05ead"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_query() {
+        let mut url = Url::parse("https://example.amazonaws.com/").unwrap();
+        url.query_pairs_mut()
+            .append_pair("b", "two words")
+            .append_pair("a", "1+1=2");
+        assert_eq!(url.query(), Some("b=two+words&a=1%2B1%3D2"));
+
+        canonicalize_query(&mut url);
+        assert_eq!(url.query(), Some("a=1%2B1%3D2&b=two%20words"));
+        assert_eq!(canonical_query(&url), url.query().unwrap());
+
+        let mut url = Url::parse("https://example.amazonaws.com/?").unwrap();
+        canonicalize_query(&mut url);
+        assert_eq!(url.as_str(), "https://example.amazonaws.com/");
+    }
+
+    #[test]
+    fn test_canonical_uri() {
+        let credentials = credentials(None);
+        let url = Url::parse("https://example.amazonaws.com/example space/ሴ").unwrap();
+
+        let signer = SigV4Signer {
+            credentials: &credentials,
+            service: "execute-api",
+            region: "us-east-1",
+        };
+        assert_eq!(
+            signer.canonical_uri(&url),
+            "/example%2520space/%25E1%2588%25B4"
+        );
+
+        let signer = SigV4Signer {
+            service: S3,
+            ..signer
+        };
+        assert_eq!(signer.canonical_uri(&url), "/example%20space/%E1%88%B4");
+    }
+}
@@ -11,6 +11,27 @@ pub struct CacheConfig {
     pub event_throughput_key: String,
     #[envconfig(from = "REDIS_API_THROUGHPUT_KEY", default = "api_throughput")]
     pub api_throughput_key: String,
+    /// Hash of the groups of rotated event access records to the keys they still
+    /// accept, also used as the channel keys revoked right away are published on
+    #[envconfig(
+        from = "REDIS_REVOKED_ACCESS_KEYS_KEY",
+        default = "revoked_access_keys"
+    )]
+    pub revoked_access_keys_key: String,
+    /// Channel the keys issued by a rotation are published on, so every instance
+    /// accepts them right away
+    #[envconfig(
+        from = "REDIS_ROTATED_ACCESS_KEYS_KEY",
+        default = "rotated_access_keys"
+    )]
+    pub rotated_access_keys_key: String,
+    /// Channel the ids of reauthorized connections are published on, so every
+    /// instance drops them from its caches
+    #[envconfig(
+        from = "REDIS_REAUTHORIZED_CONNECTIONS_KEY",
+        default = "reauthorized_connections"
+    )]
+    pub reauthorized_connections_key: String,
     #[envconfig(from = "REDIS_POOL_SIZE", default = "10")]
     pub pool_size: usize,
     #[envconfig(env = "CACHE_MAX_DELAY_SECONDS", default = "30")]
@@ -28,6 +49,9 @@ impl Default for CacheConfig {
             queue_name: "events".to_owned(),
             event_throughput_key: "event_throughput".to_owned(),
             api_throughput_key: "api_throughput".to_owned(),
+            revoked_access_keys_key: "revoked_access_keys".to_owned(),
+            rotated_access_keys_key: "rotated_access_keys".to_owned(),
+            reauthorized_connections_key: "reauthorized_connections".to_owned(),
             pool_size: 10,
             max_delay: 30,
             response_timeout: 30,
@@ -46,6 +70,21 @@ impl Display for CacheConfig {
             self.event_throughput_key
         )?;
         writeln!(f, "REDIS_API_THROUGHPUT_KEY: {}", self.api_throughput_key)?;
+        writeln!(
+            f,
+            "REDIS_REVOKED_ACCESS_KEYS_KEY: {}",
+            self.revoked_access_keys_key
+        )?;
+        writeln!(
+            f,
+            "REDIS_ROTATED_ACCESS_KEYS_KEY: {}",
+            self.rotated_access_keys_key
+        )?;
+        writeln!(
+            f,
+            "REDIS_REAUTHORIZED_CONNECTIONS_KEY: {}",
+            self.reauthorized_connections_key
+        )?;
         writeln!(f, "REDIS_POOL_SIZE: {}", self.pool_size)?;
         writeln!(f, "CACHE_WAIT_TIMEOUT_SECONDS: {}", self.max_delay)?;
         writeln!(f, "CACHE_CREATE_TIMEOUT_SECONDS: {}", self.response_timeout)?;
@@ -2,4 +2,5 @@ pub mod cache;
 pub mod database;
 pub mod environment;
 pub mod pipeline;
+pub mod script;
 pub mod secrets;
@@ -16,7 +16,9 @@ impl Default for PipelineConfig {
                 retry: RetryPolicy {
                     maximum_attempts: 3,
                     initial_interval: "1 second".to_owned(),
+                    backoff_coefficient: None,
                 },
+                delay: None,
             },
             start_to_close_timeout: "10 seconds".to_owned(),
         }
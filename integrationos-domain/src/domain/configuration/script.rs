@@ -0,0 +1,38 @@
+use envconfig::Envconfig;
+use std::fmt::{Display, Formatter, Result};
+
+#[derive(Envconfig, Debug, Clone, PartialEq, Eq)]
+pub struct ScriptConfig {
+    #[envconfig(from = "SCRIPT_TIMEOUT_MILLIS", default = "5000")]
+    pub timeout_millis: u64,
+    #[envconfig(from = "SCRIPT_MAX_HEAP_SIZE_MB", default = "256")]
+    pub max_heap_size_mb: usize,
+    #[envconfig(from = "SCRIPT_WORKERS", default = "4")]
+    pub workers: usize,
+    #[envconfig(from = "SCRIPT_QUEUE_SIZE", default = "1024")]
+    pub queue_size: usize,
+    #[envconfig(from = "SCRIPT_CACHE_SIZE", default = "512")]
+    pub cache_size: usize,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self {
+            timeout_millis: 5000,
+            max_heap_size_mb: 256,
+            workers: 4,
+            queue_size: 1024,
+            cache_size: 512,
+        }
+    }
+}
+
+impl Display for ScriptConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "SCRIPT_TIMEOUT_MILLIS: {}", self.timeout_millis)?;
+        writeln!(f, "SCRIPT_MAX_HEAP_SIZE_MB: {}", self.max_heap_size_mb)?;
+        writeln!(f, "SCRIPT_WORKERS: {}", self.workers)?;
+        writeln!(f, "SCRIPT_QUEUE_SIZE: {}", self.queue_size)?;
+        writeln!(f, "SCRIPT_CACHE_SIZE: {}", self.cache_size)
+    }
+}
@@ -8,7 +8,15 @@ use strum::{AsRefStr, EnumString};
 pub enum SecretServiceProvider {
     GoogleKms,
     IosKms,
-    // TODO: Implement LocalStorage
+    /// HashiCorp Vault, or a compatible server, through its Transit engine
+    Vault,
+    /// HashiCorp Vault, or a compatible server, storing the secrets in its KV engine
+    VaultKv,
+    /// A data key per secret, wrapped by the master key of `ENVELOPE_MASTER_PROVIDER`
+    Envelope,
+    /// A key kept in `LOCAL_SECRETS_KEY_FILE`, generated on first use. Meant for
+    /// development, where no KMS is available
+    Local,
 }
 
 #[derive(Debug, Clone, Envconfig)]
@@ -31,6 +39,29 @@ pub struct SecretsConfig {
         default = "xTtUQejH8eSNmWP5rlnHLkOWkHeflivG"
     )]
     pub ios_crypto_secret: SecretString,
+    /// Secret being rotated out, secrets encrypted with it can still be read
+    /// until they are re-encrypted
+    #[envconfig(from = "IOS_CRYPTO_PREVIOUS_SECRET")]
+    pub ios_crypto_previous_secret: Option<SecretString>,
+    #[envconfig(from = "VAULT_ADDRESS", default = "http://127.0.0.1:8200")]
+    pub vault_address: String,
+    #[envconfig(from = "VAULT_TOKEN")]
+    pub vault_token: Option<SecretString>,
+    #[envconfig(from = "VAULT_NAMESPACE")]
+    pub vault_namespace: Option<String>,
+    #[envconfig(from = "VAULT_TIMEOUT_SECS", default = "30")]
+    pub vault_timeout_secs: u64,
+    #[envconfig(from = "VAULT_TRANSIT_MOUNT", default = "transit")]
+    pub vault_transit_mount: String,
+    #[envconfig(from = "VAULT_TRANSIT_KEY", default = "integrationos")]
+    pub vault_transit_key: String,
+    #[envconfig(from = "VAULT_KV_MOUNT", default = "secret")]
+    pub vault_kv_mount: String,
+    /// Provider of the key wrapping the data keys of the envelope provider
+    #[envconfig(from = "ENVELOPE_MASTER_PROVIDER", default = "ios-kms")]
+    pub envelope_master_provider: SecretServiceProvider,
+    #[envconfig(from = "LOCAL_SECRETS_KEY_FILE", default = ".secrets.key")]
+    pub local_secrets_key_file: String,
 }
 
 impl SecretsConfig {
@@ -45,6 +76,25 @@ impl SecretsConfig {
         self
     }
 
+    #[cfg(test)]
+    pub fn with_previous_secret(mut self, secret: String) -> Self {
+        self.ios_crypto_previous_secret = Some(SecretString::new(secret));
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_vault(mut self, address: String, token: String) -> Self {
+        self.vault_address = address;
+        self.vault_token = Some(SecretString::new(token));
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_local_key_file(mut self, path: String) -> Self {
+        self.local_secrets_key_file = path;
+        self
+    }
+
     #[cfg(test)]
     pub fn with_provider(mut self, provider: SecretServiceProvider) -> Self {
         self.provider = provider;
@@ -61,6 +111,16 @@ impl Default for SecretsConfig {
             google_kms_key_ring_id: "secrets-service-local".to_owned(),
             google_kms_key_id: "secrets-service-local".to_owned(),
             ios_crypto_secret: SecretString::new("xTtUQejH8eSNmWP5rlnHLkOWkHeflivG".to_owned()),
+            ios_crypto_previous_secret: None,
+            vault_address: "http://127.0.0.1:8200".to_owned(),
+            vault_token: None,
+            vault_namespace: None,
+            vault_timeout_secs: 30,
+            vault_transit_mount: "transit".to_owned(),
+            vault_transit_key: "integrationos".to_owned(),
+            vault_kv_mount: "secret".to_owned(),
+            envelope_master_provider: SecretServiceProvider::IosKms,
+            local_secrets_key_file: ".secrets.key".to_owned(),
         }
     }
 }
@@ -69,13 +129,58 @@ impl Display for SecretsConfig {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         writeln!(f, "SECRETS_SERVICE_PROVIDER: {}", self.provider.as_ref())?;
         match self.provider {
+            SecretServiceProvider::Envelope => {
+                writeln!(
+                    f,
+                    "ENVELOPE_MASTER_PROVIDER: {}",
+                    self.envelope_master_provider.as_ref()
+                )?;
+                self.fmt_provider(f, self.envelope_master_provider)
+            }
+            provider => self.fmt_provider(f, provider),
+        }
+    }
+}
+
+impl SecretsConfig {
+    fn fmt_provider(&self, f: &mut Formatter<'_>, provider: SecretServiceProvider) -> Result {
+        match provider {
             SecretServiceProvider::GoogleKms => {
                 writeln!(f, "GOOGLE_KMS_PROJECT_ID: ****")?;
                 writeln!(f, "GOOGLE_KMS_LOCATION_ID: ****")?;
                 writeln!(f, "GOOGLE_KMS_KEY_RING_ID: ****")?;
                 writeln!(f, "GOOGLE_KMS_KEY_ID: ****")
             }
-            SecretServiceProvider::IosKms => writeln!(f, "IOS_CRYPTO_SECRET: ****"),
+            SecretServiceProvider::Vault => {
+                writeln!(f, "VAULT_ADDRESS: {}", self.vault_address)?;
+                writeln!(f, "VAULT_TOKEN: ****")?;
+                writeln!(f, "VAULT_NAMESPACE: {:?}", self.vault_namespace)?;
+                writeln!(f, "VAULT_TIMEOUT_SECS: {}", self.vault_timeout_secs)?;
+                writeln!(f, "VAULT_TRANSIT_MOUNT: {}", self.vault_transit_mount)?;
+                writeln!(f, "VAULT_TRANSIT_KEY: {}", self.vault_transit_key)
+            }
+            SecretServiceProvider::VaultKv => {
+                writeln!(f, "VAULT_ADDRESS: {}", self.vault_address)?;
+                writeln!(f, "VAULT_TOKEN: ****")?;
+                writeln!(f, "VAULT_NAMESPACE: {:?}", self.vault_namespace)?;
+                writeln!(f, "VAULT_TIMEOUT_SECS: {}", self.vault_timeout_secs)?;
+                writeln!(f, "VAULT_KV_MOUNT: {}", self.vault_kv_mount)
+            }
+            SecretServiceProvider::Local => {
+                writeln!(f, "LOCAL_SECRETS_KEY_FILE: {}", self.local_secrets_key_file)
+            }
+            SecretServiceProvider::IosKms => {
+                writeln!(f, "IOS_CRYPTO_SECRET: ****")?;
+                writeln!(
+                    f,
+                    "IOS_CRYPTO_PREVIOUS_SECRET: {}",
+                    self.ios_crypto_previous_secret
+                        .as_ref()
+                        .map_or("None", |_| "****")
+                )
+            }
+            // Envelopes cannot wrap envelopes, the master provider is checked on start
+            SecretServiceProvider::Envelope => Ok(()),
         }
     }
 }
@@ -99,6 +204,9 @@ mod tests {
         assert_eq!(config.google_kms_location_id, "global");
         assert_eq!(config.google_kms_key_ring_id, "secrets-service-local");
         assert_eq!(config.google_kms_key_id, "secrets-service-local");
+        assert_eq!(config.vault_address, "http://127.0.0.1:8200");
+        assert_eq!(config.vault_transit_mount, "transit");
+        assert_eq!(config.vault_transit_key, "integrationos");
     }
 
     #[tokio::test]
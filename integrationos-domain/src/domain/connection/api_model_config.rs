@@ -1,10 +1,12 @@
 use http::HeaderMap;
-use js_sandbox_ios::Script;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, ops::Not};
 
-use crate::{prelude::schema::json_schema::JsonSchema, IntegrationOSError, InternalError};
+use crate::{
+    prelude::schema::json_schema::JsonSchema, scripting::ScriptService, ErrorMeta,
+    IntegrationOSError, InternalError,
+};
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[cfg_attr(feature = "dummy", derive(fake::Dummy))]
@@ -13,6 +15,9 @@ pub struct ApiModelConfig {
     pub base_url: String,
     pub path: String,
     pub auth_method: AuthMethod,
+    /// Calls present the client certificate stored in the connection secret
+    #[serde(default, skip_serializing_if = "<&bool>::not")]
+    pub mtls: bool,
     #[serde(
         with = "http_serde_ext_ios::header_map::option",
         skip_serializing_if = "Option::is_none",
@@ -139,9 +144,43 @@ pub enum AuthMethod {
         realm: Option<String>,
     },
     OAuth,
+    /// Exchanges the client id and secret of the connection for an access token
+    ClientCredentials {
+        #[serde(rename = "tokenUrl")]
+        token_url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scopes: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        audience: Option<String>,
+    },
+    /// Exchanges an assertion signed with the private key of the connection for
+    /// an access token (RFC 7523)
+    JwtBearer {
+        #[serde(rename = "tokenUrl")]
+        token_url: String,
+        algorithm: JwtAlgorithm,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scopes: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        audience: Option<String>,
+    },
+    /// Signs requests with the AWS credentials of the connection
+    AwsSigV4 {
+        service: String,
+        region: String,
+    },
     None,
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+pub enum JwtAlgorithm {
+    #[serde(rename = "RS256")]
+    Rs256,
+    #[serde(rename = "ES256")]
+    Es256,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
 #[cfg_attr(feature = "dummy", derive(fake::Dummy))]
 pub enum OAuthLegacyHashAlgorithm {
@@ -172,18 +211,17 @@ impl<T> fake::Dummy<T> for Function {
 }
 
 impl Function {
-    pub fn compute<T: DeserializeOwned>(&self, payload: &Value) -> Result<T, IntegrationOSError> {
-        let mut js_code = Script::from_string(&self.0.function).map_err(|e| {
-            InternalError::script_error(
-                &e.to_string(),
-                Some("Failed to create script from function"),
-            )
-        })?;
-        let response: T = js_code.call(&self.0.entry, (payload,)).map_err(|e| {
-            InternalError::script_error(&e.to_string(), Some("Failed to call function"))
-        })?;
-
-        Ok(response)
+    pub async fn compute<T: DeserializeOwned>(
+        &self,
+        scripts: &ScriptService,
+        payload: &Value,
+    ) -> Result<T, IntegrationOSError> {
+        scripts
+            .call(&self.0.function, &self.0.entry, (payload,))
+            .await
+            .map_err(|e| {
+                InternalError::script_error(e.message().as_ref(), Some("Failed to call function"))
+            })
     }
 }
 
@@ -1,6 +1,9 @@
 use super::{api_model_config::AuthMethod, ConnectionType};
 use crate::id::{prefix::IdPrefix, Id};
-use crate::prelude::shared::{record_metadata::RecordMetadata, settings::Settings};
+use crate::prelude::{
+    event::webhook_handshake::WebhookHandshake,
+    shared::{record_metadata::RecordMetadata, redaction::RedactionRules, settings::Settings},
+};
 use serde::{Deserialize, Serialize};
 use strum::{self, AsRefStr, Display};
 
@@ -25,6 +28,13 @@ pub struct ConnectionDefinition {
     pub frontend: Frontend,
     pub paths: Paths,
     pub settings: Settings,
+    /// Copied onto the event access of every connection created from this definition
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub webhook_handshakes: Vec<WebhookHandshake>,
+    /// Added to the global redaction rules for payloads of this platform
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redaction: Option<RedactionRules>,
     pub hidden: bool,
     pub test_connection: Option<Id>,
     #[serde(flatten, default)]
@@ -137,7 +147,10 @@ impl ConnectionDefinition {
                 show_secret: false,
                 allow_custom_events: false,
                 oauth: false,
+                mtls: false,
             },
+            webhook_handshakes: vec![],
+            redaction: None,
             hidden: true,
             record_metadata: RecordMetadata::default(),
         }
@@ -3,13 +3,19 @@ use crate::{
     environment::Environment,
     id::Id,
     prelude::{ownership::Ownership, shared::record_metadata::RecordMetadata},
-    Feature, Hook,
+    Feature, Hook, IntegrationOSError, InternalError,
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use bson::doc;
+use rand::{thread_rng, RngCore};
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::ops::Not;
 
+const PKCE_VERIFIER_BYTES: usize = 64;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[cfg_attr(feature = "dummy", derive(fake::Dummy))]
 #[serde(rename_all = "camelCase")]
@@ -69,12 +75,94 @@ pub struct Frontend {
     pub ios_redirect_uri: String,
     #[serde(skip_serializing_if = "Option::is_none", default = "default_separator")]
     pub separator: Option<String>,
+    /// Whether the platform expects a PKCE code challenge on authorization
+    #[serde(default, skip_serializing_if = "<&bool>::not")]
+    pub pkce: bool,
 }
 
 fn default_separator() -> Option<String> {
     Some(String::from(" "))
 }
 
+impl Frontend {
+    /// Builds the url the user is sent to in order to authorize the platform.
+    ///
+    /// Test environments use the sandbox authorization url when the platform has
+    /// one. `scopes` overrides the scopes of the definition, both are comma
+    /// separated and joined with the separator of the platform.
+    pub fn authorization_url(
+        &self,
+        environment: Environment,
+        client_id: &str,
+        scopes: Option<&str>,
+        state: &str,
+        code_challenge: Option<&str>,
+    ) -> Result<Url, IntegrationOSError> {
+        let base = match (&self.sandbox_platform_redirect_uri, environment) {
+            (Some(sandbox), Environment::Test | Environment::Development) => sandbox,
+            _ => &self.platform_redirect_uri,
+        };
+
+        let mut url = Url::parse(base).map_err(|e| {
+            InternalError::invalid_argument(
+                &format!("Invalid platform redirect uri {base}: {e}"),
+                None,
+            )
+        })?;
+
+        let separator = self.separator.as_deref().unwrap_or(" ");
+        let scope = scopes
+            .unwrap_or(&self.scopes)
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(separator);
+
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("response_type", "code")
+                .append_pair("client_id", client_id)
+                .append_pair("redirect_uri", &self.ios_redirect_uri)
+                .append_pair("state", state);
+            if !scope.is_empty() {
+                query.append_pair("scope", &scope);
+            }
+            if let Some(challenge) = code_challenge {
+                query
+                    .append_pair("code_challenge", challenge)
+                    .append_pair("code_challenge_method", "S256");
+            }
+        }
+
+        Ok(url)
+    }
+}
+
+/// Proof key for code exchange (RFC 7636) of a single authorization
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+impl Pkce {
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; PKCE_VERIFIER_BYTES];
+        thread_rng().fill_bytes(&mut bytes);
+        Self::from_verifier(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    pub fn from_verifier(verifier: String) -> Self {
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Self {
+            verifier,
+            challenge,
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone, Serialize)]
 #[cfg_attr(feature = "dummy", derive(fake::Dummy))]
 #[serde(rename_all = "camelCase")]
@@ -106,11 +194,13 @@ pub struct Settings {
 }
 
 impl Settings {
-    pub fn platform_secret(
+    /// The platform connected for the environment, or for any environment when
+    /// it was only connected once
+    pub fn connected_platform(
         &self,
         connection_definition_id: &Id,
         environment: Environment,
-    ) -> Option<String> {
+    ) -> Option<&ConnectedPlatform> {
         self.connected_platforms
             .iter()
             .filter(|p| p.connection_definition_id == *connection_definition_id)
@@ -120,6 +210,14 @@ impl Settings {
                     .iter()
                     .find(|p| p.connection_definition_id == *connection_definition_id)
             })
+    }
+
+    pub fn platform_secret(
+        &self,
+        connection_definition_id: &Id,
+        environment: Environment,
+    ) -> Option<String> {
+        self.connected_platform(connection_definition_id, environment)
             .and_then(|p| p.secrets_service_id.clone())
     }
 }
@@ -161,3 +259,64 @@ pub struct PlatformSecret {
     pub client_id: String,
     pub client_secret: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frontend() -> Frontend {
+        Frontend {
+            platform_redirect_uri: "https://platform.com/oauth/authorize".to_string(),
+            sandbox_platform_redirect_uri: Some(
+                "https://sandbox.platform.com/oauth/authorize?prompt=consent".to_string(),
+            ),
+            scopes: "read, write".to_string(),
+            ios_redirect_uri: "https://app.integrationos.com/oauth/callback".to_string(),
+            separator: Some(" ".to_string()),
+            pkce: true,
+        }
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        // Appendix B of RFC 7636
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string());
+        assert_eq!(
+            pkce.challenge,
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+
+        let pkce = Pkce::generate();
+        assert_eq!(pkce.verifier.len(), 86);
+        assert_eq!(Pkce::from_verifier(pkce.verifier.clone()), pkce);
+    }
+
+    #[test]
+    fn test_authorization_url() {
+        let url = frontend()
+            .authorization_url(
+                Environment::Live,
+                "client",
+                None,
+                "state",
+                Some("challenge"),
+            )
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://platform.com/oauth/authorize?response_type=code&client_id=client\
+             &redirect_uri=https%3A%2F%2Fapp.integrationos.com%2Foauth%2Fcallback\
+             &state=state&scope=read+write&code_challenge=challenge&code_challenge_method=S256"
+        );
+
+        let url = frontend()
+            .authorization_url(Environment::Test, "client", Some("files"), "state", None)
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://sandbox.platform.com/oauth/authorize?prompt=consent&response_type=code\
+             &client_id=client&redirect_uri=https%3A%2F%2Fapp.integrationos.com%2Foauth%2Fcallback\
+             &state=state&scope=files"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use super::lifecycle::ConnectionLifecycle;
+use serde::{Deserialize, Serialize};
+use strum::{AsRefStr, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, AsRefStr)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum HealthStatus {
+    Healthy,
+    Unhealthy,
+}
+
+/// Outcome of the latest scheduled test of a connection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionHealth {
+    pub status: HealthStatus,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub checked_at: i64,
+    /// Number of consecutive checks with the same outcome as the latest one
+    pub streak: u32,
+}
+
+/// How many consecutive checks it takes to change the status of a connection,
+/// so that a single slow or failed call does not flip it back and forth
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthThresholds {
+    pub failures: u32,
+    pub recoveries: u32,
+}
+
+impl HealthThresholds {
+    /// Adds a check to the previous health of a connection, along with the
+    /// lifecycle change if the check made the connection cross a threshold
+    pub fn record(
+        &self,
+        previous: Option<&ConnectionHealth>,
+        error: Option<String>,
+        latency_ms: u64,
+        checked_at: i64,
+    ) -> (ConnectionHealth, Option<ConnectionLifecycle>) {
+        let passed = error.is_none();
+        let streak = match previous {
+            Some(previous) if previous.passed == passed => previous.streak.saturating_add(1),
+            _ => 1,
+        };
+
+        let status = previous
+            .map(|previous| previous.status)
+            .unwrap_or(HealthStatus::Healthy);
+        let (status, lifecycle) = match status {
+            HealthStatus::Healthy if !passed && streak >= self.failures => (
+                HealthStatus::Unhealthy,
+                Some(ConnectionLifecycle::Unhealthy),
+            ),
+            HealthStatus::Unhealthy if passed && streak >= self.recoveries => {
+                (HealthStatus::Healthy, Some(ConnectionLifecycle::Recovered))
+            }
+            status => (status, None),
+        };
+
+        let health = ConnectionHealth {
+            status,
+            passed,
+            error,
+            latency_ms,
+            checked_at,
+            streak,
+        };
+
+        (health, lifecycle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_hysteresis() {
+        let thresholds = HealthThresholds {
+            failures: 3,
+            recoveries: 2,
+        };
+        let failure = || Some("Test connection failed: 401 Unauthorized".to_string());
+
+        let (health, lifecycle) = thresholds.record(None, None, 120, 1);
+        assert_eq!(health.status, HealthStatus::Healthy);
+        assert_eq!(health.streak, 1);
+        assert_eq!(lifecycle, None);
+
+        // Failures below the threshold leave the connection healthy
+        let (health, _) = thresholds.record(Some(&health), failure(), 80, 2);
+        let (health, lifecycle) = thresholds.record(Some(&health), failure(), 80, 3);
+        assert_eq!(health.status, HealthStatus::Healthy);
+        assert_eq!(health.streak, 2);
+        assert_eq!(lifecycle, None);
+
+        let (health, lifecycle) = thresholds.record(Some(&health), failure(), 80, 4);
+        assert_eq!(health.status, HealthStatus::Unhealthy);
+        assert_eq!(health.error, failure());
+        assert_eq!(lifecycle, Some(ConnectionLifecycle::Unhealthy));
+
+        // Further failures do not announce the connection again
+        let (health, lifecycle) = thresholds.record(Some(&health), failure(), 80, 5);
+        assert_eq!(health.streak, 4);
+        assert_eq!(lifecycle, None);
+
+        // A success resets the streak without recovering on its own
+        let (health, lifecycle) = thresholds.record(Some(&health), None, 95, 6);
+        assert_eq!(health.status, HealthStatus::Unhealthy);
+        assert_eq!(health.streak, 1);
+        assert_eq!(health.error, None);
+        assert_eq!(lifecycle, None);
+
+        let (health, lifecycle) = thresholds.record(Some(&health), None, 95, 7);
+        assert_eq!(health.status, HealthStatus::Healthy);
+        assert_eq!(health.latency_ms, 95);
+        assert_eq!(health.checked_at, 7);
+        assert_eq!(lifecycle, Some(ConnectionLifecycle::Recovered));
+    }
+}
@@ -0,0 +1,114 @@
+use super::Connection;
+use crate::{
+    encrypted_access_key::EncryptedAccessKey, encrypted_data::PASSWORD_LENGTH, AccessKey, Event,
+    IntegrationOSError, InternalError,
+};
+use http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use strum::{AsRefStr, Display, EnumString};
+
+/// State changes of a connection, published as events named after them on the
+/// access key of the connection
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, AsRefStr, EnumString,
+)]
+pub enum ConnectionLifecycle {
+    #[serde(rename = "connection.created")]
+    #[strum(serialize = "connection.created")]
+    Created,
+    #[serde(rename = "connection.refreshed")]
+    #[strum(serialize = "connection.refreshed")]
+    Refreshed,
+    #[serde(rename = "connection.refresh_failed")]
+    #[strum(serialize = "connection.refresh_failed")]
+    RefreshFailed,
+    #[serde(rename = "connection.unhealthy")]
+    #[strum(serialize = "connection.unhealthy")]
+    Unhealthy,
+    #[serde(rename = "connection.recovered")]
+    #[strum(serialize = "connection.recovered")]
+    Recovered,
+    /// The database a connection points to could not be reached anymore
+    #[serde(rename = "connection.lost")]
+    #[strum(serialize = "connection.lost")]
+    Lost,
+    #[serde(rename = "connection.deleted")]
+    #[strum(serialize = "connection.deleted")]
+    Deleted,
+}
+
+impl Connection {
+    /// Builds the event announcing a state change of the connection, `data` holds
+    /// the details of the change
+    pub fn lifecycle_event(
+        &self,
+        lifecycle: ConnectionLifecycle,
+        data: Value,
+        password: &[u8; PASSWORD_LENGTH],
+    ) -> Result<Event, IntegrationOSError> {
+        let encrypted_access_key = EncryptedAccessKey::parse(&self.access_key)?;
+        let access_key = AccessKey::parse(&encrypted_access_key, password)?;
+
+        let body = json!({
+            "connectionId": self.id,
+            "connectionKey": self.key,
+            "platform": self.platform,
+            "environment": self.environment,
+            "data": data,
+        });
+        let body_str = serde_json::to_string(&body)
+            .map_err(|e| InternalError::serialize_error(&e.to_string(), None))?;
+
+        Ok(Event::new(
+            &access_key,
+            &encrypted_access_key,
+            lifecycle.as_ref(),
+            HeaderMap::new(),
+            body_str,
+        )
+        .with_parsed_body(Some(body)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_lifecycle_names() {
+        assert_eq!(
+            ConnectionLifecycle::RefreshFailed.to_string(),
+            "connection.refresh_failed"
+        );
+        assert_eq!(
+            serde_json::to_value(ConnectionLifecycle::RefreshFailed).unwrap(),
+            json!("connection.refresh_failed")
+        );
+        assert_eq!(
+            ConnectionLifecycle::from_str("connection.refresh_failed").unwrap(),
+            ConnectionLifecycle::RefreshFailed
+        );
+        assert_eq!(
+            ConnectionLifecycle::Unhealthy.to_string(),
+            "connection.unhealthy"
+        );
+        assert_eq!(
+            ConnectionLifecycle::from_str("connection.recovered").unwrap(),
+            ConnectionLifecycle::Recovered
+        );
+        assert_eq!(
+            ConnectionLifecycle::Created.to_string(),
+            "connection.created"
+        );
+        assert_eq!(
+            serde_json::to_value(ConnectionLifecycle::Lost).unwrap(),
+            json!("connection.lost")
+        );
+        assert_eq!(
+            ConnectionLifecycle::from_str("connection.deleted").unwrap(),
+            ConnectionLifecycle::Deleted
+        );
+    }
+}
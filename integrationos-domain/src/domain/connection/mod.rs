@@ -3,12 +3,16 @@ pub mod connection_definition;
 pub mod connection_model_definition;
 pub mod connection_model_schema;
 pub mod connection_oauth_definition;
+pub mod health;
+pub mod lifecycle;
 
+use self::health::ConnectionHealth;
 use super::{
     configuration::environment::Environment,
     shared::{ownership::Ownership, record_metadata::RecordMetadata, settings::Settings},
 };
 use crate::id::Id;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{hash::Hash, sync::Arc};
@@ -46,6 +50,8 @@ pub struct Connection {
     pub has_error: bool,
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub health: Option<ConnectionHealth>,
     #[serde(flatten, default)]
     pub record_metadata: RecordMetadata,
 }
@@ -55,6 +61,14 @@ impl Connection {
         self.has_error = true;
         self.error = Some(error.to_string());
     }
+
+    /// Clears the error of the connection, along with the health checks that may
+    /// have caused it
+    pub fn clear_error(&mut self) {
+        self.has_error = false;
+        self.error = None;
+        self.health = None;
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -108,6 +122,8 @@ pub struct SanitizedConnection {
     pub has_error: bool,
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub health: Option<ConnectionHealth>,
     #[serde(flatten, default)]
     pub record_metadata: RecordMetadata,
 }
@@ -146,6 +162,20 @@ pub enum OAuth {
     Disabled,
 }
 
+impl OAuth {
+    /// Whether the access token has to be refreshed before it is used. `expires_at`
+    /// is set a little before the platform expires the token.
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        matches!(
+            self,
+            OAuth::Enabled {
+                expires_at: Some(expires_at),
+                ..
+            } if *expires_at <= now.timestamp()
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Display, AsRefStr)]
 #[serde(rename_all = "lowercase")]
 #[strum(serialize_all = "lowercase")]
@@ -182,3 +212,25 @@ pub struct Throughput {
     pub key: String,
     pub limit: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::prefix::IdPrefix;
+
+    #[test]
+    fn test_oauth_is_expired() {
+        let now = Utc::now();
+        let oauth = |expires_at| OAuth::Enabled {
+            connection_oauth_definition_id: Id::now(IdPrefix::ConnectionOAuthDefinition),
+            expires_in: Some(3600),
+            expires_at,
+        };
+
+        assert!(oauth(Some(now.timestamp() - 1)).is_expired(now));
+        assert!(oauth(Some(now.timestamp())).is_expired(now));
+        assert!(!oauth(Some(now.timestamp() + 60)).is_expired(now));
+        assert!(!oauth(None).is_expired(now));
+        assert!(!OAuth::Disabled.is_expired(now));
+    }
+}
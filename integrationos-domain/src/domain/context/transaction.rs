@@ -2,8 +2,12 @@ use crate::{
     id::{prefix::IdPrefix, Id},
     prelude::{
         configuration::environment::Environment,
-        event::Event,
-        shared::{ownership::Ownership, record_metadata::RecordMetadata},
+        event::{payload_encryption::SealedValue, Event},
+        shared::{
+            ownership::Ownership,
+            record_metadata::RecordMetadata,
+            redaction::{Redactions, Redactor},
+        },
     },
 };
 use chrono::{DateTime, Utc};
@@ -25,6 +29,9 @@ pub struct Transaction {
     pub started_at: DateTime<Utc>,
     pub ownership: Ownership,
     pub event_id: Id,
+    /// Originals of tokenized values in `input` and `output`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub redacted_values: Option<SealedValue>,
     #[serde(flatten, default)]
     pub record_metadata: RecordMetadata,
 }
@@ -44,6 +51,7 @@ impl Transaction {
             started_at: ts,
             ownership: event.ownership.clone(),
             event_id: event.id,
+            redacted_values: None,
             record_metadata: Default::default(),
         }
     }
@@ -63,4 +71,18 @@ impl Transaction {
     pub fn throttled(event: &Event, key: String, input: String, output: String) -> Self {
         Self::new(event, key, input, output, "throttled".to_owned())
     }
+
+    pub fn redact(&mut self, redactor: &Redactor) -> Redactions {
+        let mut redactions = Redactions::default();
+        self.input = redactor.redact_str(&self.input, &mut redactions);
+        self.output = redactor.redact_str(&self.output, &mut redactions);
+        redactions
+    }
+
+    /// Puts the originals of tokenized values back into the input and output
+    pub fn reveal(&mut self, redactions: &Redactions) {
+        self.input = redactions.restore_str(&self.input);
+        self.output = redactions.restore_str(&self.output);
+        self.redacted_values = None;
+    }
 }
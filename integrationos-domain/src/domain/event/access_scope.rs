@@ -0,0 +1,169 @@
+use crate::connection_model_definition::CrudAction;
+use chrono::{DateTime, Utc};
+use http::Method;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(rename_all = "camelCase")]
+pub enum AccessPermission {
+    Read,
+    #[default]
+    ReadWrite,
+}
+
+/// Restrictions on what an event access key can do.
+///
+/// Every empty list means the key is not restricted on that dimension, so the
+/// default scope grants the same access as a key without a scope.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(rename_all = "camelCase")]
+pub struct AccessScope {
+    /// Top level route groups the key can call, e.g. `unified` or `connections`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<String>,
+    #[serde(default)]
+    pub permission: AccessPermission,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub connection_keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<String>,
+    /// Unified models, optionally limited to one action, e.g. `contacts` or `contacts:getMany`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unified: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub ip_allow_list: Vec<IpNet>,
+    #[serde(
+        with = "chrono::serde::ts_milliseconds_option",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Allows reading the originals of values tokenized in stored events and
+    /// transactions. Unlike the other restrictions, keys without it cannot.
+    #[serde(default)]
+    pub reveal_redacted: bool,
+}
+
+impl AccessScope {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn allows_route(&self, route: &str) -> bool {
+        self.routes.is_empty() || self.routes.iter().any(|allowed| allowed == route)
+    }
+
+    pub fn allows_method(&self, method: &Method) -> bool {
+        self.permission == AccessPermission::ReadWrite || method.is_safe()
+    }
+
+    pub fn allows_reveal(&self) -> bool {
+        self.reveal_redacted
+    }
+
+    pub fn allows_ip(&self, ip: Option<IpAddr>) -> bool {
+        if self.ip_allow_list.is_empty() {
+            return true;
+        }
+        ip.is_some_and(|ip| self.ip_allow_list.iter().any(|net| net.contains(&ip)))
+    }
+
+    /// Connection keys look like `{environment}::{platform}::{namespace}::{suffix}`
+    pub fn allows_connection(&self, connection_key: &str) -> bool {
+        let allowed_key = self.connection_keys.is_empty()
+            || self.connection_keys.iter().any(|key| key == connection_key);
+        let allowed_platform = self.platforms.is_empty()
+            || connection_key
+                .split("::")
+                .nth(1)
+                .is_some_and(|platform| self.platforms.iter().any(|p| p == platform));
+
+        allowed_key && allowed_platform
+    }
+
+    pub fn allows_unified(&self, model: &str, action: &CrudAction) -> bool {
+        if self.unified.is_empty() {
+            return true;
+        }
+
+        let model = normalize_model(model);
+        let action = action.to_string();
+        self.unified
+            .iter()
+            .any(|allowed| match allowed.split_once(':') {
+                Some((allowed_model, allowed_action)) => {
+                    normalize_model(allowed_model) == model && allowed_action == action
+                }
+                None => normalize_model(allowed) == model,
+            })
+    }
+}
+
+/// Models show up as `phone-numbers` in paths and `PhoneNumbers` in definitions
+fn normalize_model(model: &str) -> String {
+    model
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_default_scope_allows_everything() {
+        let scope = AccessScope::default();
+        assert!(!scope.is_expired(Utc::now()));
+        assert!(scope.allows_route("secrets"));
+        assert!(scope.allows_method(&Method::DELETE));
+        assert!(scope.allows_ip(None));
+        assert!(scope.allows_connection("live::stripe::default::abc"));
+        assert!(scope.allows_unified("customers", &CrudAction::Delete));
+        assert!(!scope.allows_reveal());
+    }
+
+    #[test]
+    fn test_restricted_scope() {
+        let now = Utc::now();
+        let scope = AccessScope {
+            routes: vec!["unified".to_owned()],
+            permission: AccessPermission::Read,
+            connection_keys: vec![],
+            platforms: vec!["stripe".to_owned()],
+            unified: vec!["phone-numbers".to_owned(), "customers:getMany".to_owned()],
+            ip_allow_list: vec!["10.0.0.0/8".parse().unwrap()],
+            expires_at: Some(now + Duration::hours(1)),
+            reveal_redacted: false,
+        };
+
+        assert!(!scope.is_expired(now));
+        assert!(scope.is_expired(now + Duration::hours(2)));
+
+        assert!(scope.allows_route("unified"));
+        assert!(!scope.allows_route("secrets"));
+
+        assert!(scope.allows_method(&Method::GET));
+        assert!(!scope.allows_method(&Method::POST));
+
+        assert!(scope.allows_ip(Some("10.1.2.3".parse().unwrap())));
+        assert!(!scope.allows_ip(Some("192.168.1.1".parse().unwrap())));
+        assert!(!scope.allows_ip(None));
+
+        assert!(scope.allows_connection("live::stripe::default::abc"));
+        assert!(!scope.allows_connection("live::hubspot::default::abc"));
+
+        assert!(scope.allows_unified("PhoneNumbers", &CrudAction::Delete));
+        assert!(scope.allows_unified("customers", &CrudAction::GetMany));
+        assert!(!scope.allows_unified("customers", &CrudAction::GetOne));
+        assert!(!scope.allows_unified("invoices", &CrudAction::GetMany));
+    }
+}
@@ -1,5 +1,7 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::{access_scope::AccessScope, webhook_handshake::WebhookHandshake};
 use crate::{
     id::Id,
     prelude::{
@@ -25,9 +27,33 @@ pub struct EventAccess {
     pub paths: Paths,
     #[cfg_attr(feature = "dummy", dummy(faker = "8..50"))]
     pub access_key: String,
+    /// Keys replaced by a rotation that keep working until their grace period ends
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub previous_access_keys: Vec<PreviousAccessKey>,
+    #[serde(
+        with = "chrono::serde::ts_milliseconds_option",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub last_used_at: Option<DateTime<Utc>>,
     #[serde(default = "throughput_default")]
     pub throughput: u64,
     pub environment: Environment,
+    /// Provider verification requests the gateway answers for this access key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub webhook_handshakes: Vec<WebhookHandshake>,
+    /// Restricts what the key can do, keys without a scope have full access
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub scope: Option<AccessScope>,
+    /// Secret holding the data key event payloads are encrypted with at rest,
+    /// payloads are stored in plaintext without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub data_key_id: Option<String>,
     #[serde(flatten, default)]
     pub record_metadata: RecordMetadata,
 }
@@ -36,9 +62,106 @@ fn throughput_default() -> u64 {
     500
 }
 
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(rename_all = "camelCase")]
+pub struct PreviousAccessKey {
+    pub access_key: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub expires_at: DateTime<Utc>,
+    #[serde(
+        with = "chrono::serde::ts_milliseconds_option",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Keys a rotated event access record accepts, stored under its group so the
+/// gateway, which only decrypts keys, can reject the replaced ones
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptedAccessKeys {
+    pub access_key: String,
+    #[serde(default)]
+    pub previous_access_keys: Vec<PreviousAccessKey>,
+}
+
+impl AcceptedAccessKeys {
+    pub fn accepts(&self, access_key: &str, now: DateTime<Utc>) -> bool {
+        self.access_key == access_key
+            || self
+                .previous_access_keys
+                .iter()
+                .any(|previous| previous.access_key == access_key && previous.expires_at > now)
+    }
+}
+
 impl EventAccess {
+    pub fn accepted_access_keys(&self) -> AcceptedAccessKeys {
+        AcceptedAccessKeys {
+            access_key: self.access_key.clone(),
+            previous_access_keys: self.previous_access_keys.clone(),
+        }
+    }
+
     pub fn with_key(mut self, key: String) -> Self {
         self.key = key;
         self
     }
+
+    /// Whether `access_key` is the current key or a previous one still in its grace period
+    pub fn accepts(&self, access_key: &str, now: DateTime<Utc>) -> bool {
+        self.access_key == access_key
+            || self
+                .previous_access_keys
+                .iter()
+                .any(|previous| previous.access_key == access_key && previous.expires_at > now)
+    }
+
+    /// Makes `access_key` the current key, keeping the old one valid until `expires_at`
+    pub fn rotate(&mut self, access_key: String, expires_at: DateTime<Utc>, now: DateTime<Utc>) {
+        let previous = std::mem::replace(&mut self.access_key, access_key);
+        self.previous_access_keys
+            .retain(|previous| previous.expires_at > now);
+        if expires_at > now {
+            self.previous_access_keys.push(PreviousAccessKey {
+                access_key: previous,
+                expires_at,
+                last_used_at: self.last_used_at.take(),
+            });
+        }
+        self.last_used_at = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+    use fake::{Fake, Faker};
+
+    #[test]
+    fn test_rotate_keeps_previous_key_during_grace_period() {
+        let now = Utc::now();
+        let mut event_access: EventAccess = Faker.fake();
+        event_access.previous_access_keys = vec![];
+        let old_key = event_access.access_key.clone();
+
+        event_access.rotate("new_key".to_owned(), now + Duration::hours(1), now);
+        assert!(event_access.accepts("new_key", now));
+        assert!(event_access.accepts(&old_key, now));
+        assert!(!event_access.accepts(&old_key, now + Duration::hours(2)));
+
+        // Expired keys are dropped on the next rotation, and no grace period revokes right away
+        event_access.rotate(
+            "newer_key".to_owned(),
+            now + Duration::hours(3),
+            now + Duration::hours(2),
+        );
+        event_access.rotate("newest_key".to_owned(), now, now);
+        assert_eq!(event_access.previous_access_keys.len(), 1);
+        assert_eq!(event_access.previous_access_keys[0].access_key, "new_key");
+        assert!(!event_access.accepts("newer_key", now));
+    }
 }
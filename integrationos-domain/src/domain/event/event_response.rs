@@ -1,7 +1,7 @@
 use crate::id::Id;
 use serde::{Deserialize, Serialize};
 
-use super::{event_state::EventState, hashes::HashValue, Event};
+use super::{event_state::EventState, hashes::HashValue, scheduled_event::ScheduledEvent, Event};
 
 #[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -10,6 +10,8 @@ pub struct EventResponse {
     pub key: Id,
     pub payload_byte_length: usize,
     pub hashes: [HashValue; 3],
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub scheduled_event_id: Option<Id>,
 }
 
 impl EventResponse {
@@ -19,6 +21,14 @@ impl EventResponse {
             key: event.key,
             payload_byte_length: event.payload_byte_length,
             hashes: event.hashes,
+            scheduled_event_id: None,
+        }
+    }
+
+    pub fn scheduled(event: Event, scheduled: &ScheduledEvent) -> Self {
+        Self {
+            scheduled_event_id: Some(scheduled.id),
+            ..Self::new(event)
         }
     }
 }
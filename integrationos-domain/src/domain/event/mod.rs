@@ -1,3 +1,4 @@
+pub mod access_scope;
 pub mod duplicates;
 pub mod emitted_events;
 pub mod event_access;
@@ -5,23 +6,37 @@ pub mod event_response;
 pub mod event_state;
 pub mod event_with_context;
 pub mod hashes;
+pub mod payload_encryption;
+pub mod scheduled_event;
+pub mod webhook_delivery;
+pub mod webhook_handshake;
+pub mod webhook_subscription;
 
 use chrono::{DateTime, SubsecRound, Utc};
 use http::HeaderMap;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::id::{prefix::IdPrefix, Id};
+use crate::{
+    id::{prefix::IdPrefix, Id},
+    CryptoExt, IntegrationOSError, InternalError,
+};
 
 use self::{
     duplicates::Duplicates,
     event_state::EventState,
     hashes::{HashValue, Hashes},
+    payload_encryption::{EventEncryption, SealedValue},
 };
 
 use super::{
     access_key::{encrypted_access_key::EncryptedAccessKey, AccessKey},
     configuration::environment::Environment,
-    shared::{ownership::Ownership, record_metadata::RecordMetadata},
+    shared::{
+        ownership::Ownership,
+        record_metadata::RecordMetadata,
+        redaction::{Redactions, Redactor},
+    },
 };
 
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
@@ -49,10 +64,41 @@ pub struct Event {
     pub payload_byte_length: usize,
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub duplicates: Option<Duplicates>,
+    /// Id of the provider object the event is about, read from `event_object_id_path`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub object_id: Option<String>,
+    /// When the provider says the event happened, read from `timestamp_path`
+    #[serde(
+        with = "chrono::serde::ts_milliseconds_option",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub occurred_at: Option<DateTime<Utc>>,
+    /// JSON view of the webhook body, kept when the connection parses webhook bodies
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub parsed_body: Option<Value>,
+    /// Originals of tokenized values in `body` and `headers`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub redacted_values: Option<SealedValue>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub encryption: Option<EventEncryption>,
     #[serde(flatten, default)]
     pub record_metadata: RecordMetadata,
 }
 
+/// Fields that are encrypted together and kept in [`EventEncryption::fields`]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptedFields {
+    #[serde(with = "http_serde_ext_ios::header_map")]
+    headers: HeaderMap,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    parsed_body: Option<Value>,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[cfg_attr(feature = "dummy", derive(fake::Dummy))]
 #[serde(rename_all = "camelCase")]
@@ -77,6 +123,24 @@ pub struct PublicEvent {
     pub payload_byte_length: usize,
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub duplicates: Option<Duplicates>,
+    /// Id of the provider object the event is about, read from `event_object_id_path`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub object_id: Option<String>,
+    /// When the provider says the event happened, read from `timestamp_path`
+    #[serde(
+        with = "chrono::serde::ts_milliseconds_option",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub occurred_at: Option<DateTime<Utc>>,
+    /// JSON view of the webhook body, kept when the connection parses webhook bodies
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub parsed_body: Option<Value>,
+    /// Originals of tokenized values in `body` and `headers`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub redacted_values: Option<SealedValue>,
     #[serde(flatten, default)]
     pub record_metadata: RecordMetadata,
 }
@@ -121,6 +185,21 @@ impl Event {
         self
     }
 
+    pub fn with_object_id(mut self, object_id: Option<String>) -> Self {
+        self.object_id = object_id;
+        self
+    }
+
+    pub fn with_occurred_at(mut self, occurred_at: Option<DateTime<Utc>>) -> Self {
+        self.occurred_at = occurred_at;
+        self
+    }
+
+    pub fn with_parsed_body(mut self, parsed_body: Option<Value>) -> Self {
+        self.parsed_body = parsed_body;
+        self
+    }
+
     fn new_with_timestamp_and_ids(fields: IntermediateEventFields<'_>) -> Self {
         let topic = fields.access_key.get_topic(fields.event_name);
         let access_key_data = &fields.access_key.data;
@@ -162,6 +241,11 @@ impl Event {
             hashes,
             payload_byte_length,
             duplicates: None,
+            object_id: None,
+            occurred_at: None,
+            parsed_body: None,
+            redacted_values: None,
+            encryption: None,
             record_metadata: Default::default(),
         }
     }
@@ -184,9 +268,90 @@ impl Event {
             hashes: self.hashes,
             payload_byte_length: self.payload_byte_length,
             duplicates: self.duplicates.clone(),
+            object_id: self.object_id.clone(),
+            occurred_at: self.occurred_at,
+            parsed_body: self.parsed_body.clone(),
+            redacted_values: self.redacted_values.clone(),
             record_metadata: self.record_metadata.clone(),
         }
     }
+
+    /// Redacts the payload before the event is stored. Hashes are left alone so
+    /// duplicates are still detected.
+    pub fn redact(&mut self, redactor: &Redactor) -> Redactions {
+        let mut redactions = Redactions::default();
+        if redactor.is_empty() {
+            return redactions;
+        }
+
+        self.body = redactor.redact_str(&self.body, &mut redactions);
+        redactor.redact_headers(&mut self.headers, &mut redactions);
+        self.parsed_body = self
+            .parsed_body
+            .take()
+            .map(|body| redactor.redact_value(body, &mut redactions));
+        redactions
+    }
+
+    /// Puts the originals of tokenized values back into the payload
+    pub fn reveal(&mut self, redactions: &Redactions) {
+        self.body = redactions.restore_str(&self.body);
+        redactions.restore_headers(&mut self.headers);
+        self.parsed_body = self
+            .parsed_body
+            .take()
+            .map(|body| redactions.restore_value(body));
+        self.redacted_values = None;
+    }
+
+    /// Encrypts the payload before the event is stored. Hashes are computed over
+    /// the plaintext when the event is created, so duplicates are still detected.
+    pub async fn encrypt<C: CryptoExt + Sync>(
+        &mut self,
+        crypto: &C,
+        data_key_id: String,
+    ) -> Result<(), IntegrationOSError> {
+        if self.encryption.is_some() {
+            return Ok(());
+        }
+
+        let fields = serde_json::to_string(&EncryptedFields {
+            headers: self.headers.clone(),
+            parsed_body: self.parsed_body.clone(),
+        })
+        .map_err(|e| InternalError::serialize_error(&e.to_string(), None))?;
+        let fields = crypto.encrypt(fields).await?;
+        let body = crypto.encrypt(self.body.clone()).await?;
+
+        self.body = body;
+        self.headers = HeaderMap::new();
+        self.parsed_body = None;
+        self.encryption = Some(EventEncryption {
+            data_key_id,
+            fields,
+        });
+        Ok(())
+    }
+
+    pub async fn decrypt<C: CryptoExt + Sync>(
+        &mut self,
+        crypto: &C,
+    ) -> Result<(), IntegrationOSError> {
+        let Some(encryption) = &self.encryption else {
+            return Ok(());
+        };
+
+        let fields = crypto.decrypt(encryption.fields.clone(), None).await?;
+        let fields: EncryptedFields = serde_json::from_str(&fields)
+            .map_err(|e| InternalError::deserialize_error(&e.to_string(), None))?;
+        let body = crypto.decrypt(self.body.clone(), None).await?;
+
+        self.body = body;
+        self.headers = fields.headers;
+        self.parsed_body = fields.parsed_body;
+        self.encryption = None;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -197,6 +362,7 @@ mod test {
     };
 
     use super::*;
+    use crate::IOSCrypto;
     use chrono::TimeZone;
     use http::{HeaderMap, HeaderValue};
     use once_cell::sync::Lazy;
@@ -283,6 +449,43 @@ mod test {
         assert_eq!(event.payload_byte_length, 11);
     }
 
+    #[tokio::test]
+    async fn test_event_encryption() {
+        let event = Event::new(
+            &ACCESS_KEY,
+            &EncryptedAccessKey::parse("id_live_1_foo").unwrap(),
+            "event.received",
+            HEADERS.clone(),
+            "hello world".to_owned(),
+        )
+        .with_parsed_body(Some(serde_json::json!({ "hello": "world" })));
+        let crypto = IOSCrypto::with_data_key(&IOSCrypto::generate_data_key()).unwrap();
+
+        let mut encrypted = event.clone();
+        encrypted
+            .encrypt(&crypto, "sec::1".to_owned())
+            .await
+            .unwrap();
+        assert_ne!(encrypted.body, event.body);
+        assert!(encrypted.headers.is_empty());
+        assert_eq!(encrypted.parsed_body, None);
+        assert_eq!(encrypted.hashes, event.hashes);
+        assert_eq!(
+            encrypted
+                .encryption
+                .as_ref()
+                .map(|e| e.data_key_id.as_str()),
+            Some("sec::1")
+        );
+
+        let mut decrypted = encrypted.clone();
+        decrypted.decrypt(&crypto).await.unwrap();
+        assert_eq!(decrypted, event);
+
+        let other = IOSCrypto::with_data_key(&IOSCrypto::generate_data_key()).unwrap();
+        assert!(encrypted.decrypt(&other).await.is_err());
+    }
+
     #[test]
     fn test_event_serde() {
         let event = Event::new(
@@ -0,0 +1,144 @@
+use super::Event;
+use crate::{CryptoExt, IOSCrypto, IntegrationOSError, InternalError, SecretExt};
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+
+/// Unwrapped data keys kept in memory, there is at most one per event access
+const DATA_KEY_CACHE_SIZE: u64 = 10_000;
+const DATA_KEY_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Set on events whose `body`, `headers` and `parsed_body` are encrypted at rest
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(rename_all = "camelCase")]
+pub struct EventEncryption {
+    /// Secret holding the data key of the event access the event arrived with
+    pub data_key_id: String,
+    /// Encrypted `headers` and `parsed_body`, which are left empty on the event
+    pub fields: String,
+}
+
+/// A value encrypted with a data key, stored next to what it belongs to
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(rename_all = "camelCase")]
+pub struct SealedValue {
+    /// Secret holding the data key
+    pub data_key_id: String,
+    pub ciphertext: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DataKey {
+    data_key: String,
+}
+
+/// Encrypts event payloads with the data key of their event access.
+///
+/// Data keys are stored as secrets, so they are wrapped by whichever provider
+/// the secrets service is configured with.
+#[derive(Clone)]
+pub struct PayloadEncryption {
+    secrets_client: Arc<dyn SecretExt + Sync + Send>,
+    /// Unwrapped data keys by secret id
+    data_keys: Cache<String, IOSCrypto>,
+}
+
+impl PayloadEncryption {
+    pub fn new(secrets_client: Arc<dyn SecretExt + Sync + Send>) -> Self {
+        Self {
+            secrets_client,
+            data_keys: Cache::builder()
+                .max_capacity(DATA_KEY_CACHE_SIZE)
+                .time_to_live(DATA_KEY_CACHE_TTL)
+                .build(),
+        }
+    }
+
+    /// Generates a data key for an event access and returns the id of its secret
+    pub async fn create_data_key(&self, buildable_id: &str) -> Result<String, IntegrationOSError> {
+        let data_key = DataKey {
+            data_key: IOSCrypto::generate_data_key(),
+        };
+        let secret = self
+            .secrets_client
+            .create(&json!(data_key), buildable_id)
+            .await?;
+
+        Ok(secret.id())
+    }
+
+    pub async fn encrypt_event(
+        &self,
+        event: &mut Event,
+        data_key_id: &str,
+    ) -> Result<(), IntegrationOSError> {
+        let crypto = self.data_key(data_key_id, &event.ownership.id).await?;
+        event.encrypt(&crypto, data_key_id.to_owned()).await
+    }
+
+    /// Events stored in plaintext are left as they are
+    pub async fn decrypt_event(&self, event: &mut Event) -> Result<(), IntegrationOSError> {
+        let Some(encryption) = &event.encryption else {
+            return Ok(());
+        };
+
+        let crypto = self
+            .data_key(&encryption.data_key_id, &event.ownership.id)
+            .await?;
+        event.decrypt(&crypto).await
+    }
+
+    pub async fn seal(
+        &self,
+        value: &Value,
+        data_key_id: &str,
+        buildable_id: &str,
+    ) -> Result<SealedValue, IntegrationOSError> {
+        let crypto = self.data_key(data_key_id, buildable_id).await?;
+        let ciphertext = crypto.encrypt(value.to_string()).await?;
+
+        Ok(SealedValue {
+            data_key_id: data_key_id.to_owned(),
+            ciphertext,
+        })
+    }
+
+    pub async fn open(
+        &self,
+        sealed: &SealedValue,
+        buildable_id: &str,
+    ) -> Result<Value, IntegrationOSError> {
+        let crypto = self.data_key(&sealed.data_key_id, buildable_id).await?;
+        let plaintext = crypto.decrypt(sealed.ciphertext.clone(), None).await?;
+
+        serde_json::from_str(&plaintext)
+            .map_err(|e| InternalError::deserialize_error(&e.to_string(), None))
+    }
+
+    async fn data_key(
+        &self,
+        data_key_id: &str,
+        buildable_id: &str,
+    ) -> Result<IOSCrypto, IntegrationOSError> {
+        if let Some(crypto) = self.data_keys.get(data_key_id).await {
+            return Ok(crypto);
+        }
+
+        let data_key: DataKey = self
+            .secrets_client
+            .get(data_key_id, buildable_id)
+            .await?
+            .decode()?;
+        let crypto = IOSCrypto::with_data_key(&data_key.data_key)?;
+
+        self.data_keys
+            .insert(data_key_id.to_owned(), crypto.clone())
+            .await;
+
+        Ok(crypto)
+    }
+}
@@ -0,0 +1,122 @@
+use super::{payload_encryption::SealedValue, Event};
+use crate::{
+    environment::Environment,
+    id::{prefix::IdPrefix, Id},
+    ownership::Ownership,
+    record_metadata::RecordMetadata,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum::{AsRefStr, Display};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize, AsRefStr, Display)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(rename_all = "camelCase")]
+#[strum(serialize_all = "camelCase")]
+pub enum ScheduledEventState {
+    Scheduled,
+    /// Being pushed to the queue by a scheduler, which may reclaim it once `claimed_at` is stale
+    Claimed,
+    Enqueued,
+    Cancelled,
+}
+
+/// An event, or a single pipeline of an event, waiting to be pushed to the event queue.
+///
+/// When `pipeline_key` is set only that pipeline runs once the event is delivered,
+/// which is how pipelines with a delay policy are deferred.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledEvent {
+    #[serde(rename = "_id")]
+    pub id: Id,
+    pub event_key: Id,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub pipeline_key: Option<String>,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub deliver_at: DateTime<Utc>,
+    pub state: ScheduledEventState,
+    #[serde(
+        with = "chrono::serde::ts_milliseconds_option",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub claimed_at: Option<DateTime<Utc>>,
+    /// The event before it was redacted, until it is enqueued
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub original: Option<SealedValue>,
+    pub environment: Environment,
+    pub ownership: Ownership,
+    #[serde(flatten, default)]
+    pub record_metadata: RecordMetadata,
+}
+
+impl ScheduledEvent {
+    pub fn new(event: &Event, pipeline_key: Option<String>, deliver_at: DateTime<Utc>) -> Self {
+        Self {
+            id: Id::now(IdPrefix::ScheduledEvent),
+            event_key: event.id,
+            pipeline_key,
+            deliver_at,
+            state: ScheduledEventState::Scheduled,
+            claimed_at: None,
+            original: None,
+            environment: event.environment,
+            ownership: event.ownership.clone(),
+            record_metadata: Default::default(),
+        }
+    }
+
+    pub fn with_original(self, original: SealedValue) -> Self {
+        Self {
+            original: Some(original),
+            ..self
+        }
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.state == ScheduledEventState::Scheduled && self.deliver_at <= now
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+    use serde_json::json;
+
+    #[test]
+    fn test_scheduled_event_state_serializes_camel_case() {
+        assert_eq!(
+            serde_json::to_value(ScheduledEventState::Cancelled).unwrap(),
+            json!("cancelled")
+        );
+        assert_eq!(ScheduledEventState::Enqueued.as_ref(), "enqueued");
+    }
+
+    #[test]
+    fn test_deliver_at_serializes_as_millis() {
+        let deliver_at = DateTime::from_timestamp_millis(1_700_000_000_123).unwrap();
+        let scheduled = ScheduledEvent {
+            id: Id::test(IdPrefix::ScheduledEvent),
+            event_key: Id::test(IdPrefix::Event),
+            pipeline_key: None,
+            deliver_at,
+            state: ScheduledEventState::Scheduled,
+            claimed_at: None,
+            original: None,
+            environment: Environment::Test,
+            ownership: Ownership::default(),
+            record_metadata: Default::default(),
+        };
+
+        let value = serde_json::to_value(&scheduled).unwrap();
+        assert_eq!(value["deliverAt"], json!(1_700_000_000_123i64));
+        assert!(value.get("pipelineKey").is_none());
+        assert!(value.get("claimedAt").is_none());
+
+        assert!(scheduled.is_due(deliver_at));
+        assert!(!scheduled.is_due(deliver_at - Duration::seconds(1)));
+    }
+}
@@ -0,0 +1,60 @@
+use super::Event;
+use crate::{
+    environment::Environment,
+    id::{prefix::IdPrefix, Id},
+    ownership::Ownership,
+    record_metadata::RecordMetadata,
+};
+use serde::{Deserialize, Serialize};
+use strum::{AsRefStr, Display};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, AsRefStr, Display)]
+#[serde(rename_all = "camelCase")]
+#[strum(serialize_all = "camelCase")]
+pub enum WebhookDeliveryStatus {
+    Delivered,
+    Failed,
+}
+
+/// A single attempt at delivering an event to a webhook subscription. Retries
+/// and redeliveries are recorded as attempts of their own.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDelivery {
+    #[serde(rename = "_id")]
+    pub id: Id,
+    pub subscription_id: Id,
+    pub event_key: Id,
+    pub event_name: String,
+    pub url: String,
+    pub status: WebhookDeliveryStatus,
+    /// Status code of the response, unset when no response was received
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub status_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub environment: Environment,
+    pub ownership: Ownership,
+    #[serde(flatten, default)]
+    pub record_metadata: RecordMetadata,
+}
+
+impl WebhookDelivery {
+    pub fn new(subscription_id: Id, url: &str, event: &Event) -> Self {
+        Self {
+            id: Id::now(IdPrefix::WebhookDelivery),
+            subscription_id,
+            event_key: event.id,
+            event_name: event.name.clone(),
+            url: url.to_owned(),
+            status: WebhookDeliveryStatus::Failed,
+            status_code: None,
+            error: None,
+            duration_ms: 0,
+            environment: event.environment,
+            ownership: event.ownership.clone(),
+            record_metadata: RecordMetadata::default(),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{IntegrationOSError, InternalError};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+/// A request a provider sends to verify a webhook URL before it delivers events,
+/// such as Slack's `url_verification` or Meta's `hub.challenge`.
+///
+/// Handshakes are answered by the gateway directly and never become events.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookHandshake {
+    /// HTTP method of the verification request, any method matches when unset
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub method: Option<String>,
+    /// All of them have to match for a request to be treated as a handshake
+    pub conditions: Vec<HandshakeCondition>,
+    /// Path to the challenge in the `{headers, body, query}` of the request
+    pub challenge_path: String,
+    pub response: HandshakeResponse,
+}
+
+impl WebhookHandshake {
+    pub fn matches_method(&self, method: &str) -> bool {
+        self.method
+            .as_deref()
+            .map_or(true, |expected| expected.eq_ignore_ascii_case(method))
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(rename_all = "camelCase")]
+pub struct HandshakeCondition {
+    pub path: String,
+    /// Value the path must hold, the path only has to exist when unset
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub equals: Option<String>,
+}
+
+impl HandshakeCondition {
+    pub fn matches(&self, value: Option<&str>) -> bool {
+        match (&self.equals, value) {
+            (Some(expected), Some(value)) => expected == value,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum HandshakeResponse {
+    /// The challenge itself as plain text
+    Echo,
+    /// The challenge wrapped in an object, e.g. `{ "challenge": "..." }`
+    Json { key: String },
+    /// The challenge along with its hex encoded HMAC-SHA256 signature, as Zoom CRC expects.
+    ///
+    /// The signing secret is kept in the secrets service, owned by the owner of the
+    /// connection receiving the webhook, and only read when a challenge is answered.
+    #[serde(rename_all = "camelCase")]
+    HmacSha256 {
+        secrets_service_id: String,
+        challenge_key: String,
+        signature_key: String,
+    },
+}
+
+impl HandshakeResponse {
+    /// Id of the secret the response is signed with, if any
+    pub fn secrets_service_id(&self) -> Option<&str> {
+        match self {
+            Self::HmacSha256 {
+                secrets_service_id, ..
+            } => Some(secrets_service_id),
+            _ => None,
+        }
+    }
+
+    /// Builds the answer to a challenge. Strings are sent as plain text and
+    /// anything else as JSON.
+    ///
+    /// `secret` is the value of the secret referenced by [`Self::secrets_service_id`].
+    pub fn respond(
+        &self,
+        challenge: &str,
+        secret: Option<&str>,
+    ) -> Result<Value, IntegrationOSError> {
+        match self {
+            Self::Echo => Ok(Value::String(challenge.to_owned())),
+            Self::Json { key } => Ok(json!({ key: challenge })),
+            Self::HmacSha256 {
+                challenge_key,
+                signature_key,
+                ..
+            } => {
+                let secret = secret.ok_or_else(|| {
+                    InternalError::invalid_argument("Missing handshake secret", None)
+                })?;
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|e| {
+                    InternalError::invalid_argument(&format!("Invalid handshake secret: {e}"), None)
+                })?;
+                mac.update(challenge.as_bytes());
+                let signature = hex::encode(mac.finalize().into_bytes());
+
+                Ok(json!({
+                    challenge_key: challenge,
+                    signature_key: signature,
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_handshake_conditions() {
+        let condition = HandshakeCondition {
+            path: "$.body.type".to_owned(),
+            equals: Some("url_verification".to_owned()),
+        };
+        assert!(condition.matches(Some("url_verification")));
+        assert!(!condition.matches(Some("event_callback")));
+        assert!(!condition.matches(None));
+
+        let exists = HandshakeCondition {
+            path: "$.query.validationToken".to_owned(),
+            equals: None,
+        };
+        assert!(exists.matches(Some("token")));
+        assert!(!exists.matches(None));
+    }
+
+    #[test]
+    fn test_handshake_responses() {
+        assert_eq!(
+            HandshakeResponse::Echo.respond("abc", None).unwrap(),
+            json!("abc")
+        );
+        assert_eq!(
+            HandshakeResponse::Json {
+                key: "challenge".to_owned()
+            }
+            .respond("abc", None)
+            .unwrap(),
+            json!({ "challenge": "abc" })
+        );
+
+        let zoom = HandshakeResponse::HmacSha256 {
+            secrets_service_id: "secret_id".to_owned(),
+            challenge_key: "plainToken".to_owned(),
+            signature_key: "encryptedToken".to_owned(),
+        };
+        assert_eq!(
+            zoom.respond("The quick brown fox jumps over the lazy dog", Some("key"))
+                .unwrap(),
+            json!({
+                "plainToken": "The quick brown fox jumps over the lazy dog",
+                "encryptedToken": "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8",
+            })
+        );
+        assert!(zoom.respond("abc", None).is_err());
+    }
+
+    #[test]
+    fn test_handshake_deserializes() {
+        let handshake: WebhookHandshake = serde_json::from_value(json!({
+            "method": "GET",
+            "conditions": [{ "path": "$.query['hub.mode']", "equals": "subscribe" }],
+            "challengePath": "$.query['hub.challenge']",
+            "response": { "type": "echo" },
+        }))
+        .unwrap();
+
+        assert!(handshake.matches_method("get"));
+        assert!(!handshake.matches_method("POST"));
+        assert_eq!(handshake.response, HandshakeResponse::Echo);
+    }
+}
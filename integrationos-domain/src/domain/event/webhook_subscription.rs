@@ -0,0 +1,382 @@
+use super::Event;
+use crate::{
+    configuration::pipeline::PipelineConfig,
+    destination::{Action, Destination},
+    environment::Environment,
+    id::Id,
+    lifecycle::ConnectionLifecycle,
+    ownership::Ownership,
+    policies::{Policies, RetryPolicy},
+    record_metadata::RecordMetadata,
+    signature::Signature,
+    source::Source,
+    ApplicationError, IntegrationOSError, InternalError, Pipeline,
+};
+use hmac::{Hmac, Mac};
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    Url,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-pica-signature";
+pub const WEBHOOK_EVENT_HEADER: &str = "x-pica-event";
+pub const WEBHOOK_DELIVERY_HEADER: &str = "x-pica-delivery";
+/// Prefix of the keys of the pipelines deliveries of a subscription run through
+pub const WEBHOOK_PIPELINE_PREFIX: &str = "webhook::";
+const WEBHOOK_SECRET_PREFIX: &str = "whsec_";
+const WEBHOOK_SCHEME: &str = "https";
+
+/// An endpoint of an account that is notified about lifecycle events of the
+/// connections of the account.
+///
+/// Deliveries run as a pipeline of the event, so they are retried with the retry
+/// policy of the pipeline and can be redelivered by scheduling the pipeline again.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookSubscription {
+    #[serde(rename = "_id")]
+    pub id: Id,
+    pub url: String,
+    pub events: Vec<ConnectionLifecycle>,
+    /// Secret holding the [`WebhookSecret`] deliveries are signed with
+    pub secret_id: String,
+    pub environment: Environment,
+    pub ownership: Ownership,
+    #[serde(flatten, default)]
+    pub record_metadata: RecordMetadata,
+}
+
+impl WebhookSubscription {
+    pub fn pipeline_key(&self) -> String {
+        format!("{WEBHOOK_PIPELINE_PREFIX}{}", self.id)
+    }
+
+    /// Id of the subscription a pipeline delivers to, if it is a webhook pipeline
+    pub fn id_from_pipeline_key(pipeline_key: &str) -> Option<Id> {
+        pipeline_key
+            .strip_prefix(WEBHOOK_PIPELINE_PREFIX)
+            .and_then(|id| id.parse().ok())
+    }
+
+    /// The pipeline that delivers events to the subscription. It is never stored,
+    /// the destination only describes the request the event service makes.
+    pub fn pipeline(&self) -> Pipeline {
+        let key = self.pipeline_key();
+
+        Pipeline {
+            id: key.clone(),
+            environment: self.environment,
+            name: format!("Webhook {}", self.url),
+            key,
+            source: Source {
+                r#type: "webhook".to_owned(),
+                events: self.events.iter().map(ToString::to_string).collect(),
+                group: self.id.to_string(),
+            },
+            destination: Destination {
+                platform: "webhook".into(),
+                action: Action::Passthrough {
+                    method: http::Method::POST,
+                    path: self.url.as_str().into(),
+                },
+                connection_key: self.id.to_string().into(),
+            },
+            middleware: vec![],
+            ownership: self.ownership.clone(),
+            signature: Signature {
+                header: WEBHOOK_SIGNATURE_HEADER.to_owned(),
+                algorithm: "HMAC-SHA256".to_owned(),
+                secrets: [self.secret_id.clone(), String::new()],
+            },
+            config: Some(PipelineConfig {
+                policies: Policies {
+                    retry: RetryPolicy {
+                        maximum_attempts: 5,
+                        initial_interval: "10 seconds".to_owned(),
+                        backoff_coefficient: Some(3),
+                    },
+                    delay: None,
+                },
+                start_to_close_timeout: "10 seconds".to_owned(),
+            }),
+            record_metadata: self.record_metadata.clone(),
+        }
+    }
+
+    /// Subscriptions are only delivered over https to hosts that resolve to
+    /// public addresses, so that they can not be used to reach internal services
+    pub async fn validate_url(url: &str) -> Result<Url, IntegrationOSError> {
+        let parsed = Url::parse(url)
+            .map_err(|e| ApplicationError::bad_request(&format!("Invalid url: {e}"), None))?;
+        if parsed.scheme() != WEBHOOK_SCHEME {
+            return Err(ApplicationError::bad_request(
+                "Webhook url must use https",
+                None,
+            ));
+        }
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| ApplicationError::bad_request("Webhook url has no host", None))?;
+        // IPv6 hosts are written in brackets
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        resolve_public(host, parsed.port_or_known_default().unwrap_or_default()).await?;
+
+        Ok(parsed)
+    }
+
+    /// Body sent to the subscription for a lifecycle event
+    pub fn payload(event: &Event) -> Value {
+        let data = event
+            .parsed_body
+            .clone()
+            .or_else(|| serde_json::from_str(&event.body).ok())
+            .unwrap_or_default();
+
+        json!({
+            "id": event.id,
+            "type": event.name,
+            "environment": event.environment,
+            "createdAt": event.arrived_at.timestamp_millis(),
+            "data": data,
+        })
+    }
+}
+
+/// Resolves the hosts of webhook deliveries, failing for hosts with a private
+/// address, so that a subscription whose DNS changed after it was validated
+/// still can not reach internal services
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = resolve_public(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+async fn resolve_public(host: &str, port: u16) -> Result<Vec<SocketAddr>, IntegrationOSError> {
+    let addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| {
+            ApplicationError::bad_request(&format!("Could not resolve {host}: {e}"), None)
+        })?
+        .collect::<Vec<_>>();
+
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(ApplicationError::bad_request(
+            &format!("Webhook host {host} does not resolve to a public address"),
+            None,
+        ));
+    }
+
+    Ok(addrs)
+}
+
+/// Whether the address is reachable over the internet, as opposed to loopback,
+/// private, link local and other special purpose addresses
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // Shared address space of carrier grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                // IETF protocol assignments
+                || (a == 192 && b == 0 && c == 0)
+                // Benchmarking
+                || (a == 198 && (b & 0xfe) == 18)
+                || a == 0
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            let embedded = embedded_ipv4(ip);
+            if !embedded.is_empty() {
+                return embedded.into_iter().all(|ip| is_public(IpAddr::V4(ip)));
+            }
+
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local addresses
+                || (first & 0xfe00) == 0xfc00
+                // Link local addresses
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// IPv4 addresses an IPv6 address reaches through translation or tunnelling: IPv4
+/// mapped addresses, NAT64 (`64:ff9b::/96`) and 6to4 (`2002::/16`) addresses, and
+/// both the server and the client, XORed with `0xffff`, of Teredo (`2001::/32`)
+/// addresses
+fn embedded_ipv4(ip: Ipv6Addr) -> Vec<Ipv4Addr> {
+    let ipv4 = |high: u16, low: u16| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return vec![ip];
+    }
+    match ip.segments() {
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] => vec![ipv4(high, low)],
+        [0x2002, high, low, ..] => vec![ipv4(high, low)],
+        [0x2001, 0, server_high, server_low, _, _, client_high, client_low] => vec![
+            ipv4(server_high, server_low),
+            ipv4(!client_high, !client_low),
+        ],
+        _ => vec![],
+    }
+}
+
+/// Signing secret of a subscription as it is kept in the secrets store
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WebhookSecret {
+    pub secret: String,
+}
+
+impl WebhookSecret {
+    pub fn generate() -> Self {
+        Self {
+            secret: format!(
+                "{WEBHOOK_SECRET_PREFIX}{}",
+                hex::encode(rand::random::<[u8; 32]>())
+            ),
+        }
+    }
+
+    /// Value of the signature header of a delivery, the hex encoded HMAC-SHA256 of
+    /// `{timestamp}.{body}` so that receivers can reject replayed deliveries
+    pub fn sign(&self, timestamp: i64, body: &str) -> Result<String, IntegrationOSError> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .map_err(|e| InternalError::invalid_argument(&e.to_string(), Some("webhook_secret")))?;
+        mac.update(format!("{timestamp}.{body}").as_bytes());
+
+        Ok(format!(
+            "t={timestamp},v1={}",
+            hex::encode(mac.finalize().into_bytes())
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::prefix::IdPrefix;
+    use std::time::Duration;
+
+    #[test]
+    fn test_webhook_signature() {
+        let secret = WebhookSecret {
+            secret: "whsec_test".to_owned(),
+        };
+        let signature = secret
+            .sign(1700000000000, r#"{"type":"connection.created"}"#)
+            .unwrap();
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"whsec_test").unwrap();
+        mac.update(br#"1700000000000.{"type":"connection.created"}"#);
+        let expected = hex::encode(mac.finalize().into_bytes());
+        assert_eq!(signature, format!("t=1700000000000,v1={expected}"));
+
+        let generated = WebhookSecret::generate();
+        assert!(generated.secret.starts_with(WEBHOOK_SECRET_PREFIX));
+        assert_ne!(generated, WebhookSecret::generate());
+    }
+
+    #[test]
+    fn test_public_addresses() {
+        for ip in [
+            "93.184.215.14",
+            "2606:2800:21f:cb07:6820:80da:af6b:8b2c",
+            "64:ff9b::5db8:d70e",
+            "2002:5db8:d70e::1",
+            "2001:0:5db8:d70e::a247:28f1",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "192.0.0.8",
+            "198.18.0.1",
+            "198.19.255.254",
+            // NAT64 of 127.0.0.1 and 169.254.169.254
+            "64:ff9b::7f00:1",
+            "64:ff9b::a9fe:a9fe",
+            // 6to4 of 10.0.0.1 and 169.254.169.254
+            "2002:a00:1::1",
+            "2002:a9fe:a9fe::1",
+            // Teredo with a private server, then with the client 127.0.0.1
+            "2001:0:a00:1::a247:28f1",
+            "2001:0:5db8:d70e::80ff:fffe",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_urls_must_be_public_https() {
+        for url in [
+            "not a url",
+            "http://example.com/hooks",
+            "https://127.0.0.1/hooks",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]:8443/hooks",
+            "https://localhost/hooks",
+        ] {
+            assert!(
+                WebhookSubscription::validate_url(url).await.is_err(),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_webhook_pipeline() {
+        let subscription = WebhookSubscription {
+            id: Id::now(IdPrefix::WebhookSubscription),
+            url: "https://example.com/hooks".to_owned(),
+            events: vec![ConnectionLifecycle::Created, ConnectionLifecycle::Lost],
+            secret_id: "secret".to_owned(),
+            environment: Environment::Live,
+            ownership: Ownership::new("buildable".to_owned()),
+            record_metadata: RecordMetadata::default(),
+        };
+
+        let pipeline = subscription.pipeline();
+        assert_eq!(
+            WebhookSubscription::id_from_pipeline_key(&pipeline.key),
+            Some(subscription.id)
+        );
+        assert_eq!(
+            pipeline.source.events,
+            vec!["connection.created", "connection.lost"]
+        );
+        let retry = pipeline.config.unwrap().policies.retry;
+        assert_eq!(retry.get_backoff(1).unwrap(), Duration::from_secs(30));
+        assert_eq!(WebhookSubscription::id_from_pipeline_key("pipeline"), None);
+    }
+}
@@ -5,6 +5,7 @@ use std::{convert::TryFrom, fmt::Display, fmt::Formatter};
 #[cfg_attr(feature = "dummy", derive(fake::Dummy))]
 pub enum IdPrefix {
     Archive,
+    AuditLog,
     CommonEnum,
     CommonModel,
     Connection,
@@ -37,12 +38,15 @@ pub enum IdPrefix {
     Transaction,
     UnitTest,
     EarlyAccess,
+    WebhookDelivery,
+    WebhookSubscription,
 }
 
 impl Display for IdPrefix {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             IdPrefix::Archive => write!(f, "arch"),
+            IdPrefix::AuditLog => write!(f, "audit"),
             IdPrefix::CommonEnum => write!(f, "ce"),
             IdPrefix::CommonModel => write!(f, "cm"),
             IdPrefix::Connection => write!(f, "conn"),
@@ -75,6 +79,8 @@ impl Display for IdPrefix {
             IdPrefix::Transaction => write!(f, "tx"),
             IdPrefix::UnitTest => write!(f, "ut"),
             IdPrefix::EarlyAccess => write!(f, "ea"),
+            IdPrefix::WebhookDelivery => write!(f, "wh_dlv"),
+            IdPrefix::WebhookSubscription => write!(f, "wh_sub"),
         }
     }
 }
@@ -85,6 +91,7 @@ impl TryFrom<&str> for IdPrefix {
     fn try_from(s: &str) -> Result<Self, Self::Error> {
         match s {
             "arch" => Ok(IdPrefix::Archive),
+            "audit" => Ok(IdPrefix::AuditLog),
             "ce" => Ok(IdPrefix::CommonEnum),
             "cm" => Ok(IdPrefix::CommonModel),
             "conn" => Ok(IdPrefix::Connection),
@@ -117,6 +124,8 @@ impl TryFrom<&str> for IdPrefix {
             "tx" => Ok(IdPrefix::Transaction),
             "ut" => Ok(IdPrefix::UnitTest),
             "ea" => Ok(IdPrefix::EarlyAccess),
+            "wh_dlv" => Ok(IdPrefix::WebhookDelivery),
+            "wh_sub" => Ok(IdPrefix::WebhookSubscription),
             _ => Err(InternalError::invalid_argument(
                 &format!("Invalid ID prefix: {}", s),
                 None,
@@ -129,6 +138,7 @@ impl From<IdPrefix> for String {
     fn from(id: IdPrefix) -> Self {
         match id {
             IdPrefix::Archive => "arch".to_string(),
+            IdPrefix::AuditLog => "audit".to_string(),
             IdPrefix::CommonEnum => "ce".to_string(),
             IdPrefix::CommonModel => "cm".to_string(),
             IdPrefix::Connection => "conn".to_string(),
@@ -161,6 +171,8 @@ impl From<IdPrefix> for String {
             IdPrefix::Transaction => "tx".to_string(),
             IdPrefix::UnitTest => "ut".to_string(),
             IdPrefix::EarlyAccess => "ea".to_string(),
+            IdPrefix::WebhookDelivery => "wh_dlv".to_string(),
+            IdPrefix::WebhookSubscription => "wh_sub".to_string(),
         }
     }
 }
@@ -214,6 +226,7 @@ mod test {
             IdPrefix::SessionId
         );
         assert_eq!(IdPrefix::try_from("arch").unwrap(), IdPrefix::Archive);
+        assert_eq!(IdPrefix::try_from("audit").unwrap(), IdPrefix::AuditLog);
         assert_eq!(IdPrefix::try_from("evt_ac").unwrap(), IdPrefix::EventAccess);
         assert_eq!(IdPrefix::try_from("evt_k").unwrap(), IdPrefix::EventKey);
         assert_eq!(IdPrefix::try_from("idem").unwrap(), IdPrefix::Idempotency);
@@ -238,6 +251,14 @@ mod test {
         assert_eq!(IdPrefix::try_from("tx").unwrap(), IdPrefix::Transaction);
         assert_eq!(IdPrefix::try_from("ut").unwrap(), IdPrefix::UnitTest);
         assert_eq!(IdPrefix::try_from("ea").unwrap(), IdPrefix::EarlyAccess);
+        assert_eq!(
+            IdPrefix::try_from("wh_dlv").unwrap(),
+            IdPrefix::WebhookDelivery
+        );
+        assert_eq!(
+            IdPrefix::try_from("wh_sub").unwrap(),
+            IdPrefix::WebhookSubscription
+        );
     }
 
     #[test]
@@ -263,6 +284,7 @@ mod test {
         assert_eq!(format!("{}", IdPrefix::EmbedToken), "embed_tk");
         assert_eq!(format!("{}", IdPrefix::SessionId), "session_id");
         assert_eq!(format!("{}", IdPrefix::Archive), "arch");
+        assert_eq!(format!("{}", IdPrefix::AuditLog), "audit");
         assert_eq!(format!("{}", IdPrefix::EventAccess), "evt_ac");
         assert_eq!(format!("{}", IdPrefix::EventDependency), "evt_dep");
         assert_eq!(format!("{}", IdPrefix::EventKey), "evt_k");
@@ -284,5 +306,7 @@ mod test {
         assert_eq!(format!("{}", IdPrefix::Transaction), "tx");
         assert_eq!(format!("{}", IdPrefix::UnitTest), "ut");
         assert_eq!(format!("{}", IdPrefix::EarlyAccess), "ea");
+        assert_eq!(format!("{}", IdPrefix::WebhookDelivery), "wh_dlv");
+        assert_eq!(format!("{}", IdPrefix::WebhookSubscription), "wh_sub");
     }
 }
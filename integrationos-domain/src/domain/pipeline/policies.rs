@@ -6,6 +6,19 @@ use std::time::Duration;
 #[cfg_attr(feature = "dummy", derive(fake::Dummy))]
 pub struct Policies {
     pub retry: RetryPolicy,
+    /// Interval to wait after the event arrives before running the pipeline, e.g. `24 hours`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub delay: Option<String>,
+}
+
+impl Policies {
+    pub fn get_delay(&self) -> Result<Option<Duration>, IntegrationOSError> {
+        self.delay
+            .as_deref()
+            .map(|delay| parse_interval(delay, "delay policy"))
+            .transpose()
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -15,35 +28,127 @@ pub struct RetryPolicy {
     #[cfg_attr(feature = "dummy", dummy(faker = "0..10"))]
     pub maximum_attempts: u64,
     pub initial_interval: String,
+    /// Multiplies the interval after every failed attempt, the interval stays the
+    /// same when unset
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    #[cfg_attr(feature = "dummy", dummy(default))]
+    pub backoff_coefficient: Option<u32>,
 }
 
 impl RetryPolicy {
     pub fn get_interval(&self) -> Result<Duration, IntegrationOSError> {
-        let mut parts = self.initial_interval.split(' ');
-        let num = parts
-            .next()
-            .ok_or(InternalError::configuration_error(
-                "No number in retry policy interval",
-                None,
-            ))?
-            .parse()
-            .map_err(|e| {
-                InternalError::configuration_error(
-                    &format!("Invalid retry policy interval number: {}", e),
-                    None,
-                )
-            })?;
-        let amount = parts.next().ok_or(InternalError::configuration_error(
-            "No amount in retry policy interval",
+        parse_interval(&self.initial_interval, "retry policy interval")
+    }
+
+    /// Interval to wait after the given failed attempt, counting from zero
+    pub fn get_backoff(&self, attempt: u64) -> Result<Duration, IntegrationOSError> {
+        let interval = self.get_interval()?;
+        let Some(coefficient) = self.backoff_coefficient else {
+            return Ok(interval);
+        };
+
+        let factor = coefficient.saturating_pow(attempt.min(u32::MAX as u64) as u32);
+        Ok(interval.saturating_mul(factor))
+    }
+}
+
+fn parse_interval(interval: &str, name: &str) -> Result<Duration, IntegrationOSError> {
+    let mut parts = interval.split(' ');
+    let num: u64 = parts
+        .next()
+        .ok_or(InternalError::configuration_error(
+            &format!("No number in {name}"),
             None,
-        ))?;
-        match amount {
-            "seconds" | "second" => Ok(Duration::from_secs(num)),
-            "minute" | "minutes" => Ok(Duration::from_secs(num * 60)),
-            x => Err(InternalError::configuration_error(
-                &format!("Invalid retry policy interval amount: {}", x),
+        ))?
+        .parse()
+        .map_err(|e| {
+            InternalError::configuration_error(&format!("Invalid {name} number: {}", e), None)
+        })?;
+    let amount = parts.next().ok_or(InternalError::configuration_error(
+        &format!("No amount in {name}"),
+        None,
+    ))?;
+    let unit: u64 = match amount {
+        "seconds" | "second" => 1,
+        "minute" | "minutes" => 60,
+        "hour" | "hours" => 60 * 60,
+        "day" | "days" => 60 * 60 * 24,
+        x => {
+            return Err(InternalError::configuration_error(
+                &format!("Invalid {name} amount: {}", x),
                 None,
-            )),
+            ))
         }
+    };
+    num.checked_mul(unit)
+        .map(Duration::from_secs)
+        .ok_or(InternalError::configuration_error(
+            &format!("{name} is too large: {interval}"),
+            None,
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_interval() {
+        let retry = RetryPolicy {
+            maximum_attempts: 3,
+            initial_interval: "2 minutes".to_owned(),
+            backoff_coefficient: None,
+        };
+        assert_eq!(retry.get_interval().unwrap(), Duration::from_secs(120));
+        assert_eq!(retry.get_backoff(2).unwrap(), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn test_interval_overflow_is_rejected() {
+        let retry = RetryPolicy {
+            maximum_attempts: 3,
+            initial_interval: format!("{} days", u64::MAX / 2),
+            backoff_coefficient: None,
+        };
+        assert!(retry.get_interval().is_err());
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let retry = RetryPolicy {
+            maximum_attempts: 5,
+            initial_interval: "10 seconds".to_owned(),
+            backoff_coefficient: Some(2),
+        };
+        assert_eq!(retry.get_backoff(0).unwrap(), Duration::from_secs(10));
+        assert_eq!(retry.get_backoff(1).unwrap(), Duration::from_secs(20));
+        assert_eq!(retry.get_backoff(3).unwrap(), Duration::from_secs(80));
+        // The factor saturates rather than overflowing
+        assert_eq!(
+            retry.get_backoff(64).unwrap(),
+            Duration::from_secs(10) * u32::MAX
+        );
+    }
+
+    #[test]
+    fn test_delay_policy() {
+        let mut policies = Policies {
+            retry: RetryPolicy {
+                maximum_attempts: 3,
+                initial_interval: "1 second".to_owned(),
+                backoff_coefficient: None,
+            },
+            delay: None,
+        };
+        assert_eq!(policies.get_delay().unwrap(), None);
+
+        policies.delay = Some("24 hours".to_owned());
+        assert_eq!(
+            policies.get_delay().unwrap(),
+            Some(Duration::from_secs(24 * 60 * 60))
+        );
+
+        policies.delay = Some("1 fortnight".to_owned());
+        assert!(policies.get_delay().is_err());
     }
 }
@@ -1,5 +1,6 @@
 pub mod database_secret;
 pub mod hashed_secret;
+pub mod mtls_secret;
 pub mod oauth_secret;
 
 use crate::{IntegrationOSError, InternalError};
@@ -45,6 +46,11 @@ pub struct Secret {
     encrypted_secret: String,
     #[serde(default)]
     version: Option<SecretVersion>,
+    /// Key the secret was encrypted with, unset for secrets written before key rotation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<String>,
+    #[serde(default)]
+    deleted: bool,
 }
 
 impl Secret {
@@ -61,9 +67,29 @@ impl Secret {
             author: SecretAuthor::default(),
             encrypted_secret: secret,
             version,
+            key_id: None,
+            deleted: false,
         }
     }
 
+    pub fn with_key_id(mut self, key_id: String) -> Self {
+        self.key_id = Some(key_id);
+        self
+    }
+
+    /// Replaces the encrypted value, keeping the id and ownership of the secret
+    pub fn with_encrypted_secret(
+        mut self,
+        encrypted_secret: String,
+        version: SecretVersion,
+        key_id: String,
+    ) -> Self {
+        self.encrypted_secret = encrypted_secret;
+        self.version = Some(version);
+        self.key_id = Some(key_id);
+        self
+    }
+
     pub fn id(&self) -> String {
         self.id.clone()
     }
@@ -86,6 +112,10 @@ impl Secret {
         self.version
     }
 
+    pub fn key_id(&self) -> Option<String> {
+        self.key_id.clone()
+    }
+
     pub fn created_at(&self) -> i64 {
         self.created_at as i64
     }
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// Client certificate presented to platforms that require mutual TLS
+#[derive(Clone, Deserialize, Serialize)]
+pub struct MtlsSecret {
+    /// PEM encoded certificate chain of the client
+    #[serde(rename = "MTLS_CERTIFICATE")]
+    pub certificate: String,
+    /// PEM encoded private key of the client certificate
+    #[serde(rename = "MTLS_PRIVATE_KEY")]
+    pub private_key: String,
+    /// PEM encoded CA the platform certificate is issued by, when it is not a
+    /// public one
+    #[serde(
+        rename = "MTLS_CA_CERTIFICATE",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ca_certificate: Option<String>,
+}
@@ -44,6 +44,8 @@ impl OAuthSecret {
         }
     }
 
+    /// Refresh responses usually leave out what only the initial one has, e.g. the
+    /// tenant or instance url, so their metadata is merged into the stored one
     pub fn from_refresh(
         &self,
         oauth_response: OAuthResponse,
@@ -58,7 +60,7 @@ impl OAuthSecret {
             token_type: oauth_response.token_type,
             refresh_token: oauth_response.refresh_token,
             expires_in: oauth_response.expires_in,
-            metadata,
+            metadata: merge_metadata(self.metadata.clone(), metadata),
             request_payload: self.request_payload.clone(),
         }
     }
@@ -68,6 +70,17 @@ impl OAuthSecret {
     }
 }
 
+fn merge_metadata(stored: Value, refreshed: Value) -> Value {
+    match (stored, refreshed) {
+        (Value::Object(mut stored), Value::Object(refreshed)) => {
+            stored.extend(refreshed);
+            Value::Object(stored)
+        }
+        (stored, Value::Null) => stored,
+        (_, refreshed) => refreshed,
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct OAuthLegacySecret {
@@ -80,3 +93,76 @@ pub struct OAuthLegacySecret {
     #[serde(rename = "ACCESS_TOKEN_SECRET")]
     pub access_token_secret: String,
 }
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct ClientCredentialsSecret {
+    #[serde(rename = "CLIENT_ID")]
+    pub client_id: String,
+    #[serde(rename = "CLIENT_SECRET")]
+    pub client_secret: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct JwtBearerSecret {
+    #[serde(rename = "ISSUER")]
+    pub issuer: String,
+    #[serde(rename = "SUBJECT", default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// PEM encoded PKCS#8 key the assertion is signed with
+    #[serde(rename = "PRIVATE_KEY")]
+    pub private_key: String,
+    #[serde(rename = "KEY_ID", default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+}
+
+/// Token a platform issued for client credentials or a signed assertion
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessToken {
+    pub access_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    /// Unix timestamp in seconds after which the token is no longer used
+    pub expires_at: i64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn response(access_token: &str) -> OAuthResponse {
+        OAuthResponse {
+            access_token: access_token.to_owned(),
+            expires_in: 3600,
+            refresh_token: None,
+            token_type: None,
+        }
+    }
+
+    #[test]
+    fn test_refresh_keeps_the_initial_metadata() {
+        let secret = OAuthSecret::from_init(
+            response("initial"),
+            "client".to_owned(),
+            "secret".to_owned(),
+            json!({ "tenant": "acme", "scope": "read" }),
+            None,
+        );
+
+        let refreshed = secret.from_refresh(
+            response("refreshed"),
+            None,
+            None,
+            json!({ "scope": "read write" }),
+        );
+        assert_eq!(refreshed.access_token, "refreshed");
+        assert_eq!(
+            refreshed.metadata,
+            json!({ "tenant": "acme", "scope": "read write" })
+        );
+
+        let refreshed = secret.from_refresh(response("refreshed"), None, None, Value::Null);
+        assert_eq!(refreshed.metadata, secret.metadata);
+    }
+}
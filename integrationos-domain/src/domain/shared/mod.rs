@@ -1,3 +1,4 @@
 pub mod ownership;
 pub mod record_metadata;
+pub mod redaction;
 pub mod settings;
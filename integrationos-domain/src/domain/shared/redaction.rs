@@ -0,0 +1,687 @@
+use crate::{
+    payload_encryption::{PayloadEncryption, SealedValue},
+    Event, IntegrationOSError, InternalError, MongoStore, SecretExt, Transaction,
+};
+use bson::doc;
+use http::{HeaderMap, HeaderValue};
+use mongodb::options::ReturnDocument;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{Display, Formatter},
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+use tracing::{error, warn};
+
+pub const REDACTED: &str = "[REDACTED]";
+
+const EMAIL_PATTERN: &str = r"(?i)\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b";
+/// Bearer credentials, JWTs and prefixed API keys such as `sk_live_...`
+const TOKEN_PATTERN: &str = r"(?i)\bbearer\s+[a-z0-9\-._~+/]+=*|\beyJ[a-zA-Z0-9_-]+\.[a-zA-Z0-9_-]+\.[a-zA-Z0-9_-]+|\b(?:sk|pk|rk)_(?:live|test)_[a-zA-Z0-9]{16,}\b";
+const CARD_NUMBER_PATTERN: &str = r"\b\d(?:[ -]?\d){12,18}\b";
+
+/// What to redact from third party payloads before they are stored or logged.
+///
+/// Rules are configured globally and can be extended per connection definition.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionRules {
+    /// Paths in JSON payloads, e.g. `$.customer.email` or `$.cards[*].number`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub json_paths: Vec<String>,
+    /// Header names, matched case insensitively
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub detectors: Vec<Detector>,
+    /// Replaces values with tokens whose originals are sealed next to the payload
+    /// instead of masking them
+    #[serde(default)]
+    pub tokenize: bool,
+}
+
+impl RedactionRules {
+    pub fn is_empty(&self) -> bool {
+        self.json_paths.is_empty() && self.headers.is_empty() && self.detectors.is_empty()
+    }
+
+    pub fn merge(&self, other: &RedactionRules) -> RedactionRules {
+        let mut merged = self.clone();
+        merged.json_paths.extend(other.json_paths.iter().cloned());
+        merged.headers.extend(other.headers.iter().cloned());
+        merged.detectors.extend(other.detectors.iter().cloned());
+        merged.tokenize |= other.tokenize;
+        merged
+    }
+}
+
+impl Display for RedactionRules {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+impl FromStr for RedactionRules {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "dummy", derive(fake::Dummy))]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Detector {
+    Email,
+    Token,
+    /// 13 to 19 digits passing the Luhn check, either grouped by spaces or
+    /// dashes or starting with the prefix of a card network
+    CardNumber,
+    Custom {
+        pattern: String,
+    },
+}
+
+impl Detector {
+    fn pattern(&self) -> &str {
+        match self {
+            Self::Email => EMAIL_PATTERN,
+            Self::Token => TOKEN_PATTERN,
+            Self::CardNumber => CARD_NUMBER_PATTERN,
+            Self::Custom { pattern } => pattern.as_str(),
+        }
+    }
+}
+
+/// Originals of the values replaced by tokens, keyed by token
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Redactions {
+    pub tokens: BTreeMap<String, String>,
+}
+
+impl Redactions {
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    fn replace(&mut self, tokenize: bool, original: String) -> String {
+        if !tokenize {
+            return REDACTED.to_owned();
+        }
+        let token = format!("[tok:{}]", self.tokens.len());
+        self.tokens.insert(token.clone(), original);
+        token
+    }
+
+    /// Puts the original values back in place of their tokens
+    pub fn restore(&self, redacted: &str) -> String {
+        self.tokens
+            .iter()
+            .fold(redacted.to_owned(), |restored, (token, original)| {
+                restored.replace(token, original)
+            })
+    }
+
+    /// Restores JSON documents value by value, so originals are escaped, anything
+    /// else as is. Mirrors [`Redactor::redact_str`].
+    pub fn restore_str(&self, redacted: &str) -> String {
+        if self.is_empty() {
+            return redacted.to_owned();
+        }
+        match serde_json::from_str::<Value>(redacted) {
+            Ok(value @ (Value::Object(_) | Value::Array(_))) => {
+                self.restore_value(value).to_string()
+            }
+            _ => self.restore(redacted),
+        }
+    }
+
+    pub fn restore_value(&self, value: Value) -> Value {
+        match value {
+            Value::String(s) => Value::String(self.restore(&s)),
+            Value::Array(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| self.restore_value(value))
+                    .collect(),
+            ),
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .map(|(key, value)| (key, self.restore_value(value)))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+
+    pub fn restore_headers(&self, headers: &mut HeaderMap) {
+        for value in headers.values_mut() {
+            let Ok(redacted) = value.to_str() else {
+                continue;
+            };
+            if let Ok(restored) = HeaderValue::from_str(&self.restore(redacted)) {
+                *value = restored;
+            }
+        }
+    }
+}
+
+/// Compiled [`RedactionRules`]
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    json_paths: Vec<String>,
+    headers: Vec<String>,
+    detectors: Vec<(Detector, Regex)>,
+    tokenize: bool,
+}
+
+impl Redactor {
+    pub fn new(rules: &RedactionRules) -> Result<Self, IntegrationOSError> {
+        let detectors = rules
+            .detectors
+            .iter()
+            .map(|detector| {
+                Regex::new(detector.pattern())
+                    .map(|regex| (detector.clone(), regex))
+                    .map_err(|e| {
+                        InternalError::invalid_argument(
+                            &format!("Invalid redaction pattern {}: {e}", detector.pattern()),
+                            None,
+                        )
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            json_paths: rules.json_paths.clone(),
+            headers: rules
+                .headers
+                .iter()
+                .map(|header| header.to_lowercase())
+                .collect(),
+            detectors,
+            tokenize: rules.tokenize,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.json_paths.is_empty() && self.headers.is_empty() && self.detectors.is_empty()
+    }
+
+    pub fn redact_value(&self, value: Value, redactions: &mut Redactions) -> Value {
+        let mut value = value;
+        for path in &self.json_paths {
+            let mut replace = |matched: Value| {
+                let original = match matched {
+                    Value::String(s) => s,
+                    other => other.to_string(),
+                };
+                Some(Value::String(redactions.replace(self.tokenize, original)))
+            };
+            value = match jsonpath_lib::replace_with(value.clone(), path, &mut replace) {
+                Ok(replaced) => replaced,
+                Err(e) => {
+                    warn!("Invalid redaction path {path}: {e}");
+                    value
+                }
+            };
+        }
+
+        self.detect_in_value(value, redactions)
+    }
+
+    /// Redacts JSON documents by path and detectors, anything else only by detectors
+    pub fn redact_str(&self, s: &str, redactions: &mut Redactions) -> String {
+        if self.is_empty() {
+            return s.to_owned();
+        }
+        match serde_json::from_str::<Value>(s) {
+            Ok(value @ (Value::Object(_) | Value::Array(_))) => {
+                self.redact_value(value, redactions).to_string()
+            }
+            _ => self.detect(s, redactions),
+        }
+    }
+
+    pub fn redact_headers(&self, headers: &mut HeaderMap, redactions: &mut Redactions) {
+        for (name, value) in headers.iter_mut() {
+            let redacted = if self.headers.iter().any(|header| header == name.as_str()) {
+                let original = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                redactions.replace(self.tokenize, original)
+            } else if let Ok(original) = value.to_str() {
+                self.detect(original, redactions)
+            } else {
+                continue;
+            };
+
+            if let Ok(redacted) = HeaderValue::from_str(&redacted) {
+                *value = redacted;
+            }
+        }
+    }
+
+    fn detect_in_value(&self, value: Value, redactions: &mut Redactions) -> Value {
+        match value {
+            Value::String(s) => Value::String(self.detect(&s, redactions)),
+            Value::Array(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| self.detect_in_value(value, redactions))
+                    .collect(),
+            ),
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .map(|(key, value)| (key, self.detect_in_value(value, redactions)))
+                    .collect(),
+            ),
+            other => other,
+        }
+    }
+
+    fn detect(&self, s: &str, redactions: &mut Redactions) -> String {
+        self.detectors
+            .iter()
+            .fold(s.to_owned(), |s, (detector, regex)| {
+                regex
+                    .replace_all(&s, |captures: &Captures| {
+                        let matched = &captures[0];
+                        if *detector == Detector::CardNumber && !is_card_number(matched) {
+                            return matched.to_owned();
+                        }
+                        redactions.replace(self.tokenize, matched.to_owned())
+                    })
+                    .into_owned()
+            })
+    }
+}
+
+fn is_card_number(matched: &str) -> bool {
+    let digits = matched
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect::<String>();
+    let grouped = digits.len() != matched.len();
+
+    (13..=19).contains(&digits.len()) && (grouped || has_card_prefix(&digits)) && luhn(&digits)
+}
+
+/// Whether the digits start with the issuer prefix of a major card network, so
+/// ungrouped numeric ids are not mistaken for card numbers
+fn has_card_prefix(digits: &str) -> bool {
+    let prefix = |len: usize| digits[..len].parse::<u32>().unwrap_or_default();
+
+    digits.starts_with('4')
+        || (51..=55).contains(&prefix(2))
+        || (2221..=2720).contains(&prefix(4))
+        || matches!(prefix(2), 34 | 36 | 37 | 62 | 65)
+        || (300..=305).contains(&prefix(3))
+        || (644..=649).contains(&prefix(3))
+        || prefix(4) == 6011
+        || (3528..=3589).contains(&prefix(4))
+}
+
+fn luhn(number: &str) -> bool {
+    let digits = number
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .collect::<Vec<_>>();
+
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| match i % 2 {
+            0 => *digit,
+            _ if *digit > 4 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+
+    sum % 10 == 0
+}
+
+/// The data key a [`RedactionVault`] seals the tokenized values of an ownership with
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionKey {
+    #[serde(rename = "_id")]
+    pub buildable_id: String,
+    pub data_key_id: String,
+}
+
+/// Seals values with a single data key per ownership, so the number of secrets
+/// does not grow with the number of redacted payloads
+#[derive(Clone)]
+pub struct RedactionVault {
+    encryption: PayloadEncryption,
+    keys: MongoStore<RedactionKey>,
+    /// Data key ids by ownership
+    data_key_ids: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl RedactionVault {
+    pub fn new(
+        secrets_client: Arc<dyn SecretExt + Sync + Send>,
+        keys: MongoStore<RedactionKey>,
+    ) -> Self {
+        Self {
+            encryption: PayloadEncryption::new(secrets_client),
+            keys,
+            data_key_ids: Default::default(),
+        }
+    }
+
+    pub async fn seal(
+        &self,
+        value: &Value,
+        buildable_id: &str,
+    ) -> Result<SealedValue, IntegrationOSError> {
+        let data_key_id = self.data_key_id(buildable_id).await?;
+        self.encryption
+            .seal(value, &data_key_id, buildable_id)
+            .await
+    }
+
+    pub async fn open(
+        &self,
+        sealed: &SealedValue,
+        buildable_id: &str,
+    ) -> Result<Value, IntegrationOSError> {
+        self.encryption.open(sealed, buildable_id).await
+    }
+
+    async fn data_key_id(&self, buildable_id: &str) -> Result<String, IntegrationOSError> {
+        let cached = self
+            .data_key_ids
+            .read()
+            .ok()
+            .and_then(|data_key_ids| data_key_ids.get(buildable_id).cloned());
+        if let Some(data_key_id) = cached {
+            return Ok(data_key_id);
+        }
+
+        let data_key_id = match self.keys.get_one_by_id(buildable_id).await? {
+            Some(key) => key.data_key_id,
+            None => {
+                // Concurrent writers agree on the first key stored, the others are unused
+                let data_key_id = self.encryption.create_data_key(buildable_id).await?;
+                self.keys
+                    .collection
+                    .find_one_and_update(
+                        doc! { "_id": buildable_id },
+                        doc! { "$setOnInsert": { "dataKeyId": &data_key_id } },
+                    )
+                    .upsert(true)
+                    .return_document(ReturnDocument::After)
+                    .await?
+                    .map_or(data_key_id, |key| key.data_key_id)
+            }
+        };
+
+        if let Ok(mut data_key_ids) = self.data_key_ids.write() {
+            data_key_ids.insert(buildable_id.to_owned(), data_key_id.clone());
+        }
+
+        Ok(data_key_id)
+    }
+}
+
+/// Applies the redaction rules and seals the originals of tokenized values, when
+/// a vault is available.
+#[derive(Clone, Default)]
+pub struct RedactionService {
+    rules: RedactionRules,
+    redactor: Redactor,
+    vault: Option<RedactionVault>,
+}
+
+impl RedactionService {
+    /// Without a vault tokenized values are masked instead
+    pub fn new(
+        rules: RedactionRules,
+        vault: Option<RedactionVault>,
+    ) -> Result<Self, IntegrationOSError> {
+        let rules = RedactionRules {
+            tokenize: rules.tokenize && vault.is_some(),
+            ..rules
+        };
+
+        Ok(Self {
+            redactor: Redactor::new(&rules)?,
+            rules,
+            vault,
+        })
+    }
+
+    pub fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
+    /// Redactor for the global rules extended with those of a connection definition
+    pub fn redactor_with(
+        &self,
+        rules: Option<&RedactionRules>,
+    ) -> Result<Redactor, IntegrationOSError> {
+        match rules {
+            Some(rules) if !rules.is_empty() => {
+                let mut rules = self.rules.merge(rules);
+                rules.tokenize &= self.vault.is_some();
+                Redactor::new(&rules)
+            }
+            _ => Ok(self.redactor.clone()),
+        }
+    }
+
+    fn redactor_or_global(&self, rules: Option<&RedactionRules>) -> Redactor {
+        self.redactor_with(rules).unwrap_or_else(|e| {
+            error!("Invalid connection definition redaction rules, using the global ones: {e}");
+            self.redactor.clone()
+        })
+    }
+
+    /// Redacts an event with the global rules and those of its connection definition
+    pub async fn redact_event(&self, event: &mut Event, rules: Option<&RedactionRules>) {
+        let redactions = event.redact(&self.redactor_or_global(rules));
+        event.redacted_values = self.store(redactions, &event.ownership.id).await;
+    }
+
+    /// Redacts a transaction with the global rules and those of its connection definition
+    pub async fn redact_transaction(
+        &self,
+        mut transaction: Transaction,
+        rules: Option<&RedactionRules>,
+    ) -> Transaction {
+        let redactions = transaction.redact(&self.redactor_or_global(rules));
+        transaction.redacted_values = self.store(redactions, &transaction.ownership.id).await;
+        transaction
+    }
+
+    /// Encrypts a value that must not be stored in plaintext, such as the
+    /// original of an event waiting to be delivered
+    pub async fn seal(
+        &self,
+        value: &Value,
+        buildable_id: &str,
+    ) -> Result<SealedValue, IntegrationOSError> {
+        match &self.vault {
+            Some(vault) => vault.seal(value, buildable_id).await,
+            None => Err(InternalError::configuration_error(
+                "No secrets service to seal values with",
+                None,
+            )),
+        }
+    }
+
+    pub async fn open(
+        &self,
+        sealed: &SealedValue,
+        buildable_id: &str,
+    ) -> Result<Value, IntegrationOSError> {
+        match &self.vault {
+            Some(vault) => vault.open(sealed, buildable_id).await,
+            None => Err(InternalError::configuration_error(
+                "No secrets service to open sealed values with",
+                None,
+            )),
+        }
+    }
+
+    /// Opens the originals of the values tokenized in a payload
+    pub async fn reveal(
+        &self,
+        sealed: &SealedValue,
+        buildable_id: &str,
+    ) -> Result<Redactions, IntegrationOSError> {
+        let tokens = self.open(sealed, buildable_id).await?;
+        let tokens = serde_json::from_value(tokens).map_err(|e| {
+            InternalError::deserialize_error(&format!("Invalid redacted values: {e}"), None)
+        })?;
+
+        Ok(Redactions { tokens })
+    }
+
+    /// Seals the originals of tokenized values. Tokens that cannot be sealed
+    /// cannot be resolved, so they end up masked.
+    async fn store(&self, redactions: Redactions, buildable_id: &str) -> Option<SealedValue> {
+        if self.vault.is_none() || redactions.is_empty() {
+            return None;
+        }
+
+        match self.seal(&json!(redactions.tokens), buildable_id).await {
+            Ok(sealed) => Some(sealed),
+            Err(e) => {
+                error!("Could not store redacted values: {e}");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rules() -> RedactionRules {
+        RedactionRules {
+            json_paths: vec!["$.customer.name".to_owned()],
+            headers: vec!["X-Api-Key".to_owned()],
+            detectors: vec![Detector::Email, Detector::Token, Detector::CardNumber],
+            tokenize: false,
+        }
+    }
+
+    #[test]
+    fn test_redact_value() {
+        let redactor = Redactor::new(&rules()).unwrap();
+        let mut redactions = Redactions::default();
+
+        let redacted = redactor.redact_value(
+            json!({
+                "customer": { "name": "Jane Doe", "note": "reach me at jane@example.com" },
+                "card": "4242 4242 4242 4242",
+                "order": "1234567890123",
+                "auth": "Bearer abc.def",
+            }),
+            &mut redactions,
+        );
+
+        assert_eq!(
+            redacted,
+            json!({
+                "customer": { "name": REDACTED, "note": format!("reach me at {REDACTED}") },
+                "card": REDACTED,
+                "order": "1234567890123",
+                "auth": REDACTED,
+            })
+        );
+        assert!(redactions.is_empty());
+    }
+
+    #[test]
+    fn test_redact_headers() {
+        let redactor = Redactor::new(&rules()).unwrap();
+        let mut redactions = Redactions::default();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        headers.insert("authorization", HeaderValue::from_static("Bearer abc"));
+        headers.insert("accept", HeaderValue::from_static("application/json"));
+
+        redactor.redact_headers(&mut headers, &mut redactions);
+
+        assert_eq!(headers["x-api-key"], REDACTED);
+        assert_eq!(headers["authorization"], REDACTED);
+        assert_eq!(headers["accept"], "application/json");
+    }
+
+    #[test]
+    fn test_tokenize_and_restore() {
+        let redactor = Redactor::new(&RedactionRules {
+            tokenize: true,
+            ..rules()
+        })
+        .unwrap();
+        let mut redactions = Redactions::default();
+
+        let original = "contact jane@example.com or john@example.com";
+        let redacted = redactor.redact_str(original, &mut redactions);
+
+        assert_eq!(redacted, "contact [tok:0] or [tok:1]");
+        assert_eq!(redactions.restore(&redacted), original);
+    }
+
+    #[test]
+    fn test_restore_escapes_json_originals() {
+        let redactor = Redactor::new(&RedactionRules {
+            tokenize: true,
+            ..rules()
+        })
+        .unwrap();
+        let mut redactions = Redactions::default();
+
+        let original = json!({ "customer": { "name": "Jane \"JD\" Doe" }, "id": 1 }).to_string();
+        let redacted = redactor.redact_str(&original, &mut redactions);
+        assert!(!redacted.contains("Jane"));
+
+        let restored = redactions.restore_str(&redacted);
+        assert_eq!(
+            serde_json::from_str::<Value>(&restored).unwrap(),
+            serde_json::from_str::<Value>(&original).unwrap()
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        redactor.redact_headers(&mut headers, &mut redactions);
+        assert_ne!(headers["x-api-key"], "secret");
+        redactions.restore_headers(&mut headers);
+        assert_eq!(headers["x-api-key"], "secret");
+    }
+
+    #[test]
+    fn test_card_numbers_need_grouping_or_a_network_prefix() {
+        assert!(is_card_number("4242 4242 4242 4242"));
+        assert!(is_card_number("4242424242424242"));
+        assert!(is_card_number("5555-5555-5555-4444"));
+        assert!(is_card_number("378282246310005"));
+        // Passes the Luhn check but no network starts with 1
+        assert!(!is_card_number("1000000000000008"));
+        assert!(is_card_number("1000 0000 0000 0008"));
+        assert!(!is_card_number("4242424242424241"));
+        assert!(!is_card_number("424242424242"));
+    }
+
+    #[test]
+    fn test_invalid_custom_pattern() {
+        assert!(Redactor::new(&RedactionRules {
+            detectors: vec![Detector::Custom {
+                pattern: "(".to_owned()
+            }],
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
@@ -8,4 +8,8 @@ pub struct Settings {
     pub show_secret: bool,
     pub allow_custom_events: bool,
     pub oauth: bool,
+    /// Every call to the platform presents the client certificate stored in the
+    /// connection secret, whether or not the model definition asks for it
+    #[serde(default)]
+    pub mtls: bool,
 }
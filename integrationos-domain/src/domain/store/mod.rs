@@ -106,5 +106,17 @@ generate_stores!(
     Transactions,
     "event-transactions",
     Clients,
-    "clients"
+    "clients",
+    AuditLogs,
+    "audit-logs",
+    WebhookSubscriptions,
+    "webhook-subscriptions",
+    WebhookDeliveries,
+    "webhook-deliveries",
+    SecretReencryptions,
+    "secret-reencryptions",
+    RedactionKeys,
+    "redaction-keys",
+    AuthKitTokens,
+    "authkit-tokens"
 );
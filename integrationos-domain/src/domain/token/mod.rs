@@ -1,9 +1,10 @@
 use crate::{
-    connection_oauth_definition::ConnectedPlatform, environment::Environment, ownership::Ownership,
-    record_metadata::RecordMetadata, Connection, Id,
+    connection_oauth_definition::ConnectedPlatform, environment::Environment, id::prefix::IdPrefix,
+    ownership::Ownership, record_metadata::RecordMetadata, Connection, ConnectionIdentityType, Id,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -72,3 +73,107 @@ pub struct EventLink {
     pub usage_source: String,
     pub expires_at: i64,
 }
+
+/// Short lived token a frontend connects accounts with on behalf of one of the
+/// identities of an account, so that it never needs the secret key of the account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthKitToken {
+    #[serde(rename = "_id")]
+    pub id: Id,
+    /// SHA-256 of the token, which is only returned when it is issued
+    pub token_hash: String,
+    pub identity: String,
+    pub identity_type: ConnectionIdentityType,
+    pub group: String,
+    /// Connection definitions the token can connect, any of them when empty
+    #[serde(default)]
+    pub connection_definition_ids: Vec<Id>,
+    /// Event access of the key the token was issued with, connections are
+    /// created the same way as with the key
+    pub event_access_id: Id,
+    pub environment: Environment,
+    pub ownership: Ownership,
+    /// Stored as a date so that a TTL index removes the token once it expired
+    pub expires_at: bson::DateTime,
+    #[serde(flatten, default)]
+    pub record_metadata: RecordMetadata,
+}
+
+impl AuthKitToken {
+    pub fn generate_token() -> String {
+        format!(
+            "{}_{}",
+            IdPrefix::LinkToken,
+            hex::encode(rand::random::<[u8; 32]>())
+        )
+    }
+
+    /// Tokens are looked up by their hash, so that a leaked collection does
+    /// not leak usable tokens
+    pub fn hash(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    /// Whether the token could have been generated, so that others are
+    /// rejected without looking them up
+    pub fn is_well_formed(token: &str) -> bool {
+        token
+            .strip_prefix(&format!("{}_", IdPrefix::LinkToken))
+            .is_some_and(|random| {
+                random.len() == 64 && random.bytes().all(|byte| byte.is_ascii_hexdigit())
+            })
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.timestamp_millis() <= now
+    }
+
+    pub fn allows(&self, connection_definition_id: &Id) -> bool {
+        self.connection_definition_ids.is_empty()
+            || self
+                .connection_definition_ids
+                .contains(connection_definition_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authkit_token() {
+        let allowed = Id::now(IdPrefix::ConnectionDefinition);
+        let generated = AuthKitToken::generate_token();
+        let mut token = AuthKitToken {
+            id: Id::now(IdPrefix::EmbedToken),
+            token_hash: AuthKitToken::hash(&generated),
+            identity: "org_123".to_owned(),
+            identity_type: ConnectionIdentityType::Organization,
+            group: "org_123".to_owned(),
+            connection_definition_ids: vec![],
+            event_access_id: Id::now(IdPrefix::EventAccess),
+            environment: Environment::Test,
+            ownership: Ownership::new("buildable".to_owned()),
+            expires_at: bson::DateTime::from_millis(1000),
+            record_metadata: RecordMetadata::default(),
+        };
+
+        assert!(generated.starts_with("ln_tk_"));
+        assert_ne!(generated, AuthKitToken::generate_token());
+        assert!(AuthKitToken::is_well_formed(&generated));
+        assert!(!AuthKitToken::is_well_formed("ln_tk_invalid"));
+        assert!(!AuthKitToken::is_well_formed(
+            &generated.replace("ln_tk_", "sk_")
+        ));
+        assert_eq!(token.token_hash.len(), 64);
+        assert_ne!(token.token_hash, generated);
+        assert!(!token.is_expired(999));
+        assert!(token.is_expired(1000));
+
+        assert!(token.allows(&allowed));
+        token.connection_definition_ids = vec![allowed];
+        assert!(token.allows(&allowed));
+        assert!(!token.allows(&Id::now(IdPrefix::ConnectionDefinition)));
+    }
+}
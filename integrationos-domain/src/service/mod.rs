@@ -1 +1,2 @@
+pub mod scripting;
 pub mod telemetry;
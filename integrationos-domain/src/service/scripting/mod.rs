@@ -0,0 +1,273 @@
+mod runtime;
+
+use self::runtime::ScriptRuntime;
+use crate::{script::ScriptConfig, ApplicationError, IntegrationOSError, InternalError};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+use tokio::sync::{oneshot, Semaphore};
+use tracing::error;
+
+// histogram of script execution time, including the time spent waiting for a worker
+pub const SCRIPT_LATENCY_HISTOGRAM: &str = "script_execution_seconds";
+// counter of failed script executions
+pub const SCRIPT_FAILURES_COUNTER: &str = "script_failures";
+// counter of compiled script cache lookups, labelled hit or miss
+pub const SCRIPT_CACHE_COUNTER: &str = "script_cache_lookups";
+pub const SCRIPT_STATUS_LABEL: &str = "status";
+pub const SCRIPT_CACHE_LABEL: &str = "result";
+
+struct ScriptJob {
+    code: Arc<str>,
+    entry: Arc<str>,
+    args: Value,
+    respond: oneshot::Sender<Result<Value, IntegrationOSError>>,
+}
+
+/// Executes JavaScript on a bounded pool of sandboxed V8 isolates.
+///
+/// Every worker is a dedicated OS thread owning its own isolate, so a slow or
+/// runaway script never blocks the async runtime. Calls are limited in CPU time
+/// and heap size as configured in [`ScriptConfig`], and at most `workers + queue_size`
+/// calls can be in flight before callers start waiting for a slot.
+#[derive(Clone)]
+pub struct ScriptService {
+    sender: Sender<ScriptJob>,
+    permits: Arc<Semaphore>,
+}
+
+impl ScriptService {
+    pub fn new(config: &ScriptConfig) -> Result<Self, IntegrationOSError> {
+        let (sender, receiver) = mpsc::channel::<ScriptJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..config.workers.max(1) {
+            let receiver = receiver.clone();
+            let config = config.clone();
+            thread::Builder::new()
+                .name(format!("script-worker-{i}"))
+                .spawn(move || Self::work(config, receiver))
+                .map_err(|e| {
+                    InternalError::io_err(&e.to_string(), Some("Failed to spawn script worker"))
+                })?;
+        }
+
+        Ok(Self {
+            sender,
+            permits: Arc::new(Semaphore::new(config.workers.max(1) + config.queue_size)),
+        })
+    }
+
+    fn work(config: ScriptConfig, receiver: Arc<Mutex<Receiver<ScriptJob>>>) {
+        let mut runtime = match ScriptRuntime::new(&config) {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                error!("Failed to create script runtime: {e}");
+                return;
+            }
+        };
+
+        loop {
+            let job = match receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => return,
+            };
+            let Ok(job) = job else {
+                // Every handle to the service has been dropped
+                return;
+            };
+
+            let result = runtime.call(&job.code, &job.entry, &job.args);
+            let _ = job.respond.send(result);
+        }
+    }
+
+    /// Calls the function `entry` defined in `code`.
+    ///
+    /// `args` must serialize to a JSON array, e.g. a tuple, whose elements are passed
+    /// as the function arguments. Async functions are awaited.
+    pub async fn call<A, R>(
+        &self,
+        code: &str,
+        entry: &str,
+        args: A,
+    ) -> Result<R, IntegrationOSError>
+    where
+        A: Serialize,
+        R: DeserializeOwned,
+    {
+        let start = Instant::now();
+        let result = self.execute(code, entry, args).await;
+        let status = if result.is_ok() { "success" } else { "failure" };
+        metrics::histogram!(SCRIPT_LATENCY_HISTOGRAM, start.elapsed(), SCRIPT_STATUS_LABEL => status);
+
+        result.inspect_err(|e| {
+            metrics::increment_counter!(SCRIPT_FAILURES_COUNTER, SCRIPT_STATUS_LABEL => e.as_ref().to_string());
+        })
+    }
+
+    async fn execute<A, R>(&self, code: &str, entry: &str, args: A) -> Result<R, IntegrationOSError>
+    where
+        A: Serialize,
+        R: DeserializeOwned,
+    {
+        let args = serde_json::to_value(args).map_err(|e| {
+            InternalError::serialize_error(&e.to_string(), Some("Invalid script arguments"))
+        })?;
+        if !args.is_array() {
+            return Err(InternalError::invalid_argument(
+                "Script arguments must be an array",
+                None,
+            ));
+        }
+
+        let _permit = self.permits.acquire().await.map_err(|_| {
+            ApplicationError::service_unavailable("Script service is shutting down", None)
+        })?;
+
+        let (respond, response) = oneshot::channel();
+        self.sender
+            .send(ScriptJob {
+                code: code.into(),
+                entry: entry.into(),
+                args,
+                respond,
+            })
+            .map_err(|_| {
+                ApplicationError::service_unavailable("No script worker is available", None)
+            })?;
+
+        let value = response.await.map_err(|_| {
+            InternalError::script_error("Script worker stopped", Some("Worker stopped"))
+        })??;
+
+        serde_json::from_value(value).map_err(|e| {
+            InternalError::deserialize_error(&e.to_string(), Some("Invalid script result"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn service() -> ScriptService {
+        ScriptService::new(&ScriptConfig {
+            timeout_millis: 500,
+            workers: 1,
+            cache_size: 2,
+            ..Default::default()
+        })
+        .expect("Failed to create script service")
+    }
+
+    #[tokio::test]
+    async fn test_call_with_multiple_arguments() {
+        let service = service();
+        let code =
+            "function transform(event, context) { return { name: event.name, id: context.id }; }";
+
+        let value: Value = service
+            .call(code, "transform", (json!({"name": "a"}), json!({"id": 1})))
+            .await
+            .expect("Failed to call script");
+
+        assert_eq!(value, json!({"name": "a", "id": 1}));
+    }
+
+    #[tokio::test]
+    async fn test_call_awaits_async_functions() {
+        let service = service();
+        let code = "async function compute(input) { return input.value * 2; }";
+
+        let value: i64 = service
+            .call(code, "compute", (json!({"value": 21}),))
+            .await
+            .expect("Failed to call script");
+
+        assert_eq!(value, 42);
+    }
+
+    #[tokio::test]
+    async fn test_runaway_script_times_out_and_worker_recovers() {
+        let service = service();
+
+        let result: Result<Value, _> = service
+            .call("function spin() { while (true) {} }", "spin", ((),))
+            .await;
+        assert!(result.is_err());
+
+        let value: String = service
+            .call("function hello() { return 'hello'; }", "hello", ((),))
+            .await
+            .expect("Worker did not recover after timeout");
+        assert_eq!(value, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_runaway_top_level_times_out_and_worker_recovers() {
+        let service = service();
+
+        let result: Result<Value, _> = service
+            .call("while (true) {}\nfunction spin() {}", "spin", ((),))
+            .await;
+        assert!(result.is_err());
+
+        let value: String = service
+            .call("function hello() { return 'hello'; }", "hello", ((),))
+            .await
+            .expect("Worker did not recover after timeout");
+        assert_eq!(value, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_keyed_by_content() {
+        let service = service();
+
+        for i in 0..5 {
+            let code = format!("function version() {{ return {i}; }}");
+            let value: i64 = service
+                .call(&code, "version", ((),))
+                .await
+                .expect("Failed to call script");
+            assert_eq!(value, i);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_arguments_are_not_evaluated_as_code() {
+        let service = service();
+        let input = "\")); throw new Error(\"injected\"); //";
+
+        let value: String = service
+            .call("function echo(input) { return input; }", "echo", (input,))
+            .await
+            .expect("Failed to call script");
+
+        assert_eq!(value, input);
+    }
+
+    #[tokio::test]
+    async fn test_large_numbers_stay_floats() {
+        let service = service();
+
+        let value: Value = service
+            .call(
+                "function numbers() { return { small: 3, large: 1e20 }; }",
+                "numbers",
+                ((),),
+            )
+            .await
+            .expect("Failed to call script");
+
+        assert_eq!(value, json!({ "small": 3, "large": 1e20 }));
+    }
+}
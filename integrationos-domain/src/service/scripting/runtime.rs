@@ -0,0 +1,257 @@
+use super::{SCRIPT_CACHE_COUNTER, SCRIPT_CACHE_LABEL};
+use crate::{
+    prelude::HashExt, script::ScriptConfig, HashKecAlg, IntegrationOSError, InternalError,
+};
+use deno_core::{serde_v8, v8, JsRuntime, PollEventLoopOptions, RuntimeOptions};
+use indexmap::IndexMap;
+use serde_json::{Number, Value};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+const CONSOLE_SHIM: &str =
+    "const console = { log: function(expr) { Deno.core.print(expr + '\\n', false); } };";
+
+/// A single V8 isolate owned by a script worker thread.
+///
+/// Scripts are compiled once and their entry function is kept, keyed by the hash
+/// of their content, in a bounded LRU. V8 cannot unload a compiled script, so
+/// once as many scripts have been evicted as the cache can hold, the isolate is
+/// recreated to release their memory.
+pub(super) struct ScriptRuntime {
+    runtime: JsRuntime,
+    compiled: IndexMap<String, v8::Global<v8::Function>>,
+    evicted: usize,
+    heap_exceeded: Arc<AtomicBool>,
+    timeout: Duration,
+    max_heap_size: usize,
+    cache_size: usize,
+}
+
+impl ScriptRuntime {
+    pub(super) fn new(config: &ScriptConfig) -> Result<Self, IntegrationOSError> {
+        let max_heap_size = config.max_heap_size_mb * 1024 * 1024;
+        let (runtime, heap_exceeded) = Self::isolate(max_heap_size)?;
+
+        Ok(Self {
+            runtime,
+            compiled: IndexMap::with_capacity(config.cache_size),
+            evicted: 0,
+            heap_exceeded,
+            timeout: Duration::from_millis(config.timeout_millis),
+            max_heap_size,
+            cache_size: config.cache_size.max(1),
+        })
+    }
+
+    fn isolate(max_heap_size: usize) -> Result<(JsRuntime, Arc<AtomicBool>), IntegrationOSError> {
+        let mut runtime = JsRuntime::new(RuntimeOptions {
+            create_params: Some(v8::CreateParams::default().heap_limits(0, max_heap_size)),
+            ..Default::default()
+        });
+
+        let heap_exceeded = Arc::new(AtomicBool::new(false));
+        let exceeded = heap_exceeded.clone();
+        let handle = runtime.v8_isolate().thread_safe_handle();
+        runtime.add_near_heap_limit_callback(move |current, _| {
+            // Terminate the running script and give V8 enough room to unwind it
+            // instead of aborting the whole process. The isolate is discarded afterwards.
+            exceeded.store(true, Ordering::SeqCst);
+            handle.terminate_execution();
+            current * 2
+        });
+
+        runtime
+            .execute_script("<console>", CONSOLE_SHIM.to_string())
+            .map_err(|e| {
+                InternalError::script_error(&e.to_string(), Some("Failed to initialize runtime"))
+            })?;
+
+        Ok((runtime, heap_exceeded))
+    }
+
+    fn reset(&mut self) -> Result<(), IntegrationOSError> {
+        let (runtime, heap_exceeded) = Self::isolate(self.max_heap_size)?;
+        self.runtime = runtime;
+        self.heap_exceeded = heap_exceeded;
+        self.compiled.clear();
+        self.evicted = 0;
+        Ok(())
+    }
+
+    /// Returns the cached `entry` function of the script. On a miss, room is made for
+    /// it, recreating the isolate if needed, so it can be compiled on the current one
+    fn cached(
+        &mut self,
+        hash: &str,
+    ) -> Result<Option<v8::Global<v8::Function>>, IntegrationOSError> {
+        if let Some(index) = self.compiled.get_index_of(hash) {
+            metrics::increment_counter!(SCRIPT_CACHE_COUNTER, SCRIPT_CACHE_LABEL => "hit");
+            self.compiled.move_index(index, self.compiled.len() - 1);
+            return Ok(Some(self.compiled[self.compiled.len() - 1].clone()));
+        }
+
+        metrics::increment_counter!(SCRIPT_CACHE_COUNTER, SCRIPT_CACHE_LABEL => "miss");
+        if self.compiled.len() >= self.cache_size {
+            self.compiled.shift_remove_index(0);
+            self.evicted += 1;
+            if self.evicted >= self.cache_size {
+                self.reset()?;
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Compiles the script, running its top level, and caches its `entry` function
+    fn compile(
+        &mut self,
+        hash: String,
+        code: &str,
+        entry: &str,
+    ) -> Result<v8::Global<v8::Function>, IntegrationOSError> {
+        let source = format!("(function() {{\n{code}\nreturn {entry};\n}})();");
+        let value = self
+            .runtime
+            .execute_script("<script>", source)
+            .map_err(|e| {
+                InternalError::script_error(&e.to_string(), Some("Failed to compile script"))
+            })?;
+
+        let function = {
+            let scope = &mut self.runtime.handle_scope();
+            let local = v8::Local::new(scope, value);
+            let function = v8::Local::<v8::Function>::try_from(local).map_err(|_| {
+                InternalError::script_error(
+                    &format!("{entry} is not a function"),
+                    Some("Failed to compile script"),
+                )
+            })?;
+            v8::Global::new(scope, function)
+        };
+        self.compiled.insert(hash, function.clone());
+
+        Ok(function)
+    }
+
+    /// Calls `entry` with the elements of `args`, which must be a JSON array, as its arguments
+    pub(super) fn call(
+        &mut self,
+        code: &str,
+        entry: &str,
+        args: &Value,
+    ) -> Result<Value, IntegrationOSError> {
+        let hash = HashKecAlg::new().hash(&format!("{entry}\n{code}"))?;
+        let cached = self.cached(&hash)?;
+
+        // The watchdog covers compiling too, as compiling runs the top level of the script
+        let (done, finished) = mpsc::channel::<()>();
+        let handle = self.runtime.v8_isolate().thread_safe_handle();
+        let timeout = self.timeout;
+        let watchdog = thread::spawn(move || match finished.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => handle.terminate_execution(),
+            _ => false,
+        });
+
+        let result = match cached {
+            Some(function) => Ok(function),
+            None => self.compile(hash, code, entry),
+        }
+        .and_then(|function| self.execute(&function, args));
+
+        let _ = done.send(());
+        let timed_out = watchdog.join().unwrap_or_default();
+        let heap_exceeded = self.heap_exceeded.load(Ordering::SeqCst);
+
+        if timed_out || heap_exceeded {
+            self.reset()?;
+        }
+
+        if timed_out {
+            return Err(InternalError::timeout(
+                &format!("Script exceeded the time limit of {:?}", self.timeout),
+                Some("script"),
+            ));
+        }
+
+        if heap_exceeded {
+            return Err(InternalError::script_error(
+                &format!(
+                    "Script exceeded the heap limit of {} bytes",
+                    self.max_heap_size
+                ),
+                Some("Heap limit exceeded"),
+            ));
+        }
+
+        result
+    }
+
+    fn execute(
+        &mut self,
+        function: &v8::Global<v8::Function>,
+        args: &Value,
+    ) -> Result<Value, IntegrationOSError> {
+        let args = {
+            let scope = &mut self.runtime.handle_scope();
+            args.as_array()
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .map(|arg| {
+                    let local = serde_v8::to_v8(scope, arg).map_err(|e| {
+                        InternalError::serialize_error(
+                            &e.to_string(),
+                            Some("Invalid script arguments"),
+                        )
+                    })?;
+                    Ok(v8::Global::new(scope, local))
+                })
+                .collect::<Result<Vec<_>, IntegrationOSError>>()?
+        };
+
+        let call = self.runtime.call_with_args(function, &args);
+        let global = deno_core::futures::executor::block_on(
+            self.runtime
+                .with_event_loop_promise(call, PollEventLoopOptions::default()),
+        )
+        .map_err(|e| InternalError::script_error(&e.to_string(), Some("Failed to call")))?;
+
+        let scope = &mut self.runtime.handle_scope();
+        let local = v8::Local::new(scope, global);
+        let value: Value = serde_v8::from_v8(scope, local).map_err(|e| {
+            InternalError::deserialize_error(&e.to_string(), Some("Invalid script result"))
+        })?;
+
+        Ok(sanitize_number(value))
+    }
+}
+
+/// Largest integer a double represents exactly
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// V8 only has doubles, so integral numbers within the range a double represents
+/// exactly are turned back into integers
+fn sanitize_number(value: Value) -> Value {
+    match value {
+        Value::Number(number) => match number.as_f64() {
+            Some(f) if number.is_f64() && f.fract() == 0.0 && f.abs() <= MAX_SAFE_INTEGER => {
+                Value::Number(Number::from(f as i64))
+            }
+            _ => Value::Number(number),
+        },
+        Value::Array(values) => Value::Array(values.into_iter().map(sanitize_number).collect()),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (k, sanitize_number(v)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
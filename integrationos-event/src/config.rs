@@ -1,5 +1,8 @@
 use envconfig::Envconfig;
-use integrationos_domain::{cache::CacheConfig, database::DatabaseConfig, secrets::SecretsConfig};
+use integrationos_domain::{
+    cache::CacheConfig, database::DatabaseConfig, redaction::RedactionRules, script::ScriptConfig,
+    secrets::SecretsConfig,
+};
 use std::fmt::{Display, Formatter};
 
 #[derive(Envconfig, Clone)] // Intentionally no Debug so secret is not printed
@@ -18,6 +21,8 @@ pub struct EventCoreConfig {
     pub cache: CacheConfig,
     #[envconfig(nested = true)]
     pub db_config: DatabaseConfig,
+    #[envconfig(nested = true)]
+    pub script_config: ScriptConfig,
     #[envconfig(from = "CONNECTION_CACHE_TTL_SECS", default = "86400")]
     pub connection_cache_ttl_secs: u64,
     #[envconfig(from = "CONNECTION_MODEL_SCHEMA_TTL_SECS", default = "86400")]
@@ -26,6 +31,20 @@ pub struct EventCoreConfig {
     pub connection_model_definition_cache_ttl_secs: u64,
     #[envconfig(from = "SECRET_CACHE_TTL_SECS", default = "300")]
     pub secret_cache_ttl_secs: u64,
+    #[envconfig(from = "WASM_FUEL_LIMIT", default = "1000000000")]
+    pub wasm_fuel_limit: u64,
+    #[envconfig(from = "WASM_MEMORY_LIMIT_BYTES", default = "67108864")]
+    pub wasm_memory_limit_bytes: usize,
+    #[envconfig(from = "WASM_MODULE_CACHE_SIZE", default = "100")]
+    pub wasm_module_cache_size: u64,
+    #[envconfig(from = "SCHEDULER_POLL_INTERVAL_MILLIS", default = "1000")]
+    pub scheduler_poll_interval_millis: u64,
+    /// Claimed scheduled events not enqueued within this time are claimed again
+    #[envconfig(from = "SCHEDULER_CLAIM_LEASE_SECS", default = "60")]
+    pub scheduler_claim_lease_secs: u64,
+    /// JSON encoded `RedactionRules` applied to transactions before they are stored
+    #[envconfig(from = "REDACTION_RULES", default = "{}")]
+    pub redaction_rules: RedactionRules,
 }
 
 impl Display for EventCoreConfig {
@@ -38,8 +57,27 @@ impl Display for EventCoreConfig {
             "FETCH_GOOGLE_AUTH_TOKEN: {}",
             self.fetch_google_auth_token
         )?;
+        writeln!(f, "WASM_FUEL_LIMIT: {}", self.wasm_fuel_limit)?;
+        writeln!(
+            f,
+            "WASM_MEMORY_LIMIT_BYTES: {}",
+            self.wasm_memory_limit_bytes
+        )?;
+        writeln!(f, "WASM_MODULE_CACHE_SIZE: {}", self.wasm_module_cache_size)?;
+        writeln!(
+            f,
+            "SCHEDULER_POLL_INTERVAL_MILLIS: {}",
+            self.scheduler_poll_interval_millis
+        )?;
+        writeln!(
+            f,
+            "SCHEDULER_CLAIM_LEASE_SECS: {}",
+            self.scheduler_claim_lease_secs
+        )?;
+        writeln!(f, "REDACTION_RULES: {}", self.redaction_rules)?;
         write!(f, "{}", self.secrets_config)?;
         write!(f, "{}", self.cache)?;
-        write!(f, "{}", self.db_config)
+        write!(f, "{}", self.db_config)?;
+        write!(f, "{}", self.script_config)
     }
 }
@@ -1,6 +1,7 @@
 use crate::{
     metrics::{EVENTS_HISTOGRAM, STAGE_HISTOGRAM, STAGE_LABEL, STATUS_LABEL},
     store::{ContextStore, ControlDataStore, EventStore},
+    transformer::{Transformer, WasmRuntime},
 };
 use anyhow::Result;
 use chrono::Utc;
@@ -11,14 +12,16 @@ use futures::{
 use integrationos_domain::{
     algebra::{PipelineExt, PipelineStatus},
     pipeline_context::PipelineStage,
+    redaction::RedactionService,
     root_context::RootStage,
+    scheduled_event::ScheduledEvent,
+    scripting::ScriptService,
     Event,
     {
         extractor_context::Stage as ExtractorStage, middleware::Middleware, ExtractorContext,
         PipelineContext, RootContext, Transaction,
     },
 };
-use js_sandbox_ios::Script;
 use serde_json::{json, Value};
 use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::{
@@ -41,6 +44,9 @@ where
     pub context_store: Arc<X>,
     pub event_store: Arc<Y>,
     pub control_data_store: Arc<Z>,
+    pub scripts: ScriptService,
+    pub wasm: WasmRuntime,
+    pub redaction: RedactionService,
 }
 
 macro_rules! select_contexts {
@@ -77,6 +83,21 @@ where
     Y: EventStore + Sync + Send + 'static,
     Z: ControlDataStore + Sync + Send + 'static,
 {
+    /// Redacts a transaction with the rules of the event's connection definition
+    async fn redact_transaction(&self, transaction: Transaction, event: &Event) -> Transaction {
+        let rules = self
+            .control_data_store
+            .fetch_redaction_rules(event)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Could not get redaction rules, using the global ones: {e}");
+                None
+            });
+        self.redaction
+            .redact_transaction(transaction, rules.as_ref())
+            .await
+    }
+
     #[tracing::instrument(skip(self, context), fields(event_id = %context.event_key))]
     pub async fn process_context(&self, mut context: RootContext) -> Result<RootContext> {
         let time: Instant = Instant::now();
@@ -156,10 +177,42 @@ where
             RootStage::ProcessedDuplicates => {
                 debug!("Getting pipelines");
                 let pipelines = self.control_data_store.get_pipelines(&event).await?;
-                let pipelines: HashMap<_, _> = pipelines
-                    .into_iter()
-                    .map(|p| (p.key.clone(), PipelineContext::new(p.key, &context)))
-                    .collect();
+                let mut contexts = HashMap::with_capacity(pipelines.len());
+                for pipeline in pipelines {
+                    let delay = match pipeline.config.as_ref().map(|c| c.policies.get_delay()) {
+                        Some(Ok(delay)) => delay,
+                        Some(Err(e)) => {
+                            warn!(
+                                "Invalid delay policy for {}, running now: {e}",
+                                pipeline.key
+                            );
+                            None
+                        }
+                        None => None,
+                    };
+                    if let Some(delay) = delay {
+                        // Delayed pipelines run on their own once the scheduler enqueues them
+                        let deliver_at = Utc::now() + chrono::Duration::from_std(delay)?;
+                        // The scheduled event is stored, so the original travels sealed
+                        let original = self
+                            .redaction
+                            .seal(&serde_json::to_value(&event)?, &event.ownership.id)
+                            .await?;
+                        self.event_store
+                            .schedule(
+                                ScheduledEvent::new(&event, Some(pipeline.key.clone()), deliver_at)
+                                    .with_original(original),
+                            )
+                            .await?;
+                        debug!("Scheduled pipeline {} for {deliver_at}", pipeline.key);
+                        continue;
+                    }
+                    contexts.insert(
+                        pipeline.key.clone(),
+                        PipelineContext::new(pipeline.key, &context),
+                    );
+                }
+                let pipelines = contexts;
                 trace!("Got {} pipelines", pipelines.len());
                 context.stage = RootStage::ProcessingPipelines(pipelines);
                 context
@@ -265,7 +318,7 @@ where
             }
             PipelineStage::ExecutedExtractors(contexts) => {
                 debug!("Executing transformer");
-                let Some(Middleware::Transformer { code, .. }) = pipeline
+                let Some(Middleware::Transformer { language, code }) = pipeline
                     .middleware
                     .iter()
                     .find(|m| matches!(m, Middleware::Transformer { .. }))
@@ -279,21 +332,21 @@ where
                     return Ok(context);
                 };
 
-                let mut script = Script::from_string(code.as_str())?
-                    .with_timeout(Duration::from_secs(TICK_INTERVAL_MILLIS));
-                let value: Value = script
-                    .call("transform", (event.clone(), contexts))
+                let value: Value = Transformer::new(language, code)
+                    .transform(&self.scripts, &self.wasm, &event, contexts)
+                    .await
                     .inspect_err(|_| {
                         error!("Failed to transform data with contexts");
                     })?;
 
                 trace!("Executed transformer");
-                context.transaction = Some(Transaction::completed(
+                let transaction = Transaction::completed(
                     &event,
                     format!("{}::transformer", pipeline.key),
                     "['{{event}}', '{{context}}']".to_owned(),
                     value.to_string(),
-                ));
+                );
+                context.transaction = Some(self.redact_transaction(transaction, &event).await);
                 context.stage = PipelineStage::ExecutedTransformer(Some(value));
                 Ok(context)
             }
@@ -301,7 +354,6 @@ where
                 debug!("Sending to destination");
 
                 let retry = &pipeline.config.clone().unwrap_or_default().policies.retry;
-                let retry_interval = retry.get_interval().unwrap_or(Duration::from_secs(1));
                 let mut interval =
                     tokio::time::interval(Duration::from_millis(TICK_INTERVAL_MILLIS));
                 'outer: for i in 0..retry.maximum_attempts {
@@ -323,35 +375,44 @@ where
                                 match res {
                                     Ok(value) => {
                                         trace!("Sent to destination");
-                                        context.transaction = Some(Transaction::completed(
+                                        let transaction = Transaction::completed(
                                             &event,
                                             tx_key,
                                             input,
                                             value,
-                                        ));
+                                        );
+                                        context.transaction =
+                                            Some(self.redact_transaction(transaction, &event).await);
                                         context.stage = PipelineStage::FinishedPipeline;
                                         return Ok(context);
                                     }
                                     Err(e) => {
                                         error!("Failed to send to destination: {e}");
                                         if i < retry.maximum_attempts - 1 {
-                                            context.transaction = Some(Transaction::failed(
+                                            let transaction = Transaction::failed(
                                                 &event,
                                                 tx_key,
                                                 input,
                                                 e.to_string(),
-                                            ));
+                                            );
+                                            context.transaction =
+                                                Some(self.redact_transaction(transaction, &event).await);
                                             context.timestamp = Utc::now();
                                             self.context_store.set(context.clone()).await?;
                                             context.transaction = None;
+                                            let retry_interval = retry
+                                                .get_backoff(i)
+                                                .unwrap_or(Duration::from_secs(1));
                                             sleep(retry_interval).await;
                                         } else {
-                                            context.transaction = Some(Transaction::panicked(
+                                            let transaction = Transaction::panicked(
                                                 &event,
                                                 tx_key,
                                                 input,
                                                 e.to_string(),
-                                            ));
+                                            );
+                                            context.transaction =
+                                                Some(self.redact_transaction(transaction, &event).await);
                                         }
                                         continue 'outer;
                                     }
@@ -396,7 +457,6 @@ where
         trace!("Retrieved extractor");
 
         let retry = &extractor.policies.retry;
-        let retry_interval = retry.get_interval().unwrap_or(Duration::from_secs(1));
         let max_attempts = retry.maximum_attempts;
 
         let mut tick_interval = interval(Duration::from_millis(TICK_INTERVAL_MILLIS));
@@ -415,12 +475,14 @@ where
                         let input = json!(["{{event}}"]).to_string();
                         match res {
                             Ok(value) => {
-                                context.transaction = Some(Transaction::completed(
+                                let transaction = Transaction::completed(
                                     &event,
                                     tx_key,
                                     input,
                                     serde_json::to_string(&value)?,
-                                ));
+                                );
+                                context.transaction =
+                                    Some(self.redact_transaction(transaction, &event).await);
                                 context.stage = ExtractorStage::FinishedExtractor(value);
                                 trace!("Executed extractor");
                                 self.context_store.set(context.clone()).await?;
@@ -429,23 +491,30 @@ where
                             }
                             Err(e) => {
                                 if i < max_attempts - 1 {
-                                    context.transaction = Some(Transaction::failed(
+                                    let transaction = Transaction::failed(
                                         &event,
                                         tx_key,
                                         input,
                                         e.to_string(),
-                                    ));
+                                    );
+                                    context.transaction =
+                                        Some(self.redact_transaction(transaction, &event).await);
                                     context.timestamp = Utc::now();
                                     self.context_store.set(context.clone()).await?;
                                     context.transaction = None;
+                                    let retry_interval = retry
+                                        .get_backoff(i)
+                                        .unwrap_or(Duration::from_secs(1));
                                     sleep(retry_interval).await;
                                 } else {
-                                    context.transaction = Some(Transaction::panicked(
+                                    let transaction = Transaction::panicked(
                                         &event,
                                         tx_key,
                                         input,
                                         e.to_string(),
-                                    ));
+                                    );
+                                    context.transaction =
+                                        Some(self.redact_transaction(transaction, &event).await);
                                 }
                                 continue 'outer;
                             }
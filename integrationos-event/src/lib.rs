@@ -5,4 +5,6 @@ pub mod metrics;
 pub mod mock;
 pub mod mongo_context_store;
 pub mod mongo_control_data_store;
+pub mod scheduler;
 pub mod store;
+pub mod transformer;
@@ -2,10 +2,11 @@ use anyhow::{Context, Result};
 use dotenvy::dotenv;
 use envconfig::Envconfig;
 use integrationos_domain::{
+    redaction::{RedactionService, RedactionVault},
+    scripting::ScriptService,
     secret::Secret,
-    secrets::SecretServiceProvider,
     telemetry::{get_subscriber, init_subscriber},
-    GoogleKms, IOSKms, MongoStore, SecretExt, Store,
+    MongoStore, SecretExt, SecretsClient, Store,
 };
 use integrationos_event::{
     config::EventCoreConfig,
@@ -14,6 +15,8 @@ use integrationos_event::{
     metrics::{CONCURRENT_EVENTS_GAUGE, CONCURRENT_EVENTS_PERCENTAGE_GAUGE},
     mongo_context_store::MongoContextStore,
     mongo_control_data_store::MongoControlDataStore,
+    scheduler::Scheduler,
+    transformer::{WasmLimits, WasmRuntime},
 };
 use metrics_exporter_prometheus::PrometheusBuilder;
 use mongodb::Client;
@@ -56,17 +59,20 @@ async fn main() -> Result<()> {
     let database = client.database(&config.db_config.event_db_name);
     let secrets_store = MongoStore::<Secret>::new(&database, &Store::Secrets).await?;
 
-    let secrets_client: Arc<dyn SecretExt + Sync + Send> = match config.secrets_config.provider {
-        SecretServiceProvider::GoogleKms => {
-            Arc::new(GoogleKms::new(&config.secrets_config, secrets_store).await?)
-        }
-        SecretServiceProvider::IosKms => {
-            Arc::new(IOSKms::new(&config.secrets_config, secrets_store).await?)
-        }
-    };
+    let secrets_client: Arc<dyn SecretExt + Sync + Send> =
+        Arc::new(SecretsClient::new(&config.secrets_config, secrets_store).await?);
+
+    let scripts = ScriptService::new(&config.script_config)?;
+
+    let redaction_vault = RedactionVault::new(
+        secrets_client.clone(),
+        MongoStore::new(&database, &Store::RedactionKeys).await?,
+    );
+    let redaction = RedactionService::new(config.redaction_rules.clone(), Some(redaction_vault))
+        .with_context(|| "Invalid redaction rules")?;
 
     let control_store = Arc::new(
-        MongoControlDataStore::new(&config, secrets_client)
+        MongoControlDataStore::new(&config, secrets_client, scripts.clone())
             .await
             .with_context(|| "Could not connect to mongo db")?,
     );
@@ -77,10 +83,32 @@ async fn main() -> Result<()> {
             .with_context(|| "Could not connect to context store db")?,
     );
 
+    let scheduler = Scheduler::new(
+        &config,
+        context_store.clone(),
+        control_store.clone(),
+        redaction.clone(),
+    )
+    .await
+    .with_context(|| "Could not create scheduler")?;
+    tokio::spawn(scheduler.run());
+
+    let wasm = WasmRuntime::new(
+        WasmLimits {
+            fuel: config.wasm_fuel_limit,
+            memory_bytes: config.wasm_memory_limit_bytes,
+        },
+        config.wasm_module_cache_size,
+    )
+    .with_context(|| "Could not create WASM runtime")?;
+
     let dispatcher = Dispatcher {
         context_store: context_store.clone(),
         event_store: control_store.clone(),
         control_data_store: control_store.clone(),
+        scripts,
+        wasm,
+        redaction,
     };
 
     let event_handler =
@@ -119,13 +147,17 @@ async fn main() -> Result<()> {
                 }
             }
 
-            control_store
-                .event_cache
-                .insert(
-                    event_with_context.event.id,
-                    event_with_context.event.clone(),
-                )
-                .await;
+            // Events republished by the watchdog are read from the store and can be
+            // encrypted, those are decrypted when the dispatcher fetches them
+            if event_with_context.event.encryption.is_none() {
+                control_store
+                    .event_cache
+                    .insert(
+                        event_with_context.event.id,
+                        event_with_context.event.clone(),
+                    )
+                    .await;
+            }
 
             if let Err(e) = dispatcher.process_context(event_with_context.context).await {
                 error!("Could not process event: {e}");
@@ -18,4 +18,8 @@ impl CryptoExt for MockSecretsClient {
     ) -> Result<String, IntegrationOSError> {
         Ok(data)
     }
+
+    fn key_id(&self) -> String {
+        "mock".to_owned()
+    }
 }
@@ -2,43 +2,81 @@ use crate::{
     config::EventCoreConfig,
     store::{ControlDataStore, EventStore},
 };
-use anyhow::{bail, Context as AnyhowContext, Result};
+use anyhow::{anyhow, bail, Context as AnyhowContext, Result};
 use async_trait::async_trait;
 use bson::doc;
+use chrono::Utc;
 use futures::future::join_all;
 use handlebars::Handlebars;
 use http::header::AUTHORIZATION;
+use integrationos_cache::remote::{subscribe, RedisCache};
 use integrationos_domain::{
     algebra::{FecherExt, GoogleTokenFetcher, MongoStore},
+    connection_definition::ConnectionDefinition,
     duplicates::Duplicates,
     encrypted_access_key::EncryptedAccessKey,
     event_access::EventAccess,
     extractor::HttpExtractor,
     id::Id,
+    lifecycle::ConnectionLifecycle,
     middleware::Middleware,
+    payload_encryption::PayloadEncryption,
+    redaction::RedactionRules,
+    scheduled_event::ScheduledEvent,
+    scripting::ScriptService,
+    webhook_delivery::{WebhookDelivery, WebhookDeliveryStatus},
+    webhook_subscription::{
+        PublicResolver, WebhookSecret, WebhookSubscription, WEBHOOK_DELIVERY_HEADER,
+        WEBHOOK_EVENT_HEADER, WEBHOOK_SIGNATURE_HEADER,
+    },
     Connection, Event, Pipeline, SecretExt, Store,
 };
 use integrationos_unified::unified::{UnifiedCacheTTLs, UnifiedDestination};
 use moka::future::Cache;
-use mongodb::{options::ClientOptions, Client};
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use mongodb::{
+    options::{ClientOptions, IndexOptions},
+    Client, IndexModel,
+};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    redirect::Policy,
+};
 use serde_json::{json, Value};
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tracing::{error, warn};
 
+/// How long a webhook endpoint has to answer before the attempt counts as failed
+const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+
 #[derive(Clone)]
 pub struct MongoControlDataStore {
     pub connections_store: MongoStore<Connection>,
+    pub connection_definitions_store: MongoStore<ConnectionDefinition>,
     pub event_store: MongoStore<Event>,
+    pub scheduled_event_store: MongoStore<ScheduledEvent>,
     pub event_access_store: MongoStore<EventAccess>,
     pub pipelines_store: MongoStore<Pipeline>,
+    pub webhook_subscriptions_store: MongoStore<WebhookSubscription>,
+    pub webhook_deliveries_store: MongoStore<WebhookDelivery>,
     pub connections_cache: Cache<String, Connection>,
+    /// Redaction rules by connection definition id
+    pub redaction_rules_cache: Cache<Id, Option<RedactionRules>>,
     pub event_cache: Cache<Id, Event>,
     pub event_access_cache: Cache<String, EventAccess>,
     pub pipelines_cache: Cache<String, Vec<Pipeline>>,
     pub pipeline_cache: Cache<String, Pipeline>,
     pub token_fetcher: Option<GoogleTokenFetcher>,
     pub http_client: reqwest::Client,
+    /// Only reaches public addresses and never follows redirects, as webhook
+    /// endpoints are chosen by the accounts
+    pub webhook_client: reqwest::Client,
+    pub payload_encryption: PayloadEncryption,
+    secrets_client: Arc<dyn SecretExt + Sync + Send>,
     destination_caller: UnifiedDestination,
 }
 
@@ -46,6 +84,7 @@ impl MongoControlDataStore {
     pub async fn new(
         config: &EventCoreConfig,
         secrets_client: Arc<dyn SecretExt + Sync + Send>,
+        scripts: ScriptService,
     ) -> Result<Self> {
         let mut client_options = ClientOptions::parse(&config.db_config.control_db_url)
             .await
@@ -58,8 +97,13 @@ impl MongoControlDataStore {
         let db = client.database(&config.db_config.control_db_name);
 
         let connections_store = MongoStore::new(&db.clone(), &Store::Connections).await?;
+        let connection_definitions_store =
+            MongoStore::new(&db, &Store::ConnectionDefinitions).await?;
         let event_access_store = MongoStore::new(&db.clone(), &Store::EventAccess).await?;
         let pipelines_store = MongoStore::new(&db, &Store::Pipelines).await?;
+        let webhook_subscriptions_store =
+            MongoStore::new(&db, &Store::WebhookSubscriptions).await?;
+        let webhook_deliveries_store = MongoStore::new(&db, &Store::WebhookDeliveries).await?;
 
         let mut event_client_options = ClientOptions::parse(&config.db_config.event_db_url)
             .await
@@ -78,13 +122,37 @@ impl MongoControlDataStore {
                     config.db_config.event_db_name
                 )
             })?;
+        let scheduled_event_store = MongoStore::new(&event_db, &Store::ScheduledEvents).await?;
+
+        // Backs the object id lookup of duplicate detection
+        if let Err(e) = event_store
+            .collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "topic": 1, "objectId": 1 })
+                    .options(IndexOptions::builder().sparse(true).build())
+                    .build(),
+            )
+            .await
+        {
+            warn!("Could not create the object id index of events: {e}");
+        }
 
-        Ok(Self {
+        let store = Self {
             connections_store,
+            connection_definitions_store,
             event_store,
+            scheduled_event_store,
             event_access_store,
             pipelines_store,
+            webhook_subscriptions_store,
+            webhook_deliveries_store,
             connections_cache: Cache::builder()
+                .max_capacity(config.cache_size)
+                .time_to_live(Duration::from_secs(config.cache_ttl_secs))
+                .support_invalidation_closures()
+                .build(),
+            redaction_rules_cache: Cache::builder()
                 .max_capacity(config.cache_size)
                 .time_to_live(Duration::from_secs(config.cache_ttl_secs))
                 .build(),
@@ -107,10 +175,17 @@ impl MongoControlDataStore {
                 None
             },
             http_client: reqwest::Client::new(),
+            webhook_client: reqwest::Client::builder()
+                .redirect(Policy::none())
+                .dns_resolver(Arc::new(PublicResolver))
+                .build()?,
+            payload_encryption: PayloadEncryption::new(secrets_client.clone()),
+            secrets_client: secrets_client.clone(),
             destination_caller: UnifiedDestination::new(
                 config.db_config.clone(),
                 config.cache_size,
                 secrets_client,
+                scripts,
                 UnifiedCacheTTLs {
                     connection_cache_ttl_secs: config.connection_cache_ttl_secs,
                     connection_model_definition_cache_ttl_secs: config
@@ -120,8 +195,44 @@ impl MongoControlDataStore {
                     secret_cache_ttl_secs: config.secret_cache_ttl_secs,
                 },
             )
-            .await?,
-        })
+            .await?
+            .with_oauth_refresh(
+                RedisCache::new(&config.cache)
+                    .await
+                    .with_context(|| "Could not connect to redis for oauth refreshes")?
+                    .inner,
+            ),
+        };
+
+        // Connections reauthorized through the API are dropped from the caches
+        let subscriber = store.clone();
+        subscribe(
+            &config.cache,
+            config.cache.reauthorized_connections_key.clone(),
+            move |id| {
+                let Ok(id) = id.parse::<Id>() else {
+                    warn!("Received an invalid reauthorized connection id");
+                    return;
+                };
+
+                let store = subscriber.clone();
+                tokio::spawn(async move { store.remove_connection(id).await });
+            },
+        );
+
+        Ok(store)
+    }
+
+    async fn remove_connection(&self, id: Id) {
+        if let Err(e) = self
+            .connections_cache
+            .invalidate_entries_if(move |_, connection| connection.id == id)
+        {
+            error!("Could not evict connection {id}: {e}");
+        }
+        if let Err(e) = self.destination_caller.remove_connection(id).await {
+            error!("Could not evict cached secret of connection {id}: {e}");
+        }
     }
 
     async fn fetch_google_auth_token(&self, url: &str) -> Option<String> {
@@ -191,6 +302,16 @@ impl MongoControlDataStore {
         if let Some(pipeline) = self.pipeline_cache.get(pipeline_id).await {
             return Ok(pipeline);
         }
+        if let Some(subscription_id) = WebhookSubscription::id_from_pipeline_key(pipeline_id) {
+            let pipeline = self
+                .fetch_webhook_subscription(&subscription_id)
+                .await?
+                .pipeline();
+            self.pipeline_cache
+                .insert(pipeline_id.to_string(), pipeline.clone())
+                .await;
+            return Ok(pipeline);
+        }
         let Some(pipeline) = self
             .pipelines_store
             .get_one(doc! { "key": pipeline_id })
@@ -208,6 +329,107 @@ impl MongoControlDataStore {
             .await;
         Ok(pipeline)
     }
+
+    /// Pipelines delivering a connection lifecycle event to the webhook
+    /// subscriptions of the account that listen to it
+    #[tracing::instrument(skip(self, event), fields(event.key = %event.key))]
+    pub async fn fetch_webhook_pipelines(&self, event: &Event) -> Result<Vec<Pipeline>> {
+        if ConnectionLifecycle::from_str(&event.name).is_err() {
+            return Ok(vec![]);
+        }
+
+        let filter = doc! {
+            "ownership.buildableId": event.ownership.id.as_ref(),
+            "environment": event.environment.to_string(),
+            "events": &event.name,
+            "active": true,
+            "deleted": false,
+        };
+        let subscriptions = self
+            .webhook_subscriptions_store
+            .get_many(Some(filter), None, None, None, None)
+            .await
+            .with_context(|| "Could not query mongodb for webhook subscriptions")?;
+
+        Ok(subscriptions
+            .iter()
+            .map(WebhookSubscription::pipeline)
+            .collect())
+    }
+
+    async fn fetch_webhook_subscription(&self, id: &Id) -> Result<WebhookSubscription> {
+        let Some(subscription) = self
+            .webhook_subscriptions_store
+            .get_one(doc! { "_id": id.to_string(), "deleted": false })
+            .await
+            .with_context(|| "Could not query mongodb for webhook subscription")?
+        else {
+            bail!("Webhook subscription {id} does not exist");
+        };
+        Ok(subscription)
+    }
+
+    /// Posts the signed event to the subscription and records the attempt. The
+    /// attempt fails unless the endpoint answers with a success status, so that the
+    /// dispatcher retries it.
+    #[tracing::instrument(skip(self, event), fields(event.key = %event.key))]
+    async fn send_to_webhook(&self, event: &Event, subscription_id: &Id) -> Result<String> {
+        let subscription = self.fetch_webhook_subscription(subscription_id).await?;
+        let secret = self
+            .secrets_client
+            .get(&subscription.secret_id, &subscription.ownership.id)
+            .await?
+            .decode::<WebhookSecret>()?;
+
+        let mut delivery = WebhookDelivery::new(subscription.id, &subscription.url, event);
+        let body = serde_json::to_string(&WebhookSubscription::payload(event))?;
+        let signature = secret.sign(Utc::now().timestamp_millis(), &body)?;
+
+        let started = Instant::now();
+        // The url is checked again as its host may resolve elsewhere by now
+        let response = match WebhookSubscription::validate_url(&subscription.url).await {
+            Ok(url) => self
+                .webhook_client
+                .post(url)
+                .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+                .header(CONTENT_TYPE, "application/json")
+                .header(WEBHOOK_SIGNATURE_HEADER, signature)
+                .header(WEBHOOK_EVENT_HEADER, &event.name)
+                .header(WEBHOOK_DELIVERY_HEADER, delivery.id.to_string())
+                .body(body)
+                .send()
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        delivery.duration_ms = started.elapsed().as_millis() as u64;
+
+        let result = match response {
+            Ok(response) => {
+                let status = response.status();
+                let text = response.text().await.unwrap_or_default();
+                delivery.status_code = Some(status.as_u16());
+                if status.is_success() {
+                    delivery.status = WebhookDeliveryStatus::Delivered;
+                    Ok(text)
+                } else {
+                    delivery.error = Some(format!("Webhook endpoint responded with {status}"));
+                    Err(anyhow!("Webhook endpoint responded with {status}: {text}"))
+                }
+            }
+            Err(e) => {
+                let result = Err(anyhow!("Could not deliver webhook: {e}"));
+                delivery.error = Some(e);
+                result
+            }
+        };
+
+        if let Err(e) = self.webhook_deliveries_store.create_one(&delivery).await {
+            error!("Could not record webhook delivery {}: {e}", delivery.id);
+        }
+
+        result
+    }
 }
 
 #[async_trait]
@@ -240,6 +462,24 @@ impl ControlDataStore for MongoControlDataStore {
         Ok(connection)
     }
 
+    #[tracing::instrument(skip(self, event), fields(event.key = %event.key))]
+    async fn fetch_redaction_rules(&self, event: &Event) -> Result<Option<RedactionRules>> {
+        // Events of event accesses without a connection only get the global rules
+        let Ok(connection) = self.fetch_connection(event).await else {
+            return Ok(None);
+        };
+
+        self.redaction_rules_cache
+            .try_get_with(connection.connection_definition_id, async {
+                self.connection_definitions_store
+                    .get_one_by_id(&connection.connection_definition_id.to_string())
+                    .await
+                    .map(|definition| definition.and_then(|definition| definition.redaction))
+            })
+            .await
+            .map_err(|e| anyhow!("Could not get redaction rules of connection definition: {e}"))
+    }
+
     #[tracing::instrument(skip(self, event), fields(event.key = %event.key))]
     async fn verify_event(&self, event: &Event) -> Result<bool> {
         Ok(self.fetch_event_access(event).await?.is_some())
@@ -247,7 +487,8 @@ impl ControlDataStore for MongoControlDataStore {
 
     #[tracing::instrument(skip(self, event), fields(event.key = %event.key))]
     async fn get_pipelines(&self, event: &Event) -> Result<Vec<Pipeline>> {
-        let pipelines = self.fetch_pipelines(event).await?;
+        let mut pipelines = self.fetch_pipelines(event).await?;
+        pipelines.extend(self.fetch_webhook_pipelines(event).await?);
         let mut futs = Vec::with_capacity(pipelines.len());
         for p in &pipelines {
             futs.push(self.pipeline_cache.insert(p.key.clone(), p.clone()));
@@ -353,6 +594,10 @@ impl ControlDataStore for MongoControlDataStore {
         pipeline: &Pipeline,
         context: Option<Value>,
     ) -> Result<String> {
+        if let Some(subscription_id) = WebhookSubscription::id_from_pipeline_key(&pipeline.key) {
+            return self.send_to_webhook(event, &subscription_id).await;
+        }
+
         let response = self
             .destination_caller
             .send_to_destination(
@@ -379,7 +624,7 @@ impl EventStore for MongoControlDataStore {
             return Ok(event);
         }
 
-        let Some(event) = self
+        let Some(mut event) = self
             .event_store
             .get_one_by_id(event_key.to_string().as_str())
             .await
@@ -390,6 +635,10 @@ impl EventStore for MongoControlDataStore {
         else {
             bail!("Could not find event");
         };
+        self.payload_encryption
+            .decrypt_event(&mut event)
+            .await
+            .with_context(|| format!("Could not decrypt event {event_key}"))?;
         self.event_cache.insert(*event_key, event.clone()).await;
         Ok(event)
     }
@@ -412,24 +661,37 @@ impl EventStore for MongoControlDataStore {
 
     #[tracing::instrument(skip(self, event), fields(event.key = %event.key))]
     async fn get_duplicates(&self, event: &Event) -> Result<Duplicates> {
+        let mut matches = vec![
+            doc! {
+                "hashes.hash": {
+                    "$eq": &event.hashes[0].hash,
+                }
+            },
+            doc! {
+                "hashes.hash": {
+                    "$eq": &event.hashes[1].hash,
+                }
+            },
+            doc! {
+                "hashes.hash": {
+                    "$eq": &event.hashes[2].hash,
+                }
+            },
+        ];
+        // The same event about the same provider object, redelivered with a different body
+        if let Some(object_id) = &event.object_id {
+            let mut same_object = doc! {
+                "topic": &event.topic,
+                "objectId": object_id,
+            };
+            if let Some(occurred_at) = event.occurred_at {
+                same_object.insert("occurredAt", occurred_at.timestamp_millis());
+            }
+            matches.push(same_object);
+        }
+
         let query = doc! {
-            "$or": [
-                {
-                    "hashes.hash": {
-                        "$eq": &event.hashes[0].hash,
-                    }
-                },
-                {
-                    "hashes.hash": {
-                        "$eq": &event.hashes[1].hash,
-                    }
-                },
-                {
-                    "hashes.hash": {
-                        "$eq": &event.hashes[2].hash,
-                    }
-                },
-            ],
+            "$or": matches,
             "_id": {
                 "$ne": event.id.to_string()
             }
@@ -440,4 +702,16 @@ impl EventStore for MongoControlDataStore {
             possible_collision: duplicate_count == 1,
         })
     }
+
+    #[tracing::instrument(skip(self, scheduled_event), fields(scheduled_event.id = %scheduled_event.id))]
+    async fn schedule(&self, scheduled_event: ScheduledEvent) -> Result<()> {
+        self.scheduled_event_store
+            .create_one(&scheduled_event)
+            .await
+            .map_err(|e| {
+                error!("Could not schedule event: {e}");
+                e
+            })?;
+        Ok(())
+    }
 }
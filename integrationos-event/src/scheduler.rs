@@ -0,0 +1,191 @@
+use crate::{
+    config::EventCoreConfig,
+    store::{ContextStore, EventStore},
+};
+use anyhow::{Context, Result};
+use bson::doc;
+use chrono::Utc;
+use integrationos_cache::remote::RedisCache;
+use integrationos_domain::{
+    event_with_context::EventWithContext,
+    redaction::RedactionService,
+    root_context::RootStage,
+    scheduled_event::{ScheduledEvent, ScheduledEventState},
+    MongoStore, PipelineContext, RootContext, Store,
+};
+use mongodb::Client;
+use redis::AsyncCommands;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{sync::Mutex, time::sleep};
+use tracing::{debug, error, info};
+
+/// Pushes scheduled events onto the event queue once they are due.
+///
+/// Every event core replica runs a scheduler. Due events are claimed one at a
+/// time by atomically moving them from `scheduled` to `claimed`, so an event is
+/// only ever enqueued by a single replica, and marked `enqueued` once pushed.
+/// A claim that is not completed within the claim lease, e.g. because the
+/// replica crashed, is picked up again by any scheduler.
+///
+/// The stored event is redacted, so the original is delivered from the sealed
+/// copy kept on the scheduled event, which is dropped once it is enqueued.
+pub struct Scheduler<X, Y>
+where
+    X: ContextStore + Sync + Send + 'static,
+    Y: EventStore + Sync + Send + 'static,
+{
+    queue_name: String,
+    poll_interval: Duration,
+    claim_lease: Duration,
+    redis: Arc<Mutex<RedisCache>>,
+    scheduled_events: MongoStore<ScheduledEvent>,
+    context_store: Arc<X>,
+    event_store: Arc<Y>,
+    redaction: RedactionService,
+}
+
+impl<X, Y> Scheduler<X, Y>
+where
+    X: ContextStore + Sync + Send + 'static,
+    Y: EventStore + Sync + Send + 'static,
+{
+    pub async fn new(
+        config: &EventCoreConfig,
+        context_store: Arc<X>,
+        event_store: Arc<Y>,
+        redaction: RedactionService,
+    ) -> Result<Self> {
+        let redis = RedisCache::new(&config.cache).await?;
+        let client = Client::with_uri_str(&config.db_config.event_db_url)
+            .await
+            .with_context(|| "Could not connect to events mongodb")?;
+        let scheduled_events = MongoStore::new(
+            &client.database(&config.db_config.event_db_name),
+            &Store::ScheduledEvents,
+        )
+        .await?;
+
+        Ok(Self {
+            queue_name: config.cache.queue_name.clone(),
+            poll_interval: Duration::from_millis(config.scheduler_poll_interval_millis),
+            claim_lease: Duration::from_secs(config.scheduler_claim_lease_secs),
+            redis: Arc::new(Mutex::new(redis)),
+            scheduled_events,
+            context_store,
+            event_store,
+            redaction,
+        })
+    }
+
+    pub async fn run(self) {
+        info!("Polling for scheduled events");
+        loop {
+            match self.enqueue_due().await {
+                Ok(0) => {}
+                Ok(count) => info!("Enqueued {count} scheduled events"),
+                Err(e) => error!("Could not enqueue scheduled events: {e}"),
+            }
+            sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn enqueue_due(&self) -> Result<usize> {
+        let mut count = 0;
+        while let Some(scheduled_event) = self.claim().await? {
+            let id = scheduled_event.id.to_string();
+            if let Err(e) = self.enqueue(scheduled_event).await {
+                error!("Could not enqueue scheduled event {id}, retrying later: {e}");
+                self.release(&id, ScheduledEventState::Scheduled).await?;
+                break;
+            }
+            self.release(&id, ScheduledEventState::Enqueued).await?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    async fn claim(&self) -> Result<Option<ScheduledEvent>> {
+        let now = Utc::now().timestamp_millis();
+        let stale = now - self.claim_lease.as_millis() as i64;
+        let scheduled_event = self
+            .scheduled_events
+            .collection
+            .find_one_and_update(
+                doc! {
+                    "$or": [
+                        { "state": ScheduledEventState::Scheduled.as_ref() },
+                        {
+                            "state": ScheduledEventState::Claimed.as_ref(),
+                            "claimedAt": { "$lte": stale },
+                        },
+                    ],
+                    "deliverAt": { "$lte": now },
+                    "deleted": false,
+                },
+                doc! {
+                    "$set": {
+                        "state": ScheduledEventState::Claimed.as_ref(),
+                        "claimedAt": now,
+                        "updatedAt": now,
+                    }
+                },
+            )
+            .sort(doc! { "deliverAt": 1 })
+            .await?;
+
+        Ok(scheduled_event)
+    }
+
+    /// Ends the claim on the scheduled event, moving it to `state`
+    async fn release(&self, id: &str, state: ScheduledEventState) -> Result<()> {
+        let unset = match state {
+            ScheduledEventState::Enqueued => doc! { "claimedAt": "", "original": "" },
+            _ => doc! { "claimedAt": "" },
+        };
+        self.scheduled_events
+            .update_one(
+                id,
+                doc! {
+                    "$set": {
+                        "state": state.as_ref(),
+                        "updatedAt": Utc::now().timestamp_millis(),
+                    },
+                    "$unset": unset,
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn enqueue(&self, scheduled_event: ScheduledEvent) -> Result<()> {
+        let event = match &scheduled_event.original {
+            Some(original) => serde_json::from_value(
+                self.redaction
+                    .open(original, &scheduled_event.ownership.id)
+                    .await?,
+            )?,
+            None => self.event_store.get(&scheduled_event.event_key).await?,
+        };
+
+        let mut context = RootContext::new(event.id);
+        if let Some(pipeline_key) = scheduled_event.pipeline_key {
+            // Delayed pipelines skip the stages the event already went through
+            let pipeline = PipelineContext::new(pipeline_key.clone(), &context);
+            context.stage =
+                RootStage::ProcessingPipelines(HashMap::from([(pipeline_key, pipeline)]));
+        }
+        self.context_store.set(context.clone()).await?;
+
+        let payload = serde_json::to_vec(&EventWithContext::new(event, context))?;
+        self.redis
+            .lock()
+            .await
+            .inner
+            .lpush::<&str, Vec<u8>, ()>(&self.queue_name, payload)
+            .await
+            .with_context(|| "Could not push scheduled event to queue")?;
+
+        debug!("Enqueued scheduled event {}", scheduled_event.id);
+        Ok(())
+    }
+}
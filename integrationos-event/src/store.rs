@@ -3,6 +3,8 @@ use async_trait::async_trait;
 use integrationos_domain::{
     algebra::PipelineExt,
     id::Id,
+    redaction::RedactionRules,
+    scheduled_event::ScheduledEvent,
     {duplicates::Duplicates, extractor::HttpExtractor, Connection, Event, Pipeline},
 };
 use serde::{Deserialize, Serialize};
@@ -20,6 +22,8 @@ pub trait ContextStore {
 #[async_trait]
 pub trait ControlDataStore {
     async fn fetch_connection(&self, event: &Event) -> Result<Connection>;
+    /// Redaction rules of the connection definition of the event's connection
+    async fn fetch_redaction_rules(&self, event: &Event) -> Result<Option<RedactionRules>>;
     async fn verify_event(&self, event: &Event) -> Result<bool>;
     async fn get_pipelines(&self, event: &Event) -> Result<Vec<Pipeline>>;
     async fn get_pipeline(&self, pipeline_key: &str) -> Result<Pipeline>;
@@ -39,4 +43,5 @@ pub trait EventStore {
     async fn get(&self, event_key: &Id) -> Result<Event>;
     async fn set(&self, event: Event) -> Result<()>;
     async fn get_duplicates(&self, event: &Event) -> Result<Duplicates>;
+    async fn schedule(&self, scheduled_event: ScheduledEvent) -> Result<()>;
 }
@@ -0,0 +1,289 @@
+use anyhow::Result;
+use base64::{prelude::BASE64_STANDARD, Engine as _};
+use integrationos_domain::{scripting::ScriptService, Event, IntegrationOSError, InternalError};
+use moka::future::Cache;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc};
+use wasmtime::{Config, Engine, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Runtime used to execute the `Middleware::Transformer` of a pipeline.
+///
+/// JavaScript transformers export a `transform(event, context)` function and run
+/// on the shared [`ScriptService`]. WASM transformers are base64 encoded modules
+/// following the JSON ABI described in [`WasmTransformer`] and run on the shared
+/// [`WasmRuntime`].
+pub enum Transformer<'a> {
+    JavaScript(&'a str),
+    Wasm(&'a str),
+}
+
+impl<'a> Transformer<'a> {
+    pub fn new(language: &str, code: &'a str) -> Self {
+        match language.to_lowercase().as_str() {
+            "wasm" | "rust" => Transformer::Wasm(code),
+            _ => Transformer::JavaScript(code),
+        }
+    }
+
+    pub async fn transform(
+        &self,
+        scripts: &ScriptService,
+        wasm: &WasmRuntime,
+        event: &Event,
+        contexts: HashMap<String, Value>,
+    ) -> Result<Value> {
+        match self {
+            Transformer::JavaScript(code) => {
+                Ok(scripts.call(code, "transform", (event, contexts)).await?)
+            }
+            Transformer::Wasm(code) => {
+                let transformer = wasm.compile(code).await?;
+                let event = serde_json::to_value(event)?;
+                // Instantiating and running the module is CPU bound, keep it off the async workers
+                tokio::task::spawn_blocking(move || transformer.transform(&event, &contexts))
+                    .await?
+            }
+        }
+    }
+}
+
+/// Limits applied to every invocation of a WASM transformer
+#[derive(Debug, Clone, Copy)]
+pub struct WasmLimits {
+    /// Amount of fuel an invocation can burn, roughly one unit per instruction
+    pub fuel: u64,
+    /// Maximum linear memory an invocation can grow to
+    pub memory_bytes: usize,
+}
+
+impl Default for WasmLimits {
+    fn default() -> Self {
+        Self {
+            fuel: 1_000_000_000,
+            memory_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Shared engine for WASM transformers, keeping the compiled modules keyed by
+/// the hash of their code so a module is only compiled once
+#[derive(Clone)]
+pub struct WasmRuntime {
+    engine: Engine,
+    modules: Arc<Cache<String, Module>>,
+    limits: WasmLimits,
+}
+
+impl WasmRuntime {
+    pub fn new(limits: WasmLimits, cache_size: u64) -> Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+
+        Ok(Self {
+            engine: Engine::new(&config)?,
+            modules: Arc::new(Cache::new(cache_size)),
+            limits,
+        })
+    }
+
+    /// Returns the transformer for the base64 encoded module, compiling it on first use
+    pub async fn compile(&self, code: &str) -> Result<WasmTransformer> {
+        let code = code.trim();
+        let hash = format!("{:x}", Sha256::digest(code.as_bytes()));
+        let module = self
+            .modules
+            .try_get_with(hash, async {
+                let bytes = BASE64_STANDARD.decode(code).map_err(|e| {
+                    InternalError::invalid_argument(
+                        &format!("Invalid base64 WASM transformer: {e}"),
+                        None,
+                    )
+                })?;
+                Module::new(&self.engine, bytes).map_err(|e| {
+                    InternalError::script_error(
+                        &e.to_string(),
+                        Some("Failed to compile WASM module"),
+                    )
+                })
+            })
+            .await
+            .map_err(|e: Arc<IntegrationOSError>| e.as_ref().clone())?;
+
+        Ok(WasmTransformer {
+            engine: self.engine.clone(),
+            module,
+            limits: self.limits,
+        })
+    }
+}
+
+struct WasmState {
+    limits: StoreLimits,
+}
+
+/// A compiled WASM transformer module.
+///
+/// The module must export:
+/// - `memory`: its linear memory
+/// - `alloc(len: i32) -> i32`: returns a pointer to `len` writable bytes
+/// - `transform(event_ptr: i32, event_len: i32, context_ptr: i32, context_len: i32) -> i64`:
+///   receives the JSON encoded event and extractor contexts and returns the location of the
+///   JSON encoded result, packed as `(ptr << 32) | len`
+pub struct WasmTransformer {
+    engine: Engine,
+    module: Module,
+    limits: WasmLimits,
+}
+
+impl WasmTransformer {
+    pub fn transform(&self, event: &Value, contexts: &HashMap<String, Value>) -> Result<Value> {
+        let mut store = Store::new(
+            &self.engine,
+            WasmState {
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.limits.memory_bytes)
+                    .instances(1)
+                    .build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.limits.fuel)?;
+
+        let instance = Linker::new(&self.engine)
+            .instantiate(&mut store, &self.module)
+            .map_err(|e| {
+                InternalError::script_error(&e.to_string(), Some("Failed to instantiate module"))
+            })?;
+
+        let event = serde_json::to_vec(event)?;
+        let contexts = serde_json::to_vec(contexts)?;
+        let (event_ptr, event_len) = Self::write(&mut store, &instance, &event)?;
+        let (context_ptr, context_len) = Self::write(&mut store, &instance, &contexts)?;
+
+        let packed = instance
+            .get_typed_func::<(i32, i32, i32, i32), i64>(&mut store, "transform")?
+            .call(&mut store, (event_ptr, event_len, context_ptr, context_len))
+            .map_err(|e| {
+                InternalError::script_error(&e.to_string(), Some("Failed to call transform"))
+            })?;
+
+        let ptr = (packed as u64 >> 32) as usize;
+        let len = (packed as u64 & u32::MAX as u64) as usize;
+        let memory = Self::memory(&mut store, &instance)?;
+        let output = memory.data(&store).get(ptr..ptr + len).ok_or_else(|| {
+            InternalError::script_error(
+                "Transform returned a pointer outside of memory",
+                Some("Invalid WASM result"),
+            )
+        })?;
+
+        Ok(serde_json::from_slice(output)?)
+    }
+
+    fn memory(store: &mut Store<WasmState>, instance: &Instance) -> Result<wasmtime::Memory> {
+        instance.get_memory(&mut *store, "memory").ok_or_else(|| {
+            InternalError::script_error(
+                "Module does not export a memory",
+                Some("Invalid WASM module"),
+            )
+            .into()
+        })
+    }
+
+    fn write(
+        store: &mut Store<WasmState>,
+        instance: &Instance,
+        bytes: &[u8],
+    ) -> Result<(i32, i32)> {
+        let len = i32::try_from(bytes.len())?;
+        let ptr = instance
+            .get_typed_func::<i32, i32>(&mut *store, "alloc")?
+            .call(&mut *store, len)?;
+        Self::memory(store, instance)?.write(&mut *store, ptr as usize, bytes)?;
+
+        Ok((ptr, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ALLOC: &str = r#"
+        (memory (export "memory") 1)
+        (global $next (mut i32) (i32.const 1024))
+        (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+    "#;
+
+    fn module(transform: &str) -> String {
+        BASE64_STANDARD.encode(format!("(module {ALLOC} {transform})"))
+    }
+
+    fn runtime() -> WasmRuntime {
+        WasmRuntime::new(WasmLimits::default(), 10).expect("Failed to create runtime")
+    }
+
+    #[tokio::test]
+    async fn test_wasm_transformer_returns_contexts() {
+        let code = module(
+            r#"(func (export "transform") (param i32 i32 i32 i32) (result i64)
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get 2)) (i64.const 32))
+                    (i64.extend_i32_u (local.get 3))))"#,
+        );
+        let transformer = runtime()
+            .compile(&code)
+            .await
+            .expect("Failed to compile module");
+
+        let contexts = HashMap::from([("extractor".to_string(), json!({ "id": 1 }))]);
+        let value = transformer
+            .transform(&json!({ "name": "event" }), &contexts)
+            .expect("Failed to transform");
+
+        assert_eq!(value, serde_json::to_value(contexts).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_wasm_transformer_runs_out_of_fuel() {
+        let code = module(
+            r#"(func (export "transform") (param i32 i32 i32 i32) (result i64)
+                (loop $spin (br $spin))
+                (unreachable))"#,
+        );
+        let transformer = runtime()
+            .compile(&code)
+            .await
+            .expect("Failed to compile module");
+
+        assert!(transformer
+            .transform(&json!({ "name": "event" }), &HashMap::new())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wasm_runtime_compiles_module_once() {
+        let code = module(
+            r#"(func (export "transform") (param i32 i32 i32 i32) (result i64)
+                (i64.const 0))"#,
+        );
+        let runtime = runtime();
+        runtime
+            .compile(&code)
+            .await
+            .expect("Failed to compile module");
+        runtime
+            .compile(&code)
+            .await
+            .expect("Failed to compile module");
+        runtime.modules.run_pending_tasks().await;
+
+        assert_eq!(runtime.modules.entry_count(), 1);
+    }
+}
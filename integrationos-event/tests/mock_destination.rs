@@ -15,13 +15,16 @@ use integrationos_domain::{
     id::{prefix::IdPrefix, Id},
     ownership::Ownership,
     record_metadata::RecordMetadata,
+    scripting::ScriptService,
     secret::Secret,
     settings::Settings,
-    Connection, ConnectionType, IntegrationOSError, Pipeline, SecretExt, SecretVersion, Throughput,
+    Connection, ConnectionType, Event, IntegrationOSError, Pipeline, SecretExt, SecretVersion,
+    Throughput,
 };
 use integrationos_event::{
-    config::EventCoreConfig, mongo_control_data_store::MongoControlDataStore,
-    store::ControlDataStore,
+    config::EventCoreConfig,
+    mongo_control_data_store::MongoControlDataStore,
+    store::{ControlDataStore, EventStore},
 };
 use mockito::Server;
 use mongodb::Client;
@@ -56,6 +59,7 @@ pub async fn seed_db(config: &EventCoreConfig, base_url: String) -> Id {
             auth_method: AuthMethod::BearerToken {
                 value: "{{STRIPE_SECRET_KEY}}".to_string(),
             },
+            mtls: false,
             headers: None,
             query_params: None,
             schemas: SchemasInput {
@@ -102,6 +106,7 @@ pub async fn seed_db(config: &EventCoreConfig, base_url: String) -> Id {
         group: "group".to_string(),
         has_error: false,
         error: None,
+        health: None,
         platform: "platform".to_string().into(),
         environment: Environment::Live,
         secrets_service_id: "secrets_service_id".to_string(),
@@ -129,11 +134,62 @@ pub async fn seed_db(config: &EventCoreConfig, base_url: String) -> Id {
     conn.id
 }
 
+#[derive(Clone)]
+struct SecretsClient;
+
+#[async_trait::async_trait]
+impl SecretExt for SecretsClient {
+    async fn get(&self, _id: &str, buildable_id: &str) -> Result<Secret, IntegrationOSError> {
+        Ok(Secret::new(
+            r#"{"STRIPE_SECRET_KEY": "Stripe secret key"}"#.to_string(),
+            Some(SecretVersion::V2),
+            buildable_id.to_string(),
+            None,
+        ))
+    }
+
+    async fn create(
+        &self,
+        _secret: &Value,
+        buildable_id: &str,
+    ) -> Result<Secret, IntegrationOSError> {
+        Ok(Secret::new(
+            json!({ "STRIPE_SECRET_KEY": "Stripe secret key" }).to_string(),
+            Some(SecretVersion::V2),
+            buildable_id.to_string(),
+            None,
+        ))
+    }
+
+    async fn update(
+        &self,
+        _id: &str,
+        secret: &Value,
+        buildable_id: &str,
+    ) -> Result<Secret, IntegrationOSError> {
+        self.create(secret, buildable_id).await
+    }
+
+    async fn delete(&self, _id: &str, _buildable_id: &str) -> Result<(), IntegrationOSError> {
+        Ok(())
+    }
+
+    async fn reencrypt(&self, _secret: Secret) -> Result<bool, IntegrationOSError> {
+        Ok(false)
+    }
+
+    fn key_id(&self) -> String {
+        "mock".to_string()
+    }
+}
+
 async fn get_control_store(
     config: &EventCoreConfig,
     secrets_client: Arc<dyn SecretExt + Sync + Send>,
 ) -> MongoControlDataStore {
-    MongoControlDataStore::new(config, secrets_client)
+    let scripts =
+        ScriptService::new(&config.script_config).expect("Failed to create script service");
+    MongoControlDataStore::new(config, secrets_client, scripts)
         .await
         .expect("Failed to create control data store")
 }
@@ -169,34 +225,6 @@ async fn test_send_to_destination() {
 
     seed_db(&config, mock_server.url() + "/api").await;
 
-    #[derive(Clone)]
-    struct SecretsClient;
-
-    #[async_trait::async_trait]
-    impl SecretExt for SecretsClient {
-        async fn get(&self, _id: &str, buildable_id: &str) -> Result<Secret, IntegrationOSError> {
-            Ok(Secret::new(
-                r#"{"STRIPE_SECRET_KEY": "Stripe secret key"}"#.to_string(),
-                Some(SecretVersion::V2),
-                buildable_id.to_string(),
-                None,
-            ))
-        }
-
-        async fn create(
-            &self,
-            _secret: &Value,
-            buildable_id: &str,
-        ) -> Result<Secret, IntegrationOSError> {
-            Ok(Secret::new(
-                json!({ "STRIPE_SECRET_KEY": "Stripe secret key" }).to_string(),
-                Some(SecretVersion::V2),
-                buildable_id.to_string(),
-                None,
-            ))
-        }
-    }
-
     let store = get_control_store(&config, Arc::new(SecretsClient)).await;
 
     let mut pipeline: Pipeline = Faker.fake();
@@ -228,3 +256,59 @@ async fn test_send_to_destination() {
 
     mock.assert_async().await;
 }
+
+#[tokio::test]
+async fn test_events_about_the_same_object_are_duplicates() {
+    let docker = Docker::default();
+    let mongo = docker.run(Mongo);
+    let host_port = mongo.get_host_port_ipv4(27017);
+    let connection_string = format!("mongodb://127.0.0.1:{host_port}/?directConnection=true");
+
+    let config = EventCoreConfig::init_from_hashmap(&HashMap::from([
+        (
+            "CONTROL_DATABASE_URL".to_string(),
+            connection_string.clone(),
+        ),
+        (
+            "CONTROL_DATABASE_NAME".to_string(),
+            Uuid::new_v4().to_string(),
+        ),
+        ("EVENT_DATABASE_URL".to_string(), connection_string),
+        (
+            "EVENT_DATABASE_NAME".to_string(),
+            Uuid::new_v4().to_string(),
+        ),
+    ]))
+    .unwrap();
+
+    let store = get_control_store(&config, Arc::new(SecretsClient)).await;
+
+    let mut stored: Event = Faker.fake();
+    stored.object_id = Some("cus_123".to_string());
+    stored.occurred_at = None;
+    store
+        .event_store
+        .create_one(&stored)
+        .await
+        .expect("Failed to store event");
+
+    // Redelivered with a different body, so none of its hashes match
+    let mut redelivered: Event = Faker.fake();
+    redelivered.topic = stored.topic.clone();
+    redelivered.object_id = stored.object_id.clone();
+    redelivered.occurred_at = None;
+    let duplicates = store
+        .get_duplicates(&redelivered)
+        .await
+        .expect("Failed to get duplicates");
+    assert!(duplicates.possible_collision);
+
+    let mut other: Event = Faker.fake();
+    other.topic = stored.topic.clone();
+    other.object_id = Some("cus_456".to_string());
+    let duplicates = store
+        .get_duplicates(&other)
+        .await
+        .expect("Failed to get duplicates");
+    assert!(!duplicates.possible_collision);
+}
@@ -6,7 +6,11 @@ use integrationos_domain::{
     algebra::PipelineExt,
     id::{prefix::IdPrefix, Id},
     pipeline_context::PipelineStage,
+    redaction::{RedactionRules, RedactionService},
     root_context::RootStage,
+    scheduled_event::ScheduledEvent,
+    script::ScriptConfig,
+    scripting::ScriptService,
     {
         duplicates::Duplicates, extractor::HttpExtractor, Connection, Event, ExtractorContext,
         Pipeline, PipelineContext, RootContext,
@@ -15,6 +19,7 @@ use integrationos_domain::{
 use integrationos_event::{
     dispatcher::Dispatcher,
     store::{ContextStore, ControlDataStore, EventStore},
+    transformer::{WasmLimits, WasmRuntime},
 };
 use serde_json::Value;
 use std::{
@@ -29,6 +34,7 @@ pub struct MockStorage {
     pub contexts: Contexts,
     pub pipelines: Arc<Mutex<HashMap<String, Pipeline>>>,
     pub events: Arc<Mutex<HashMap<Id, Event>>>,
+    pub scheduled_events: Arc<Mutex<Vec<ScheduledEvent>>>,
     pub drop_at: Option<RootStage>,
     pub fail_at: Option<RootStage>,
     pub fail_pipeline_at: Option<PipelineStage>,
@@ -40,6 +46,7 @@ impl MockStorage {
             contexts: Arc::new(Mutex::new(HashMap::new())),
             pipelines: Arc::new(Mutex::new(HashMap::new())),
             events: Arc::new(Mutex::new(HashMap::new())),
+            scheduled_events: Arc::new(Mutex::new(Vec::new())),
             drop_at: None,
             fail_at: None,
             fail_pipeline_at: None,
@@ -90,6 +97,10 @@ impl ControlDataStore for MockStorage {
         unimplemented!()
     }
 
+    async fn fetch_redaction_rules(&self, _event: &Event) -> Result<Option<RedactionRules>> {
+        Ok(None)
+    }
+
     async fn verify_event(&self, _event: &Event) -> Result<bool> {
         fail_at!(
             self.fail_at,
@@ -175,6 +186,11 @@ impl EventStore for MockStorage {
             possible_collision: true,
         })
     }
+
+    async fn schedule(&self, scheduled_event: ScheduledEvent) -> Result<()> {
+        self.scheduled_events.lock().unwrap().push(scheduled_event);
+        Ok(())
+    }
 }
 
 #[tokio::test]
@@ -244,6 +260,9 @@ async fn run_dispatcher() {
         context_store: store.clone(),
         event_store: store.clone(),
         control_data_store: store.clone(),
+        scripts: ScriptService::new(&ScriptConfig::default()).unwrap(),
+        wasm: WasmRuntime::new(WasmLimits::default(), 10).unwrap(),
+        redaction: RedactionService::default(),
     };
 
     let context = RootContext::new(event.id);
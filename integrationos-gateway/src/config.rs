@@ -1,6 +1,8 @@
 use envconfig::Envconfig;
 use integrationos_domain::{
     cache::CacheConfig,
+    redaction::RedactionRules,
+    secrets::SecretsConfig,
     {database::DatabaseConfig, environment::Environment},
 };
 use std::{
@@ -14,14 +16,28 @@ pub struct Config {
     pub address: SocketAddr,
     #[envconfig(from = "CACHE_SIZE", default = "10000")]
     pub cache_size: u64,
+    #[envconfig(from = "WEBHOOK_CONFIG_CACHE_TTL_SECS", default = "60")]
+    pub webhook_config_cache_ttl_secs: u64,
+    /// How long event accesses are assumed to keep their data key
+    #[envconfig(from = "DATA_KEY_CACHE_TTL_SECS", default = "60")]
+    pub data_key_cache_ttl_secs: u64,
+    #[envconfig(from = "REVOCATION_REFRESH_INTERVAL_SECS", default = "60")]
+    pub revocation_refresh_interval_secs: u64,
+    #[envconfig(from = "MAX_BATCH_SIZE", default = "1000")]
+    pub max_batch_size: usize,
     #[envconfig(from = "SECRET", default = "32KFFT_i4UpkJmyPwY2TGzgHpxfXs7zS")]
     pub secret_key: String,
     #[envconfig(from = "ENVIRONMENT", default = "live")]
     pub environment: Environment,
+    /// JSON encoded `RedactionRules` applied to events before they are stored
+    #[envconfig(from = "REDACTION_RULES", default = "{}")]
+    pub redaction_rules: RedactionRules,
     #[envconfig(nested = true)]
     pub redis: CacheConfig,
     #[envconfig(nested = true)]
     pub db: DatabaseConfig,
+    #[envconfig(nested = true)]
+    pub secrets_config: SecretsConfig,
 }
 
 impl Config {
@@ -34,10 +50,28 @@ impl Display for Config {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "SERVER_ADDRESS: {}", self.address)?;
         writeln!(f, "CACHE_SIZE: {}", self.cache_size)?;
+        writeln!(
+            f,
+            "WEBHOOK_CONFIG_CACHE_TTL_SECS: {}",
+            self.webhook_config_cache_ttl_secs
+        )?;
+        writeln!(
+            f,
+            "DATA_KEY_CACHE_TTL_SECS: {}",
+            self.data_key_cache_ttl_secs
+        )?;
+        writeln!(
+            f,
+            "REVOCATION_REFRESH_INTERVAL_SECS: {}",
+            self.revocation_refresh_interval_secs
+        )?;
+        writeln!(f, "MAX_BATCH_SIZE: {}", self.max_batch_size)?;
         writeln!(f, "SECRET: ****")?;
         writeln!(f, "ENVIRONMENT: {}", self.environment)?;
+        writeln!(f, "REDACTION_RULES: {}", self.redaction_rules)?;
         writeln!(f, "{}", self.redis)?;
-        writeln!(f, "{}", self.db)
+        writeln!(f, "{}", self.db)?;
+        writeln!(f, "{}", self.secrets_config)
     }
 }
 
@@ -46,10 +80,16 @@ impl Default for Config {
         Self {
             address: "0.0.0.0:3000".parse().unwrap(),
             cache_size: 10_000,
+            webhook_config_cache_ttl_secs: 60,
+            data_key_cache_ttl_secs: 60,
+            revocation_refresh_interval_secs: 60,
+            max_batch_size: 1_000,
             secret_key: "32KFFT_i4UpkJmyPwY2TGzgHpxfXs7zS".to_owned(),
             environment: Environment::Test,
+            redaction_rules: RedactionRules::default(),
             redis: CacheConfig::default(),
             db: DatabaseConfig::default(),
+            secrets_config: SecretsConfig::default(),
         }
     }
 }
@@ -64,8 +104,13 @@ mod tests {
         let config = Config::new();
         assert_eq!(config.address, "0.0.0.0:3000".parse().unwrap());
         assert_eq!(config.cache_size, 10_000);
+        assert_eq!(config.webhook_config_cache_ttl_secs, 60);
+        assert_eq!(config.data_key_cache_ttl_secs, 60);
+        assert_eq!(config.revocation_refresh_interval_secs, 60);
+        assert_eq!(config.max_batch_size, 1_000);
         assert_eq!(config.secret_key, "32KFFT_i4UpkJmyPwY2TGzgHpxfXs7zS");
         assert_eq!(config.environment, Environment::Test);
+        assert_eq!(config.redaction_rules, RedactionRules::default());
         assert_eq!(config.redis.url, "redis://localhost:6379");
         assert_eq!(config.redis.queue_name, "events");
         assert_eq!(config.redis.event_throughput_key, "event_throughput");
@@ -81,17 +126,24 @@ mod tests {
     #[test]
     fn test_config_display() {
         let config = Config::new();
-        let mut display = r"SERVER_ADDRESS: 0.0.0.0:3000
+        let mut display = r#"SERVER_ADDRESS: 0.0.0.0:3000
 CACHE_SIZE: 10000
+WEBHOOK_CONFIG_CACHE_TTL_SECS: 60
+DATA_KEY_CACHE_TTL_SECS: 60
+REVOCATION_REFRESH_INTERVAL_SECS: 60
+MAX_BATCH_SIZE: 1000
 SECRET: ****
 ENVIRONMENT: test
-"
+REDACTION_RULES: {"tokenize":false}
+"#
         .to_string();
 
         display += &config.redis.to_string();
         display += "\n";
         display += &config.db.to_string();
         display += "\n";
+        display += &config.secrets_config.to_string();
+        display += "\n";
 
         assert_eq!(config.to_string(), display);
     }
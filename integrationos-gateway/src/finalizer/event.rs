@@ -1,5 +1,18 @@
 use async_trait::async_trait;
-use integrationos_domain::{encrypted_access_key::EncryptedAccessKey, Event};
+use chrono::{DateTime, Utc};
+use integrationos_domain::{
+    encrypted_access_key::EncryptedAccessKey, scheduled_event::ScheduledEvent, settings::Settings,
+    webhook_handshake::WebhookHandshake, Event,
+};
+
+/// What the gateway needs to know about the connection behind a webhook access key
+#[derive(Debug, Clone, Default)]
+pub struct WebhookConfig {
+    pub settings: Option<Settings>,
+    pub handshakes: Vec<WebhookHandshake>,
+    /// Owner of the connection, whose secrets handshakes are signed with
+    pub ownership_id: Option<String>,
+}
 
 #[async_trait]
 pub trait FinalizeEvent {
@@ -9,4 +22,28 @@ pub trait FinalizeEvent {
         event_name: &str,
         access_key: &EncryptedAccessKey,
     ) -> Result<String, anyhow::Error>;
+
+    /// Acknowledges many events at once, returning the outcome of each event in order.
+    ///
+    /// Events are stored independently, so a failure only concerns the events it is
+    /// returned for and only those should be sent again.
+    async fn finalize_events(&self, events: &[Event]) -> Vec<Result<String, anyhow::Error>>;
+
+    async fn schedule_event(
+        &self,
+        event: &Event,
+        deliver_at: DateTime<Utc>,
+    ) -> Result<ScheduledEvent, anyhow::Error>;
+
+    async fn get_webhook_config(
+        &self,
+        access_key: &EncryptedAccessKey,
+    ) -> Result<WebhookConfig, anyhow::Error>;
+
+    /// Reads the value of a secret used to answer webhook handshakes
+    async fn get_handshake_secret(
+        &self,
+        secrets_service_id: &str,
+        ownership_id: &str,
+    ) -> Result<String, anyhow::Error>;
 }
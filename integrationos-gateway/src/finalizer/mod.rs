@@ -1,17 +1,28 @@
 pub mod event;
 
-use self::event::FinalizeEvent;
+use self::event::{FinalizeEvent, WebhookConfig};
 use crate::config::Config;
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use integrationos_cache::remote::RedisCache;
 use integrationos_domain::{
-    algebra::MongoStore, encrypted_access_key::EncryptedAccessKey,
-    event_with_context::EventWithContext, Event, RootContext, Store,
+    algebra::MongoStore,
+    connection_definition::ConnectionDefinition,
+    encrypted_access_key::EncryptedAccessKey,
+    event_access::EventAccess,
+    event_with_context::EventWithContext,
+    payload_encryption::PayloadEncryption,
+    redaction::{RedactionRules, RedactionService, RedactionVault},
+    scheduled_event::ScheduledEvent,
+    secret::Secret,
+    Connection, Event, RootContext, SecretExt, SecretsClient, Store,
 };
-use mongodb::Collection;
+use moka::future::Cache;
+use mongodb::{bson::doc, error::ErrorKind, Collection};
 use redis::AsyncCommands;
-use std::sync::Arc;
+use serde_json::Value;
+use std::{collections::HashSet, sync::Arc, time::Duration};
 use tokio::sync::Mutex;
 use tracing::{debug, error};
 
@@ -19,7 +30,19 @@ pub struct Finalizer {
     redis: Arc<Mutex<RedisCache>>,
     context_collection: Collection<RootContext>,
     event_store: MongoStore<Event>,
+    scheduled_event_store: MongoStore<ScheduledEvent>,
+    connection_store: MongoStore<Connection>,
+    connection_definition_store: MongoStore<ConnectionDefinition>,
+    event_access_store: MongoStore<EventAccess>,
     queue_name: String,
+    redaction: RedactionService,
+    payload_encryption: PayloadEncryption,
+    secrets_client: Arc<dyn SecretExt + Sync + Send>,
+    /// Data key of the event access behind each access key, `None` when its
+    /// events are stored in plaintext
+    data_key_ids: Cache<String, Option<String>>,
+    /// Redaction rules of the connection definition behind each access key
+    redaction_rules: Cache<String, Option<RedactionRules>>,
 }
 
 impl Finalizer {
@@ -44,13 +67,168 @@ impl Finalizer {
                     config.db.event_db_name
                 )
             })?;
+        let scheduled_event_store = MongoStore::new(&mongo, &Store::ScheduledEvents)
+            .await
+            .with_context(|| "Could not connect to scheduled events store")?;
+
+        let control_mongo = mongodb::Client::with_uri_str(config.db.control_db_url)
+            .await
+            .with_context(|| "Could not connect to control mongodb")?;
+        let control_db = control_mongo.database(&config.db.control_db_name);
+        let connection_store = MongoStore::new(&control_db, &Store::Connections)
+            .await
+            .with_context(|| "Could not connect to connections store")?;
+        let connection_definition_store =
+            MongoStore::new(&control_db, &Store::ConnectionDefinitions)
+                .await
+                .with_context(|| "Could not connect to connection definitions store")?;
+        let event_access_store = MongoStore::new(&control_db, &Store::EventAccess)
+            .await
+            .with_context(|| "Could not connect to event access store")?;
+        let secrets_store = MongoStore::<Secret>::new(&control_db, &Store::Secrets)
+            .await
+            .with_context(|| "Could not connect to secrets store")?;
+
+        let secrets_client: Arc<dyn SecretExt + Sync + Send> =
+            Arc::new(SecretsClient::new(&config.secrets_config, secrets_store).await?);
+        let redaction_vault = RedactionVault::new(
+            secrets_client.clone(),
+            MongoStore::new(&control_db, &Store::RedactionKeys)
+                .await
+                .with_context(|| "Could not connect to redaction keys store")?,
+        );
+        let redaction =
+            RedactionService::new(config.redaction_rules.clone(), Some(redaction_vault))
+                .map_err(|e| anyhow!("Invalid redaction rules: {e}"))?;
+
         Ok(Self {
             redis: Arc::new(Mutex::new(redis)),
             context_collection,
             event_store,
+            scheduled_event_store,
+            connection_store,
+            connection_definition_store,
+            event_access_store,
             queue_name: config.redis.queue_name,
+            redaction,
+            payload_encryption: PayloadEncryption::new(secrets_client.clone()),
+            secrets_client,
+            data_key_ids: Cache::builder()
+                .max_capacity(config.cache_size)
+                .time_to_live(Duration::from_secs(config.data_key_cache_ttl_secs))
+                .build(),
+            redaction_rules: Cache::builder()
+                .max_capacity(config.cache_size)
+                .time_to_live(Duration::from_secs(config.data_key_cache_ttl_secs))
+                .build(),
         })
     }
+
+    /// Redacts and, when its event access has a data key, encrypts the copy of an
+    /// event that is stored
+    async fn stored_event(&self, event: &Event) -> Result<Event> {
+        let mut stored_event = event.clone();
+        let rules = self.redaction_rules(&event.access_key).await?;
+        self.redaction
+            .redact_event(&mut stored_event, rules.as_ref())
+            .await;
+
+        if let Some(data_key_id) = self.data_key_id(&event.access_key).await? {
+            self.payload_encryption
+                .encrypt_event(&mut stored_event, &data_key_id)
+                .await
+                .map_err(|e| anyhow!("Could not encrypt event: {e}"))?;
+        }
+
+        Ok(stored_event)
+    }
+
+    /// Fails the pending events at the `failed` positions, returning those still pending
+    fn fail(
+        results: &mut [Result<String, anyhow::Error>],
+        pending: Vec<usize>,
+        failed: &HashSet<usize>,
+        reason: &str,
+    ) -> Vec<usize> {
+        pending
+            .into_iter()
+            .enumerate()
+            .filter_map(|(position, index)| {
+                if failed.contains(&position) {
+                    results[index] = Err(anyhow!("{reason}"));
+                    None
+                } else {
+                    Some(index)
+                }
+            })
+            .collect()
+    }
+
+    async fn data_key_id(&self, access_key: &str) -> Result<Option<String>> {
+        self.data_key_ids
+            .try_get_with_by_ref(access_key, async {
+                self.event_access_store
+                    .get_one(doc! {
+                        "$or": [
+                            { "accessKey": access_key },
+                            { "previousAccessKeys.accessKey": access_key },
+                        ],
+                        "deleted": false,
+                    })
+                    .await
+                    .map(|event_access| event_access.and_then(|e| e.data_key_id))
+            })
+            .await
+            .map_err(|e| anyhow!("Could not get data key of event access: {e}"))
+    }
+
+    async fn redaction_rules(&self, access_key: &str) -> Result<Option<RedactionRules>> {
+        self.redaction_rules
+            .try_get_with_by_ref(access_key, async {
+                // Keys replaced by a rotation still belong to the connection
+                let event_access = self
+                    .event_access_store
+                    .get_one(doc! {
+                        "$or": [
+                            { "accessKey": access_key },
+                            { "previousAccessKeys.accessKey": access_key },
+                        ],
+                        "deleted": false,
+                    })
+                    .await?;
+                let access_key = event_access
+                    .as_ref()
+                    .map_or(access_key, |event_access| event_access.access_key.as_str());
+                let Some(connection) = self
+                    .connection_store
+                    .get_one(doc! { "accessKey": access_key, "deleted": false })
+                    .await?
+                else {
+                    return Ok(None);
+                };
+
+                self.connection_definition_store
+                    .get_one_by_id(&connection.connection_definition_id.to_string())
+                    .await
+                    .map(|definition| definition.and_then(|definition| definition.redaction))
+            })
+            .await
+            .map_err(|e| anyhow!("Could not get redaction rules of connection: {e}"))
+    }
+}
+
+/// Positions of the documents an unordered `insert_many` could not write, all of
+/// them when the error is not about individual documents
+fn failed_indices(error: &mongodb::error::Error, len: usize) -> HashSet<usize> {
+    match error.kind.as_ref() {
+        ErrorKind::InsertMany(error) if error.write_concern_error.is_none() => error
+            .write_errors
+            .iter()
+            .flatten()
+            .map(|error| error.index)
+            .collect(),
+        _ => (0..len).collect(),
+    }
 }
 
 #[async_trait]
@@ -61,13 +239,16 @@ impl FinalizeEvent for Finalizer {
         _event_name: &str,
         _access_key: &EncryptedAccessKey,
     ) -> Result<String, anyhow::Error> {
-        match self.event_store.create_one(event).await {
+        // Only the stored copy is redacted and encrypted, pipelines still receive the
+        // original payload
+        let stored_event = self.stored_event(event).await?;
+        match self.event_store.create_one(&stored_event).await {
             Err(e) => {
                 error!("Failed to save event: {e}");
                 bail!(e);
             }
             Ok(r) => {
-                debug!("Inserted event {event:?} => result for insertion {r:?}");
+                debug!("Inserted event {stored_event:?} => result for insertion {r:?}");
             }
         }
         let context = RootContext::new(event.id);
@@ -92,4 +273,184 @@ impl FinalizeEvent for Finalizer {
             }
         }
     }
+
+    async fn finalize_events(&self, events: &[Event]) -> Vec<Result<String, anyhow::Error>> {
+        let mut results: Vec<Result<String, anyhow::Error>> = events
+            .iter()
+            .map(|_| Ok("Sent on redis".to_string()))
+            .collect();
+
+        let mut pending = Vec::with_capacity(events.len());
+        let mut stored_events = Vec::with_capacity(events.len());
+        for (index, event) in events.iter().enumerate() {
+            match self.stored_event(event).await {
+                Ok(stored_event) => {
+                    pending.push(index);
+                    stored_events.push(stored_event);
+                }
+                Err(e) => results[index] = Err(e),
+            }
+        }
+        if pending.is_empty() {
+            return results;
+        }
+
+        // Unordered, so a failing event does not prevent the following ones from being stored
+        if let Err(e) = self
+            .event_store
+            .collection
+            .insert_many(&stored_events)
+            .ordered(false)
+            .await
+        {
+            error!("Failed to save events: {e}");
+            let failed = failed_indices(&e, pending.len());
+            pending = Self::fail(&mut results, pending, &failed, "Failed to save event");
+        }
+        if pending.is_empty() {
+            return results;
+        }
+
+        let mut contexts: Vec<(usize, RootContext)> = pending
+            .iter()
+            .map(|index| (*index, RootContext::new(events[*index].id)))
+            .collect();
+        if let Err(e) = self
+            .context_collection
+            .insert_many(contexts.iter().map(|(_, context)| context))
+            .ordered(false)
+            .await
+        {
+            error!("Failed to save event contexts: {e}");
+            let failed = failed_indices(&e, pending.len());
+            pending = Self::fail(
+                &mut results,
+                pending,
+                &failed,
+                "Failed to save event context",
+            );
+            contexts.retain(|(index, _)| pending.contains(index));
+        }
+        if pending.is_empty() {
+            return results;
+        }
+
+        // Atomic, so either every remaining event is queued or none is
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        pending.clear();
+        for (index, context) in contexts {
+            match serde_json::to_vec(&EventWithContext::new(events[index].clone(), context)) {
+                Ok(msg) => {
+                    pipe.lpush(&self.queue_name, msg).ignore();
+                    pending.push(index);
+                }
+                Err(e) => results[index] = Err(e.into()),
+            }
+        }
+
+        let mut conn = self.redis.lock().await;
+        match pipe.query_async::<()>(&mut conn.inner).await {
+            Ok(()) => debug!("Sent {} events on redis", pending.len()),
+            Err(e) => {
+                error!("Could not publish to redis: {e}");
+                for index in pending {
+                    results[index] = Err(anyhow!("Could not publish to redis: {e}"));
+                }
+            }
+        }
+
+        results
+    }
+
+    async fn schedule_event(
+        &self,
+        event: &Event,
+        deliver_at: DateTime<Utc>,
+    ) -> Result<ScheduledEvent, anyhow::Error> {
+        // The root context is only created once the scheduler enqueues the event,
+        // otherwise the watchdog would consider it dead while it waits. Like any
+        // other event the stored copy is redacted, the original is sealed on the
+        // scheduled event until it is delivered.
+        let stored_event = self.stored_event(event).await?;
+        let original = self
+            .redaction
+            .seal(&serde_json::to_value(event)?, &event.ownership.id)
+            .await
+            .map_err(|e| anyhow!("Could not seal scheduled event: {e}"))?;
+        if let Err(e) = self.event_store.create_one(&stored_event).await {
+            error!("Failed to save event: {e}");
+            bail!(e);
+        }
+
+        let scheduled_event = ScheduledEvent::new(event, None, deliver_at).with_original(original);
+        match self
+            .scheduled_event_store
+            .create_one(&scheduled_event)
+            .await
+        {
+            Ok(()) => {
+                debug!("Scheduled event {} for {deliver_at}", event.id);
+                Ok(scheduled_event)
+            }
+            Err(e) => {
+                error!("Failed to save scheduled event: {e}");
+                bail!(e);
+            }
+        }
+    }
+
+    async fn get_webhook_config(
+        &self,
+        access_key: &EncryptedAccessKey,
+    ) -> Result<WebhookConfig, anyhow::Error> {
+        let access_key = access_key.to_string();
+        // Keys replaced by a rotation keep receiving webhooks during their grace period
+        let event_access = self
+            .event_access_store
+            .get_one(doc! {
+                "$or": [
+                    { "accessKey": &access_key },
+                    { "previousAccessKeys.accessKey": &access_key },
+                ],
+                "deleted": false,
+            })
+            .await?;
+        let connection = self
+            .connection_store
+            .get_one(doc! {
+                "accessKey": event_access
+                    .as_ref()
+                    .map_or(access_key.as_str(), |event_access| event_access.access_key.as_str()),
+                "deleted": false,
+            })
+            .await?;
+
+        Ok(WebhookConfig {
+            settings: connection.map(|connection| connection.settings),
+            ownership_id: event_access
+                .as_ref()
+                .map(|event_access| event_access.ownership.id.to_string()),
+            handshakes: event_access
+                .map(|event_access| event_access.webhook_handshakes)
+                .unwrap_or_default(),
+        })
+    }
+
+    async fn get_handshake_secret(
+        &self,
+        secrets_service_id: &str,
+        ownership_id: &str,
+    ) -> Result<String, anyhow::Error> {
+        let secret = self
+            .secrets_client
+            .get(secrets_service_id, ownership_id)
+            .await
+            .map_err(|e| anyhow!("Could not get handshake secret: {e}"))?;
+
+        match secret.as_value() {
+            Ok(Value::String(secret)) => Ok(secret),
+            _ => bail!("Handshake secret {secrets_service_id} is not a string"),
+        }
+    }
 }
@@ -1,5 +1,6 @@
 pub mod config;
 pub mod finalizer;
 pub mod mock;
+pub mod revocation;
 pub mod server;
 pub mod util;
@@ -1,6 +1,13 @@
-use crate::finalizer::event::FinalizeEvent;
+use crate::finalizer::event::{FinalizeEvent, WebhookConfig};
 use async_trait::async_trait;
-use integrationos_domain::{encrypted_access_key::EncryptedAccessKey, Event};
+use chrono::{DateTime, Utc};
+use integrationos_domain::{
+    encrypted_access_key::EncryptedAccessKey,
+    scheduled_event::ScheduledEvent,
+    settings::Settings,
+    webhook_handshake::{HandshakeCondition, HandshakeResponse, WebhookHandshake},
+    Event,
+};
 
 pub struct MockFinalizer;
 
@@ -14,4 +21,46 @@ impl FinalizeEvent for MockFinalizer {
     ) -> Result<String, anyhow::Error> {
         Ok("sent".to_owned())
     }
+
+    async fn finalize_events(&self, events: &[Event]) -> Vec<Result<String, anyhow::Error>> {
+        events.iter().map(|_| Ok("sent".to_owned())).collect()
+    }
+
+    async fn schedule_event(
+        &self,
+        event: &Event,
+        deliver_at: DateTime<Utc>,
+    ) -> Result<ScheduledEvent, anyhow::Error> {
+        Ok(ScheduledEvent::new(event, None, deliver_at))
+    }
+
+    async fn get_webhook_config(
+        &self,
+        _access_key: &EncryptedAccessKey,
+    ) -> Result<WebhookConfig, anyhow::Error> {
+        Ok(WebhookConfig {
+            settings: Some(Settings {
+                parse_webhook_body: true,
+                ..Default::default()
+            }),
+            handshakes: vec![WebhookHandshake {
+                method: None,
+                conditions: vec![HandshakeCondition {
+                    path: "$.body.type".to_owned(),
+                    equals: Some("url_verification".to_owned()),
+                }],
+                challenge_path: "$.body.challenge".to_owned(),
+                response: HandshakeResponse::Echo,
+            }],
+            ownership_id: None,
+        })
+    }
+
+    async fn get_handshake_secret(
+        &self,
+        _secrets_service_id: &str,
+        _ownership_id: &str,
+    ) -> Result<String, anyhow::Error> {
+        Ok("secret".to_owned())
+    }
 }
@@ -0,0 +1,158 @@
+use crate::config::Config;
+use chrono::{DateTime, Utc};
+use integrationos_cache::remote::{subscribe, RedisCache};
+use integrationos_domain::{
+    encrypted_access_key::EncryptedAccessKey, event_access::AcceptedAccessKeys, AccessKey,
+};
+use moka::future::Cache;
+use redis::AsyncCommands;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tracing::{error, trace, warn};
+
+/// Keys accepted by rotated event access records, by the group of the record.
+///
+/// Access keys are validated by decrypting them, so without this list a rotated
+/// key would keep working for as long as the secret does not change.
+#[derive(Clone, Default)]
+pub struct Revocations {
+    accepted: Arc<RwLock<HashMap<String, AcceptedAccessKeys>>>,
+    /// Keys revoked right away since the last refresh, mapped to when they were revoked
+    revoked: Arc<RwLock<HashMap<String, DateTime<Utc>>>>,
+}
+
+impl Revocations {
+    pub fn is_revoked(&self, group: &str, access_key: &str, now: DateTime<Utc>) -> bool {
+        self.revoked.read().unwrap().contains_key(access_key)
+            || self
+                .accepted
+                .read()
+                .unwrap()
+                .get(group)
+                .is_some_and(|accepted| !accepted.accepts(access_key, now))
+    }
+
+    pub fn revoke(&self, access_key: String, revoked_at: DateTime<Utc>) {
+        self.revoked.write().unwrap().insert(access_key, revoked_at);
+    }
+
+    /// Replaces the accepted keys with the ones loaded from redis. Keys revoked
+    /// before the load started are part of it and no longer tracked separately.
+    fn replace(&self, accepted: HashMap<String, String>, loaded_at: DateTime<Utc>) {
+        let accepted = accepted
+            .into_iter()
+            .filter_map(|(group, keys)| match serde_json::from_str(&keys) {
+                Ok(keys) => Some((group, keys)),
+                Err(e) => {
+                    warn!("Invalid accepted access keys for group {group}: {e}");
+                    None
+                }
+            })
+            .collect();
+
+        *self.accepted.write().unwrap() = accepted;
+        self.revoked
+            .write()
+            .unwrap()
+            .retain(|_, revoked_at| *revoked_at >= loaded_at);
+    }
+
+    /// Keeps the accepted keys in sync with the redis hash the API writes to.
+    ///
+    /// Immediate revocations are also published so they take effect without
+    /// waiting for the next refresh, and evict the key from the access key cache.
+    pub fn watch(&self, config: &Config, cache: Cache<EncryptedAccessKey<'static>, AccessKey>) {
+        let revocations = self.clone();
+        let redis_config = config.redis.clone();
+        let refresh_interval = Duration::from_secs(config.revocation_refresh_interval_secs);
+        tokio::spawn(async move {
+            let mut redis = loop {
+                match RedisCache::new(&redis_config).await {
+                    Ok(redis) => break redis,
+                    Err(e) => {
+                        error!("Could not connect to redis to load revoked access keys: {e}");
+                        tokio::time::sleep(refresh_interval).await;
+                    }
+                }
+            };
+
+            loop {
+                let loaded_at = Utc::now();
+                match redis
+                    .inner
+                    .hgetall::<_, HashMap<String, String>>(&redis_config.revoked_access_keys_key)
+                    .await
+                {
+                    Ok(loaded) => {
+                        trace!("Loaded accepted access keys of {} groups", loaded.len());
+                        revocations.replace(loaded, loaded_at);
+                    }
+                    Err(e) => error!("Could not load revoked access keys: {e}"),
+                }
+
+                tokio::time::sleep(refresh_interval).await;
+            }
+        });
+
+        let revocations = self.clone();
+        subscribe(
+            &config.redis,
+            config.redis.revoked_access_keys_key.clone(),
+            move |access_key| {
+                let Ok(encrypted_access_key) = EncryptedAccessKey::parse(&access_key) else {
+                    warn!("Received an invalid revoked access key");
+                    return;
+                };
+                let encrypted_access_key = encrypted_access_key.to_static();
+                revocations.revoke(access_key, Utc::now());
+
+                let cache = cache.clone();
+                tokio::spawn(async move { cache.invalidate(&encrypted_access_key).await });
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use integrationos_domain::event_access::PreviousAccessKey;
+
+    #[test]
+    fn test_revocations() {
+        let now = Utc::now();
+        let revocations = Revocations::default();
+        let accepted = AcceptedAccessKeys {
+            access_key: "current".to_owned(),
+            previous_access_keys: vec![PreviousAccessKey {
+                access_key: "grace".to_owned(),
+                expires_at: now + Duration::hours(1),
+                last_used_at: None,
+            }],
+        };
+        revocations.replace(
+            HashMap::from([(
+                "group".to_owned(),
+                serde_json::to_string(&accepted).unwrap(),
+            )]),
+            now,
+        );
+
+        assert!(!revocations.is_revoked("group", "current", now));
+        assert!(!revocations.is_revoked("group", "grace", now));
+        assert!(revocations.is_revoked("group", "grace", now + Duration::hours(2)));
+        assert!(revocations.is_revoked("group", "revoked", now));
+        assert!(!revocations.is_revoked("other", "unknown", now));
+
+        revocations.revoke("grace".to_owned(), now);
+        assert!(revocations.is_revoked("group", "grace", now));
+
+        // Dropped once a refresh started after the revocation
+        revocations.replace(HashMap::new(), now + Duration::seconds(1));
+        assert!(!revocations.is_revoked("group", "grace", now));
+    }
+}
@@ -1,17 +1,22 @@
 use crate::{
-    config::Config, finalizer::event::FinalizeEvent, mock::finalizer::MockFinalizer,
-    util::get_value_from_path,
+    config::Config,
+    finalizer::event::{FinalizeEvent, WebhookConfig},
+    mock::finalizer::MockFinalizer,
+    revocation::Revocations,
+    util::PathContext,
 };
 use anyhow::{anyhow, Result};
 use axum::{
     body::Bytes,
     debug_handler,
     extract::{Path, Query, State},
-    http::{HeaderMap, HeaderName, StatusCode},
+    http::{header::CONTENT_TYPE, HeaderMap, HeaderName, Method, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
 use axum_prometheus::PrometheusMetricLayer;
+use chrono::{DateTime, Duration, Utc};
 use integrationos_domain::{
     encrypted_access_key::EncryptedAccessKey, encrypted_data::PASSWORD_LENGTH,
     event_response::EventResponse, event_type::EventType, AccessKey, Event,
@@ -37,19 +42,46 @@ const MISSING_HEADER_ERROR: (StatusCode, &str) =
 pub struct AppState {
     pub config: Config,
     pub cache: Cache<EncryptedAccessKey<'static>, AccessKey>,
+    pub webhook_configs: Cache<EncryptedAccessKey<'static>, WebhookConfig>,
+    pub revocations: Revocations,
     pub finalizer: Arc<dyn FinalizeEvent + Sync + Send>,
 }
 
 impl AppState {
     pub fn new(config: Config, finalizer: Arc<dyn FinalizeEvent + Sync + Send>) -> Self {
         let cache = Cache::new(config.cache_size);
+        // Connections can be updated after they are created, so they are not cached forever
+        let webhook_configs = Cache::builder()
+            .max_capacity(config.cache_size)
+            .time_to_live(std::time::Duration::from_secs(
+                config.webhook_config_cache_ttl_secs,
+            ))
+            .build();
         Self {
             config,
             cache,
+            webhook_configs,
+            revocations: Revocations::default(),
             finalizer,
         }
     }
 
+    pub async fn get_webhook_config(
+        &self,
+        encrypted_access_key: &EncryptedAccessKey<'static>,
+    ) -> WebhookConfig {
+        self.webhook_configs
+            .try_get_with(
+                encrypted_access_key.clone(),
+                self.finalizer.get_webhook_config(encrypted_access_key),
+            )
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to get webhook config: {e:?}");
+                WebhookConfig::default()
+            })
+    }
+
     pub fn get_secret_key(&self) -> [u8; PASSWORD_LENGTH] {
         // We validate that the config must have 32 byte secret key in main.rs
         // So this is safe to unwrap
@@ -58,9 +90,77 @@ impl AppState {
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct EventRequest {
     pub event: String,
     pub payload: Value,
+    /// Delivers the event at the given time instead of right away
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub deliver_at: Option<DateTime<Utc>>,
+    /// Delivers the event after the given number of seconds instead of right away
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub delay: Option<u64>,
+}
+
+impl EventRequest {
+    pub fn get_deliver_at(&self) -> Result<Option<DateTime<Utc>>, (StatusCode, &'static str)> {
+        match (self.deliver_at, self.delay) {
+            (Some(_), Some(_)) => Err((
+                StatusCode::BAD_REQUEST,
+                "Only one of deliverAt and delay can be set",
+            )),
+            (Some(deliver_at), None) => Ok(Some(deliver_at)),
+            (None, Some(delay)) => i64::try_from(delay)
+                .ok()
+                .and_then(Duration::try_seconds)
+                .and_then(|delay| Utc::now().checked_add_signed(delay))
+                .map(Some)
+                .ok_or((StatusCode::BAD_REQUEST, "Invalid delay")),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// Event fields read from a webhook request through the access key paths
+#[derive(Default)]
+struct WebhookFields {
+    object_id: Option<String>,
+    occurred_at: Option<DateTime<Utc>>,
+    parsed_body: Option<Value>,
+}
+
+impl WebhookFields {
+    async fn new(
+        access_key: &AccessKey,
+        encrypted_access_key: &EncryptedAccessKey<'static>,
+        context: PathContext,
+        state: &AppState,
+    ) -> Self {
+        // Missing ids and timestamps only disable dedupe and event time filtering
+        let object_id = access_key
+            .data
+            .event_object_id_path
+            .as_deref()
+            .and_then(|path| context.select_string(path).ok());
+        let occurred_at = access_key
+            .data
+            .timestamp_path
+            .as_deref()
+            .and_then(|path| context.select_timestamp(path).ok());
+
+        let parsed_body = state
+            .get_webhook_config(encrypted_access_key)
+            .await
+            .settings
+            .filter(|settings| settings.parse_webhook_body)
+            .map(|_| context.body().clone());
+
+        Self {
+            object_id,
+            occurred_at,
+            parsed_body,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -103,16 +203,19 @@ impl Server {
         let state = Arc::new(AppState::new(self.config.clone(), self.finalizer.clone()));
         let mut router = Router::new()
             .route("/emit", post(post_event_sk))
-            .route("/emit/:id", post(post_event_id))
+            .route("/emit/batch", post(post_events_sk))
+            .route("/emit/:id", post(post_event_id).get(get_event_id))
             .layer(SetSensitiveRequestHeadersLayer::new(once(
                 HeaderName::from_lowercase(HEADER_STR.as_bytes()).unwrap(),
             )))
             .layer(TraceLayer::new_for_http())
             .route("/", get(get_root))
             .layer(CorsLayer::new().allow_origin(Any))
-            .with_state(state);
+            .with_state(state.clone());
 
         if !cfg!(test) {
+            state.revocations.watch(&self.config, state.cache.clone());
+
             let (prometheus_layer, metric_handle) = PrometheusMetricLayer::pair();
             router = router
                 .route("/metrics", get(|| async move { metric_handle.render() }))
@@ -121,13 +224,10 @@ impl Server {
         router
     }
 
-    pub async fn handle_event(
+    async fn get_access_key(
         encrypted_access_key: EncryptedAccessKey<'_>,
-        payload: Bytes,
-        query: Option<HashMap<String, String>>,
-        headers: HeaderMap,
-        state: Arc<AppState>,
-    ) -> Result<Json<EventResponse>, (StatusCode, &'static str)> {
+        state: &AppState,
+    ) -> Result<(AccessKey, EncryptedAccessKey<'static>), (StatusCode, &'static str)> {
         if encrypted_access_key.prefix.environment != state.config.environment {
             warn!("Identifier is wrong environment");
             return Err(INVALID_ACCESS_KEY_ERROR);
@@ -149,34 +249,154 @@ impl Server {
             return Err(INVALID_ACCESS_KEY_ERROR);
         };
 
-        let (name, payload) = if access_key.prefix.event_type == EventType::SecretKey {
-            let payload = match serde_json::from_slice::<EventRequest>(&payload) {
-                Ok(payload) => payload,
-                Err(e) => {
-                    warn!("Failed to deserialize payload: {e:?}");
-                    return Err((StatusCode::BAD_REQUEST, "Failed to deserialize payload"));
-                }
+        if state.revocations.is_revoked(
+            &access_key.data.group,
+            &encrypted_access_key.to_string(),
+            Utc::now(),
+        ) {
+            warn!("Identifier has been revoked");
+            return Err(INVALID_ACCESS_KEY_ERROR);
+        }
+
+        Ok((access_key, encrypted_access_key))
+    }
+
+    /// Answers a provider's webhook URL verification request without creating an event.
+    ///
+    /// Returns `None` when the request does not match any handshake of the access key.
+    pub async fn handle_handshake(
+        encrypted_access_key: EncryptedAccessKey<'_>,
+        method: &Method,
+        payload: &[u8],
+        query: &Option<HashMap<String, String>>,
+        headers: &HeaderMap,
+        state: &AppState,
+    ) -> Result<Option<Response>, (StatusCode, &'static str)> {
+        let (_, encrypted_access_key) = Self::get_access_key(encrypted_access_key, state).await?;
+        let config = state.get_webhook_config(&encrypted_access_key).await;
+        if config.handshakes.is_empty() {
+            return Ok(None);
+        }
+        let Ok(context) = PathContext::new(headers, payload, query) else {
+            return Ok(None);
+        };
+
+        let matched = config
+            .handshakes
+            .iter()
+            .filter(|handshake| handshake.matches_method(method.as_str()))
+            .filter(|handshake| {
+                handshake.conditions.iter().all(|condition| {
+                    condition.matches(context.select_string(&condition.path).ok().as_deref())
+                })
+            })
+            .find_map(|handshake| {
+                let challenge = context.select_string(&handshake.challenge_path).ok()?;
+                Some((handshake, challenge))
+            });
+        let Some((handshake, challenge)) = matched else {
+            return Ok(None);
+        };
+
+        let secret = match (
+            handshake.response.secrets_service_id(),
+            &config.ownership_id,
+        ) {
+            (Some(secrets_service_id), Some(ownership_id)) => Some(
+                state
+                    .finalizer
+                    .get_handshake_secret(secrets_service_id, ownership_id)
+                    .await
+                    .map_err(|e| {
+                        error!("Failed to get webhook handshake secret: {e}");
+                        (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            "Failed to answer webhook handshake",
+                        )
+                    })?,
+            ),
+            _ => None,
+        };
+
+        match handshake.response.respond(&challenge, secret.as_deref()) {
+            Ok(Value::String(text)) => Ok(Some(text.into_response())),
+            Ok(value) => Ok(Some(Json(value).into_response())),
+            Err(e) => {
+                error!("Failed to answer webhook handshake: {e}");
+                Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to answer webhook handshake",
+                ))
+            }
+        }
+    }
+
+    pub async fn handle_event(
+        encrypted_access_key: EncryptedAccessKey<'_>,
+        payload: Bytes,
+        query: Option<HashMap<String, String>>,
+        headers: HeaderMap,
+        state: Arc<AppState>,
+    ) -> Result<Json<EventResponse>, (StatusCode, &'static str)> {
+        let (access_key, encrypted_access_key) =
+            Self::get_access_key(encrypted_access_key, &state).await?;
+
+        let (name, payload, deliver_at, webhook) =
+            if access_key.prefix.event_type == EventType::SecretKey {
+                let payload = match serde_json::from_slice::<EventRequest>(&payload) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        warn!("Failed to deserialize payload: {e:?}");
+                        return Err((StatusCode::BAD_REQUEST, "Failed to deserialize payload"));
+                    }
+                };
+                let deliver_at = payload.get_deliver_at()?;
+                (
+                    payload.event,
+                    payload.payload.to_string(),
+                    deliver_at,
+                    WebhookFields::default(),
+                )
+            } else {
+                let context = match PathContext::new(&headers, &payload, &query) {
+                    Ok(context) => context,
+                    Err(e) => {
+                        warn!("Failed to read webhook request: {e:?}");
+                        return Err((StatusCode::BAD_REQUEST, "Failed to deserialize payload"));
+                    }
+                };
+                let name = context
+                    .select_string(&access_key.data.event_path)
+                    .unwrap_or("null".to_string());
+                let webhook =
+                    WebhookFields::new(&access_key, &encrypted_access_key, context, &state).await;
+                let payload = match String::from_utf8(payload.to_vec()) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        warn!("Failed to deserialize payload: {e:?}");
+                        return Err((StatusCode::BAD_REQUEST, "Failed to deserialize payload"));
+                    }
+                };
+                (name, payload, None, webhook)
             };
-            (payload.event, payload.payload.to_string())
-        } else {
-            let name = get_value_from_path(
-                &mut access_key.data.event_path.clone(),
-                &headers,
-                &payload,
-                &query,
-            )
-            .unwrap_or("null".to_string());
-            let payload = match String::from_utf8(payload.to_vec()) {
-                Ok(payload) => payload,
+
+        let event = Event::new(&access_key, &encrypted_access_key, &name, headers, payload)
+            .with_object_id(webhook.object_id)
+            .with_occurred_at(webhook.occurred_at)
+            .with_parsed_body(webhook.parsed_body);
+
+        if let Some(deliver_at) = deliver_at.filter(|deliver_at| *deliver_at > Utc::now()) {
+            return match state.finalizer.schedule_event(&event, deliver_at).await {
+                Ok(scheduled_event) => Ok(Json(EventResponse::scheduled(event, &scheduled_event))),
                 Err(e) => {
-                    warn!("Failed to deserialize payload: {e:?}");
-                    return Err((StatusCode::BAD_REQUEST, "Failed to deserialize payload"));
+                    error!("Failed to schedule event: {e:?}");
+                    Err((
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Failed to schedule event",
+                    ))
                 }
             };
-            (name, payload)
-        };
-
-        let event = Event::new(&access_key, &encrypted_access_key, &name, headers, payload);
+        }
 
         match state
             .finalizer
@@ -193,14 +413,97 @@ impl Server {
             }
         }
     }
+
+    /// Acknowledges a batch of secret key events, validating the access key only once.
+    ///
+    /// Items that cannot be parsed, scheduled or stored fail on their own, while all
+    /// the remaining events are stored and queued in bulk.
+    pub async fn handle_events(
+        encrypted_access_key: EncryptedAccessKey<'_>,
+        requests: Vec<Result<EventRequest, String>>,
+        headers: HeaderMap,
+        state: Arc<AppState>,
+    ) -> Result<Json<Vec<BatchEventResponse>>, (StatusCode, &'static str)> {
+        let (access_key, encrypted_access_key) =
+            Self::get_access_key(encrypted_access_key, &state).await?;
+
+        let mut responses = Vec::with_capacity(requests.len());
+        let mut pending = Vec::with_capacity(requests.len());
+        let mut events = Vec::with_capacity(requests.len());
+        for request in requests {
+            let request = match request {
+                Ok(request) => request,
+                Err(error) => {
+                    responses.push(BatchEventResponse::Error { error });
+                    continue;
+                }
+            };
+            let deliver_at = match request.get_deliver_at() {
+                Ok(deliver_at) => deliver_at.filter(|deliver_at| *deliver_at > Utc::now()),
+                Err((_, error)) => {
+                    responses.push(BatchEventResponse::Error {
+                        error: error.to_owned(),
+                    });
+                    continue;
+                }
+            };
+
+            let event = Event::new(
+                &access_key,
+                &encrypted_access_key,
+                &request.event,
+                headers.clone(),
+                request.payload.to_string(),
+            );
+
+            match deliver_at {
+                Some(deliver_at) => {
+                    match state.finalizer.schedule_event(&event, deliver_at).await {
+                        Ok(scheduled_event) => responses.push(BatchEventResponse::Event(
+                            EventResponse::scheduled(event, &scheduled_event),
+                        )),
+                        Err(e) => {
+                            error!("Failed to schedule event: {e:?}");
+                            responses.push(BatchEventResponse::Error {
+                                error: "Failed to schedule event".to_owned(),
+                            });
+                        }
+                    }
+                }
+                None => {
+                    pending.push(responses.len());
+                    events.push(event.clone());
+                    responses.push(BatchEventResponse::Event(EventResponse::new(event)));
+                }
+            }
+        }
+
+        if !events.is_empty() {
+            let results = state.finalizer.finalize_events(&events).await;
+            for (index, result) in pending.into_iter().zip(results) {
+                if let Err(e) = result {
+                    error!("Failed to finalize event: {e:?}");
+                    responses[index] = BatchEventResponse::Error {
+                        error: "Failed to acknowledge event".to_owned(),
+                    };
+                }
+            }
+        }
+
+        Ok(Json(responses))
+    }
 }
 
-#[debug_handler]
-async fn post_event_sk(
-    headers: HeaderMap,
-    State(state): State<Arc<AppState>>,
-    body: Bytes,
-) -> Result<Json<EventResponse>, (StatusCode, &'static str)> {
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum BatchEventResponse {
+    Event(EventResponse),
+    Error { error: String },
+}
+
+fn get_secret_key(
+    headers: &HeaderMap,
+) -> Result<EncryptedAccessKey<'_>, (StatusCode, &'static str)> {
     let Some(identifier) = headers.get(HEADER_STR) else {
         return Err(MISSING_HEADER_ERROR);
     };
@@ -223,18 +526,11 @@ async fn post_event_sk(
         return Err(INVALID_ACCESS_KEY_ERROR);
     }
 
-    Server::handle_event(encrypted_key, body, None, headers.clone(), state).await
+    Ok(encrypted_key)
 }
 
-#[debug_handler]
-async fn post_event_id(
-    headers: HeaderMap,
-    query: Option<Query<HashMap<String, String>>>,
-    Path(identifier): Path<String>,
-    State(state): State<Arc<AppState>>,
-    body: Bytes,
-) -> Result<Json<EventResponse>, (StatusCode, &'static str)> {
-    let encrypted_key = match EncryptedAccessKey::parse(&identifier) {
+fn get_id_key(identifier: &str) -> Result<EncryptedAccessKey<'_>, (StatusCode, &'static str)> {
+    let encrypted_key = match EncryptedAccessKey::parse(identifier) {
         Ok(e) => e,
         Err(e) => {
             warn!("Could not parse identifier: {e}");
@@ -247,9 +543,107 @@ async fn post_event_id(
         return Err(INVALID_ACCESS_KEY_ERROR);
     }
 
+    Ok(encrypted_key)
+}
+
+#[debug_handler]
+async fn post_event_sk(
+    headers: HeaderMap,
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<Json<EventResponse>, (StatusCode, &'static str)> {
+    let encrypted_key = get_secret_key(&headers)?;
+
+    Server::handle_event(encrypted_key, body, None, headers.clone(), state).await
+}
+
+#[debug_handler]
+async fn post_events_sk(
+    headers: HeaderMap,
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<Json<Vec<BatchEventResponse>>, (StatusCode, &'static str)> {
+    let encrypted_key = get_secret_key(&headers)?;
+
+    let is_ndjson = headers
+        .get(CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .is_some_and(|c| c.starts_with("application/x-ndjson"));
+
+    let items: Vec<Result<Value, String>> = if is_ndjson {
+        let Ok(body) = std::str::from_utf8(&body) else {
+            return Err((StatusCode::BAD_REQUEST, "Failed to deserialize payload"));
+        };
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| e.to_string()))
+            .collect()
+    } else {
+        match serde_json::from_slice::<Vec<Value>>(&body) {
+            Ok(items) => items.into_iter().map(Ok).collect(),
+            Err(e) => {
+                warn!("Failed to deserialize batch: {e:?}");
+                return Err((StatusCode::BAD_REQUEST, "Failed to deserialize payload"));
+            }
+        }
+    };
+
+    if items.len() > state.config.max_batch_size {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "Too many events in batch"));
+    }
+
+    let requests = items
+        .into_iter()
+        .map(|item| item.and_then(|v| serde_json::from_value(v).map_err(|e| e.to_string())))
+        .collect();
+
+    let headers = headers.clone();
+    Server::handle_events(encrypted_key, requests, headers, state).await
+}
+
+#[debug_handler]
+async fn post_event_id(
+    headers: HeaderMap,
+    query: Option<Query<HashMap<String, String>>>,
+    Path(identifier): Path<String>,
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<Response, (StatusCode, &'static str)> {
+    let encrypted_key = get_id_key(&identifier)?;
+    let query = query.map(|q| q.0);
+
+    if let Some(response) = Server::handle_handshake(
+        encrypted_key.clone(),
+        &Method::POST,
+        &body,
+        &query,
+        &headers,
+        &state,
+    )
+    .await?
+    {
+        return Ok(response);
+    }
+
+    Server::handle_event(encrypted_key, body, query, headers, state)
+        .await
+        .map(IntoResponse::into_response)
+}
+
+/// Some providers verify the webhook URL with a GET before they start posting events
+#[debug_handler]
+async fn get_event_id(
+    headers: HeaderMap,
+    query: Option<Query<HashMap<String, String>>>,
+    Path(identifier): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, (StatusCode, &'static str)> {
+    let encrypted_key = get_id_key(&identifier)?;
     let query = query.map(|q| q.0);
 
-    Server::handle_event(encrypted_key, body, query, headers, state).await
+    Server::handle_handshake(encrypted_key, &Method::GET, &[], &query, &headers, &state)
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, "No webhook handshake matched"))
 }
 
 async fn get_root() {}
@@ -265,6 +659,7 @@ mod tests {
         event_state::EventState,
         hashes::{HashType, HashValue},
     };
+    use serde_json::json;
     use tower::ServiceExt;
 
     use super::*;
@@ -332,6 +727,49 @@ mod tests {
         assert_eq!(&body[..], b"Invalid access key");
     }
 
+    #[tokio::test]
+    async fn test_emit_id_answers_handshake() {
+        let router = Server::default().get_router();
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/emit/{VALID_ID_KEY}"))
+                    .header(CONTENT_TYPE, "application/json")
+                    .method(Method::POST)
+                    .body(Body::from(
+                        json!({ "type": "url_verification", "challenge": "3eZbrw1aBm2rZgRNFdxV2595E9CY3gmdALWMmHkvFXO7tYXAYM8P" })
+                            .to_string(),
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            &body[..],
+            b"3eZbrw1aBm2rZgRNFdxV2595E9CY3gmdALWMmHkvFXO7tYXAYM8P"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_emit_id_without_handshake() {
+        let router = Server::default().get_router();
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/emit/{VALID_ID_KEY}?hub.challenge=123"))
+                    .method(Method::GET)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
     #[tokio::test]
     async fn test_emit_sk() {
         let router = Server::default().get_router();
@@ -374,6 +812,147 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_emit_sk_with_delay_is_scheduled() {
+        let router = Server::default().get_router();
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/emit")
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(HEADER_STR, VALID_SK_KEY)
+                    .method(Method::POST)
+                    .body(Body::from(
+                        "{\"event\": \"foo\", \"payload\": \"bar\", \"delay\": 86400}",
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let resp = serde_json::from_slice::<EventResponse>(&body).unwrap();
+        assert_eq!(resp.status, EventState::Acknowledged);
+        assert!(resp.scheduled_event_id.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_emit_sk_with_delay_and_deliver_at() {
+        let router = Server::default().get_router();
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/emit")
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(HEADER_STR, VALID_SK_KEY)
+                    .method(Method::POST)
+                    .body(Body::from(
+                        "{\"event\": \"foo\", \"payload\": \"bar\", \"delay\": 60, \"deliverAt\": \"2030-01-01T00:00:00Z\"}",
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_delay_overflowing_i64_is_rejected() {
+        let request = EventRequest {
+            event: "foo".to_owned(),
+            payload: json!("bar"),
+            deliver_at: None,
+            delay: Some(u64::MAX),
+        };
+
+        assert_eq!(
+            request.get_deliver_at(),
+            Err((StatusCode::BAD_REQUEST, "Invalid delay"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_emit_batch() {
+        let router = Server::default().get_router();
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/emit/batch")
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(HEADER_STR, VALID_SK_KEY)
+                    .method(Method::POST)
+                    .body(Body::from(
+                        r#"[{"event": "foo", "payload": "bar"}, {"payload": "missing event"}, {"event": "baz", "payload": {"a": 1}}]"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let resp = serde_json::from_slice::<Vec<BatchEventResponse>>(&body).unwrap();
+        assert_eq!(resp.len(), 3);
+        assert!(
+            matches!(resp[0], BatchEventResponse::Event(ref e) if e.status == EventState::Acknowledged)
+        );
+        assert!(matches!(resp[1], BatchEventResponse::Error { .. }));
+        assert!(matches!(resp[2], BatchEventResponse::Event(_)));
+    }
+
+    #[tokio::test]
+    async fn test_emit_batch_ndjson() {
+        let router = Server::default().get_router();
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/emit/batch")
+                    .header(CONTENT_TYPE, "application/x-ndjson")
+                    .header(HEADER_STR, VALID_SK_KEY)
+                    .method(Method::POST)
+                    .body(Body::from(
+                        "{\"event\": \"foo\", \"payload\": \"bar\"}\n\n{\"event\": \"baz\", \"payload\": \"qux\", \"delay\": 60}\nnot json\n",
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let resp = serde_json::from_slice::<Vec<BatchEventResponse>>(&body).unwrap();
+        assert_eq!(resp.len(), 3);
+        assert!(
+            matches!(resp[0], BatchEventResponse::Event(ref e) if e.scheduled_event_id.is_none())
+        );
+        assert!(
+            matches!(resp[1], BatchEventResponse::Event(ref e) if e.scheduled_event_id.is_some())
+        );
+        assert!(matches!(resp[2], BatchEventResponse::Error { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_emit_batch_too_large() {
+        let router = Server::default().get_router();
+        let batch = vec![json!({"event": "foo", "payload": "bar"}); 1_001];
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/emit/batch")
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(HEADER_STR, VALID_SK_KEY)
+                    .method(Method::POST)
+                    .body(Body::from(serde_json::to_vec(&batch).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
     #[tokio::test]
     async fn test_invalid_emit_sk() {
         let router = Server::default().get_router();
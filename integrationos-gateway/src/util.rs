@@ -1,48 +1,315 @@
 use anyhow::{anyhow, Result};
-use http::HeaderMap;
-use serde_json::json;
+use chrono::{DateTime, Utc};
+use http::{header::CONTENT_TYPE, HeaderMap};
+use mime::Mime;
+use quick_xml::{events::Event as XmlEvent, Reader};
+use serde_json::{json, Map, Value};
 use std::collections::HashMap;
 
+/// Timestamps below this are considered to be in seconds rather than milliseconds
+const MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+/// The `{headers, body, query}` object access key paths are evaluated against.
+///
+/// Paths starting with `$` are JSONPath expressions, paths starting with `_.` are
+/// the legacy dot separated form, and anything else is returned as a literal.
+pub struct PathContext(Value);
+
+impl PathContext {
+    pub fn new(
+        headers: &HeaderMap,
+        body: &[u8],
+        query: &Option<HashMap<String, String>>,
+    ) -> Result<Self> {
+        let body = parse_body(headers, body);
+        let headers =
+            http_serde_ext_ios::header_map::serialize(headers, serde_json::value::Serializer)?;
+
+        Ok(Self(json!({
+            "headers": headers,
+            "body": body,
+            "query": query,
+        })))
+    }
+
+    /// The JSON view of the request body paths under `body` are evaluated against
+    pub fn body(&self) -> &Value {
+        &self.0["body"]
+    }
+
+    pub fn select(&self, path: &str) -> Result<Value> {
+        if path.starts_with('$') {
+            let mut values = jsonpath_lib::select(&self.0, path)
+                .map_err(|e| anyhow!("Invalid path {path}: {e}"))?;
+            return match values.len() {
+                0 => Err(anyhow!("No value found for path: {}", path)),
+                1 => Ok(values.remove(0).clone()),
+                _ => Ok(Value::Array(values.into_iter().cloned().collect())),
+            };
+        }
+
+        if path.len() < 2 || &path[0..2] != "_." {
+            return Ok(Value::String(path.to_owned()));
+        }
+
+        let mut obj = &self.0;
+        for key in path.split('.').skip(1) {
+            obj = obj
+                .get(key)
+                .ok_or_else(|| anyhow!("No value found for path: {}", path))?;
+        }
+        Ok(obj.clone())
+    }
+
+    pub fn select_string(&self, path: &str) -> Result<String> {
+        let value = self.select(path)?;
+        Ok(value
+            .as_str()
+            .map(|s| s.to_owned())
+            .unwrap_or_else(|| value.to_string()))
+    }
+
+    /// Accepts RFC 3339 strings and unix timestamps in seconds or milliseconds
+    pub fn select_timestamp(&self, path: &str) -> Result<DateTime<Utc>> {
+        let value = self.select(path)?;
+        let millis = match &value {
+            Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f as i64)),
+            Value::String(s) => match DateTime::parse_from_rfc3339(s) {
+                Ok(timestamp) => return Ok(timestamp.with_timezone(&Utc)),
+                Err(_) => s.parse::<i64>().ok(),
+            },
+            _ => None,
+        }
+        .map(|t| {
+            if t.abs() < MILLIS_THRESHOLD {
+                t * 1_000
+            } else {
+                t
+            }
+        })
+        .ok_or_else(|| anyhow!("Value at {path} is not a timestamp: {value}"))?;
+
+        DateTime::from_timestamp_millis(millis)
+            .ok_or_else(|| anyhow!("Value at {path} is out of range: {value}"))
+    }
+}
+
+/// Normalizes a request body into JSON based on its content type.
+///
+/// Form posts become an object of their fields, XML documents an object keyed by
+/// the root element and multipart bodies an object of their non file fields. Bodies
+/// that cannot be parsed are kept as a single string.
+pub fn parse_body(headers: &HeaderMap, body: &[u8]) -> Value {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<Mime>().ok());
+
+    let parsed = match &content_type {
+        Some(content_type)
+            if content_type.type_() == mime::APPLICATION
+                && content_type.subtype() == mime::WWW_FORM_URLENCODED =>
+        {
+            Some(parse_form(body))
+        }
+        Some(content_type)
+            if content_type.subtype() == mime::XML || content_type.suffix() == Some(mime::XML) =>
+        {
+            parse_xml(body).ok()
+        }
+        Some(content_type)
+            if content_type.type_() == mime::MULTIPART
+                && content_type.subtype() == mime::FORM_DATA =>
+        {
+            content_type
+                .get_param(mime::BOUNDARY)
+                .map(|boundary| parse_multipart(body, boundary.as_str()))
+        }
+        _ => serde_json::from_slice::<Value>(body).ok(),
+    };
+
+    parsed.unwrap_or_else(|| Value::String(String::from_utf8_lossy(body).into_owned()))
+}
+
+/// Adds a value to an object, turning repeated keys into an array
+fn insert_repeated(map: &mut Map<String, Value>, key: String, value: Value) {
+    match map.get_mut(&key) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            map.insert(key, value);
+        }
+    }
+}
+
+fn parse_form(body: &[u8]) -> Value {
+    let mut fields = Map::new();
+    for (key, value) in form_urlencoded::parse(body) {
+        insert_repeated(
+            &mut fields,
+            key.into_owned(),
+            Value::String(value.into_owned()),
+        );
+    }
+    Value::Object(fields)
+}
+
+/// Attributes are kept as `@name` keys and text next to child elements as `#text`
+fn parse_xml(body: &[u8]) -> Result<Value> {
+    let mut reader = Reader::from_reader(body);
+    reader.config_mut().trim_text(true);
+
+    // Open elements with their attributes and children, and the text they contain
+    let mut stack: Vec<(String, Map<String, Value>, String)> = vec![];
+    let mut root = Map::new();
+
+    loop {
+        let (name, fields, text) = match reader.read_event()? {
+            XmlEvent::Start(element) => {
+                stack.push(xml_element(&element)?);
+                continue;
+            }
+            XmlEvent::Empty(element) => xml_element(&element)?,
+            XmlEvent::Text(text) => {
+                if let Some((_, _, content)) = stack.last_mut() {
+                    content.push_str(&text.unescape()?);
+                }
+                continue;
+            }
+            XmlEvent::CData(data) => {
+                if let Some((_, _, content)) = stack.last_mut() {
+                    content.push_str(&String::from_utf8_lossy(&data));
+                }
+                continue;
+            }
+            XmlEvent::End(_) => stack
+                .pop()
+                .ok_or_else(|| anyhow!("Unexpected closing element"))?,
+            XmlEvent::Eof => break,
+            _ => continue,
+        };
+
+        let value = match (fields.is_empty(), text.is_empty()) {
+            (true, _) => Value::String(text),
+            (false, true) => Value::Object(fields),
+            (false, false) => {
+                let mut fields = fields;
+                fields.insert("#text".to_owned(), Value::String(text));
+                Value::Object(fields)
+            }
+        };
+        match stack.last_mut() {
+            Some((_, parent, _)) => insert_repeated(parent, name, value),
+            None => insert_repeated(&mut root, name, value),
+        }
+    }
+
+    if root.is_empty() || !stack.is_empty() {
+        return Err(anyhow!("Body is not a complete XML document"));
+    }
+    Ok(Value::Object(root))
+}
+
+fn xml_element(
+    element: &quick_xml::events::BytesStart,
+) -> Result<(String, Map<String, Value>, String)> {
+    let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+    let mut attributes = Map::new();
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = String::from_utf8_lossy(attribute.key.as_ref());
+        attributes.insert(
+            format!("@{key}"),
+            Value::String(attribute.unescape_value()?.into_owned()),
+        );
+    }
+    Ok((name, attributes, String::new()))
+}
+
+/// Files are replaced by their name and size, only text fields are kept whole
+fn parse_multipart(body: &[u8], boundary: &str) -> Value {
+    let delimiter = format!("--{boundary}");
+    let mut fields = Map::new();
+
+    for part in split_bytes(body, delimiter.as_bytes()).into_iter().skip(1) {
+        // The closing delimiter is followed by `--`
+        if part.starts_with(b"--") {
+            break;
+        }
+        let part = part.strip_prefix(b"\r\n").unwrap_or(part);
+        let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+        let Some(end) = find_bytes(part, b"\r\n\r\n") else {
+            continue;
+        };
+        let (head, content) = (&part[..end], &part[end + 4..]);
+
+        let head = String::from_utf8_lossy(head);
+        let Some(disposition) = head
+            .lines()
+            .find(|line| line.to_ascii_lowercase().starts_with("content-disposition"))
+        else {
+            continue;
+        };
+        let Some(name) = disposition_param(disposition, "name") else {
+            continue;
+        };
+
+        let value = match disposition_param(disposition, "filename") {
+            Some(filename) => json!({ "filename": filename, "size": content.len() }),
+            None => Value::String(String::from_utf8_lossy(content).into_owned()),
+        };
+        insert_repeated(&mut fields, name, value);
+    }
+
+    Value::Object(fields)
+}
+
+fn disposition_param(disposition: &str, param: &str) -> Option<String> {
+    disposition.split(';').skip(1).find_map(|pair| {
+        let (key, value) = pair.trim().split_once('=')?;
+        key.eq_ignore_ascii_case(param)
+            .then(|| value.trim_matches('"').to_owned())
+    })
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn split_bytes<'a>(mut haystack: &'a [u8], needle: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = vec![];
+    while let Some(index) = find_bytes(haystack, needle) {
+        parts.push(&haystack[..index]);
+        haystack = &haystack[index + needle.len()..];
+    }
+    parts.push(haystack);
+    parts
+}
+
 pub fn get_value_from_path(
     path: &mut String,
     headers: &HeaderMap,
     body: &[u8],
     query: &Option<HashMap<String, String>>,
 ) -> Result<String> {
-    if path.len() < 2 || &path[0..2] != "_." {
+    if !path.starts_with('$') && (path.len() < 2 || &path[0..2] != "_.") {
         return Ok(path.to_owned());
     }
 
-    let body = serde_json::from_slice::<serde_json::Value>(body)?;
-    let headers =
-        http_serde_ext_ios::header_map::serialize(headers, serde_json::value::Serializer)?;
-    let mut obj = json!({
-        "headers": headers,
-        "body": body,
-        "query": query,
-    });
-
-    for key in path.split('.').skip(1) {
-        let temp = match obj.get(key) {
-            Some(t) => t,
-            None => {
-                return Err(anyhow!("No value found for path: {}", path));
-            }
-        };
-        obj = temp.clone();
-    }
-
-    Ok(obj
-        .as_str()
-        .map(|s| s.to_owned())
-        .unwrap_or_else(|| obj.to_string()))
+    PathContext::new(headers, body, query)?.select_string(path)
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
-    use http::HeaderMap;
+    use http::{header::CONTENT_TYPE, HeaderMap};
+    use serde_json::json;
 
     use super::*;
 
@@ -148,4 +415,122 @@ mod tests {
         let mut path = "_.headers.foo".to_owned();
         assert!(get_value_from_path(&mut path, &headers, body, &query).is_err());
     }
+
+    #[test]
+    fn test_json_path() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-event-type", "order.created".parse().unwrap());
+        let body = br#"{"data": {"object": {"id": "ord_1", "created": 1689703967}}, "sent_at": "2023-07-18T18:12:47Z"}"#;
+        let context = PathContext::new(&headers, body, &None).unwrap();
+
+        assert_eq!(
+            context.select_string("$.headers['x-event-type']").unwrap(),
+            "order.created"
+        );
+        assert_eq!(
+            context.select_string("$.body.data.object.id").unwrap(),
+            "ord_1"
+        );
+        assert!(context.select("$.body.data.missing").is_err());
+
+        let created = context
+            .select_timestamp("$.body.data.object.created")
+            .unwrap();
+        assert_eq!(created.timestamp_millis(), 1_689_703_967_000);
+        let sent_at = context.select_timestamp("$.body.sent_at").unwrap();
+        assert_eq!(sent_at.timestamp(), 1_689_703_967);
+        assert!(context.select_timestamp("$.body.data.object.id").is_err());
+    }
+
+    #[test]
+    fn test_json_path_with_non_json_body() {
+        let context = PathContext::new(&HeaderMap::new(), b"a=1&b=2", &None).unwrap();
+        assert_eq!(context.select_string("$.body").unwrap(), "a=1&b=2");
+    }
+
+    #[test]
+    fn test_parse_form_body() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            "application/x-www-form-urlencoded".parse().unwrap(),
+        );
+        let body = b"EventType=message.received&MessageSid=SM1&To=%2B1555&To=%2B1556";
+
+        assert_eq!(
+            parse_body(&headers, body),
+            json!({
+                "EventType": "message.received",
+                "MessageSid": "SM1",
+                "To": ["+1555", "+1556"],
+            })
+        );
+
+        let context = PathContext::new(&headers, body, &None).unwrap();
+        assert_eq!(
+            context.select_string("$.body.EventType").unwrap(),
+            "message.received"
+        );
+        let mut path = "_.body.MessageSid".to_owned();
+        assert_eq!(
+            get_value_from_path(&mut path, &headers, body, &None).unwrap(),
+            "SM1"
+        );
+    }
+
+    #[test]
+    fn test_parse_xml_body() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "text/xml; charset=utf-8".parse().unwrap());
+        let body = br#"<?xml version="1.0"?>
+            <notification type="order">
+                <event>order.created</event>
+                <item id="1">First</item>
+                <item id="2"><![CDATA[Second & last]]></item>
+                <empty/>
+            </notification>"#;
+
+        assert_eq!(
+            parse_body(&headers, body),
+            json!({
+                "notification": {
+                    "@type": "order",
+                    "event": "order.created",
+                    "item": [
+                        { "@id": "1", "#text": "First" },
+                        { "@id": "2", "#text": "Second & last" },
+                    ],
+                    "empty": "",
+                }
+            })
+        );
+
+        let body = b"<notification><event>";
+        assert_eq!(parse_body(&headers, body), json!("<notification><event>"));
+    }
+
+    #[test]
+    fn test_parse_multipart_body() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            "multipart/form-data; boundary=XyZ".parse().unwrap(),
+        );
+        let body = b"--XyZ\r\n\
+            Content-Disposition: form-data; name=\"event\"\r\n\r\n\
+            inbound.email\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"attachment\"; filename=\"a.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            hello\r\n\
+            --XyZ--\r\n";
+
+        assert_eq!(
+            parse_body(&headers, body),
+            json!({
+                "event": "inbound.email",
+                "attachment": { "filename": "a.txt", "size": 5 },
+            })
+        );
+    }
 }
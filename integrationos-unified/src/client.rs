@@ -1,13 +1,17 @@
+use crate::access_token::access_token;
+use chrono::Utc;
 use http::HeaderMap;
 use indexmap::IndexMap;
+use integrationos_cache::local::access_token_cache::AccessTokenCache;
 use integrationos_domain::{
     api_model_config::{ApiModelConfig, AuthMethod, OAuthLegacyHashAlgorithm},
+    canonicalize_query,
     oauth_secret::OAuthLegacySecret,
     prelude::oauth_secret::OAuthSecret,
-    AuthorizationType, IntegrationOSError, InternalError, Nonce, OAuthData, SignableRequest,
-    SignatureMethod, SigningKey,
+    AuthorizationType, AwsCredentials, Id, IntegrationOSError, InternalError, Nonce, OAuthData,
+    SigV4Signer, SignableRequest, SignatureMethod, SigningKey,
 };
-use reqwest::{Client, Response, Url};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
 use serde_json::Value;
 use std::collections::HashMap;
 
@@ -16,6 +20,7 @@ pub struct CallerClient<'a> {
     config: &'a ApiModelConfig,
     action: http::Method,
     client: &'a Client,
+    access_tokens: Option<(&'a AccessTokenCache, Id)>,
 }
 
 impl<'a> CallerClient<'a> {
@@ -24,9 +29,21 @@ impl<'a> CallerClient<'a> {
             config,
             action,
             client,
+            access_tokens: None,
         }
     }
 
+    /// Reuses access tokens exchanged for client credentials or assertions of
+    /// the connection until they expire
+    pub fn with_access_tokens(
+        mut self,
+        access_tokens: &'a AccessTokenCache,
+        connection_id: Id,
+    ) -> Self {
+        self.access_tokens = Some((access_tokens, connection_id));
+        self
+    }
+
     pub async fn make_request(
         &self,
         payload: Option<Vec<u8>>,
@@ -68,6 +85,15 @@ impl<'a> CallerClient<'a> {
             request_builder = request_builder.body(payload);
         }
 
+        // Exchanged tokens can be revoked before they expire, the request is sent
+        // again with a new one if the platform rejects the cached one
+        let retry = match (&self.config.auth_method, self.access_tokens) {
+            (AuthMethod::ClientCredentials { .. } | AuthMethod::JwtBearer { .. }, Some(_)) => {
+                request_builder.try_clone()
+            }
+            _ => None,
+        };
+
         request_builder = match &self.config.auth_method {
             AuthMethod::BearerToken { value } => request_builder.bearer_auth(value),
             AuthMethod::ApiKey { key, value } => request_builder.header(key, value),
@@ -151,20 +177,91 @@ impl<'a> CallerClient<'a> {
                     ),
                 )
             }
+            AuthMethod::ClientCredentials { .. } | AuthMethod::JwtBearer { .. } => {
+                self.with_access_token(request_builder, secret).await?
+            }
+            AuthMethod::AwsSigV4 { service, region } => {
+                let credentials =
+                    serde_json::from_value::<AwsCredentials>(secret.cloned().unwrap_or_default())
+                        .map_err(|e| {
+                        InternalError::invalid_argument(&e.to_string(), Some("aws_secret"))
+                    })?;
+
+                // The signature covers the final url, headers and body of the request
+                let mut request = request_builder.build().map_err(|e| {
+                    InternalError::invalid_argument(&e.to_string(), Some("endpoint"))
+                })?;
+                let body = request
+                    .body()
+                    .and_then(|body| body.as_bytes())
+                    .map(<[u8]>::to_vec)
+                    .unwrap_or_default();
+                // Sent with the same query encoding the signature covers
+                canonicalize_query(request.url_mut());
+                let method = request.method().clone();
+                let url = request.url().clone();
+
+                SigV4Signer {
+                    credentials: &credentials,
+                    service,
+                    region,
+                }
+                .sign(&method, &url, request.headers_mut(), &body, Utc::now())?;
+
+                RequestBuilder::from_parts(self.client.clone(), request)
+            }
             AuthMethod::None => request_builder,
         };
 
-        let res = request_builder.send().await.map_err(|e| {
-            InternalError::io_err(
-                &format!("Failed to send request: {}", e),
-                Some("reqwest::Error"),
-            )
-        })?;
+        let res = send(request_builder).await?;
+
+        match (retry, self.access_tokens) {
+            (Some(retry), Some((access_tokens, connection_id)))
+                if res.status() == StatusCode::UNAUTHORIZED =>
+            {
+                access_tokens.remove(&connection_id).await?;
+                send(self.with_access_token(retry, secret).await?).await
+            }
+            _ => Ok(res),
+        }
+    }
 
-        Ok(res)
+    async fn with_access_token(
+        &self,
+        request_builder: RequestBuilder,
+        secret: Option<&Value>,
+    ) -> Result<RequestBuilder, IntegrationOSError> {
+        let token = access_token(
+            self.client,
+            self.access_tokens,
+            &self.config.auth_method,
+            secret,
+        )
+        .await?;
+
+        Ok(match token {
+            Some(token) => request_builder.header(
+                http::header::AUTHORIZATION,
+                format!(
+                    "{} {}",
+                    token.token_type.unwrap_or("Bearer".into()),
+                    token.access_token
+                ),
+            ),
+            None => request_builder,
+        })
     }
 }
 
+async fn send(request_builder: RequestBuilder) -> Result<Response, IntegrationOSError> {
+    request_builder.send().await.map_err(|e| {
+        InternalError::io_err(
+            &format!("Failed to send request: {}", e),
+            Some("reqwest::Error"),
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -197,6 +294,7 @@ mod tests {
             auth_method: AuthMethod::BearerToken {
                 value: "sample-key".to_string(),
             },
+            mtls: false,
             headers: None,
             query_params: None,
             content: None,
@@ -272,6 +370,7 @@ mod tests {
             auth_method: AuthMethod::BearerToken {
                 value: "sample-key".to_string(),
             },
+            mtls: false,
             headers: None,
             content: None,
             query_params: None,
@@ -326,4 +425,73 @@ mod tests {
         let response = res.bytes().await.unwrap();
         assert_eq!(response, "Not found".as_bytes().to_vec());
     }
+
+    #[tokio::test]
+    async fn test_aws_sigv4_make_request() {
+        let mut mock_server = Server::new_async().await;
+
+        let mock = mock_server
+            .mock("POST", "/prod/search")
+            .match_query(mockito::Matcher::UrlEncoded("q".into(), "term".into()))
+            .match_header(
+                "authorization",
+                mockito::Matcher::Regex(
+                    r"^AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/\d{8}/eu-west-1/es/aws4_request, SignedHeaders=host;x-amz-date;x-amz-security-token, Signature=[0-9a-f]{64}$"
+                        .to_string(),
+                ),
+            )
+            .match_header("x-amz-security-token", "session")
+            .match_header("x-amz-date", mockito::Matcher::Regex(r"^\d{8}T\d{6}Z$".to_string()))
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let api_model_config = ApiModelConfig {
+            base_url: mock_server.url() + "/prod",
+            path: "search".to_string(),
+            auth_method: AuthMethod::AwsSigV4 {
+                service: "es".to_string(),
+                region: "eu-west-1".to_string(),
+            },
+            mtls: false,
+            headers: None,
+            content: None,
+            query_params: None,
+            schemas: SchemasInput {
+                headers: None,
+                query_params: None,
+                path_params: None,
+                body: None,
+            },
+            samples: SamplesInput {
+                headers: None,
+                query_params: None,
+                path_params: None,
+                body: None,
+            },
+            responses: vec![],
+            paths: None,
+        };
+
+        let secret = serde_json::json!({
+            "AWS_ACCESS_KEY_ID": "AKIDEXAMPLE",
+            "AWS_SECRET_ACCESS_KEY": "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "AWS_SESSION_TOKEN": "session",
+        });
+        let query_params = HashMap::from([("q".to_string(), "term".to_string())]);
+
+        let client = Client::new();
+        let res = CallerClient::new(&api_model_config, http::Method::POST, &client)
+            .make_request(
+                Some(b"{}".to_vec()),
+                Some(&secret),
+                None,
+                Some(&query_params),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        mock.assert_async().await;
+    }
 }
@@ -1,4 +1,7 @@
+pub mod access_token;
 pub mod client;
+pub mod mtls;
+pub mod oauth;
 pub mod request;
 pub mod unified;
 pub mod utility;
@@ -0,0 +1,661 @@
+use bson::doc;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use integrationos_domain::{
+    algebra::{MongoStore, TemplateExt},
+    api_model_config::{ApiModelConfig, ContentType},
+    connection_oauth_definition::{
+        Computation, ComputeRequest, ConnectionOAuthDefinition, OAuthResponse,
+    },
+    oauth_secret::OAuthSecret,
+    scripting::ScriptService,
+    Connection, DefaultTemplate, ErrorMeta, IntegrationOSError, InternalError, OAuth, SecretExt,
+};
+use moka::future::Cache;
+use redis::{aio::ConnectionManager, Script};
+use reqwest::Request;
+use serde_json::{to_string_pretty, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+/// Seconds before the platform expiry at which a token is considered expired
+pub const EXPIRY_MARGIN_SECS: i64 = 120;
+const LOCK_PREFIX: &str = "oauth-refresh";
+const LOCK_LEASE: Duration = Duration::from_secs(30);
+/// The holder extends its lease at this interval for as long as the refresh runs
+const LOCK_RENEW_INTERVAL: Duration = Duration::from_secs(10);
+const LOCK_WAIT: Duration = Duration::from_secs(30);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const ACCESS_TOKEN: &str = "OAUTH_ACCESS_TOKEN";
+/// OAuth 2.0 error of a refresh token that is expired, revoked or invalid
+const INVALID_GRANT: &str = "invalid_grant";
+
+// Only deletes the lock if it is still held by the caller
+const RELEASE: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+// Only extends the lock if it is still held by the caller
+const EXTEND: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+/// Builds the token request of an oauth definition, either the init or the refresh one
+pub async fn oauth_request(
+    config: &ApiModelConfig,
+    compute: &ComputeRequest,
+    payload: &Value,
+    template: &impl TemplateExt,
+    scripts: &ScriptService,
+) -> Result<Request, IntegrationOSError> {
+    let computation = match &compute.computation {
+        Some(computation) => Some(
+            computation
+                .compute::<Computation>(scripts, payload)
+                .await
+                .map_err(|e| {
+                    error!("Failed to compute oauth payload: {}", e);
+                    InternalError::script_error(e.message().as_ref(), None)
+                })?,
+        ),
+        None => None,
+    };
+
+    let headers = header(config, computation.as_ref(), template)?;
+    let query = query(config, computation.as_ref(), template)?;
+    let body = body(payload, computation.as_ref(), template)?;
+
+    let request = reqwest::Client::new().post(config.uri()).headers(headers);
+
+    let request = match config.content {
+        Some(ContentType::Json) => request.json(&body).query(&query),
+        Some(ContentType::Form) => request.form(&body).query(&query),
+        _ => request.query(&query),
+    };
+
+    request.build().map_err(|e| {
+        error!("Failed to build static request: {}", e);
+        InternalError::unknown(&e.to_string(), None)
+    })
+}
+
+/// Whether the token endpoint turned down the refresh for good with an
+/// `invalid_grant`, e.g. after the user revoked access. Other failures, including
+/// rejected client credentials or rate limits, may go away on retry.
+pub fn is_revoked(error: &IntegrationOSError) -> bool {
+    error.is_application()
+        && error
+            .key()
+            .to_string()
+            .ends_with(&format!("::{INVALID_GRANT}"))
+}
+
+fn query(
+    config: &ApiModelConfig,
+    computation: Option<&Computation>,
+    template: &impl TemplateExt,
+) -> Result<Option<Value>, IntegrationOSError> {
+    let query_params = config.query_params.as_ref().map(|query_params| {
+        let mut map = HashMap::new();
+        for (key, value) in query_params {
+            let key = key.to_string();
+            let value = value.as_str();
+
+            map.insert(key, value.to_string());
+        }
+        map
+    });
+
+    match query_params {
+        Some(query_params) => {
+            let payload = computation.and_then(|computation| computation.clone().query_params);
+
+            let query_params_str = to_string_pretty(&query_params).map_err(|e| {
+                error!("Failed to serialize query params: {}", e);
+                InternalError::serialize_error(&e.to_string(), None)
+            })?;
+
+            let query_params = template.render(&query_params_str, payload.as_ref())?;
+
+            let query_params: BTreeMap<String, String> = serde_json::from_str(&query_params)
+                .map_err(|e| {
+                    error!("Failed to deserialize query params: {}", e);
+                    InternalError::deserialize_error(&e.to_string(), None)
+                })?;
+
+            Ok(Some(serde_json::to_value(query_params).map_err(|e| {
+                error!("Failed to serialize query params: {}", e);
+                InternalError::serialize_error(&e.to_string(), None)
+            })?))
+        }
+        None => Ok(None),
+    }
+}
+
+fn body(
+    payload: &Value,
+    computation: Option<&Computation>,
+    template: &impl TemplateExt,
+) -> Result<Option<Value>, IntegrationOSError> {
+    let body = computation.and_then(|computation| computation.clone().body);
+
+    match body {
+        Some(body) => {
+            let body_str = to_string_pretty(&body).map_err(|e| {
+                error!("Failed to serialize body: {}", e);
+                InternalError::serialize_error(&e.to_string(), None)
+            })?;
+
+            let body = template.render(&body_str, Some(payload))?;
+
+            Ok(Some(serde_json::from_str(&body).map_err(|e| {
+                error!("Failed to deserialize body: {}", e);
+                InternalError::deserialize_error(&e.to_string(), None)
+            })?))
+        }
+        None => Ok(None),
+    }
+}
+
+fn header(
+    config: &ApiModelConfig,
+    computation: Option<&Computation>,
+    template: &impl TemplateExt,
+) -> Result<HeaderMap, IntegrationOSError> {
+    let headers = config.headers.as_ref().and_then(|headers| {
+        let mut map = HashMap::new();
+        for (key, value) in headers {
+            let key = key.to_string();
+            let value = value.to_str().ok()?;
+
+            map.insert(key, value.to_string());
+        }
+        Some(map)
+    });
+
+    match headers {
+        Some(headers) => {
+            let payload = computation.and_then(|computation| computation.clone().headers);
+
+            let headers_str = to_string_pretty(&headers).map_err(|e| {
+                error!("Failed to serialize headers: {}", e);
+                InternalError::serialize_error(&e.to_string(), None)
+            })?;
+
+            let headers = template.render(&headers_str, payload.as_ref())?;
+
+            let headers: BTreeMap<String, String> =
+                serde_json::from_str(&headers).map_err(|e| {
+                    error!("Failed to deserialize headers: {}", e);
+                    InternalError::deserialize_error(&e.to_string(), None)
+                })?;
+
+            headers
+                .iter()
+                .try_fold(HeaderMap::new(), |mut header_map, (key, value)| {
+                    let key = HeaderName::from_str(key).map_err(|e| {
+                        error!("Failed to parse header name: {}", e);
+                        InternalError::invalid_argument(&e.to_string(), None)
+                    })?;
+
+                    let value = HeaderValue::from_str(value).map_err(|e| {
+                        error!("Failed to parse header value: {}", e);
+                        InternalError::invalid_argument(&e.to_string(), None)
+                    })?;
+
+                    header_map.insert(key, value);
+
+                    Ok(header_map)
+                })
+        }
+        None => Ok(HeaderMap::new()),
+    }
+}
+
+/// Refreshes the oauth tokens of connections through the refresh request of their
+/// oauth definition.
+///
+/// Refreshes of the same connection are serialized with a lock in Redis, so when
+/// many requests find an expired token only one of them calls the token endpoint
+/// and the others pick up the secret it stored.
+#[derive(Clone)]
+pub struct OAuthRefresh {
+    oauth_definitions_store: MongoStore<ConnectionOAuthDefinition>,
+    connections_store: MongoStore<Connection>,
+    secrets_client: Arc<dyn SecretExt + Sync + Send>,
+    scripts: ScriptService,
+    template: DefaultTemplate,
+    http_client: reqwest::Client,
+    redis: ConnectionManager,
+    /// Latest `expires_at` by connection id, connections may be read from stale caches
+    expires_at: Cache<String, i64>,
+}
+
+impl OAuthRefresh {
+    pub fn new(
+        oauth_definitions_store: MongoStore<ConnectionOAuthDefinition>,
+        connections_store: MongoStore<Connection>,
+        secrets_client: Arc<dyn SecretExt + Sync + Send>,
+        scripts: ScriptService,
+        http_client: reqwest::Client,
+        redis: ConnectionManager,
+    ) -> Self {
+        Self {
+            oauth_definitions_store,
+            connections_store,
+            secrets_client,
+            scripts,
+            template: DefaultTemplate::default(),
+            http_client,
+            redis,
+            expires_at: Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(Duration::from_secs(60 * 60))
+                .build(),
+        }
+    }
+
+    /// Whether the token of the connection has to be refreshed before it is used
+    pub async fn is_expired(&self, connection: &Connection) -> bool {
+        let now = Utc::now();
+        match self.expires_at.get(&connection.id.to_string()).await {
+            Some(expires_at) => expires_at <= now.timestamp(),
+            None => connection
+                .oauth
+                .as_ref()
+                .is_some_and(|oauth| oauth.is_expired(now)),
+        }
+    }
+
+    /// Refreshes the token of the connection and returns its new secret.
+    ///
+    /// `rejected` is the secret the platform turned down. If another request already
+    /// replaced it, or the token is no longer expired, the stored secret is returned
+    /// without calling the token endpoint. Returns `None` for connections without oauth.
+    pub async fn refresh(
+        &self,
+        connection: &Connection,
+        rejected: Option<&Value>,
+    ) -> Result<Option<Value>, IntegrationOSError> {
+        self.refresh_with_lock(connection, rejected, Utc::now())
+            .await
+    }
+
+    /// Refreshes the token of the connection if it expires before `expires_before`,
+    /// so that it can be renewed ahead of time
+    pub async fn refresh_expiring(
+        &self,
+        connection: &Connection,
+        expires_before: DateTime<Utc>,
+    ) -> Result<Option<Value>, IntegrationOSError> {
+        self.refresh_with_lock(connection, None, expires_before)
+            .await
+    }
+
+    async fn refresh_with_lock(
+        &self,
+        connection: &Connection,
+        rejected: Option<&Value>,
+        expires_before: DateTime<Utc>,
+    ) -> Result<Option<Value>, IntegrationOSError> {
+        if !matches!(connection.oauth, Some(OAuth::Enabled { .. })) {
+            return Ok(None);
+        }
+
+        let key = format!("{LOCK_PREFIX}::{}", connection.id);
+        let holder = self.lock(&key).await?;
+        // A slow token endpoint must not let the lease run out, otherwise a waiting
+        // request would refresh again with a refresh token that may be rotated
+        let refresh = self.refresh_locked(connection, rejected, expires_before);
+        tokio::pin!(refresh);
+        let result = loop {
+            tokio::select! {
+                result = &mut refresh => break result,
+                _ = tokio::time::sleep(LOCK_RENEW_INTERVAL) => self.extend(&key, &holder).await,
+            }
+        };
+        self.unlock(&key, &holder).await;
+
+        result
+    }
+
+    async fn refresh_locked(
+        &self,
+        connection: &Connection,
+        rejected: Option<&Value>,
+        expires_before: DateTime<Utc>,
+    ) -> Result<Option<Value>, IntegrationOSError> {
+        let connection = self
+            .connections_store
+            .get_one_by_id(&connection.id.to_string())
+            .await?
+            .ok_or_else(|| InternalError::key_not_found("Connection", None))?;
+        let Some(OAuth::Enabled {
+            connection_oauth_definition_id,
+            ..
+        }) = &connection.oauth
+        else {
+            return Ok(None);
+        };
+
+        let stored = self
+            .secrets_client
+            .get(&connection.secrets_service_id, &connection.ownership.id)
+            .await?
+            .as_value()?;
+
+        let replaced =
+            rejected.is_some_and(|rejected| rejected.get(ACCESS_TOKEN) != stored.get(ACCESS_TOKEN));
+        let expired = connection
+            .oauth
+            .as_ref()
+            .is_some_and(|oauth| oauth.is_expired(expires_before));
+        if replaced || (rejected.is_none() && !expired) {
+            debug!(
+                "Token of connection {} was already refreshed",
+                connection.id
+            );
+            self.remember_expiry(&connection).await;
+            return Ok(Some(stored));
+        }
+
+        let secret: OAuthSecret = serde_json::from_value(stored.clone()).map_err(|e| {
+            error!(
+                "Secret of connection {} is not an oauth secret: {e}",
+                connection.id
+            );
+            InternalError::deserialize_error(&e.to_string(), None)
+        })?;
+
+        let definition = self
+            .oauth_definitions_store
+            .get_one_by_id(&connection_oauth_definition_id.to_string())
+            .await?
+            .ok_or_else(|| InternalError::key_not_found("Connection OAuth definition", None))?;
+        let definition = if definition.is_full_template_enabled {
+            self.template.render_as(&definition, Some(&stored))?
+        } else {
+            definition
+        };
+
+        let request = oauth_request(
+            &definition.configuration.refresh,
+            &definition.compute.refresh,
+            &stored,
+            &self.template,
+            &self.scripts,
+        )
+        .await?;
+
+        let response = self.http_client.execute(request).await.map_err(|e| {
+            error!("Failed to execute oauth refresh request: {e}");
+            InternalError::connection_error(&e.to_string(), None)
+        })?;
+        let status = response.status();
+        let response = response.json::<Value>().await.map_err(|e| {
+            error!("Failed to decode oauth refresh response: {e}");
+            InternalError::deserialize_error(&e.to_string(), None)
+        })?;
+        if !status.is_success() {
+            error!(
+                "Platform rejected the token refresh of connection {} with {status}",
+                connection.id
+            );
+            let revoked = response.get("error").and_then(Value::as_str) == Some(INVALID_GRANT);
+            return Err(IntegrationOSError::from_err_code(
+                status,
+                &format!("Failed to refresh oauth token: {response}"),
+                revoked.then_some(INVALID_GRANT),
+            ));
+        }
+
+        let mut decoded: OAuthResponse = definition
+            .compute
+            .refresh
+            .response
+            .compute(&self.scripts, &response)
+            .await
+            .map_err(|e| {
+                error!("Failed to decode oauth refresh response: {:?}", e);
+                InternalError::script_error(e.message().as_ref(), None)
+            })?;
+        // Platforms that do not rotate refresh tokens leave them out of the response
+        if decoded.refresh_token.is_none() {
+            decoded.refresh_token = secret.refresh_token.clone();
+        }
+
+        let refreshed = secret.from_refresh(decoded, None, None, response);
+        self.secrets_client
+            .update(
+                &connection.secrets_service_id,
+                &refreshed.as_json(),
+                &connection.ownership.id,
+            )
+            .await?;
+
+        let expires_at = (Utc::now() + ChronoDuration::seconds(refreshed.expires_in as i64)
+            - ChronoDuration::seconds(EXPIRY_MARGIN_SECS))
+        .timestamp();
+        let oauth = OAuth::Enabled {
+            connection_oauth_definition_id: connection_oauth_definition_id.clone(),
+            expires_in: Some(refreshed.expires_in),
+            expires_at: Some(expires_at),
+        };
+        // Variant names are camel cased but their fields are not, so go through serde
+        let oauth = bson::to_bson(&oauth).map_err(|e| {
+            error!(
+                "Failed to serialize oauth of connection {}: {e}",
+                connection.id
+            );
+            InternalError::serialize_error(&e.to_string(), None)
+        })?;
+        self.connections_store
+            .update_one(
+                &connection.id.to_string(),
+                doc! {
+                    "$set": {
+                        "oauth": oauth,
+                        "updatedAt": Utc::now().timestamp_millis(),
+                    }
+                },
+            )
+            .await?;
+        self.expires_at
+            .insert(connection.id.to_string(), expires_at)
+            .await;
+
+        info!("Refreshed oauth token of connection {}", connection.id);
+
+        Ok(Some(refreshed.as_json()))
+    }
+
+    async fn remember_expiry(&self, connection: &Connection) {
+        if let Some(OAuth::Enabled {
+            expires_at: Some(expires_at),
+            ..
+        }) = connection.oauth
+        {
+            self.expires_at
+                .insert(connection.id.to_string(), expires_at)
+                .await;
+        }
+    }
+
+    /// Waits for the refresh lock of a connection and returns the id of the holder
+    async fn lock(&self, key: &str) -> Result<String, IntegrationOSError> {
+        let holder = Uuid::new_v4().to_string();
+        let mut redis = self.redis.clone();
+        let deadline = tokio::time::Instant::now() + LOCK_WAIT;
+
+        loop {
+            let acquired: Option<String> = redis::cmd("SET")
+                .arg(key)
+                .arg(&holder)
+                .arg("NX")
+                .arg("PX")
+                .arg(LOCK_LEASE.as_millis() as u64)
+                .query_async(&mut redis)
+                .await
+                .map_err(|e| {
+                    error!("Could not take oauth refresh lock {key}: {e}");
+                    InternalError::connection_error(&e.to_string(), None)
+                })?;
+
+            if acquired.is_some() {
+                return Ok(holder);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(InternalError::timeout(
+                    "Timed out waiting for the oauth refresh lock",
+                    None,
+                ));
+            }
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn extend(&self, key: &str, holder: &str) {
+        let mut redis = self.redis.clone();
+        let extended: Result<i64, _> = Script::new(EXTEND)
+            .key(key)
+            .arg(holder)
+            .arg(LOCK_LEASE.as_millis() as u64)
+            .invoke_async(&mut redis)
+            .await;
+        match extended {
+            Ok(0) => error!("Lost oauth refresh lock {key} while refreshing"),
+            Ok(_) => {}
+            Err(e) => error!("Could not extend oauth refresh lock {key}: {e}"),
+        }
+    }
+
+    async fn unlock(&self, key: &str, holder: &str) {
+        let mut redis = self.redis.clone();
+        let released: Result<i64, _> = Script::new(RELEASE)
+            .key(key)
+            .arg(holder)
+            .invoke_async(&mut redis)
+            .await;
+        if let Err(e) = released {
+            // The lease expires on its own
+            error!("Could not release oauth refresh lock {key}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::{Method, StatusCode};
+    use integrationos_domain::{
+        api_model_config::{AuthMethod, Compute, Function, Lang, SamplesInput, SchemasInput},
+        script::ScriptConfig,
+    };
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_oauth_refresh_request() {
+        let config = ApiModelConfig {
+            base_url: "https://auth.example.com/".to_string(),
+            path: "oauth/token".to_string(),
+            auth_method: AuthMethod::None,
+            mtls: false,
+            headers: None,
+            query_params: Some(BTreeMap::from([(
+                "tenant".to_string(),
+                "{{tenant}}".to_string(),
+            )])),
+            content: Some(ContentType::Form),
+            schemas: SchemasInput {
+                headers: None,
+                query_params: None,
+                path_params: None,
+                body: None,
+            },
+            samples: SamplesInput {
+                headers: None,
+                query_params: None,
+                path_params: None,
+                body: None,
+            },
+            responses: vec![],
+            paths: None,
+        };
+        let compute = ComputeRequest {
+            computation: Some(Function(Compute {
+                entry: "compute".to_string(),
+                function: r#"function compute(payload) {
+                    return {
+                        queryParams: { tenant: payload.OAUTH_METADATA.tenant },
+                        body: { grant_type: "refresh_token", refresh_token: "{{OAUTH_REFRESH_TOKEN}}" },
+                    };
+                }"#
+                .to_string(),
+                language: Lang::JavaScript,
+            })),
+            response: Function(Compute {
+                entry: "compute".to_string(),
+                function: "function compute(response) { return response; }".to_string(),
+                language: Lang::JavaScript,
+            }),
+        };
+        let payload = json!({
+            "OAUTH_REFRESH_TOKEN": "refresh-token",
+            "OAUTH_METADATA": { "tenant": "acme" },
+        });
+        let scripts = ScriptService::new(&ScriptConfig::default()).expect("Script service");
+
+        let request = oauth_request(
+            &config,
+            &compute,
+            &payload,
+            &DefaultTemplate::default(),
+            &scripts,
+        )
+        .await
+        .expect("Failed to build refresh request");
+
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(
+            request.url().as_str(),
+            "https://auth.example.com/oauth/token?tenant=acme"
+        );
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|body| String::from_utf8_lossy(body).to_string());
+        assert_eq!(
+            body.as_deref(),
+            Some("grant_type=refresh_token&refresh_token=refresh-token")
+        );
+    }
+
+    #[test]
+    fn test_only_invalid_grant_is_revoked() {
+        let revoked = IntegrationOSError::from_err_code(
+            StatusCode::BAD_REQUEST,
+            "Failed to refresh oauth token",
+            Some(INVALID_GRANT),
+        );
+        let rejected_client = IntegrationOSError::from_err_code(
+            StatusCode::UNAUTHORIZED,
+            "Failed to refresh oauth token",
+            None,
+        );
+
+        assert!(is_revoked(&revoked));
+        assert!(!is_revoked(&rejected_client));
+    }
+}
@@ -1,5 +1,7 @@
 use crate::{
     client::CallerClient,
+    mtls::MtlsClients,
+    oauth::OAuthRefresh,
     request::{
         PathParams, RequestCrud, RequestCrudBorrowed, ResponseCrud, ResponseCrudToMap,
         ResponseCrudToMapRequest,
@@ -12,7 +14,7 @@ use futures::{future::join_all, join, FutureExt};
 use handlebars::Handlebars;
 use http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode};
 use integrationos_cache::local::{
-    connection_cache::ConnectionCacheArcStrKey,
+    access_token_cache::AccessTokenCache, connection_cache::ConnectionCacheArcStrKey,
     connection_model_definition_cache::ConnectionModelDefinitionDestinationKey,
     connection_model_schema_cache::ConnectionModelSchemaCache, secrets_cache::SecretCache,
 };
@@ -22,6 +24,7 @@ use integrationos_domain::{
         ConnectionModelDefinition, CrudAction, CrudMapping, PlatformInfo,
     },
     connection_model_schema::ConnectionModelSchema,
+    connection_oauth_definition::ConnectionOAuthDefinition,
     database::DatabaseConfig,
     destination::{Action, Destination},
     environment::Environment,
@@ -29,21 +32,20 @@ use integrationos_domain::{
     hashed_secret::HashedSecret,
     id::{prefix::IdPrefix, Id},
     prelude::{MongoStore, TimedExt},
-    ApplicationError, Connection, ErrorMeta, IntegrationOSError, SecretExt, Store,
+    redaction::{Redactions, Redactor},
+    scripting::ScriptService,
+    ApplicationError, Connection, ErrorMeta, IntegrationOSError, SecretExt, Store, Unit,
 };
-use js_sandbox_ios::Script;
 use mongodb::{
     options::{Collation, CollationStrength, FindOneOptions},
     Client,
 };
+use redis::aio::ConnectionManager;
+use serde::Serialize;
 use serde_json::{json, Number, Value};
-use std::{cell::RefCell, collections::HashMap, str::FromStr, sync::Arc};
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
 use tracing::{debug, error};
 
-thread_local! {
-    static JS_RUNTIME: RefCell<Script> = RefCell::new(Script::new());
-}
-
 pub struct UnifiedResponse {
     pub response: Response<Value>,
     pub metadata: Value,
@@ -57,9 +59,19 @@ pub struct UnifiedDestination {
     pub connection_model_definitions_store: MongoStore<ConnectionModelDefinition>,
     pub connection_model_schemas_cache: ConnectionModelSchemaCache,
     pub connection_model_schemas_store: MongoStore<ConnectionModelSchema>,
+    pub oauth_definitions_store: MongoStore<ConnectionOAuthDefinition>,
     pub secrets_client: Arc<dyn SecretExt + Sync + Send>,
     pub secrets_cache: SecretCache,
+    pub scripts: ScriptService,
     pub http_client: reqwest::Client,
+    /// Access tokens exchanged for server to server credentials
+    pub access_tokens: AccessTokenCache,
+    /// Clients of connections calling platforms that require mutual TLS
+    pub mtls_clients: MtlsClients,
+    /// Applied to payloads before they are logged
+    pub redactor: Redactor,
+    /// Refreshes expired oauth tokens, without it they are used as they are
+    pub oauth_refresh: Option<OAuthRefresh>,
 }
 
 pub struct UnifiedCacheTTLs {
@@ -74,6 +86,7 @@ impl UnifiedDestination {
         db_config: DatabaseConfig,
         cache_size: u64,
         secrets_client: Arc<dyn SecretExt + Sync + Send>,
+        scripts: ScriptService,
         cache_ttls: UnifiedCacheTTLs,
     ) -> Result<Self, IntegrationOSError> {
         let http_client = reqwest::Client::new();
@@ -88,6 +101,8 @@ impl UnifiedDestination {
             cache_ttls.connection_model_schema_cache_ttl_secs,
         );
         let secrets_cache = SecretCache::new(cache_size, cache_ttls.secret_cache_ttl_secs);
+        let access_tokens = AccessTokenCache::new(cache_size);
+        let mtls_clients = MtlsClients::new(cache_size, cache_ttls.connection_cache_ttl_secs);
 
         let client = Client::with_uri_str(&db_config.control_db_url)
             .await
@@ -105,6 +120,8 @@ impl UnifiedDestination {
             MongoStore::new(&db, &Store::ConnectionModelDefinitions).await?;
         let connection_model_schemas_store =
             MongoStore::new(&db, &Store::ConnectionModelSchemas).await?;
+        let oauth_definitions_store =
+            MongoStore::new(&db, &Store::ConnectionOAuthDefinitions).await?;
 
         Ok(Self {
             connections_cache,
@@ -113,12 +130,178 @@ impl UnifiedDestination {
             connection_model_definitions_store,
             connection_model_schemas_cache,
             connection_model_schemas_store,
+            oauth_definitions_store,
             secrets_client,
             secrets_cache,
+            scripts,
             http_client,
+            access_tokens,
+            mtls_clients,
+            redactor: Redactor::default(),
+            oauth_refresh: None,
         })
     }
 
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Forgets everything cached for the connection, so that the next call reads
+    /// its record and secret again after they were replaced
+    pub async fn remove_connection(&self, id: Id) -> Result<Unit, IntegrationOSError> {
+        self.connections_cache.remove_connection(id)?;
+        self.secrets_cache.remove_connection(id)?;
+        self.access_tokens.remove(&id).await?;
+        self.mtls_clients.remove(&id).await
+    }
+
+    /// Calls platforms with the given client, and with its timeout when they
+    /// require mutual TLS
+    pub fn with_http_client(mut self, http_client: reqwest::Client, timeout: Duration) -> Self {
+        self.http_client = http_client;
+        self.mtls_clients = self.mtls_clients.with_timeout(timeout);
+        self
+    }
+
+    /// Refreshes oauth tokens that expired or were rejected by the platform,
+    /// using Redis to make sure only one refresh per connection is in flight
+    pub fn with_oauth_refresh(mut self, redis: ConnectionManager) -> Self {
+        self.oauth_refresh = Some(OAuthRefresh::new(
+            self.oauth_definitions_store.clone(),
+            self.connections_store.clone(),
+            self.secrets_client.clone(),
+            self.scripts.clone(),
+            self.http_client.clone(),
+            redis,
+        ));
+        self
+    }
+
+    /// Refreshes the token of the connection ahead of the request if it expired.
+    ///
+    /// Failures are only logged, the platform then decides whether the old token
+    /// is still good enough.
+    async fn refresh_expired(&self, connection: &Connection) {
+        let Some(oauth_refresh) = &self.oauth_refresh else {
+            return;
+        };
+        if !oauth_refresh.is_expired(connection).await {
+            return;
+        }
+
+        match oauth_refresh.refresh(connection, None).await {
+            Ok(Some(secret)) => {
+                if let Err(e) = self.secrets_cache.set(connection, &secret).await {
+                    error!(
+                        "Failed to cache refreshed secret of connection {}: {e}",
+                        connection.id
+                    );
+                }
+            }
+            Ok(None) => {}
+            Err(e) => error!(
+                "Failed to refresh expired token of connection {}: {e}",
+                connection.id
+            ),
+        }
+    }
+
+    /// Executes the model definition and, if the platform rejects the token,
+    /// refreshes it and retries once
+    async fn execute_with_refresh(
+        &self,
+        connection: &Connection,
+        config: &ConnectionModelDefinition,
+        headers: HeaderMap,
+        query_params: &HashMap<String, String>,
+        secret: &Value,
+        context: Option<Vec<u8>>,
+    ) -> Result<reqwest::Response, IntegrationOSError> {
+        let Some(oauth_refresh) = &self.oauth_refresh else {
+            return self
+                .execute(
+                    Some(connection),
+                    config,
+                    headers,
+                    query_params,
+                    secret,
+                    context,
+                )
+                .await;
+        };
+
+        let response = self
+            .execute(
+                Some(connection),
+                config,
+                headers.clone(),
+                query_params,
+                secret,
+                context.clone(),
+            )
+            .await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        let refreshed = match oauth_refresh.refresh(connection, Some(secret)).await {
+            Ok(Some(refreshed)) => refreshed,
+            Ok(None) => return Ok(response),
+            Err(e) => {
+                error!(
+                    "Failed to refresh rejected token of connection {}: {e}",
+                    connection.id
+                );
+                return Ok(response);
+            }
+        };
+        if let Err(e) = self.secrets_cache.set(connection, &refreshed).await {
+            error!(
+                "Failed to cache refreshed secret of connection {}: {e}",
+                connection.id
+            );
+        }
+
+        // The secret of the request also carries the ids and path params of the call
+        let mut secret = secret.clone();
+        if let (Value::Object(secret), Value::Object(refreshed)) = (&mut secret, refreshed) {
+            secret.extend(refreshed);
+        }
+
+        debug!(
+            "Retrying request of connection {} with a refreshed token",
+            connection.id
+        );
+        // `config` is still the unrendered definition, e.g. the passthrough one with
+        // only its route filled in, so its url and headers are rendered again from
+        // the refreshed secret rather than reusing the rejected token
+        self.execute(
+            Some(connection),
+            config,
+            headers,
+            query_params,
+            &secret,
+            context,
+        )
+        .await
+    }
+
+    fn redacted<T: Serialize>(&self, value: &T) -> String {
+        let value = serde_json::to_value(value).unwrap_or_default();
+        let value = self
+            .redactor
+            .redact_value(value, &mut Redactions::default());
+        serde_json::to_string_pretty(&value).unwrap_or_default()
+    }
+
+    fn redacted_headers(&self, headers: &HeaderMap) -> HeaderMap {
+        let mut headers = headers.clone();
+        self.redactor
+            .redact_headers(&mut headers, &mut Redactions::default());
+        headers
+    }
+
     pub async fn get_connection_model_definition(
         &self,
         destination: &Destination,
@@ -202,21 +385,71 @@ impl UnifiedDestination {
         secret: &Value,
         context: Option<Vec<u8>>,
     ) -> Result<reqwest::Response, IntegrationOSError> {
-        let renderer = Handlebars::new();
-
-        let config_str = serde_json::to_string(&config)
-            .map_err(|e| InternalError::invalid_argument(&e.to_string(), None))?;
+        self.execute(None, config, headers, query_params, secret, context)
+            .await
+    }
 
-        let config = renderer
-            .render_template(&config_str, secret)
-            .map_err(|e| InternalError::invalid_argument(&e.to_string(), None))?;
+    /// Calls the test model definition of an existing connection with its stored
+    /// secret, refreshing its token like any other call
+    pub async fn test_connection(
+        &self,
+        connection: &Connection,
+        config: &ConnectionModelDefinition,
+    ) -> Result<reqwest::Response, IntegrationOSError> {
+        let secret = self
+            .secrets_client
+            .get(&connection.secrets_service_id, &connection.ownership.id)
+            .await?
+            .as_value()?;
+        let context = config
+            .test_connection_payload
+            .as_ref()
+            .map(serde_json::to_vec)
+            .transpose()
+            .map_err(|e| InternalError::serialize_error(&e.to_string(), None))?;
+
+        self.execute_with_refresh(
+            connection,
+            config,
+            HeaderMap::new(),
+            &HashMap::new(),
+            &secret,
+            context,
+        )
+        .await
+    }
 
-        let config: ConnectionModelDefinition = serde_json::from_str(&config)
-            .map_err(|e| InternalError::invalid_argument(&e.to_string(), None))?;
+    /// Mutual TLS clients and access tokens are only pooled for existing
+    /// connections, calls without one build a client and exchange a token for
+    /// the request
+    async fn execute(
+        &self,
+        connection: Option<&Connection>,
+        config: &ConnectionModelDefinition,
+        headers: HeaderMap,
+        query_params: &HashMap<String, String>,
+        secret: &Value,
+        context: Option<Vec<u8>>,
+    ) -> Result<reqwest::Response, IntegrationOSError> {
+        let config = render_config(config, secret)?;
 
         match config.platform_info {
             PlatformInfo::Api(ref c) => {
-                let api_caller = CallerClient::new(c, config.action, &self.http_client);
+                // Platforms may require mutual TLS for every call of their connections
+                let mtls = c.mtls || connection.is_some_and(|connection| connection.settings.mtls);
+                let client = match (mtls, connection) {
+                    (false, _) => self.http_client.clone(),
+                    (true, Some(connection)) => {
+                        self.mtls_clients
+                            .get_or_build(connection.id, secret)
+                            .await?
+                    }
+                    (true, None) => self.mtls_clients.build(secret)?,
+                };
+                let mut api_caller = CallerClient::new(c, config.action, &client);
+                if let Some(connection) = connection {
+                    api_caller = api_caller.with_access_tokens(&self.access_tokens, connection.id);
+                }
 
                 let response = api_caller
                     .make_request(context, Some(secret), Some(headers), Some(query_params))
@@ -263,6 +496,8 @@ impl UnifiedDestination {
                 }
             });
 
+        self.refresh_expired(&connection).await;
+
         let secret_fut =
             self.secrets_cache
                 .get_or_insert_with_fn(connection.as_ref().clone(), || async {
@@ -349,11 +584,7 @@ impl UnifiedDestination {
             key
         );
 
-        let ConnectionModelSchema {
-            id: schema_id,
-            mapping,
-            ..
-        } = cms;
+        let ConnectionModelSchema { mapping, .. } = cms;
 
         if let Some(id) = id {
             let secret = &mut secret;
@@ -363,17 +594,6 @@ impl UnifiedDestination {
             }
         }
 
-        let crud_script_namespace = if self.secrets_cache.max_capacity() == 0 {
-            "$".to_string() + &uuid::Uuid::new_v4().simple().to_string()
-        } else {
-            config.id.to_string().replace([':', '-'], "_")
-        };
-        let schema_script_namespace = if self.secrets_cache.max_capacity() == 0 {
-            "$".to_string() + &uuid::Uuid::new_v4().simple().to_string()
-        } else {
-            schema_id.to_string().replace([':', '-'], "_")
-        };
-
         let mut metadata = json!({
             "timestamp": Utc::now().timestamp_millis(),
             "platformRateLimitRemaining": 0,
@@ -397,27 +617,13 @@ impl UnifiedDestination {
             if let Some(js) = mapping.as_ref().map(|m| m.from_common_model.as_str()) {
                 debug!(
                     "Mapping request body {}\nUsing js {js}",
-                    serde_json::to_string_pretty(&body)
-                        .map_err(|e| {
-                            error!("Failed to convert body to pretty string for connection model. ID: {}, Body: {}, Error: {}", config.id, body, e);
-                        })
-                        .unwrap_or_default(),
+                    self.redacted(&body),
                 );
 
-                let ns: String = schema_script_namespace.clone() + "_mapFromCommonModel";
-                JS_RUNTIME
-                    .with_borrow_mut(|script| script.add_script(&ns, "mapFromCommonModel", js))
-                    .map_err(|e| {
-                        error!("Failed to create request schema mapping script for connection model. ID: {}, Error: {}", config.id, e);
-
-                        ApplicationError::bad_request(
-                            &format!("Failed while creating request schema mapping script: {e}"),
-                            None,
-                        )
-                        .set_meta(&metadata)
-                    })?;
-                let body = JS_RUNTIME
-                    .with_borrow_mut(|script| script.call_namespace(&ns, body))
+                let body: Value = self
+                    .scripts
+                    .call(js, "mapFromCommonModel", (body,))
+                    .await
                     .map_err(|e| {
                         error!("Failed to run request schema mapping script for connection model. ID: {}, Error: {}", config.id, e);
 
@@ -428,18 +634,9 @@ impl UnifiedDestination {
                         .set_meta(&metadata)
                     })?;
 
-                tokio::task::yield_now().await;
-
                 let body = remove_nulls(&body);
 
-                debug!(
-                    "Mapped body to {}",
-                    serde_json::to_string_pretty(&body)
-                        .map_err(|e| {
-                            error!("Failed to convert mapped body to pretty string. ID: {}, Body: {}, Error: {}", config.id, body, e);
-                        })
-                        .unwrap_or_default(),
-                );
+                debug!("Mapped body to {}", self.redacted(&body),);
 
                 Some(body)
             } else {
@@ -460,14 +657,6 @@ impl UnifiedDestination {
         }) = &config.mapping
         {
             if !js.is_empty() {
-                let ns: String = crud_script_namespace.clone() + "_mapFromCrudRequest";
-                JS_RUNTIME
-                    .with_borrow_mut(|script| script.add_script(&ns, "mapCrudRequest", js.as_str()))
-                    .map_err(|e| {
-                        error!("Failed to create request crud mapping script for connection model. ID: {}, JS: {}, Error: {}", config.id, js, e);
-                        ApplicationError::bad_request(&e.to_string(), None).set_meta(&metadata)
-                    })?;
-
                 const PASSTHROUGH_PARAMS: &str = "passthroughForward";
                 const PASSHTROUGH_HEADERS: &str = "x-pica-passthrough-forward";
 
@@ -509,15 +698,13 @@ impl UnifiedDestination {
 
                 debug!(
                     "Mapping request crud {}\nUsing js {js}",
-                    serde_json::to_string_pretty(&request)
-                        .map_err(|e| {
-                            error!("Failed to convert request crud to pretty string. ID: {}, Request: {:?}, Error: {}", config.id, request, e);
-                        })
-                        .unwrap_or_default(),
+                    self.redacted(&request),
                 );
 
-                let res: RequestCrud = JS_RUNTIME
-                    .with_borrow_mut(|script| script.call_namespace(&ns, request))
+                let res: RequestCrud = self
+                    .scripts
+                    .call(js, "mapCrudRequest", (&request,))
+                    .await
                     .map_err(|e| {
                         error!("Failed to run request crud mapping script for connection model. ID: {}, Error: {}", config.id, e);
 
@@ -528,14 +715,7 @@ impl UnifiedDestination {
                         .set_meta(&metadata)
                     })?;
 
-                debug!(
-                    "Mapped request crud to {}",
-                    serde_json::to_string_pretty(&res)
-                        .map_err(|e| {
-                            error!("Failed to convert crud to pretty string. ID: {}, Res: {:?}, Error: {}", config.id, res, e);
-                        })
-                        .unwrap_or_default(),
-                );
+                debug!("Mapped request crud to {}", self.redacted(&res),);
 
                 headers = res.headers;
 
@@ -569,18 +749,14 @@ impl UnifiedDestination {
         {
             if let Some(path) = path.strip_prefix("$.body.") {
                 body = body.map(|body| json!({path: body}));
-                debug!(
-                    "Mapped request body to {path}: {}",
-                    serde_json::to_string_pretty(&body)
-                        .map_err(|e| {
-                            error!("Failed to convert mapped body to pretty string. ID: {}, Body: {:?}, Error: {}", config.id, body, e);
-                        })
-                        .unwrap_or_default(),
-                );
+                debug!("Mapped request body to {path}: {}", self.redacted(&body),);
             }
         }
 
-        debug!("Executing model definition with config {config:#?}, headers {headers:#?}, query params {query_params:#?}");
+        debug!(
+            "Executing model definition with config {config:#?}, headers {:#?}, query params {query_params:#?}",
+            self.redacted_headers(&headers)
+        );
 
         let context = match body {
             None | Some(Value::Null) => None,
@@ -596,7 +772,14 @@ impl UnifiedDestination {
 
         let mut latency = 0i64;
         let mut res = self
-            .execute_model_definition(&config, headers, &query_params, &secret, context)
+            .execute_with_refresh(
+                &connection,
+                &config,
+                headers,
+                &query_params,
+                &secret,
+                context,
+            )
             .timed(|_, duration| {
                 latency = duration.as_millis() as i64;
             })
@@ -612,7 +795,7 @@ impl UnifiedDestination {
         debug!(
             "Executed model definition with status code {}, headers: {:#?}",
             res.status(),
-            res.headers()
+            self.redacted_headers(res.headers())
         );
 
         let headers = std::mem::take(res.headers_mut());
@@ -651,17 +834,7 @@ impl UnifiedDestination {
             None
         };
 
-        debug!(
-            "Received response body: {}",
-            serde_json::to_string_pretty(&body)
-                .map_err(|e| {
-                    error!(
-                        "Failed to convert body to pretty string. ID: {}, Body: {:?}, Error: {} ",
-                        config.id, body, e
-                    );
-                })
-                .unwrap_or_default(),
-        );
+        debug!("Received response body: {}", self.redacted(&body),);
 
         let pagination = if config.action_name == CrudAction::GetMany {
             if let Some(CrudMapping {
@@ -670,17 +843,6 @@ impl UnifiedDestination {
             }) = &config.mapping
             {
                 if !js.is_empty() {
-                    let ns: String = crud_script_namespace + "_mapToCrudRequest";
-                    JS_RUNTIME
-                        .with_borrow_mut(|script| {
-                            script.add_script(&ns, "mapCrudRequest", js.as_str())
-                        })
-                        .map_err(|e| {
-                            error!("Failed to create response crud mapping script for connection model. ID: {}, JS: {}, Error: {}", config.id, js, e);
-
-                            ApplicationError::bad_request(&e.to_string(), None).set_meta(&metadata)
-                        })?;
-
                     let pagination = if let (
                         Some(ModelPaths {
                             response:
@@ -719,14 +881,13 @@ impl UnifiedDestination {
 
                     debug!(
                         "Mapping response crud {}\nUsing js {js}",
-                        serde_json::to_string_pretty(&res_to_map).map_err(|e| {
-                            error!("Failed to convert response crud to pretty string. ID: {}, Response to Map: {:?}, Error: {}", config.id, res_to_map, e);
-                        })
-                        .unwrap_or_default(),
+                        self.redacted(&res_to_map),
                     );
 
-                    let res: ResponseCrud = JS_RUNTIME
-                        .with_borrow_mut(|script| script.call_namespace(&ns, &res_to_map))
+                    let res: ResponseCrud = self
+                        .scripts
+                        .call(js, "mapCrudRequest", (&res_to_map,))
+                        .await
                         .map_err(|e| {
                             ApplicationError::bad_request(
                                 &format!("Failed while running response crud mapping script. ID: {}, Error: {}", config.id, e),
@@ -735,17 +896,7 @@ impl UnifiedDestination {
                             .set_meta(&metadata)
                         })?;
 
-                    tokio::task::yield_now().await;
-
-                    debug!(
-                        "Mapped response crud to {}",
-                        serde_json::to_string_pretty(&res).map_err(|e| {
-                            error!("Failed to convert response crud to pretty string. ID: {}, Response: {:?}, Error: {}", config.id, res, e);
-
-                            InternalError::invalid_argument(&e.to_string(), None)
-                                .set_meta(&metadata)
-                        })?
-                    );
+                    debug!("Mapped response crud to {}", self.redacted(&res));
 
                     res.pagination
                 } else {
@@ -829,14 +980,7 @@ impl UnifiedDestination {
             } else {
                 None
             };
-            debug!(
-                "Mapped response body to {path}: {}",
-                serde_json::to_string_pretty(&body)
-                    .map_err(|e| {
-                        error!("Could not convert mapped body to pretty string {body:?}: {e}");
-                    })
-                    .unwrap_or_default(),
-            );
+            debug!("Mapped response body to {path}: {}", self.redacted(&body),);
         }
 
         if matches!(
@@ -853,22 +997,9 @@ impl UnifiedDestination {
                 )
                 .set_meta(&metadata));
             };
-            let ns: String = schema_script_namespace + "_mapToCommonModel";
-            JS_RUNTIME
-                .with_borrow_mut(|script| script.add_script(&ns, "mapToCommonModel", js))
-                .map_err(|e| {
-                    error!("Failed to create response schema mapping script for connection model. ID: {}, JS: {}, Error: {}", config.id, js, e);
-
-                    ApplicationError::bad_request(&e.to_string(), None).set_meta(&metadata)
-                })?;
-
             debug!(
                 "Mapping response body {}\nUsing js {js}",
-                serde_json::to_string_pretty(&body)
-                    .map_err(|e| {
-                        error!("Could not convert body to pretty string {body:?}: {e}");
-                    })
-                    .unwrap_or_default(),
+                self.redacted(&body),
             );
 
             const ID_KEY: &str = "id";
@@ -878,20 +1009,17 @@ impl UnifiedDestination {
                 let mut futs = Vec::with_capacity(arr.len());
                 for body in arr {
                     futs.push(async {
-                        let res =
-                            JS_RUNTIME.with_borrow_mut(|script| {
-                                script
-                            .add_script(&ns, "mapToCommonModel", js)
-                            .and_then(|_| script.call_namespace(&ns, body))
+                        let res: Result<Value, IntegrationOSError> = self
+                            .scripts
+                            .call(js, "mapToCommonModel", (body,))
+                            .await
                             .map_err(|e| {
                                 ApplicationError::bad_request(
                                     &format!("Failed while running response schema mapping script: {}. ID: {}", e, config.id),
                                     None,
                                 )
                                 .set_meta(&metadata)
-                            })
                             });
-                        tokio::task::yield_now().await;
                         res.map(|mut body| {
                             if let Value::Object(map) = &mut body {
                                 if !map.contains_key(MODIFY_TOKEN_KEY) {
@@ -909,9 +1037,10 @@ impl UnifiedDestination {
                     .collect::<Result<Vec<Value>, _>>()?;
                 Value::Array(values)
             } else if let Some(body) = &body {
-                JS_RUNTIME
-                    .with_borrow_mut(|script| script.call_namespace(&ns, body))
-                    .map(|mut body| {
+                self.scripts
+                    .call(js, "mapToCommonModel", (body,))
+                    .await
+                    .map(|mut body: Value| {
                         if let Value::Object(map) = &mut body {
                             if !map.contains_key(MODIFY_TOKEN_KEY) {
                                 let v = map.get(ID_KEY).cloned().unwrap_or(json!(""));
@@ -940,14 +1069,7 @@ impl UnifiedDestination {
             body = None;
         }
 
-        debug!(
-            "Mapped response body to {}",
-            serde_json::to_string_pretty(&body)
-                .map_err(|e| {
-                    error!("Could not convert body to pretty string {body:?}: {e}");
-                })
-                .unwrap_or_default(),
-        );
+        debug!("Mapped response body to {}", self.redacted(&body),);
 
         let mut response = json!({});
 
@@ -1103,6 +1225,8 @@ impl UnifiedDestination {
             ));
         }
 
+        self.refresh_expired(&connection).await;
+
         let secret = self
             .secrets_cache
             .get_or_insert_with_fn(connection.as_ref().clone(), || async {
@@ -1135,7 +1259,30 @@ impl UnifiedDestination {
             _ => config.clone(),
         };
 
-        self.execute_model_definition(&templated_config, headers, &query_params, &secret, context)
-            .await
+        self.execute_with_refresh(
+            &connection,
+            &templated_config,
+            headers,
+            &query_params,
+            &secret,
+            context,
+        )
+        .await
     }
 }
+
+/// Renders the templates of a model definition, e.g. a base url or header that
+/// carries the token, with the secret of the call
+fn render_config(
+    config: &ConnectionModelDefinition,
+    secret: &Value,
+) -> Result<ConnectionModelDefinition, IntegrationOSError> {
+    let config_str = serde_json::to_string(config)
+        .map_err(|e| InternalError::invalid_argument(&e.to_string(), None))?;
+
+    let config = Handlebars::new()
+        .render_template(&config_str, secret)
+        .map_err(|e| InternalError::invalid_argument(&e.to_string(), None))?;
+
+    serde_json::from_str(&config).map_err(|e| InternalError::invalid_argument(&e.to_string(), None))
+}
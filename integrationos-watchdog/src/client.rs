@@ -1,16 +1,29 @@
-use crate::config::WatchdogConfig;
+use crate::{
+    config::WatchdogConfig,
+    health_checker::HealthChecker,
+    leader::LeaderLease,
+    metrics::{DEAD_CONTEXTS_COUNTER, DROPPED_CONTEXTS_COUNTER, REPUBLISHED_CONTEXTS_COUNTER},
+    oauth_refresher::OAuthRefresher,
+};
 use bson::{doc, Bson, Document};
 use chrono::Utc;
 use futures::{future::join_all, TryStreamExt};
-use integrationos_cache::remote::RedisCache;
+use integrationos_cache::{lifecycle::LifecyclePublisher, remote::RedisCache};
 use integrationos_domain::{
     cache::CacheConfig, database::DatabaseConfig, event_with_context::EventWithContext,
-    pipeline_context::PipelineStage, prelude::MongoStore, root_context::RootStage, Event,
-    ExtractorContext, IntegrationOSError, InternalError, PipelineContext, RootContext, Store,
+    pipeline_context::PipelineStage, prelude::MongoStore, root_context::RootStage,
+    scripting::ScriptService, secret::Secret, secrets::SecretServiceProvider, EnvelopeKms, Event,
+    ExtractorContext, GoogleKms, IOSKms, IntegrationOSError, InternalError, PipelineContext,
+    PipelineStatus, RootContext, SecretExt, Store, VaultKms,
+};
+use integrationos_unified::{
+    oauth::OAuthRefresh,
+    unified::{UnifiedCacheTTLs, UnifiedDestination},
 };
-use mongodb::options::FindOneOptions;
-use redis::{AsyncCommands, LposOptions, RedisResult};
+use mongodb::{options::FindOneOptions, Collection};
+use redis::{aio::ConnectionManager, AsyncCommands, LposOptions, RedisResult};
 use std::fmt::Display;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::task::JoinHandle;
 use tracing::{error, info, warn};
@@ -57,19 +70,31 @@ impl WatchdogClient {
 
         info!("Initializing connection to cache");
 
+        let leader = LeaderLease::new(
+            self.watchdog.leader_key.clone(),
+            Duration::from_secs(self.watchdog.leader_lease_duration),
+        );
+        leader.campaign(cache.inner.clone());
+
         let mut redis_clone = cache.inner.clone();
+        let leader_clone = leader.clone();
         tokio::spawn(async move {
             loop {
-                let _: RedisResult<String> = async { redis_clone.del(key.clone()).await }.await;
+                if leader_clone.is_leader() {
+                    let _: RedisResult<String> = async { redis_clone.del(key.clone()).await }.await;
+                }
                 tokio::time::sleep(Duration::from_secs(1)).await;
             }
         });
 
         let key = self.cache.api_throughput_key.clone();
         let mut redis_clone = cache.inner.clone();
+        let leader_clone = leader.clone();
         tokio::spawn(async move {
             loop {
-                let _: RedisResult<String> = async { redis_clone.del(key.clone()).await }.await;
+                if leader_clone.is_leader() {
+                    let _: RedisResult<String> = async { redis_clone.del(key.clone()).await }.await;
+                }
                 tokio::time::sleep(Duration::from_secs(60)).await;
             }
         });
@@ -107,7 +132,24 @@ impl WatchdogClient {
 
         info!("Initialized connection to storage");
 
+        self.start_connection_tasks(
+            &leader,
+            cache.inner.clone(),
+            event_store.clone(),
+            root_coll.clone(),
+        )
+        .await?;
+
         loop {
+            if !leader.is_leader() {
+                info!(
+                    "Not the leader, sleeping for {} seconds",
+                    self.watchdog.poll_duration
+                );
+                tokio::time::sleep(Duration::from_secs(self.watchdog.poll_duration)).await;
+                continue;
+            }
+
             info!("Polling for unresponsive contexts");
             let mut count = 0;
             let timestamp =
@@ -167,10 +209,18 @@ impl WatchdogClient {
             info!("Fetched event keys");
 
             'outer: while let Some(event_key) = event_keys.try_next().await? {
+                // The lease can be lost while republishing, another replica takes over from here
+                if !leader.is_leader() {
+                    warn!("Lost leadership while republishing, stopping");
+                    break;
+                }
+
                 let Some(Bson::String(event_key)) = event_key.get("_id") else {
                     error!("Could not get _id out of event keys response");
                     continue;
                 };
+                metrics::increment_counter!(DEAD_CONTEXTS_COUNTER);
+
                 // Sort by earliest timestamp to get latest context
                 let options = FindOneOptions::builder()
                     .sort(doc! { "timestamp": -1 })
@@ -299,8 +349,57 @@ impl WatchdogClient {
                     continue;
                 }
 
+                let counter_key = format!("watchdog:republished:{event_key}");
+                // Incremented and expired atomically so no counter outlives its TTL
+                let attempts: RedisResult<(u64, bool)> = redis::pipe()
+                    .atomic()
+                    .incr(&counter_key, 1)
+                    .expire(&counter_key, self.watchdog.republish_counter_ttl as i64)
+                    .query_async(&mut cache.inner)
+                    .await;
+                let attempts = match attempts {
+                    Ok((attempts, _)) => attempts,
+                    Err(e) => {
+                        error!("Could not increment republish counter for {event_key}: {e}");
+                        continue;
+                    }
+                };
+
+                if attempts > self.watchdog.max_republish_attempts {
+                    warn!(
+                        "Dropping {event_key} after {} republish attempts",
+                        attempts - 1
+                    );
+
+                    let mut root_context = event_with_context.context;
+                    root_context.status = PipelineStatus::Dropped {
+                        reason: format!(
+                            "Exceeded {} republish attempts",
+                            self.watchdog.max_republish_attempts
+                        ),
+                    };
+                    root_context.timestamp = Utc::now();
+
+                    if let Err(e) = root_coll.insert_one(&root_context).await {
+                        error!("Could not drop context {event_key}: {e}");
+                        continue;
+                    }
+                    if let Err(e) = event_store
+                        .update_one(event_key, doc! { "$set": { "state": "dropped" } })
+                        .await
+                    {
+                        error!("Could not mark event {event_key} as dropped: {e}");
+                    }
+
+                    metrics::increment_counter!(DROPPED_CONTEXTS_COUNTER);
+                    continue;
+                }
+
                 match cache.inner.lpush(&self.cache.queue_name, payload).await {
-                    Ok(()) => count += 1,
+                    Ok(()) => {
+                        metrics::increment_counter!(REPUBLISHED_CONTEXTS_COUNTER);
+                        count += 1
+                    }
                     Err(e) => error!("Could not publish event to redis: {e}"),
                 }
             }
@@ -313,4 +412,99 @@ impl WatchdogClient {
             tokio::time::sleep(Duration::from_secs(self.watchdog.poll_duration)).await;
         }
     }
+
+    /// Starts the tasks keeping connections working, refreshing their tokens and
+    /// checking their health
+    async fn start_connection_tasks(
+        &self,
+        leader: &LeaderLease,
+        redis: ConnectionManager,
+        event_store: MongoStore<Event>,
+        context_collection: Collection<RootContext>,
+    ) -> Result<(), IntegrationOSError> {
+        let control_mongo = mongodb::Client::with_uri_str(self.database.control_db_url.clone())
+            .await
+            .map_err(|e| {
+                error!("Could not connect to control db: {e}");
+                InternalError::io_err(e.to_string().as_str(), None)
+            })?;
+        let control_db = control_mongo.database(&self.database.control_db_name);
+        let connections_store = MongoStore::new(&control_db, &Store::Connections).await?;
+        let oauth_definitions_store =
+            MongoStore::new(&control_db, &Store::ConnectionOAuthDefinitions).await?;
+        let connection_definitions_store =
+            MongoStore::new(&control_db, &Store::ConnectionDefinitions).await?;
+        let model_definitions_store =
+            MongoStore::new(&control_db, &Store::ConnectionModelDefinitions).await?;
+        let secrets_store = MongoStore::<Secret>::new(&control_db, &Store::Secrets).await?;
+
+        let secrets_config = &self.watchdog.secrets_config;
+        let secrets_client: Arc<dyn SecretExt + Sync + Send> = match secrets_config.provider {
+            SecretServiceProvider::GoogleKms => {
+                Arc::new(GoogleKms::new(secrets_config, secrets_store).await?)
+            }
+            SecretServiceProvider::IosKms => {
+                Arc::new(IOSKms::new(secrets_config, secrets_store).await?)
+            }
+            SecretServiceProvider::Vault => {
+                Arc::new(VaultKms::new(secrets_config, secrets_store).await?)
+            }
+            SecretServiceProvider::Envelope => {
+                Arc::new(EnvelopeKms::new(secrets_config, secrets_store).await?)
+            }
+        };
+        let scripts = ScriptService::new(&self.watchdog.script_config)?;
+
+        let refresh = OAuthRefresh::new(
+            oauth_definitions_store,
+            connections_store.clone(),
+            secrets_client.clone(),
+            scripts.clone(),
+            reqwest::Client::new(),
+            redis.clone(),
+        );
+        let lifecycle = LifecyclePublisher::new(
+            event_store,
+            context_collection,
+            redis,
+            self.cache.queue_name.clone(),
+            &self.watchdog.event_access_password,
+        )?;
+
+        // Checks run every interval, so nothing is worth caching for longer
+        let cache_ttl = self.watchdog.health_check_interval;
+        let destination = UnifiedDestination::new(
+            self.database.clone(),
+            self.watchdog.health_check_cache_size,
+            secrets_client,
+            scripts,
+            UnifiedCacheTTLs {
+                connection_cache_ttl_secs: cache_ttl,
+                connection_model_definition_cache_ttl_secs: cache_ttl,
+                connection_model_schema_cache_ttl_secs: cache_ttl,
+                secret_cache_ttl_secs: cache_ttl,
+            },
+        )
+        .await?
+        .with_oauth_refresh(redis);
+
+        OAuthRefresher::new(
+            &self.watchdog,
+            refresh,
+            connections_store.clone(),
+            lifecycle.clone(),
+        )
+        .start(leader.clone());
+        HealthChecker::new(
+            &self.watchdog,
+            destination,
+            connections_store,
+            connection_definitions_store,
+            model_definitions_store,
+            lifecycle,
+        )
+        .start(leader.clone());
+
+        Ok(())
+    }
 }
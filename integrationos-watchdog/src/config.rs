@@ -1,5 +1,7 @@
 use envconfig::Envconfig;
-use integrationos_domain::{cache::CacheConfig, database::DatabaseConfig};
+use integrationos_domain::{
+    cache::CacheConfig, database::DatabaseConfig, script::ScriptConfig, secrets::SecretsConfig,
+};
 use std::fmt::{Display, Formatter};
 
 #[derive(Envconfig, Clone)] // Intentionally no Debug so secret is not printed
@@ -8,17 +10,105 @@ pub struct WatchdogConfig {
     pub event_timeout: u64,
     #[envconfig(from = "POLL_DURATION", default = "10")] // 10 seconds
     pub poll_duration: u64,
+    #[envconfig(from = "LEADER_KEY", default = "watchdog-leader")]
+    pub leader_key: String,
+    #[envconfig(from = "LEADER_LEASE_DURATION", default = "30")] // 30 seconds
+    pub leader_lease_duration: u64,
+    #[envconfig(from = "MAX_REPUBLISH_ATTEMPTS", default = "5")]
+    pub max_republish_attempts: u64,
+    #[envconfig(from = "REPUBLISH_COUNTER_TTL", default = "86400")] // 1 day
+    pub republish_counter_ttl: u64,
+    #[envconfig(from = "OAUTH_REFRESH_INTERVAL", default = "60")] // 1 minute
+    pub oauth_refresh_interval: u64,
+    /// Tokens expiring within this many seconds are refreshed ahead of time
+    #[envconfig(from = "OAUTH_REFRESH_WINDOW", default = "900")] // 15 minutes
+    pub oauth_refresh_window: u64,
+    #[envconfig(from = "OAUTH_REFRESH_CONCURRENCY", default = "10")]
+    pub oauth_refresh_concurrency: usize,
+    #[envconfig(from = "OAUTH_REFRESH_MAX_ATTEMPTS", default = "3")]
+    pub oauth_refresh_max_attempts: u32,
+    /// Doubled after every failed attempt
+    #[envconfig(from = "OAUTH_REFRESH_BACKOFF_MS", default = "1000")]
+    pub oauth_refresh_backoff_ms: u64,
+    #[envconfig(from = "HEALTH_CHECK_INTERVAL", default = "300")] // 5 minutes
+    pub health_check_interval: u64,
+    #[envconfig(from = "HEALTH_CHECK_TIMEOUT", default = "30")] // 30 seconds
+    pub health_check_timeout: u64,
+    #[envconfig(from = "HEALTH_CHECK_CONCURRENCY", default = "10")]
+    pub health_check_concurrency: usize,
+    /// Consecutive failed checks before a connection is marked as unhealthy
+    #[envconfig(from = "HEALTH_CHECK_FAILURE_THRESHOLD", default = "3")]
+    pub health_check_failure_threshold: u32,
+    /// Consecutive passed checks before an unhealthy connection recovers
+    #[envconfig(from = "HEALTH_CHECK_RECOVERY_THRESHOLD", default = "2")]
+    pub health_check_recovery_threshold: u32,
+    /// Mutual TLS clients and access tokens kept between health checks
+    #[envconfig(from = "HEALTH_CHECK_CACHE_SIZE", default = "1000")]
+    pub health_check_cache_size: u64,
+    /// 32 bytes, required as it decrypts the access keys of events
+    #[envconfig(from = "EVENT_ACCESS_PASSWORD")]
+    pub event_access_password: String,
     #[envconfig(nested = true)]
     pub redis: CacheConfig,
     #[envconfig(nested = true)]
     pub db: DatabaseConfig,
+    #[envconfig(nested = true)]
+    pub secrets_config: SecretsConfig,
+    #[envconfig(nested = true)]
+    pub script_config: ScriptConfig,
 }
 
 impl Display for WatchdogConfig {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "POLL_DURATION: {}", self.poll_duration)?;
         writeln!(f, "EVENT_TIMEOUT: {}", self.event_timeout)?;
+        writeln!(f, "LEADER_KEY: {}", self.leader_key)?;
+        writeln!(f, "LEADER_LEASE_DURATION: {}", self.leader_lease_duration)?;
+        writeln!(f, "MAX_REPUBLISH_ATTEMPTS: {}", self.max_republish_attempts)?;
+        writeln!(f, "REPUBLISH_COUNTER_TTL: {}", self.republish_counter_ttl)?;
+        writeln!(f, "OAUTH_REFRESH_INTERVAL: {}", self.oauth_refresh_interval)?;
+        writeln!(f, "OAUTH_REFRESH_WINDOW: {}", self.oauth_refresh_window)?;
+        writeln!(
+            f,
+            "OAUTH_REFRESH_CONCURRENCY: {}",
+            self.oauth_refresh_concurrency
+        )?;
+        writeln!(
+            f,
+            "OAUTH_REFRESH_MAX_ATTEMPTS: {}",
+            self.oauth_refresh_max_attempts
+        )?;
+        writeln!(
+            f,
+            "OAUTH_REFRESH_BACKOFF_MS: {}",
+            self.oauth_refresh_backoff_ms
+        )?;
+        writeln!(f, "HEALTH_CHECK_INTERVAL: {}", self.health_check_interval)?;
+        writeln!(f, "HEALTH_CHECK_TIMEOUT: {}", self.health_check_timeout)?;
+        writeln!(
+            f,
+            "HEALTH_CHECK_CONCURRENCY: {}",
+            self.health_check_concurrency
+        )?;
+        writeln!(
+            f,
+            "HEALTH_CHECK_FAILURE_THRESHOLD: {}",
+            self.health_check_failure_threshold
+        )?;
+        writeln!(
+            f,
+            "HEALTH_CHECK_RECOVERY_THRESHOLD: {}",
+            self.health_check_recovery_threshold
+        )?;
+        writeln!(
+            f,
+            "HEALTH_CHECK_CACHE_SIZE: {}",
+            self.health_check_cache_size
+        )?;
+        writeln!(f, "EVENT_ACCESS_PASSWORD: ***")?;
         writeln!(f, "{}", self.redis)?;
-        writeln!(f, "{}", self.db)
+        writeln!(f, "{}", self.db)?;
+        writeln!(f, "{}", self.secrets_config)?;
+        write!(f, "{}", self.script_config)
     }
 }
@@ -0,0 +1,314 @@
+use crate::{
+    config::WatchdogConfig,
+    leader::LeaderLease,
+    metrics::{HEALTH_CHANGES_COUNTER, HEALTH_CHECKS_COUNTER, OUTCOME_LABEL},
+};
+use bson::doc;
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use integrationos_cache::lifecycle::LifecyclePublisher;
+use integrationos_domain::{
+    connection_definition::ConnectionDefinition,
+    connection_model_definition::ConnectionModelDefinition,
+    health::{HealthStatus, HealthThresholds},
+    id::Id,
+    lifecycle::ConnectionLifecycle,
+    prelude::MongoStore,
+    Connection, IntegrationOSError, InternalError,
+};
+use integrationos_unified::unified::UnifiedDestination;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// Connections read at once, each page is checked before the next one is read
+const PAGE_SIZE: u64 = 100;
+
+/// Runs the test model definition of every active connection on a schedule and
+/// records the outcome on the connection.
+///
+/// Connections are only marked as errored after several failed checks in a row,
+/// and only recover after several passed ones. Both changes are announced with
+/// a lifecycle event.
+pub struct HealthChecker {
+    destination: UnifiedDestination,
+    connections_store: MongoStore<Connection>,
+    connection_definitions_store: MongoStore<ConnectionDefinition>,
+    model_definitions_store: MongoStore<ConnectionModelDefinition>,
+    lifecycle: LifecyclePublisher,
+    thresholds: HealthThresholds,
+    interval: Duration,
+    timeout: Duration,
+    concurrency: usize,
+}
+
+impl HealthChecker {
+    pub fn new(
+        config: &WatchdogConfig,
+        destination: UnifiedDestination,
+        connections_store: MongoStore<Connection>,
+        connection_definitions_store: MongoStore<ConnectionDefinition>,
+        model_definitions_store: MongoStore<ConnectionModelDefinition>,
+        lifecycle: LifecyclePublisher,
+    ) -> Self {
+        Self {
+            destination,
+            connections_store,
+            connection_definitions_store,
+            model_definitions_store,
+            lifecycle,
+            thresholds: HealthThresholds {
+                failures: config.health_check_failure_threshold.max(1),
+                recoveries: config.health_check_recovery_threshold.max(1),
+            },
+            interval: Duration::from_secs(config.health_check_interval),
+            timeout: Duration::from_secs(config.health_check_timeout),
+            concurrency: config.health_check_concurrency.max(1),
+        }
+    }
+
+    /// Checks every connection each interval while this replica is the leader
+    pub fn start(self, leader: LeaderLease) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if leader.is_leader() {
+                    if let Err(e) = self.check_connections().await {
+                        error!("Could not check health of connections: {e}");
+                    }
+                }
+                tokio::time::sleep(self.interval).await;
+            }
+        })
+    }
+
+    async fn check_connections(&self) -> Result<(), IntegrationOSError> {
+        let tests = self
+            .connection_definitions_store
+            .get_many(
+                Some(doc! {
+                    "testConnection": { "$ne": null },
+                    "deleted": false,
+                }),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await?
+            .into_iter()
+            .filter_map(|definition| Some((definition.id, definition.test_connection?)))
+            .collect::<HashMap<Id, Id>>();
+
+        if tests.is_empty() {
+            return Ok(());
+        }
+
+        let model_definitions = self
+            .model_definitions_store
+            .get_many(
+                Some(doc! {
+                    "_id": { "$in": tests.values().map(Id::to_string).collect::<Vec<_>>() },
+                }),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await?
+            .into_iter()
+            .map(|definition| (definition.id, definition))
+            .collect::<HashMap<_, _>>();
+
+        // Connections errored for another reason, like a revoked token, need to
+        // be reauthorized rather than checked
+        let filter = doc! {
+            "connectionDefinitionId": {
+                "$in": tests.keys().map(Id::to_string).collect::<Vec<_>>()
+            },
+            "$or": [
+                { "hasError": false },
+                { "health.status": HealthStatus::Unhealthy.as_ref() },
+            ],
+            "active": true,
+            "deleted": false,
+        };
+        let mut after: Option<String> = None;
+        let mut count = 0;
+
+        loop {
+            let mut filter = filter.clone();
+            if let Some(after) = &after {
+                filter.insert("_id", doc! { "$gt": after });
+            }
+            let connections = self
+                .connections_store
+                .get_many(
+                    Some(filter),
+                    None,
+                    Some(doc! { "_id": 1 }),
+                    Some(PAGE_SIZE),
+                    None,
+                )
+                .await?;
+
+            let Some(last) = connections.last() else {
+                break;
+            };
+            after = Some(last.id.to_string());
+            let page_size = connections.len();
+            count += page_size;
+
+            stream::iter(connections)
+                .for_each_concurrent(self.concurrency, |connection| {
+                    let model_definition = tests
+                        .get(&connection.connection_definition_id)
+                        .and_then(|id| model_definitions.get(id));
+
+                    async move {
+                        match model_definition {
+                            Some(model_definition) => {
+                                self.check_connection(connection, model_definition).await
+                            }
+                            None => warn!(
+                                "Test model definition of connection {} not found",
+                                connection.id
+                            ),
+                        }
+                    }
+                })
+                .await;
+
+            if (page_size as u64) < PAGE_SIZE {
+                break;
+            }
+        }
+
+        if count > 0 {
+            info!("Checked health of {count} connections");
+        }
+
+        Ok(())
+    }
+
+    async fn check_connection(
+        &self,
+        mut connection: Connection,
+        model_definition: &ConnectionModelDefinition,
+    ) {
+        let started = Instant::now();
+        let error = match tokio::time::timeout(
+            self.timeout,
+            self.destination
+                .test_connection(&connection, model_definition),
+        )
+        .await
+        {
+            Ok(Ok(response)) if response.status().is_success() => None,
+            Ok(Ok(response)) => Some(format!("Test connection failed: {}", response.status())),
+            Ok(Err(e)) => Some(format!("Test connection failed: {e}")),
+            Err(_) => Some(format!(
+                "Test connection timed out after {} seconds",
+                self.timeout.as_secs()
+            )),
+        };
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let outcome = if error.is_none() { "passed" } else { "failed" };
+        metrics::increment_counter!(HEALTH_CHECKS_COUNTER, OUTCOME_LABEL => outcome);
+
+        let (health, lifecycle) = self.thresholds.record(
+            connection.health.as_ref(),
+            error,
+            latency_ms,
+            Utc::now().timestamp_millis(),
+        );
+
+        match lifecycle {
+            Some(ConnectionLifecycle::Unhealthy) => {
+                warn!(
+                    "Connection {} is unhealthy: {}",
+                    connection.id,
+                    health.error.as_deref().unwrap_or_default()
+                );
+                connection.mark_error(&format!(
+                    "Health check failed: {}",
+                    health.error.as_deref().unwrap_or_default()
+                ));
+            }
+            Some(_) => {
+                info!("Connection {} recovered", connection.id);
+                connection.clear_error();
+            }
+            None => {}
+        }
+        connection.health = Some(health);
+
+        match self.save(&connection, lifecycle.is_some()).await {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(
+                    "Connection {} was updated while it was checked, discarding the outcome",
+                    connection.id
+                );
+                return;
+            }
+            Err(e) => {
+                error!(
+                    "Could not record health of connection {}: {e}",
+                    connection.id
+                );
+                return;
+            }
+        }
+
+        if let Some(lifecycle) = lifecycle {
+            metrics::increment_counter!(HEALTH_CHANGES_COUNTER, OUTCOME_LABEL => lifecycle.to_string());
+
+            let data = serde_json::to_value(&connection.health).unwrap_or_default();
+            if let Err(e) = self.lifecycle.publish(&connection, lifecycle, data).await {
+                error!(
+                    "Could not publish {lifecycle} event of connection {}: {e}",
+                    connection.id
+                );
+            }
+        }
+    }
+
+    /// The error of the connection is only written when its status changed.
+    ///
+    /// Nothing is written when the connection was updated while it was checked,
+    /// e.g. by a reauthorization, as the outcome is about its previous secret.
+    /// Returns whether the outcome was recorded.
+    async fn save(
+        &self,
+        connection: &Connection,
+        changed: bool,
+    ) -> Result<bool, IntegrationOSError> {
+        let health = bson::to_bson(&connection.health)
+            .map_err(|e| InternalError::serialize_error(&e.to_string(), None))?;
+
+        let mut update = doc! { "health": health };
+        if changed {
+            update.insert("hasError", connection.has_error);
+            update.insert("error", connection.error.clone());
+            update.insert("updatedAt", Utc::now().timestamp_millis());
+        }
+
+        let result = self
+            .connections_store
+            .collection
+            .update_one(
+                doc! {
+                    "_id": connection.id.to_string(),
+                    "updatedAt": connection.record_metadata.updated_at,
+                },
+                doc! { "$set": update },
+            )
+            .await?;
+
+        Ok(result.matched_count > 0)
+    }
+}
@@ -0,0 +1,90 @@
+use crate::metrics::LEADER_GAUGE;
+use redis::{aio::ConnectionManager, RedisResult, Script};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+// Extends the lease if we already hold it, otherwise takes it only if nobody else does
+const ACQUIRE_OR_RENEW: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+    return 1
+end
+return 0
+"#;
+
+/// Leader election over a Redis key holding a lease.
+///
+/// Every replica competes for the same key. The holder renews the lease three
+/// times per lease duration; if it dies, the lease expires and another replica
+/// takes over on its next attempt. Only the leader should act on shared state.
+#[derive(Clone)]
+pub struct LeaderLease {
+    key: String,
+    holder: String,
+    lease: Duration,
+    is_leader: Arc<AtomicBool>,
+}
+
+impl LeaderLease {
+    pub fn new(key: String, lease: Duration) -> Self {
+        Self {
+            key,
+            holder: Uuid::new_v4().to_string(),
+            lease,
+            is_leader: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::SeqCst)
+    }
+
+    /// Tries to take or extend the lease, returning whether this replica is the leader
+    pub async fn acquire(&self, redis: &mut ConnectionManager) -> RedisResult<bool> {
+        let acquired: i64 = Script::new(ACQUIRE_OR_RENEW)
+            .key(&self.key)
+            .arg(&self.holder)
+            .arg(self.lease.as_millis() as u64)
+            .invoke_async(redis)
+            .await?;
+
+        Ok(acquired == 1)
+    }
+
+    /// Keeps competing for the lease in the background until the process exits
+    pub fn campaign(&self, mut redis: ConnectionManager) {
+        let lease = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match lease.acquire(&mut redis).await {
+                    Ok(acquired) => lease.set_leader(acquired),
+                    Err(e) => {
+                        // Without Redis we cannot know whether someone else took over
+                        error!("Could not renew leader lease: {e}");
+                        lease.set_leader(false);
+                    }
+                }
+                tokio::time::sleep(lease.lease / 3).await;
+            }
+        });
+    }
+
+    fn set_leader(&self, leader: bool) {
+        let was_leader = self.is_leader.swap(leader, Ordering::SeqCst);
+        match (was_leader, leader) {
+            (false, true) => info!("Acquired leader lease {}", self.key),
+            (true, false) => warn!("Lost leader lease {}", self.key),
+            _ => {}
+        }
+        metrics::gauge!(LEADER_GAUGE, if leader { 1.0 } else { 0.0 });
+    }
+}
@@ -1,7 +1,16 @@
 mod client;
 mod config;
+mod health_checker;
+mod leader;
+mod metrics;
+mod oauth_refresher;
 
 use crate::client::WatchdogClient;
+use crate::metrics::{
+    DEAD_CONTEXTS_COUNTER, DROPPED_CONTEXTS_COUNTER, HEALTH_CHANGES_COUNTER, HEALTH_CHECKS_COUNTER,
+    LEADER_GAUGE, OAUTH_REFRESHES_COUNTER, OAUTH_REFRESH_RETRIES_COUNTER,
+    REPUBLISHED_CONTEXTS_COUNTER,
+};
 use anyhow::{Context, Result};
 use config::WatchdogConfig;
 use dotenvy::dotenv;
@@ -11,6 +20,7 @@ use integrationos_domain::{
     database::DatabaseConfig,
     telemetry::{get_subscriber, init_subscriber},
 };
+use metrics_exporter_prometheus::PrometheusBuilder;
 use tracing::info;
 
 #[tokio::main]
@@ -26,6 +36,43 @@ async fn main() -> Result<()> {
 
     info!("Starting watchdog with config: {watchdog_config}{cache_config}{database_config}");
 
+    PrometheusBuilder::new()
+        .install()
+        .with_context(|| "failed to install prometheus server")?;
+
+    ::metrics::describe_counter!(
+        DEAD_CONTEXTS_COUNTER,
+        "number of unfinished contexts found without progress within the event timeout"
+    );
+    ::metrics::describe_counter!(
+        REPUBLISHED_CONTEXTS_COUNTER,
+        "number of dead contexts pushed back onto the event queue"
+    );
+    ::metrics::describe_counter!(
+        DROPPED_CONTEXTS_COUNTER,
+        "number of events dropped after exceeding the maximum republish attempts"
+    );
+    ::metrics::describe_counter!(
+        OAUTH_REFRESHES_COUNTER,
+        "number of oauth tokens refreshed ahead of expiry, by outcome"
+    );
+    ::metrics::describe_counter!(
+        OAUTH_REFRESH_RETRIES_COUNTER,
+        "number of oauth refresh attempts retried after a transient failure"
+    );
+    ::metrics::describe_counter!(
+        HEALTH_CHECKS_COUNTER,
+        "number of scheduled connection health checks, by outcome"
+    );
+    ::metrics::describe_counter!(
+        HEALTH_CHANGES_COUNTER,
+        "number of connections that turned unhealthy or recovered"
+    );
+    ::metrics::describe_gauge!(
+        LEADER_GAUGE,
+        "whether this replica currently holds the watchdog leader lease"
+    );
+
     let client = WatchdogClient::new(watchdog_config, cache_config, database_config);
 
     client.start().await??;
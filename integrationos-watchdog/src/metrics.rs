@@ -0,0 +1,17 @@
+// counter of unfinished contexts found without progress within the event timeout
+pub const DEAD_CONTEXTS_COUNTER: &str = "watchdog_dead_contexts";
+// counter of dead contexts pushed back onto the event queue
+pub const REPUBLISHED_CONTEXTS_COUNTER: &str = "watchdog_republished_contexts";
+// counter of events dropped after exceeding the maximum republish attempts
+pub const DROPPED_CONTEXTS_COUNTER: &str = "watchdog_dropped_contexts";
+// 1 if this replica currently holds the leader lease, 0 otherwise
+pub const LEADER_GAUGE: &str = "watchdog_leader";
+// counter of proactive oauth refreshes, labelled with their outcome
+pub const OAUTH_REFRESHES_COUNTER: &str = "watchdog_oauth_refreshes";
+// counter of oauth refresh attempts retried after a transient failure
+pub const OAUTH_REFRESH_RETRIES_COUNTER: &str = "watchdog_oauth_refresh_retries";
+// counter of scheduled connection health checks, labelled with their outcome
+pub const HEALTH_CHECKS_COUNTER: &str = "watchdog_health_checks";
+// counter of connections that turned unhealthy or recovered, labelled with the change
+pub const HEALTH_CHANGES_COUNTER: &str = "watchdog_health_changes";
+pub const OUTCOME_LABEL: &str = "outcome";
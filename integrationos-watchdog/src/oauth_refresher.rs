@@ -0,0 +1,219 @@
+use crate::{
+    config::WatchdogConfig,
+    leader::LeaderLease,
+    metrics::{OAUTH_REFRESHES_COUNTER, OAUTH_REFRESH_RETRIES_COUNTER, OUTCOME_LABEL},
+};
+use bson::doc;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use futures::{stream, StreamExt};
+use integrationos_cache::lifecycle::LifecyclePublisher;
+use integrationos_domain::{
+    lifecycle::ConnectionLifecycle, prelude::MongoStore, Connection, IntegrationOSError,
+};
+use integrationos_unified::oauth::{is_revoked, OAuthRefresh};
+use serde_json::json;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// Connections read at once, each page is refreshed before the next one is read
+const PAGE_SIZE: u64 = 100;
+
+/// Refreshes oauth tokens before they expire, so that connections nobody used
+/// for a while still work the next time they are called.
+///
+/// Connections whose refresh is rejected by the platform are marked as errored
+/// and announced with a lifecycle event, they need to be authorized again.
+/// Successful refreshes are announced too.
+pub struct OAuthRefresher {
+    refresh: OAuthRefresh,
+    connections_store: MongoStore<Connection>,
+    lifecycle: LifecyclePublisher,
+    interval: Duration,
+    window: ChronoDuration,
+    concurrency: usize,
+    max_attempts: u32,
+    backoff: Duration,
+}
+
+impl OAuthRefresher {
+    pub fn new(
+        config: &WatchdogConfig,
+        refresh: OAuthRefresh,
+        connections_store: MongoStore<Connection>,
+        lifecycle: LifecyclePublisher,
+    ) -> Self {
+        Self {
+            refresh,
+            connections_store,
+            lifecycle,
+            interval: Duration::from_secs(config.oauth_refresh_interval),
+            window: ChronoDuration::seconds(config.oauth_refresh_window as i64),
+            concurrency: config.oauth_refresh_concurrency.max(1),
+            max_attempts: config.oauth_refresh_max_attempts.max(1),
+            backoff: Duration::from_millis(config.oauth_refresh_backoff_ms),
+        }
+    }
+
+    /// Refreshes expiring tokens every interval while this replica is the leader
+    pub fn start(self, leader: LeaderLease) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if leader.is_leader() {
+                    if let Err(e) = self.refresh_expiring().await {
+                        error!("Could not refresh expiring oauth tokens: {e}");
+                    }
+                }
+                tokio::time::sleep(self.interval).await;
+            }
+        })
+    }
+
+    async fn refresh_expiring(&self) -> Result<(), IntegrationOSError> {
+        let expires_before = Utc::now() + self.window;
+        let mut after: Option<String> = None;
+        let mut count = 0;
+
+        loop {
+            let mut filter = doc! {
+                "oauth.enabled.expires_at": { "$lte": expires_before.timestamp() },
+                "hasError": false,
+                "active": true,
+                "deleted": false,
+            };
+            if let Some(after) = &after {
+                filter.insert("_id", doc! { "$gt": after });
+            }
+            let connections = self
+                .connections_store
+                .get_many(
+                    Some(filter),
+                    None,
+                    Some(doc! { "_id": 1 }),
+                    Some(PAGE_SIZE),
+                    None,
+                )
+                .await?;
+
+            let Some(last) = connections.last() else {
+                break;
+            };
+            after = Some(last.id.to_string());
+            let page_size = connections.len();
+            count += page_size;
+
+            stream::iter(connections)
+                .for_each_concurrent(self.concurrency, |connection| {
+                    self.refresh_connection(connection, expires_before)
+                })
+                .await;
+
+            if (page_size as u64) < PAGE_SIZE {
+                break;
+            }
+        }
+
+        if count > 0 {
+            info!("Went through {count} expiring oauth tokens");
+        }
+
+        Ok(())
+    }
+
+    async fn refresh_connection(&self, mut connection: Connection, expires_before: DateTime<Utc>) {
+        let mut attempt = 1;
+        loop {
+            let error = match self
+                .refresh
+                .refresh_expiring(&connection, expires_before)
+                .await
+            {
+                Ok(refreshed) => {
+                    metrics::increment_counter!(OAUTH_REFRESHES_COUNTER, OUTCOME_LABEL => "refreshed");
+                    if refreshed.is_some() {
+                        self.announce_refresh(&connection).await;
+                    }
+                    return;
+                }
+                Err(e) => e,
+            };
+
+            if is_revoked(&error) {
+                warn!(
+                    "Platform revoked the oauth token of connection {}: {error}",
+                    connection.id
+                );
+                metrics::increment_counter!(OAUTH_REFRESHES_COUNTER, OUTCOME_LABEL => "revoked");
+                self.mark_revoked(&mut connection, &error).await;
+                return;
+            }
+
+            if attempt >= self.max_attempts {
+                // The connection is picked up again on the next scan
+                error!(
+                    "Could not refresh oauth token of connection {} after {attempt} attempts: {error}",
+                    connection.id
+                );
+                metrics::increment_counter!(OAUTH_REFRESHES_COUNTER, OUTCOME_LABEL => "failed");
+                return;
+            }
+
+            metrics::increment_counter!(OAUTH_REFRESH_RETRIES_COUNTER);
+            tokio::time::sleep(self.backoff * 2u32.pow(attempt - 1)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn announce_refresh(&self, connection: &Connection) {
+        if let Err(e) = self
+            .lifecycle
+            .publish(connection, ConnectionLifecycle::Refreshed, json!({}))
+            .await
+        {
+            error!(
+                "Could not publish refresh of connection {}: {e}",
+                connection.id
+            );
+        }
+    }
+
+    async fn mark_revoked(&self, connection: &mut Connection, error: &IntegrationOSError) {
+        let reason = format!("OAuth token refresh was rejected: {error}");
+        connection.mark_error(&reason);
+
+        if let Err(e) = self
+            .connections_store
+            .update_one(
+                &connection.id.to_string(),
+                doc! {
+                    "$set": {
+                        "hasError": connection.has_error,
+                        "error": connection.error.clone(),
+                        "updatedAt": Utc::now().timestamp_millis(),
+                    }
+                },
+            )
+            .await
+        {
+            error!(
+                "Could not mark connection {} as errored: {e}",
+                connection.id
+            );
+        }
+
+        if let Err(e) = self
+            .lifecycle
+            .publish(
+                connection,
+                ConnectionLifecycle::RefreshFailed,
+                json!({ "reason": reason }),
+            )
+            .await
+        {
+            error!(
+                "Could not publish refresh failure of connection {}: {e}",
+                connection.id
+            );
+        }
+    }
+}